@@ -0,0 +1,277 @@
+use std::{
+    cell::RefCell,
+    fs::File,
+    io, mem,
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::fs::MetadataExt,
+    },
+    ptr,
+};
+
+use io_backends::prelude::*;
+
+const QUEUE_DEPTH: libc::c_long = 8;
+
+const IOCB_CMD_PREAD: u16 = 0;
+const IOCB_CMD_PWRITE: u16 = 1;
+
+type AioContextT = libc::c_ulong;
+
+/// `struct iocb` from `linux/aio_abi.h` (little endian layout).
+#[repr(C)]
+#[derive(Default)]
+struct Iocb {
+    aio_data: u64,
+    aio_key: u32,
+    aio_rw_flags: i32,
+    aio_lio_opcode: u16,
+    aio_reqprio: i16,
+    aio_fildes: u32,
+    aio_buf: u64,
+    aio_nbytes: u64,
+    aio_offset: i64,
+    aio_reserved2: u64,
+    aio_flags: u32,
+    aio_resfd: u32,
+}
+
+/// `struct io_event` from `linux/aio_abi.h`.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+struct IoEvent {
+    data: u64,
+    obj: u64,
+    res: i64,
+    res2: i64,
+}
+
+// both structs are shared with the kernel, their layout must match exactly
+const _: () = assert!(mem::size_of::<Iocb>() == 64 && mem::size_of::<IoEvent>() == 32);
+
+thread_local! {
+    /// Set up on first use, so that a failing `io_setup` fails the request instead of
+    /// the thread.
+    static THREAD_CONTEXT: RefCell<Option<AioContext>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with the context of the calling thread.
+fn with_context<R>(action: Action, f: impl FnOnce(&mut AioContext) -> Result<R>) -> Result<R> {
+    let result = THREAD_CONTEXT.try_with(|ctx| {
+        let mut ctx = ctx.borrow_mut();
+        if ctx.is_none() {
+            *ctx = Some(AioContext::new()?);
+        }
+        let result = f(ctx.as_mut().unwrap());
+        if ctx.as_ref().is_some_and(|ctx| ctx.ctx == 0) {
+            // the context was torn down after a failed wait, set up a new one next time
+            *ctx = None;
+        }
+        result
+    });
+    match result {
+        Ok(result) => result,
+        Err(e) => Err(BackendError::map(&e, action)),
+    }
+}
+
+pub struct AioContext {
+    ctx: AioContextT,
+    user_data: u64,
+}
+
+impl AioContext {
+    fn new() -> io::Result<Self> {
+        let mut ctx: AioContextT = 0;
+        let ret = unsafe { libc::syscall(libc::SYS_io_setup, QUEUE_DEPTH, &mut ctx) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(AioContext { ctx, user_data: 0 })
+    }
+
+    /// Submits a single request and waits for its completion. Returns only once the
+    /// kernel is done with `buffer`, even if waiting fails.
+    fn submit(
+        &mut self,
+        opcode: u16,
+        fd: RawFd,
+        buffer: u64,
+        length: u64,
+        offset: u64,
+    ) -> io::Result<u64> {
+        let user_data = {
+            self.user_data += 1;
+            self.user_data
+        };
+
+        let mut iocb = Iocb {
+            aio_data: user_data,
+            aio_lio_opcode: opcode,
+            aio_fildes: fd as u32,
+            aio_buf: buffer,
+            aio_nbytes: length,
+            aio_offset: offset as i64,
+            ..Default::default()
+        };
+        let mut iocbs = [&mut iocb as *mut Iocb];
+
+        loop {
+            let ret =
+                unsafe { libc::syscall(libc::SYS_io_submit, self.ctx, 1, iocbs.as_mut_ptr()) };
+            match ret {
+                1 => break,
+                0 => continue,
+                _ => {
+                    let e = io::Error::last_os_error();
+                    match e.raw_os_error() {
+                        Some(libc::EINTR | libc::EAGAIN) => continue,
+                        _ => return Err(e),
+                    }
+                }
+            }
+        }
+
+        let mut event = IoEvent::default();
+        loop {
+            let ret = unsafe {
+                libc::syscall(
+                    libc::SYS_io_getevents,
+                    self.ctx,
+                    1,
+                    1,
+                    &mut event as *mut IoEvent,
+                    ptr::null_mut::<libc::timespec>(),
+                )
+            };
+            match ret {
+                1 if event.data == user_data => break,
+                1 | 0 => continue,
+                _ => {
+                    let e = io::Error::last_os_error();
+                    if e.raw_os_error() != Some(libc::EINTR) {
+                        self.abandon(&mut iocb);
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        match event.res {
+            res if res < 0 => Err(io::Error::from_raw_os_error(-res as i32)),
+            res => Ok(res as u64),
+        }
+    }
+
+    /// Stops waiting for `iocb`. Unless the request can be cancelled, the context is
+    /// destroyed, which blocks until all its requests have completed.
+    fn abandon(&mut self, iocb: &mut Iocb) {
+        let mut event = IoEvent::default();
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_io_cancel,
+                self.ctx,
+                iocb as *mut Iocb,
+                &mut event as *mut IoEvent,
+            )
+        };
+        if ret == 0 {
+            return;
+        }
+
+        unsafe {
+            libc::syscall(libc::SYS_io_destroy, self.ctx);
+        }
+        self.ctx = 0;
+    }
+
+    fn read(&mut self, buffer: &mut [u8], fd: RawFd, offset: u64) -> Result<u64> {
+        let mut n_read = 0;
+        while n_read < buffer.len() {
+            let rest = &mut buffer[n_read..];
+            let n = self
+                .submit(
+                    IOCB_CMD_PREAD,
+                    fd,
+                    rest.as_mut_ptr() as u64,
+                    rest.len() as u64,
+                    offset + n_read as u64,
+                )
+                .map_err(|e| BackendError::map(&e, Action::Read))?;
+            if n == 0 {
+                break;
+            }
+            n_read += n as usize;
+        }
+        Ok(n_read as u64)
+    }
+
+    fn write(&mut self, buffer: &[u8], fd: RawFd, offset: u64) -> Result<u64> {
+        let mut n_written = 0;
+        while n_written < buffer.len() {
+            let rest = &buffer[n_written..];
+            let n = self
+                .submit(
+                    IOCB_CMD_PWRITE,
+                    fd,
+                    rest.as_ptr() as u64,
+                    rest.len() as u64,
+                    offset + n_written as u64,
+                )
+                .map_err(|e| BackendError::map(&e, Action::Write))?;
+            if n == 0 {
+                return Err(BackendError::map(
+                    &io::Error::from(io::ErrorKind::WriteZero),
+                    Action::Write,
+                ));
+            }
+            n_written += n as usize;
+        }
+        Ok(n_written as u64)
+    }
+}
+
+impl Drop for AioContext {
+    fn drop(&mut self) {
+        if self.ctx != 0 {
+            unsafe {
+                libc::syscall(libc::SYS_io_destroy, self.ctx);
+            }
+        }
+    }
+}
+
+pub struct AioObject {
+    file: File,
+    fd: RawFd,
+}
+
+impl BackendObject for AioObject {
+    fn new(file: File, _config: &Config) -> Result<Self> {
+        let fd = file.as_raw_fd();
+        Ok(AioObject { file, fd })
+    }
+
+    fn read(&self, buffer: &mut [u8], offset: u64, _length: u64) -> Result<u64> {
+        with_context(Action::Read, |ctx| ctx.read(buffer, self.fd, offset))
+    }
+
+    fn write(&mut self, buffer: &[u8], offset: u64, _length: u64) -> Result<u64> {
+        with_context(Action::Write, |ctx| ctx.write(buffer, self.fd, offset))
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        // completed requests have reached the page cache, like with the posix backend
+        Ok(())
+    }
+
+    fn status(&self) -> Result<(i64, u64)> {
+        let metadata = self.file.metadata()?;
+        Ok((metadata.atime(), metadata.size() as _))
+    }
+}
+
+pub struct Adapter {}
+
+impl JuleaAdapter<AioObject> for Adapter {}
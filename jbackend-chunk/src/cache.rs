@@ -0,0 +1,111 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use rustc_hash::FxHashMap;
+
+use io_backends::prelude::*;
+
+struct Entry {
+    file: Arc<File>,
+    /// Orders the lookups, the entry with the lowest one is evicted first.
+    tick: u64,
+}
+
+struct Files {
+    entries: FxHashMap<(PathBuf, u64), Entry>,
+    tick: u64,
+}
+
+/// Keeps the chunk files of all objects open, up to a bound on the number of file
+/// descriptors. The least recently used chunk is closed when the bound is reached,
+/// files still in use are closed once they are released.
+pub struct FdCache {
+    files: Mutex<Files>,
+    capacity: usize,
+}
+
+impl FdCache {
+    pub fn new(capacity: usize) -> Self {
+        FdCache {
+            files: Mutex::new(Files {
+                entries: FxHashMap::default(),
+                tick: 0,
+            }),
+            capacity,
+        }
+    }
+
+    /// Returns chunk `index` of the object in `dir`, or `None` if it does not exist
+    /// and `create` is not set.
+    pub fn get(&self, dir: &Path, index: u64, create: bool) -> Result<Option<Arc<File>>> {
+        let mut files = self
+            .files
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+        files.tick += 1;
+        let tick = files.tick;
+
+        let key = (dir.to_path_buf(), index);
+        if let Some(entry) = files.entries.get_mut(&key) {
+            entry.tick = tick;
+            return Ok(Some(entry.file.clone()));
+        }
+
+        let file = match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(create)
+            .truncate(false)
+            .open(chunk_path(dir, index))
+        {
+            Ok(file) => Arc::new(file),
+            Err(e) if e.kind() == ErrorKind::NotFound && !create => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        if files.entries.len() >= self.capacity {
+            let oldest = files
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.tick)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                files.entries.remove(&oldest);
+            }
+        }
+        files.entries.insert(
+            key,
+            Entry {
+                file: file.clone(),
+                tick,
+            },
+        );
+        Ok(Some(file))
+    }
+
+    /// Closes the cached chunks of the object in `dir`.
+    pub fn evict(&self, dir: &Path) -> Result<()> {
+        self.files
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?
+            .entries
+            .retain(|(cached, _), _| cached != dir);
+        Ok(())
+    }
+}
+
+pub fn chunk_path(dir: &Path, index: u64) -> PathBuf {
+    dir.join(index.to_string())
+}
+
+/// Parses the index of a chunk from its file name, other files yield `None`.
+pub fn chunk_index(name: &str) -> Option<u64> {
+    match name.starts_with('0') && name.len() > 1 {
+        true => None,
+        false => name.parse().ok(),
+    }
+}
@@ -0,0 +1,309 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    io::ErrorKind,
+    os::unix::fs::{FileExt, MetadataExt},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use io_backends::prelude::*;
+
+use crate::cache::{chunk_index, chunk_path, FdCache};
+
+/// File in every object directory that records the chunk size of the object.
+pub const LAYOUT_FILE: &str = ".jchunk";
+
+const DEFAULT_CHUNK_SIZE: u64 = 1 << 30;
+const DEFAULT_FDS: usize = 256;
+
+/// An object stored as the directory `<root>/<namespace>/<name>`, holding chunk `i`,
+/// bytes `i * chunk_size..(i + 1) * chunk_size`, in the file named `i`. Chunks are
+/// only created when they are written to, missing ones read as zeros.
+pub struct ChunkedObject {
+    dir: PathBuf,
+    chunk_size: u64,
+    /// The size as of the last status or write through this object. Other handles of
+    /// the object may have grown it since.
+    size: AtomicU64,
+    /// Chunks written since the last sync.
+    dirty: Mutex<BTreeSet<u64>>,
+}
+
+impl ChunkedObject {
+    fn open(dir: PathBuf) -> Result<Self> {
+        let layout = fs::read_to_string(dir.join(LAYOUT_FILE))?;
+        let chunk_size =
+            layout
+                .trim()
+                .parse()
+                .ok()
+                .filter(|size| *size > 0)
+                .ok_or(BackendError::new(
+                    &format!("{:?} is corrupted", dir.join(LAYOUT_FILE)),
+                    Action::Open,
+                ))?;
+
+        let object = ChunkedObject {
+            dir,
+            chunk_size,
+            size: AtomicU64::new(0),
+            dirty: Mutex::new(BTreeSet::new()),
+        };
+        object.status()?;
+        Ok(object)
+    }
+
+    /// Splits the `length` bytes at `offset` into `(chunk, offset in chunk, length)`.
+    fn pieces(&self, offset: u64, length: u64) -> impl Iterator<Item = (u64, u64, usize)> {
+        let chunk_size = self.chunk_size;
+        let end = offset + length;
+
+        let mut position = offset;
+        std::iter::from_fn(move || {
+            if position >= end {
+                return None;
+            }
+            let at = position % chunk_size;
+            let n = (chunk_size - at).min(end - position);
+            let piece = (position / chunk_size, at, n as usize);
+            position += n;
+            Some(piece)
+        })
+    }
+
+    /// Computes the size from the last chunk, earlier chunks are full or holes.
+    fn status(&self) -> Result<(i64, u64)> {
+        let mut last = None;
+        for entry in fs::read_dir(&self.dir)? {
+            if let Some(index) = entry?.file_name().to_str().and_then(chunk_index) {
+                last = last.max(Some(index));
+            }
+        }
+
+        let last_mod = fs::metadata(&self.dir)?.atime();
+        match last {
+            Some(index) => {
+                let len = fs::metadata(chunk_path(&self.dir, index))?.len();
+                let size = index * self.chunk_size + len;
+                self.size.fetch_max(size, Ordering::Relaxed);
+                Ok((last_mod, size))
+            }
+            None => Ok((last_mod, 0)),
+        }
+    }
+
+    /// Only lists the chunks if the read goes past the cached size.
+    fn read(&self, cache: &FdCache, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        let mut size = self.size.load(Ordering::Relaxed);
+        if offset + buffer.len() as u64 > size {
+            size = self.status()?.1;
+        }
+        let length = match size.checked_sub(offset) {
+            Some(available) => available.min(buffer.len() as u64),
+            None => return Ok(0),
+        };
+
+        let mut done = 0;
+        for (index, at, n) in self.pieces(offset, length) {
+            let piece = &mut buffer[done..done + n];
+            done += n;
+
+            let Some(file) = cache.get(&self.dir, index, false)? else {
+                piece.fill(0);
+                continue;
+            };
+            let mut n_read = 0;
+            while n_read < n {
+                match file.read_at(&mut piece[n_read..], at + n_read as u64) {
+                    Ok(0) => break,
+                    Ok(n) => n_read += n,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(BackendError::map(&e, Action::Read)),
+                }
+            }
+            // a chunk before the last one ends early if its tail was never written
+            piece[n_read..].fill(0);
+        }
+        Ok(length)
+    }
+
+    fn write(&self, cache: &FdCache, buffer: &[u8], offset: u64) -> Result<u64> {
+        let mut dirty = self
+            .dirty
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+
+        let mut done = 0;
+        for (index, at, n) in self.pieces(offset, buffer.len() as u64) {
+            let file = cache
+                .get(&self.dir, index, true)?
+                .ok_or(BackendError::new_internal("Chunk was not created"))?;
+            file.write_all_at(&buffer[done..done + n], at)
+                .map_err(|e| BackendError::map(&e, Action::Write))?;
+            dirty.insert(index);
+            done += n;
+        }
+        self.size
+            .fetch_max(offset + buffer.len() as u64, Ordering::Relaxed);
+        Ok(buffer.len() as u64)
+    }
+
+    /// Syncs the chunks written since the last sync, and the directory for the chunks
+    /// that were created.
+    fn sync(&self, cache: &FdCache) -> Result<()> {
+        let mut dirty = self
+            .dirty
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+
+        for index in dirty.iter() {
+            if let Some(file) = cache.get(&self.dir, *index, false)? {
+                file.sync_data()
+                    .map_err(|e| BackendError::map(&e, Action::Sync))?;
+            }
+        }
+        if !dirty.is_empty() {
+            File::open(&self.dir)?.sync_all()?;
+        }
+        dirty.clear();
+        Ok(())
+    }
+}
+
+pub struct ChunkBackend {
+    root: PathBuf,
+    chunk_size: u64,
+    cache: FdCache,
+}
+
+impl ChunkBackend {
+    pub fn new(root: PathBuf, config: &Config) -> Result<Self> {
+        let chunk_size = config.get_size("chunk_size")?.unwrap_or(DEFAULT_CHUNK_SIZE);
+        let fds = match config.get("chunk_fds") {
+            Some(v) => v.parse().map_err(|_| {
+                BackendError::new(
+                    &format!("Invalid value '{v}' for backend option 'chunk_fds'"),
+                    Action::Init,
+                )
+            })?,
+            None => DEFAULT_FDS,
+        };
+        if chunk_size == 0 || fds == 0 {
+            return Err(BackendError::new(
+                "Neither 'chunk_size' nor 'chunk_fds' may be 0",
+                Action::Init,
+            ));
+        }
+        fs::create_dir_all(&root)?;
+
+        Ok(ChunkBackend {
+            root,
+            chunk_size,
+            cache: FdCache::new(fds),
+        })
+    }
+}
+
+impl NamespaceBackend for ChunkBackend {
+    type Object = ChunkedObject;
+
+    fn init(path: &str, config: &Config) -> Result<Self> {
+        ChunkBackend::new(PathBuf::from(path), config)
+    }
+
+    /// Creates the directory of a new object with the configured chunk size.
+    fn create(&self, namespace: &str, name: &str) -> Result<ChunkedObject> {
+        let dir = self.root.join(namespace).join(name);
+        if let Some(parent) = dir.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::create_dir(&dir)?;
+        let temp = dir.join(format!("{TEMP_PREFIX}{LAYOUT_FILE}"));
+        fs::write(&temp, format!("{}\n", self.chunk_size))?;
+        fs::rename(&temp, dir.join(LAYOUT_FILE))?;
+        ChunkedObject::open(dir)
+    }
+
+    fn open(&self, namespace: &str, name: &str) -> Result<ChunkedObject> {
+        ChunkedObject::open(self.root.join(namespace).join(name))
+    }
+
+    fn delete(&self, object: &ChunkedObject) -> Result<()> {
+        self.cache.evict(&object.dir)?;
+        fs::remove_dir_all(&object.dir).map_err(|e| BackendError::map(&e, Action::Delete))
+    }
+
+    fn status(&self, object: &ChunkedObject) -> Result<(i64, u64)> {
+        object.status()
+    }
+
+    fn sync(&self, object: &ChunkedObject) -> Result<()> {
+        object.sync(&self.cache)
+    }
+
+    fn read(&self, object: &ChunkedObject, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        object.read(&self.cache, buffer, offset)
+    }
+
+    fn write(&self, object: &ChunkedObject, buffer: &[u8], offset: u64) -> Result<u64> {
+        object.write(&self.cache, buffer, offset)
+    }
+
+    /// Copies the layout file and every chunk into a new object directory, which is
+    /// removed again if the copy fails.
+    fn copy(
+        &self,
+        src_namespace: &str,
+        src_name: &str,
+        dst_namespace: &str,
+        dst_name: &str,
+    ) -> Result<()> {
+        let src = self.root.join(src_namespace).join(src_name);
+        let dst = self.root.join(dst_namespace).join(dst_name);
+        if let Some(dir) = dst.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::create_dir(&dst)?;
+
+        let res = (|| -> Result<()> {
+            for entry in fs::read_dir(&src)? {
+                let name = entry?.file_name();
+                if name.to_string_lossy().starts_with(TEMP_PREFIX) {
+                    continue;
+                }
+                let src_file = File::open(src.join(&name))?;
+                let dst_file = File::options()
+                    .write(true)
+                    .create_new(true)
+                    .open(dst.join(&name))?;
+                copy_file(&src_file, &dst_file)?;
+            }
+            Ok(())
+        })();
+        if res.is_err() {
+            let _ = fs::remove_dir_all(&dst);
+        }
+        res
+    }
+
+    fn names(&self, namespace: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(self.root.join(namespace))? {
+            let name = entry?.file_name().into_string().map_err(|_| {
+                BackendError::new("Unable to convert file name to UTF-8", Action::Iter)
+            })?;
+            if !name.starts_with(TEMP_PREFIX) && name.starts_with(prefix.unwrap_or_default()) {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+}
+
+pub struct Adapter {}
+
+impl NamespaceAdapter<ChunkBackend> for Adapter {}
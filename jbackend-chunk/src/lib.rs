@@ -0,0 +1,181 @@
+mod cache;
+mod chunk;
+
+use io_backends::generate_backend;
+use io_backends::prelude::*;
+use log::debug;
+use log::info;
+
+generate_backend!(chunk);
+
+#[no_mangle]
+pub unsafe extern "C" fn backend_info() -> *mut JBackend {
+    match init_logger() {
+        Ok(_) => info!("logger initialized."),
+        Err(e) => {
+            let _ = println!("Error while initializing logger: {e:?}");
+        }
+    };
+    debug!("backend info called");
+    &mut BACKEND
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CString;
+    use std::fs;
+    use std::path::Path;
+    use std::ptr;
+
+    use io_backends::prelude::*;
+    use io_backends::test_facility::writes;
+    use io_backends::testing::*;
+
+    use crate::chunk::{Adapter, ChunkBackend};
+    use crate::BACKEND;
+
+    type Backend = NamespaceData<ChunkBackend>;
+
+    fn init(root: &Path, options: &str) -> Backend {
+        let path = CString::new(format!("{}{options}", root.to_str().unwrap())).unwrap();
+        unsafe { Adapter::backend_init(path.as_ptr()).unwrap() }
+    }
+
+    fn open(backend: &Backend, name: &str) -> ObjectHandle {
+        let name = CString::new(name).unwrap();
+        unsafe { Adapter::backend_open(backend, "ns\0".as_ptr().cast(), name.as_ptr()).unwrap() }
+    }
+
+    fn read_all(backend: &Backend, handle: &ObjectHandle) -> Vec<u8> {
+        let mut buffer = vec![0u8; 32768];
+        let mut read = 0;
+        let ret = unsafe {
+            Adapter::j_read(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+                buffer.as_mut_ptr().cast(),
+                buffer.len() as u64,
+                0,
+                &mut read,
+            )
+        };
+        assert_eq!(ret, TRUE);
+        buffer.truncate(read as usize);
+        buffer
+    }
+
+    fn write(backend: &Backend, handle: &ObjectHandle, data: &[u8], offset: u64) {
+        let mut written = 0;
+        let ret = unsafe {
+            Adapter::j_write(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+                data.as_ptr().cast(),
+                data.len() as u64,
+                offset,
+                &mut written,
+            )
+        };
+        assert_eq!((ret, written), (TRUE, data.len() as u64));
+    }
+
+    fn size(backend: &Backend, handle: &ObjectHandle) -> u64 {
+        let (mut modified, mut size) = (0, 0);
+        let ret = unsafe {
+            Adapter::j_status(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+                &mut modified,
+                &mut size,
+            )
+        };
+        assert_eq!(ret, TRUE);
+        size
+    }
+
+    #[test]
+    fn repeated_writes() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory =
+            |_namespace| Box::into_raw(Box::new(ptr::null_mut() as gpointer)).cast::<gpointer>();
+
+        writes::test_writes_with_options(&backend, data_factory, "?chunk_size=64K&chunk_fds=4")
+    }
+
+    #[test]
+    fn test_chunk_layout() {
+        let temp = setup();
+        let dir = temp.join("ns/obj");
+        let chunks = || {
+            let mut names: Vec<String> = fs::read_dir(&dir)
+                .unwrap()
+                .map(|e| e.unwrap().file_name().into_string().unwrap())
+                .collect();
+            names.sort();
+            names
+        };
+        let backend = init(&temp, "?chunk_size=4K&chunk_fds=2");
+
+        let handle = unsafe {
+            Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "obj\0".as_ptr().cast())
+                .unwrap()
+        };
+        assert_eq!(size(&backend, &handle), 0);
+        assert!(unsafe {
+            Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "obj\0".as_ptr().cast())
+        }
+        .is_err());
+
+        // only the chunk that is written to exists, the hole before it costs nothing
+        write(&backend, &handle, b"end", 3 * 4096 + 10);
+        assert_eq!(chunks(), [".jchunk", "3"]);
+        assert_eq!(size(&backend, &handle), 3 * 4096 + 13);
+        let mut expected = vec![0u8; 3 * 4096 + 10];
+        expected.extend_from_slice(b"end");
+        assert_eq!(read_all(&backend, &handle), expected);
+
+        // a write across three chunks, with fewer cached descriptors
+        let data: Vec<u8> = (0..9000u32).map(|i| (i % 251) as u8).collect();
+        write(&backend, &handle, &data, 1000);
+        expected[1000..10_000].copy_from_slice(&data);
+        assert_eq!(chunks(), [".jchunk", "0", "1", "2", "3"]);
+        assert_eq!(read_all(&backend, &handle), expected);
+        let ret = unsafe {
+            Adapter::j_sync(
+                &backend as *const _ as gpointer,
+                &handle as *const _ as gpointer,
+            )
+        };
+        assert_eq!(ret, TRUE);
+        drop(backend);
+
+        // objects keep the chunk size they were created with
+        let backend = init(&temp, "?chunk_size=64K");
+        let handle = open(&backend, "obj");
+        assert_eq!(size(&backend, &handle), 3 * 4096 + 13);
+        assert_eq!(read_all(&backend, &handle), expected);
+
+        let ret = unsafe {
+            Adapter::j_copy(
+                &backend as *const _ as gpointer,
+                "ns\0".as_ptr().cast(),
+                "obj\0".as_ptr().cast(),
+                "ns\0".as_ptr().cast(),
+                "copy\0".as_ptr().cast(),
+            )
+        };
+        assert_eq!(ret, TRUE);
+        assert_eq!(read_all(&backend, &open(&backend, "copy")), expected);
+
+        let ret = unsafe {
+            Adapter::j_delete(
+                &backend as *const _ as gpointer,
+                &handle as *const _ as gpointer,
+            )
+        };
+        assert_eq!(ret, TRUE);
+        assert!(!dir.exists());
+
+        shutdown(temp)
+    }
+}
@@ -0,0 +1,106 @@
+use log::warn;
+
+use io_backends::prelude::*;
+
+/// How a chunk is stored. Recorded per chunk, so objects stay readable after the
+/// codec of their namespace was changed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Raw = 1,
+    Lz4 = 2,
+    Zstd = 3,
+}
+
+impl Encoding {
+    pub fn from_u8(value: u8) -> Result<Self> {
+        match value {
+            1 => Ok(Encoding::Raw),
+            2 => Ok(Encoding::Lz4),
+            3 => Ok(Encoding::Zstd),
+            _ => Err(BackendError::new(
+                &format!("Unknown chunk encoding {value}"),
+                Action::Read,
+            )),
+        }
+    }
+}
+
+/// The codec new chunks are compressed with, set with `compress=<lz4|zstd|none>`
+/// and `compress_level=<n>`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Codec {
+    None,
+    Lz4,
+    Zstd(i32),
+}
+
+impl Codec {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let level = match config.get("compress_level") {
+            Some(v) => Some(v.parse::<i32>().map_err(|_| {
+                BackendError::new(
+                    &format!("Invalid value '{v}' for backend option 'compress_level'"),
+                    Action::Init,
+                )
+            })?),
+            None => None,
+        };
+
+        match config.get("compress") {
+            None | Some("lz4") => {
+                if level.is_some() {
+                    warn!("lz4 has no compression levels, ignoring 'compress_level'");
+                }
+                Ok(Codec::Lz4)
+            }
+            Some("zstd") => Ok(Codec::Zstd(
+                level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL),
+            )),
+            Some("none") => Ok(Codec::None),
+            Some(v) => Err(BackendError::new(
+                &format!("Invalid value '{v}' for backend option 'compress'"),
+                Action::Init,
+            )),
+        }
+    }
+
+    /// Compresses a chunk. Chunks that do not get smaller are returned as they are.
+    pub fn compress(&self, data: &[u8]) -> Result<(Encoding, Vec<u8>)> {
+        let compressed = match self {
+            Codec::None => None,
+            Codec::Lz4 => Some((Encoding::Lz4, lz4_flex::block::compress_prepend_size(data))),
+            Codec::Zstd(level) => Some((
+                Encoding::Zstd,
+                zstd::bulk::compress(data, *level)
+                    .map_err(|e| BackendError::map(&e, Action::Write))?,
+            )),
+        };
+
+        match compressed {
+            Some((encoding, payload)) if payload.len() < data.len() => Ok((encoding, payload)),
+            _ => Ok((Encoding::Raw, data.to_vec())),
+        }
+    }
+}
+
+/// Restores a chunk of `raw_len` bytes.
+pub fn decompress(encoding: Encoding, payload: Vec<u8>, raw_len: usize) -> Result<Vec<u8>> {
+    let data = match encoding {
+        Encoding::Raw => payload,
+        Encoding::Lz4 => lz4_flex::block::decompress_size_prepended(&payload)
+            .map_err(|e| BackendError::map(&e, Action::Read))?,
+        Encoding::Zstd => zstd::bulk::decompress(&payload, raw_len)
+            .map_err(|e| BackendError::map(&e, Action::Read))?,
+    };
+
+    if data.len() != raw_len {
+        return Err(BackendError::new(
+            &format!(
+                "Chunk decompressed to {} b instead of {raw_len} b",
+                data.len()
+            ),
+            Action::Read,
+        ));
+    }
+    Ok(data)
+}
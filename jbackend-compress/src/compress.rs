@@ -0,0 +1,421 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    os::unix::fs::{FileExt, MetadataExt},
+    sync::{Arc, Mutex},
+};
+
+use log::{debug, trace};
+
+use io_backends::prelude::*;
+
+use crate::codec::{decompress, Codec, Encoding};
+
+const MAGIC: &[u8; 4] = b"JCMP";
+const VERSION: u32 = 1;
+
+/// magic, version, chunk size, logical size, index offset, index entries
+const HEADER_LEN: u64 = 40;
+/// offset, stored length, raw length, encoding
+const ENTRY_LEN: usize = 17;
+
+const DEFAULT_CHUNK_SIZE: u64 = 64 << 10;
+/// Number of modified chunks kept uncompressed in memory before they are written.
+const DIRTY_CHUNKS: usize = 16;
+
+static STATES: SharedStates<State> = SharedStates::new();
+
+/// Where a stored chunk lives in the file. Chunks that were never written or only
+/// contain zeros have no extent and read as zeros.
+#[derive(Debug, Clone, Copy)]
+struct Extent {
+    offset: u64,
+    len: u32,
+    raw_len: u32,
+    encoding: Encoding,
+}
+
+/// An object stored as independently compressed fixed-size chunks.
+///
+/// The file starts with a header pointing to the chunk index, which is written behind
+/// the chunks on sync. Chunks are always appended, so nothing the index on disk refers
+/// to is overwritten before a new index replaces it. The space superseded chunks leave
+/// behind is reclaimed on close once it exceeds the live data. All handles of an object
+/// share its index, so chunks appended through one handle are never overwritten by
+/// another.
+pub struct CompressedObject {
+    file: File,
+    state: Arc<Mutex<State>>,
+}
+
+/// The index of an open object with its unsaved changes.
+struct State {
+    file: File,
+    codec: Codec,
+    chunk_size: u64,
+    size: u64,
+    index: Vec<Option<Extent>>,
+    /// End of the used part of the file.
+    end: u64,
+    /// Bytes held by the chunks in `index`.
+    live: u64,
+    /// Modified chunks by number, uncompressed and `chunk_size` long.
+    dirty: BTreeMap<u64, Vec<u8>>,
+    /// Whether the index on disk is out of date.
+    changed: bool,
+}
+
+/// Returns the chunk size set by `compress_chunk`. Extents record lengths in 32 bits,
+/// so chunks must be smaller than 4 GiB.
+fn chunk_size(config: &Config) -> Result<u64> {
+    let chunk_size = config
+        .get_size("compress_chunk")?
+        .unwrap_or(DEFAULT_CHUNK_SIZE);
+    if chunk_size == 0 || chunk_size > u32::MAX as u64 {
+        return Err(BackendError::new(
+            &format!("Invalid chunk size {chunk_size} for backend option 'compress_chunk'"),
+            Action::Init,
+        ));
+    }
+    Ok(chunk_size)
+}
+
+impl State {
+    fn load(file: File, config: &Config) -> Result<Self> {
+        let codec = Codec::from_config(config)?;
+        let len = file.metadata()?.len();
+
+        if len == 0 {
+            let chunk_size = chunk_size(config).map_err(|e| e.set_action(Action::Open))?;
+            return Ok(State {
+                file,
+                codec,
+                chunk_size,
+                size: 0,
+                index: Vec::new(),
+                end: HEADER_LEN,
+                live: 0,
+                dirty: BTreeMap::new(),
+                changed: true,
+            });
+        }
+
+        let mut header = [0u8; HEADER_LEN as usize];
+        file.read_exact_at(&mut header, 0)
+            .map_err(|e| BackendError::map(&e, Action::Open))?;
+        let chunk_size = u64_at(&header, 8);
+        if &header[..4] != MAGIC
+            || u32_at(&header, 4) != VERSION
+            || chunk_size == 0
+            || chunk_size > u32::MAX as u64
+        {
+            return Err(BackendError::new(
+                "File is not a compressed object",
+                Action::Open,
+            ));
+        }
+        let size = u64_at(&header, 16);
+        let index_offset = u64_at(&header, 24);
+        let entries = u64_at(&header, 32) as usize;
+
+        let mut raw_index = vec![0u8; entries * ENTRY_LEN];
+        file.read_exact_at(&mut raw_index, index_offset)
+            .map_err(|e| BackendError::map(&e, Action::Open))?;
+
+        let mut index = Vec::with_capacity(entries);
+        let mut live = 0;
+        for entry in raw_index.chunks(ENTRY_LEN) {
+            let extent = match entry[16] {
+                0 => None,
+                encoding => Some(Extent {
+                    offset: u64_at(entry, 0),
+                    len: u32_at(entry, 8),
+                    raw_len: u32_at(entry, 12),
+                    encoding: Encoding::from_u8(encoding)?,
+                }),
+            };
+            live += extent.map_or(0, |e| e.len as u64);
+            index.push(extent);
+        }
+        debug!("loaded index of {entries} chunks, {live} b for {size} b of data");
+
+        Ok(State {
+            file,
+            codec,
+            chunk_size,
+            size,
+            index,
+            end: index_offset + raw_index.len() as u64,
+            live,
+            dirty: BTreeMap::new(),
+            changed: false,
+        })
+    }
+
+    /// Returns chunk `n` uncompressed and padded to the chunk size.
+    fn chunk(&self, n: u64) -> Result<Vec<u8>> {
+        if let Some(data) = self.dirty.get(&n) {
+            return Ok(data.clone());
+        }
+
+        let extent = match self.index.get(n as usize).copied().flatten() {
+            Some(extent) => extent,
+            None => return Ok(vec![0u8; self.chunk_size as usize]),
+        };
+
+        let mut payload = vec![0u8; extent.len as usize];
+        self.file
+            .read_exact_at(&mut payload, extent.offset)
+            .map_err(|e| BackendError::map(&e, Action::Read))?;
+        let mut data = decompress(extent.encoding, payload, extent.raw_len as usize)?;
+        data.resize(self.chunk_size as usize, 0);
+
+        Ok(data)
+    }
+
+    /// Compresses and writes back chunk `n`. Its index entry is only replaced once the
+    /// chunk was written.
+    fn store(&mut self, n: u64, data: &[u8]) -> Result<()> {
+        let start = n * self.chunk_size;
+        let raw_len = self.size.saturating_sub(start).min(self.chunk_size) as usize;
+        let data = &data[..raw_len];
+
+        let extent = match data.iter().all(|b| *b == 0) {
+            true => {
+                trace!("chunk {n} is empty");
+                None
+            }
+            false => {
+                let (encoding, payload) = self.codec.compress(data)?;
+                let offset = self.end;
+                trace!(
+                    "storing chunk {n} as {encoding:?}: {raw_len} b => {} b at {offset}",
+                    payload.len()
+                );
+                self.file
+                    .write_all_at(&payload, offset)
+                    .map_err(|e| BackendError::map(&e, Action::Write))?;
+                self.end += payload.len() as u64;
+                Some(Extent {
+                    offset,
+                    len: payload.len() as u32,
+                    raw_len: raw_len as u32,
+                    encoding,
+                })
+            }
+        };
+
+        if self.index.len() <= n as usize {
+            self.index.resize(n as usize + 1, None);
+        }
+        let old = std::mem::replace(&mut self.index[n as usize], extent);
+        self.live -= old.map_or(0, |e| e.len as u64);
+        self.live += extent.map_or(0, |e| e.len as u64);
+        self.changed = true;
+
+        Ok(())
+    }
+
+    /// Writes back all dirty chunks. Chunks that could not be written stay dirty.
+    fn flush(&mut self) -> Result<()> {
+        while let Some((n, data)) = self.dirty.pop_first() {
+            if let Err(e) = self.store(n, &data) {
+                self.dirty.insert(n, data);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the index behind all chunks and points the header to it.
+    fn persist(&mut self) -> Result<()> {
+        self.flush()?;
+        if !self.changed {
+            return Ok(());
+        }
+
+        let mut raw_index = Vec::with_capacity(self.index.len() * ENTRY_LEN);
+        for extent in &self.index {
+            let mut entry = [0u8; ENTRY_LEN];
+            if let Some(extent) = extent {
+                entry[0..8].copy_from_slice(&extent.offset.to_le_bytes());
+                entry[8..12].copy_from_slice(&extent.len.to_le_bytes());
+                entry[12..16].copy_from_slice(&extent.raw_len.to_le_bytes());
+                entry[16] = extent.encoding as u8;
+            }
+            raw_index.extend_from_slice(&entry);
+        }
+
+        // the previous index stays intact until the new header is written, which only
+        // happens once the chunks and the new index are on disk
+        let index_offset = self.end;
+        self.file
+            .write_all_at(&raw_index, index_offset)
+            .and_then(|_| self.file.sync_data())
+            .map_err(|e| BackendError::map(&e, Action::Sync))?;
+        self.end += raw_index.len() as u64;
+
+        let mut header = [0u8; HEADER_LEN as usize];
+        header[0..4].copy_from_slice(MAGIC);
+        header[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        header[8..16].copy_from_slice(&self.chunk_size.to_le_bytes());
+        header[16..24].copy_from_slice(&self.size.to_le_bytes());
+        header[24..32].copy_from_slice(&index_offset.to_le_bytes());
+        header[32..40].copy_from_slice(&(self.index.len() as u64).to_le_bytes());
+        self.file
+            .write_all_at(&header, 0)
+            .map_err(|e| BackendError::map(&e, Action::Sync))?;
+
+        self.changed = false;
+        Ok(())
+    }
+
+    fn read(&self, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        let end = self.size.min(offset + buffer.len() as u64);
+        let mut position = offset;
+
+        while position < end {
+            let n = position / self.chunk_size;
+            let at = (position % self.chunk_size) as usize;
+            let len = ((self.chunk_size - at as u64).min(end - position)) as usize;
+
+            let chunk = self.chunk(n)?;
+            let done = (position - offset) as usize;
+            buffer[done..done + len].copy_from_slice(&chunk[at..at + len]);
+            position += len as u64;
+        }
+
+        Ok(end.saturating_sub(offset))
+    }
+
+    fn write(&mut self, buffer: &[u8], offset: u64) -> Result<u64> {
+        let end = offset + buffer.len() as u64;
+        let mut position = offset;
+
+        while position < end {
+            let n = position / self.chunk_size;
+            let at = (position % self.chunk_size) as usize;
+            let len = ((self.chunk_size - at as u64).min(end - position)) as usize;
+
+            let mut chunk = match self.dirty.remove(&n) {
+                Some(chunk) => chunk,
+                None => self.chunk(n)?,
+            };
+            let done = (position - offset) as usize;
+            chunk[at..at + len].copy_from_slice(&buffer[done..done + len]);
+            self.dirty.insert(n, chunk);
+            position += len as u64;
+        }
+        self.size = self.size.max(end);
+        self.changed = true;
+
+        if self.dirty.len() > DIRTY_CHUNKS {
+            self.flush()?;
+        }
+        Ok(buffer.len() as u64)
+    }
+
+    /// Copies all chunks in file order to `position` and on, then writes the index
+    /// behind them and syncs it.
+    fn relocate(&mut self, mut position: u64) -> Result<()> {
+        let mut order: Vec<usize> = (0..self.index.len())
+            .filter(|n| self.index[*n].is_some())
+            .collect();
+        order.sort_by_key(|n| self.index[*n].map(|e| e.offset));
+
+        for n in order {
+            let extent = self.index[n].as_mut().unwrap();
+            let mut payload = vec![0u8; extent.len as usize];
+            self.file.read_exact_at(&mut payload, extent.offset)?;
+            self.file.write_all_at(&payload, position)?;
+            extent.offset = position;
+            position += extent.len as u64;
+        }
+
+        self.end = position;
+        self.changed = true;
+        self.persist()?;
+        self.file
+            .sync_data()
+            .map_err(|e| BackendError::map(&e, Action::Sync))
+    }
+
+    /// Moves all chunks to the front of the file, dropping superseded chunks and indexes.
+    ///
+    /// The chunks are first copied behind everything the index on disk refers to and
+    /// only then to the front, which nothing refers to anymore at that point. This
+    /// needs the front to hold the chunks and their index without reaching the copy.
+    fn compact(&mut self) -> Result<()> {
+        let tail = self.end;
+        if HEADER_LEN + self.live + (self.index.len() * ENTRY_LEN) as u64 > tail {
+            return Ok(());
+        }
+
+        debug!(
+            "compacting {} b of chunks in {} b",
+            self.live,
+            self.end - HEADER_LEN
+        );
+        self.relocate(tail)?;
+        self.relocate(HEADER_LEN)?;
+        self.file.set_len(self.end)?;
+        Ok(())
+    }
+}
+
+impl BackendObject for CompressedObject {
+    fn init(config: &Config) -> Result<()> {
+        chunk_size(config).map(|_| ())
+    }
+
+    fn new(file: File, config: &Config) -> Result<Self> {
+        let state =
+            STATES.get_or_init(&file.metadata()?, || State::load(file.try_clone()?, config))?;
+        Ok(CompressedObject { file, state })
+    }
+
+    fn read(&self, buffer: &mut [u8], offset: u64, _length: u64) -> Result<u64> {
+        lock(&self.state)?.read(buffer, offset)
+    }
+
+    fn write(&mut self, buffer: &[u8], offset: u64, _length: u64) -> Result<u64> {
+        lock(&self.state)?.write(buffer, offset)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        let mut state = lock(&self.state)?;
+        state.persist()?;
+        state
+            .file
+            .sync_data()
+            .map_err(|e| BackendError::map(&e, Action::Sync))
+    }
+
+    fn status(&self) -> Result<(i64, u64)> {
+        let metadata = self.file.metadata()?;
+        Ok((metadata.atime(), lock(&self.state)?.size))
+    }
+
+    fn close(&mut self) -> Result<()> {
+        let mut state = lock(&self.state)?;
+        state.persist()?;
+
+        let garbage = state.end - HEADER_LEN - state.live;
+        if garbage > state.live && garbage > state.chunk_size {
+            state.compact()?;
+        }
+        Ok(())
+    }
+}
+
+fn u32_at(buffer: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(buffer[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(buffer: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(buffer[at..at + 8].try_into().unwrap())
+}
+
+pub struct Adapter {}
+
+impl JuleaAdapter<CompressedObject> for Adapter {}
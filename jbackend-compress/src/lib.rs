@@ -0,0 +1,232 @@
+mod codec;
+mod compress;
+
+use io_backends::generate_backend;
+use io_backends::prelude::*;
+use log::debug;
+use log::info;
+
+generate_backend!(compress);
+
+#[no_mangle]
+pub unsafe extern "C" fn backend_info() -> *mut JBackend {
+    match init_logger() {
+        Ok(_) => info!("logger initialized."),
+        Err(e) => {
+            let _ = println!("Error while initializing logger: {e:?}");
+        }
+    };
+    debug!("backend info called");
+    &mut BACKEND
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{self, File, OpenOptions};
+
+    use io_backends::prelude::*;
+    use io_backends::test_facility::writes;
+    use io_backends::testing::*;
+
+    use crate::compress::CompressedObject;
+    use crate::BACKEND;
+
+    /// Pseudo-random bytes that no codec can compress.
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545f4914f6cdd1du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn open(path: &std::path::Path, config: &Config) -> CompressedObject {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .unwrap();
+        CompressedObject::new(file, config).unwrap()
+    }
+
+    #[test]
+    fn repeated_writes() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory = |namespace| {
+            let data = Backend::<CompressedObject> {
+                object_store: ObjectStore::new(),
+                namespace,
+                config: Config::default(),
+                pending: PendingLinks::new(),
+            };
+            Box::into_raw(Box::new(data)).cast::<gpointer>()
+        };
+
+        writes::test_writes_with_options(&backend, data_factory, "?compress=zstd&compress_level=3")
+    }
+
+    #[test]
+    fn test_compress_random_access() {
+        let temp = setup();
+        let path = temp.join(CREATE_FILE);
+
+        for codec in ["lz4", "zstd"] {
+            let mut config = Config::default();
+            config.set("compress", codec);
+            config.set("compress_chunk", "4K");
+            fs::write(&path, b"").unwrap();
+
+            let mut object = open(&path, &config);
+            let text = b"All work and no play makes Jack a dull boy. ".repeat(1000);
+            object.write(&text, 0, text.len() as u64).unwrap();
+            object.write(b"JULEA", 10_000, 5).unwrap();
+            object.write(b"end", 1 << 20, 3).unwrap();
+            assert_eq!(object.status().unwrap().1, (1 << 20) + 3);
+
+            let mut buffer = vec![0u8; 12];
+            assert_eq!(object.read(&mut buffer, 9_998, 12).unwrap(), 12);
+            assert_eq!(&buffer[2..7], b"JULEA");
+            assert_eq!(object.read(&mut buffer, (1 << 20) - 9, 12).unwrap(), 12);
+            assert_eq!(&buffer, b"\0\0\0\0\0\0\0\0\0end");
+            object.close().unwrap();
+            drop(object);
+
+            // the logical size is preserved, the stored data is much smaller
+            assert!(fs::metadata(&path).unwrap().len() < text.len() as u64 / 4);
+            let object = open(&path, &Config::default());
+            assert_eq!(object.status().unwrap().1, (1 << 20) + 3);
+
+            let mut buffer = vec![0u8; text.len()];
+            assert_eq!(
+                object.read(&mut buffer, 0, text.len() as u64).unwrap(),
+                text.len() as u64
+            );
+            assert_eq!(&buffer[..10_000], &text[..10_000]);
+            assert_eq!(&buffer[10_000..10_005], b"JULEA");
+            assert_eq!(&buffer[10_005..], &text[10_005..]);
+        }
+
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_compress_incompressible() {
+        let temp = setup();
+        let path = temp.join(CREATE_FILE);
+        let mut config = Config::default();
+        config.set("compress", "zstd");
+        config.set("compress_level", "19");
+
+        let mut object = open(&path, &config);
+        let data = noise(256 << 10);
+        object.write(&data, 0, data.len() as u64).unwrap();
+        object.sync().unwrap();
+
+        // raw chunks only add the header and the index
+        let stored = fs::metadata(&path).unwrap().len();
+        assert!(stored >= data.len() as u64 && stored < data.len() as u64 + 4096);
+
+        let mut buffer = vec![0u8; data.len()];
+        assert_eq!(
+            object.read(&mut buffer, 0, data.len() as u64).unwrap(),
+            data.len() as u64
+        );
+        assert_eq!(buffer, data);
+
+        // overwriting with compressible data frees the space on close
+        for _ in 0..4 {
+            object.write(&noise(64 << 10), 0, 64 << 10).unwrap();
+            object.sync().unwrap();
+        }
+        object
+            .write(&vec![7u8; data.len()], 0, data.len() as u64)
+            .unwrap();
+        object.close().unwrap();
+        drop(object);
+        assert!(fs::metadata(&path).unwrap().len() < 4096);
+
+        let object = open(&path, &Config::default());
+        assert_eq!(
+            object.read(&mut buffer, 0, data.len() as u64).unwrap(),
+            data.len() as u64
+        );
+        assert!(buffer.iter().all(|b| *b == 7));
+
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_compress_shared_handles() {
+        let temp = setup();
+        let path = temp.join(CREATE_FILE);
+        let mut config = Config::default();
+        config.set("compress_chunk", "4K");
+
+        // both handles append their chunks to the same index
+        let mut first = open(&path, &config);
+        let mut second = open(&path, &config);
+        let (a, b) = (noise(8 << 10), noise(12 << 10));
+        first.write(&a, 0, a.len() as u64).unwrap();
+        first.sync().unwrap();
+        second.write(&b, 8 << 10, b.len() as u64).unwrap();
+        second.sync().unwrap();
+        first.write(&a, 20 << 10, a.len() as u64).unwrap();
+        first.close().unwrap();
+        second.close().unwrap();
+        drop((first, second));
+
+        let object = open(&path, &config);
+        let mut buffer = vec![0u8; 28 << 10];
+        assert_eq!(object.read(&mut buffer, 0, 28 << 10).unwrap(), 28 << 10);
+        assert_eq!(&buffer[..8 << 10], &a);
+        assert_eq!(&buffer[8 << 10..20 << 10], &b);
+        assert_eq!(&buffer[20 << 10..], &a);
+
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_compress_failed_flush() {
+        let temp = setup();
+        let path = temp.join(CREATE_FILE);
+        let mut config = Config::default();
+        config.set("compress_chunk", "4K");
+
+        let mut object = open(&path, &config);
+        let (a, b) = (noise(8 << 10), noise(8 << 10));
+        object.write(&a, 0, a.len() as u64).unwrap();
+        object.close().unwrap();
+        drop(object);
+
+        // chunks that cannot be written back stay dirty and readable
+        let mut object = CompressedObject::new(File::open(&path).unwrap(), &config).unwrap();
+        object.write(&b, 0, b.len() as u64).unwrap();
+        assert!(object.sync().is_err());
+        assert!(object.sync().is_err());
+        let mut buffer = vec![0u8; 8 << 10];
+        assert_eq!(object.read(&mut buffer, 0, 8 << 10).unwrap(), 8 << 10);
+        assert_eq!(buffer, b);
+        drop(object);
+
+        let object = open(&path, &config);
+        assert_eq!(object.read(&mut buffer, 0, 8 << 10).unwrap(), 8 << 10);
+        assert_eq!(buffer, a);
+
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_compress_chunk_size() {
+        for (size, valid) in [("0", false), ("4K", true), ("4G", false)] {
+            let mut config = Config::default();
+            config.set("compress_chunk", size);
+            assert_eq!(CompressedObject::init(&config).is_ok(), valid, "{size}");
+        }
+    }
+}
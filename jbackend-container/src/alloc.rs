@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+
+/// Free space of a container file. Extents are handed out first fit, and space at the
+/// end of the file is returned to it instead of being kept in the free list.
+#[derive(Debug, Default)]
+pub struct Allocator {
+    /// Free extents by offset, adjacent extents are always merged.
+    free: BTreeMap<u64, u64>,
+    /// End of the last allocated extent.
+    end: u64,
+}
+
+impl Allocator {
+    /// Rebuilds the free space from the extents in use, no extent starts before `start`.
+    pub fn from_used(start: u64, mut used: Vec<(u64, u64)>) -> Self {
+        used.sort_unstable();
+
+        let mut allocator = Allocator {
+            free: BTreeMap::new(),
+            end: start,
+        };
+        for (offset, len) in used.into_iter().filter(|(_, len)| *len > 0) {
+            if offset > allocator.end {
+                allocator.free.insert(allocator.end, offset - allocator.end);
+            }
+            allocator.end = allocator.end.max(offset + len);
+        }
+        allocator
+    }
+
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    pub fn allocate(&mut self, len: u64) -> u64 {
+        let fit = self
+            .free
+            .iter()
+            .find(|(_, free)| **free >= len)
+            .map(|(offset, free)| (*offset, *free));
+
+        match fit {
+            Some((offset, free)) => {
+                self.free.remove(&offset);
+                if free > len {
+                    self.free.insert(offset + len, free - len);
+                }
+                offset
+            }
+            None => {
+                let offset = self.end;
+                self.end += len;
+                offset
+            }
+        }
+    }
+
+    /// Grows the extent at `offset` from `len` to `new_len` bytes if the space behind
+    /// it is free.
+    pub fn extend(&mut self, offset: u64, len: u64, new_len: u64) -> bool {
+        let behind = offset + len;
+        let needed = new_len - len;
+
+        if behind == self.end {
+            self.end += needed;
+            return true;
+        }
+        match self.free.get(&behind).copied() {
+            Some(free) if free >= needed => {
+                self.free.remove(&behind);
+                if free > needed {
+                    self.free.insert(behind + needed, free - needed);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn release(&mut self, mut offset: u64, mut len: u64) {
+        if len == 0 {
+            return;
+        }
+
+        if let Some((before, before_len)) = self.free.range(..offset).next_back() {
+            if before + before_len == offset {
+                offset = *before;
+                len += before_len;
+                self.free.remove(&offset);
+            }
+        }
+        if let Some(after_len) = self.free.remove(&(offset + len)) {
+            len += after_len;
+        }
+
+        if offset + len == self.end {
+            self.end = offset;
+        } else {
+            self.free.insert(offset, len);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::{fs::create_dir_all, path::Path, sync::Arc};
+
+use log::info;
+
+use io_backends::prelude::*;
+
+use crate::store::{not_found, Container, ContainerStore};
+
+/// An open object. The id tells it apart from objects created later under its name.
+pub struct ContainerObject {
+    container: Arc<Container>,
+    name: String,
+    id: u64,
+}
+
+impl NamespaceBackend for ContainerStore {
+    type Object = ContainerObject;
+
+    fn init(path: &str, config: &Config) -> Result<Self> {
+        create_dir_all(path)?;
+        ContainerStore::new(Path::new(path), config)
+    }
+
+    fn fini(&self) -> Result<()> {
+        self.flush()?;
+        let (data, len) = self.usage()?;
+        info!("{data} b of object data in {len} b of containers");
+        Ok(())
+    }
+
+    fn create(&self, namespace: &str, name: &str) -> Result<ContainerObject> {
+        let container = self
+            .container(namespace, true)?
+            .ok_or(BackendError::new_internal("Container was not created"))?;
+        let id = container.create(name)?;
+        Ok(ContainerObject {
+            container,
+            name: String::from(name),
+            id,
+        })
+    }
+
+    fn open(&self, namespace: &str, name: &str) -> Result<ContainerObject> {
+        let container = self
+            .container(namespace, false)?
+            .ok_or_else(|| not_found(Action::Open))?;
+        let id = container.lookup(name)?;
+        Ok(ContainerObject {
+            container,
+            name: String::from(name),
+            id,
+        })
+    }
+
+    /// Frees the space of the object. Other handles of the object fail from now on.
+    fn delete(&self, object: &ContainerObject) -> Result<()> {
+        object.container.remove(&object.name, object.id)
+    }
+
+    fn status(&self, object: &ContainerObject) -> Result<(i64, u64)> {
+        object.container.status(&object.name, object.id)
+    }
+
+    /// Syncs the container of the object, including the index and thereby all objects
+    /// created or deleted in its namespace.
+    fn sync(&self, object: &ContainerObject) -> Result<()> {
+        object.container.flush()
+    }
+
+    fn read(&self, object: &ContainerObject, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        object
+            .container
+            .read(&object.name, object.id, buffer, offset)
+    }
+
+    fn write(&self, object: &ContainerObject, buffer: &[u8], offset: u64) -> Result<u64> {
+        object
+            .container
+            .write(&object.name, object.id, buffer, offset)
+    }
+
+    fn copy(
+        &self,
+        src_namespace: &str,
+        src_name: &str,
+        dst_namespace: &str,
+        dst_name: &str,
+    ) -> Result<()> {
+        ContainerStore::copy(self, src_namespace, src_name, dst_namespace, dst_name).map(|_| ())
+    }
+
+    /// Takes the matching names from the index of the namespace's container.
+    fn names(&self, namespace: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+        match self.container(namespace, false)? {
+            Some(container) => container.names(prefix),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+pub struct Adapter {}
+
+impl NamespaceAdapter<ContainerStore> for Adapter {}
@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+
+use io_backends::prelude::*;
+
+const MAGIC: &[u8; 4] = b"JCNT";
+const VERSION: u32 = 1;
+
+/// magic, version, block size, index offset, index length, index checksum
+pub const HEADER_LEN: usize = 40;
+/// name length, offset, capacity, size, modification time
+const ENTRY_LEN: usize = 36;
+
+/// The extent an object occupies in its container.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Slot {
+    pub offset: u64,
+    /// Bytes reserved for the object, a multiple of the block size.
+    pub capacity: u64,
+    pub size: u64,
+    pub modified: i64,
+}
+
+/// The first bytes of a container file, locating its index.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+    pub block: u64,
+    pub index_offset: u64,
+    pub index_len: u64,
+    pub index_crc: u32,
+}
+
+impl Header {
+    pub fn new(block: u64) -> Self {
+        Header {
+            block,
+            index_offset: 0,
+            index_len: 0,
+            index_crc: crc32c::crc32c(&[]),
+        }
+    }
+
+    pub fn encode(&self) -> [u8; HEADER_LEN] {
+        let mut raw = [0u8; HEADER_LEN];
+        raw[0..4].copy_from_slice(MAGIC);
+        raw[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        raw[8..16].copy_from_slice(&self.block.to_le_bytes());
+        raw[16..24].copy_from_slice(&self.index_offset.to_le_bytes());
+        raw[24..32].copy_from_slice(&self.index_len.to_le_bytes());
+        raw[32..36].copy_from_slice(&self.index_crc.to_le_bytes());
+        raw
+    }
+
+    pub fn decode(raw: &[u8]) -> Option<Self> {
+        if raw.len() < HEADER_LEN || &raw[0..4] != MAGIC || raw[4..8] != VERSION.to_le_bytes() {
+            return None;
+        }
+        let u64_at = |at: usize| u64::from_le_bytes(raw[at..at + 8].try_into().unwrap());
+
+        Some(Header {
+            block: u64_at(8),
+            index_offset: u64_at(16),
+            index_len: u64_at(24),
+            index_crc: u32::from_le_bytes(raw[32..36].try_into().unwrap()),
+        })
+    }
+}
+
+pub fn encode<'a>(objects: impl ExactSizeIterator<Item = (&'a String, &'a Slot)>) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(8 + objects.len() * (ENTRY_LEN + 16));
+    raw.extend_from_slice(&(objects.len() as u64).to_le_bytes());
+    for (name, slot) in objects {
+        raw.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        raw.extend_from_slice(&slot.offset.to_le_bytes());
+        raw.extend_from_slice(&slot.capacity.to_le_bytes());
+        raw.extend_from_slice(&slot.size.to_le_bytes());
+        raw.extend_from_slice(&slot.modified.to_le_bytes());
+        raw.extend_from_slice(name.as_bytes());
+    }
+    raw
+}
+
+pub fn decode(raw: &[u8]) -> Result<BTreeMap<String, Slot>> {
+    let invalid = || BackendError::new("Container index is corrupt", Action::Init);
+    let u64_at = |at: usize| -> Result<u64> {
+        raw.get(at..at + 8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+            .ok_or_else(invalid)
+    };
+
+    let mut objects = BTreeMap::new();
+    if raw.is_empty() {
+        return Ok(objects);
+    }
+
+    let mut at = 8;
+    for _ in 0..u64_at(0)? {
+        let name_len = raw
+            .get(at..at + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .ok_or_else(invalid)? as usize;
+        let slot = Slot {
+            offset: u64_at(at + 4)?,
+            capacity: u64_at(at + 12)?,
+            size: u64_at(at + 20)?,
+            modified: u64_at(at + 28)? as i64,
+        };
+        at += ENTRY_LEN;
+
+        let name = raw
+            .get(at..at + name_len)
+            .and_then(|b| std::str::from_utf8(b).ok())
+            .ok_or_else(invalid)?;
+        objects.insert(String::from(name), slot);
+        at += name_len;
+    }
+    Ok(objects)
+}
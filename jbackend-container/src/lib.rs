@@ -0,0 +1,251 @@
+mod alloc;
+mod container;
+mod index;
+mod store;
+
+use io_backends::generate_backend;
+use io_backends::prelude::*;
+use log::debug;
+use log::error;
+use log::info;
+
+generate_backend!(container);
+
+#[no_mangle]
+pub unsafe extern "C" fn backend_info() -> *mut JBackend {
+    match init_logger() {
+        Ok(_) => info!("logger initialized."),
+        Err(e) => {
+            let _ = println!("Error while initializing logger: {e:?}");
+        }
+    };
+    debug!("backend info called");
+    &mut BACKEND
+}
+
+/// Offline compaction of the container file at `path`, which no backend may be using.
+#[no_mangle]
+pub unsafe extern "C" fn backend_compact(path: *const gchar) -> gboolean {
+    match read_str(path).and_then(|path| store::compact(std::path::Path::new(&path))) {
+        Ok(reclaimed) => {
+            info!("Reclaimed {reclaimed} b");
+            TRUE
+        }
+        Err(e) => {
+            error!("{e}");
+            FALSE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CString;
+    use std::fs;
+    use std::path::Path;
+    use std::ptr;
+
+    use io_backends::prelude::*;
+    use io_backends::test_facility::writes;
+    use io_backends::testing::{self, *};
+
+    type Backend = NamespaceData<ContainerStore>;
+
+    use crate::container::Adapter;
+    use crate::store::{self, ContainerStore, CONTAINER_SUFFIX};
+    use crate::BACKEND;
+
+    fn init(root: &Path) -> Backend {
+        let path = CString::new(format!("{}?container_block=1K", root.to_str().unwrap())).unwrap();
+        unsafe { Adapter::backend_init(path.as_ptr()).unwrap() }
+    }
+
+    fn write(backend: &Backend, handle: &ObjectHandle, data: &[u8], offset: u64) {
+        let mut written = 0;
+        let ret = unsafe {
+            Adapter::j_write(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+                data.as_ptr().cast(),
+                data.len() as u64,
+                offset,
+                &mut written,
+            )
+        };
+        assert_eq!((ret, written), (TRUE, data.len() as u64));
+    }
+
+    fn read(backend: &Backend, handle: &ObjectHandle, buffer: &mut [u8]) -> u64 {
+        let mut read = 0;
+        let ret = unsafe {
+            Adapter::j_read(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+                buffer.as_mut_ptr().cast(),
+                buffer.len() as u64,
+                0,
+                &mut read,
+            )
+        };
+        assert_eq!(ret, TRUE);
+        read
+    }
+
+    fn names(backend: &Backend, namespace: &str, prefix: Option<&str>) -> Vec<String> {
+        let object = unsafe { BACKEND.anon1.object };
+        unsafe { testing::names(&object, backend as *const _ as gpointer, namespace, prefix) }
+    }
+
+    #[test]
+    fn repeated_writes() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory =
+            |_namespace| Box::into_raw(Box::new(ptr::null_mut() as gpointer)).cast::<gpointer>();
+
+        writes::test_writes(&backend, data_factory)
+    }
+
+    #[test]
+    fn test_container_space_reuse() {
+        let temp = setup();
+        let backend = init(&temp);
+        let container = temp.join(format!("ns{CONTAINER_SUFFIX}"));
+
+        unsafe {
+            let a =
+                Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "a\0".as_ptr().cast())
+                    .unwrap();
+            let b =
+                Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "b\0".as_ptr().cast())
+                    .unwrap();
+            write(&backend, &a, &[b'a'; 1024], 0);
+            write(&backend, &b, &[b'b'; 1024], 0);
+            let b_other =
+                Adapter::backend_open(&backend, "ns\0".as_ptr().cast(), "b\0".as_ptr().cast())
+                    .unwrap();
+
+            // 'a' cannot grow in place, it is moved behind 'b'
+            write(&backend, &a, &[b'A'; 10], 1500);
+            let mut buffer = vec![0u8; 4096];
+            assert_eq!(read(&backend, &a, &mut buffer), 1510);
+            assert!(buffer[..1024].iter().all(|c| *c == b'a'));
+            assert!(buffer[1024..1500].iter().all(|c| *c == 0));
+            assert!(buffer[1500..1510].iter().all(|c| *c == b'A'));
+            assert_eq!(read(&backend, &b, &mut buffer), 1024);
+            assert!(buffer[..1024].iter().all(|c| *c == b'b'));
+
+            assert_eq!(
+                Adapter::j_sync(&backend as *const _ as gpointer, &a as *const _ as gpointer),
+                TRUE
+            );
+            let len = fs::metadata(&container).unwrap().len();
+
+            // the space of deleted objects is handed out again after the next sync
+            Adapter::backend_delete(&backend, &b).unwrap();
+            assert_eq!(
+                Adapter::j_sync(&backend as *const _ as gpointer, &a as *const _ as gpointer),
+                TRUE
+            );
+            let c =
+                Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "c\0".as_ptr().cast())
+                    .unwrap();
+            write(&backend, &c, &[b'c'; 1024], 0);
+            assert_eq!(
+                Adapter::j_sync(&backend as *const _ as gpointer, &c as *const _ as gpointer),
+                TRUE
+            );
+            assert!(fs::metadata(&container).unwrap().len() <= len);
+            assert_eq!(read(&backend, &c, &mut buffer), 1024);
+            assert!(buffer[..1024].iter().all(|c| *c == b'c'));
+
+            // a new object under a deleted name is not reachable through old handles
+            let b2 =
+                Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "b\0".as_ptr().cast())
+                    .unwrap();
+            assert_eq!(read(&backend, &b2, &mut buffer), 0);
+            assert!(Adapter::backend_delete(&backend, &b_other).is_err());
+        }
+
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_container_index() {
+        let temp = setup();
+        let backend = init(&temp);
+
+        unsafe {
+            for name in ["b-2\0", "a-1\0", "b-1\0"] {
+                let handle = Adapter::backend_create(
+                    &backend,
+                    "one\0".as_ptr().cast(),
+                    name.as_ptr().cast(),
+                )
+                .unwrap();
+                write(&backend, &handle, &name.as_bytes().repeat(500), 0);
+            }
+            let handle =
+                Adapter::backend_create(&backend, "two\0".as_ptr().cast(), "a-1\0".as_ptr().cast())
+                    .unwrap();
+            write(&backend, &handle, b"Hello, world!", 0);
+            Adapter::backend_delete(&backend, &handle).unwrap();
+
+            assert_eq!(
+                Adapter::j_copy(
+                    &backend as *const _ as gpointer,
+                    "one\0".as_ptr().cast(),
+                    "b-1\0".as_ptr().cast(),
+                    "two\0".as_ptr().cast(),
+                    "c-1\0".as_ptr().cast(),
+                ),
+                TRUE
+            );
+        }
+
+        // the index is persisted when the backend is released
+        unsafe { Adapter::j_fini(Box::into_raw(Box::new(backend)).cast()) };
+        let backend = init(&temp);
+        assert_eq!(names(&backend, "one", None), ["a-1", "b-1", "b-2"]);
+        assert_eq!(names(&backend, "one", Some("b-")), ["b-1", "b-2"]);
+        assert_eq!(names(&backend, "two", None), ["c-1"]);
+        assert!(names(&backend, "three", None).is_empty());
+        assert!(!temp.join(format!("three{CONTAINER_SUFFIX}")).exists());
+
+        let container = temp.join(format!("one{CONTAINER_SUFFIX}"));
+        unsafe {
+            let handle =
+                Adapter::backend_open(&backend, "one\0".as_ptr().cast(), "b-2\0".as_ptr().cast())
+                    .unwrap();
+            Adapter::backend_delete(&backend, &handle).unwrap();
+            let handle =
+                Adapter::backend_open(&backend, "one\0".as_ptr().cast(), "a-1\0".as_ptr().cast())
+                    .unwrap();
+            write(&backend, &handle, b"grown", 5000);
+        }
+        unsafe { Adapter::j_fini(Box::into_raw(Box::new(backend)).cast()) };
+
+        // compaction drops the space left by 'b-2' and the relocated 'a-1'
+        let before = fs::metadata(&container).unwrap().len();
+        assert!(store::compact(&container).unwrap() > 0);
+        assert!(fs::metadata(&container).unwrap().len() < before);
+
+        let backend = init(&temp);
+        assert_eq!(names(&backend, "one", None), ["a-1", "b-1"]);
+        unsafe {
+            let mut buffer = vec![0u8; 8192];
+            let handle =
+                Adapter::backend_open(&backend, "one\0".as_ptr().cast(), "a-1\0".as_ptr().cast())
+                    .unwrap();
+            assert_eq!(read(&backend, &handle, &mut buffer), 5005);
+            assert_eq!(&buffer[..4], b"a-1\0");
+            assert_eq!(&buffer[5000..5005], b"grown");
+            let handle =
+                Adapter::backend_open(&backend, "two\0".as_ptr().cast(), "c-1\0".as_ptr().cast())
+                    .unwrap();
+            assert_eq!(read(&backend, &handle, &mut buffer), 2000);
+            assert_eq!(&buffer[..4], b"b-1\0");
+        }
+
+        shutdown(temp);
+    }
+}
@@ -0,0 +1,559 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind},
+    iter,
+    ops::Bound,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, info, trace};
+use rustc_hash::FxHashMap;
+
+use io_backends::prelude::*;
+
+use crate::alloc::Allocator;
+use crate::index::{self, Header, Slot, HEADER_LEN};
+
+/// File name suffix of the container holding the objects of a namespace.
+pub const CONTAINER_SUFFIX: &str = ".jcont";
+
+const DEFAULT_BLOCK_SIZE: u64 = 4096;
+/// Size of the pieces data is moved in when objects are relocated, copied or compacted.
+const COPY_CHUNK_SIZE: u64 = 1 << 20;
+
+struct Object {
+    slot: Slot,
+    /// Tells objects apart that were created under the same name.
+    id: u64,
+}
+
+struct State {
+    objects: BTreeMap<String, Object>,
+    alloc: Allocator,
+    /// The header of the index on disk.
+    header: Header,
+    /// Extents that the index on disk may still reference. They are only reused once
+    /// the index was rewritten, so a crash never exposes data of another object.
+    released: Vec<(u64, u64)>,
+    dirty: bool,
+    next_id: u64,
+}
+
+impl State {
+    fn object(&self, name: &str, id: u64, action: Action) -> Result<&Object> {
+        self.objects
+            .get(name)
+            .filter(|object| object.id == id)
+            .ok_or_else(|| not_found(action))
+    }
+}
+
+/// A single file holding all objects of a namespace, `<root>/<namespace>.jcont`.
+///
+/// The first block holds a header that locates the index, which maps object names to
+/// the extent each object occupies. An object's extent grows in place while the space
+/// behind it is free, otherwise the object is relocated into an extent of twice the
+/// size. The index is rewritten into free space on sync, and the header is only
+/// updated once the new index is on disk, so a crash loses nothing that was synced.
+pub struct Container {
+    file: File,
+    block: u64,
+    state: RwLock<State>,
+}
+
+impl Container {
+    /// Opens the container at `path`, or creates it with blocks of `block` bytes if
+    /// `create` is set. Returns `None` if it does not exist and is not created.
+    pub fn open(path: &Path, block: u64, create: bool) -> Result<Option<Self>> {
+        if create {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+        }
+        let file = match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(create)
+            .truncate(false)
+            .open(path)
+        {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let header = match file.metadata()?.len() {
+            0 => {
+                let header = Header::new(block);
+                file.write_all_at(&header.encode(), 0)?;
+                header
+            }
+            _ => {
+                let mut raw = [0u8; HEADER_LEN];
+                file.read_exact_at(&mut raw, 0)?;
+                Header::decode(&raw).ok_or(BackendError::new(
+                    &format!("{path:?} is not a container"),
+                    Action::Init,
+                ))?
+            }
+        };
+
+        let mut raw = vec![0u8; header.index_len as usize];
+        file.read_exact_at(&mut raw, header.index_offset)?;
+        if crc32c::crc32c(&raw) != header.index_crc {
+            return Err(BackendError::new(
+                &format!("Index of {path:?} is corrupt"),
+                Action::Init,
+            ));
+        }
+        let objects: BTreeMap<String, Object> = index::decode(&raw)?
+            .into_iter()
+            .enumerate()
+            .map(|(id, (name, slot))| {
+                (
+                    name,
+                    Object {
+                        slot,
+                        id: id as u64,
+                    },
+                )
+            })
+            .collect();
+
+        let used = objects
+            .values()
+            .map(|object| (object.slot.offset, object.slot.capacity))
+            .chain(iter::once((
+                header.index_offset,
+                round_up(header.index_len, header.block),
+            )))
+            .collect();
+        debug!("opened {path:?} with {} objects", objects.len());
+
+        Ok(Some(Container {
+            file,
+            block: header.block,
+            state: RwLock::new(State {
+                next_id: objects.len() as u64,
+                objects,
+                alloc: Allocator::from_used(header.block, used),
+                header,
+                released: Vec::new(),
+                dirty: false,
+            }),
+        }))
+    }
+
+    fn read_state(&self) -> Result<RwLockReadGuard<'_, State>> {
+        self.state
+            .read()
+            .map_err(|e| BackendError::map(&e, Action::Internal))
+    }
+
+    fn write_state(&self) -> Result<RwLockWriteGuard<'_, State>> {
+        self.state
+            .write()
+            .map_err(|e| BackendError::map(&e, Action::Internal))
+    }
+
+    /// Adds an empty object and returns its id. No space is allocated until it is written.
+    pub fn create(&self, name: &str) -> Result<u64> {
+        let mut state = self.write_state()?;
+        if state.objects.contains_key(name) {
+            return Err(BackendError::map(
+                &io::Error::from(ErrorKind::AlreadyExists),
+                Action::Create,
+            ));
+        }
+
+        let id = state.next_id;
+        state.next_id += 1;
+        state.objects.insert(
+            String::from(name),
+            Object {
+                slot: Slot {
+                    offset: 0,
+                    capacity: 0,
+                    size: 0,
+                    modified: now(),
+                },
+                id,
+            },
+        );
+        state.dirty = true;
+        Ok(id)
+    }
+
+    /// Returns the id of the object called `name`.
+    pub fn lookup(&self, name: &str) -> Result<u64> {
+        self.read_state()?
+            .objects
+            .get(name)
+            .map(|object| object.id)
+            .ok_or_else(|| not_found(Action::Open))
+    }
+
+    pub fn remove(&self, name: &str, id: u64) -> Result<()> {
+        let mut state = self.write_state()?;
+        let slot = state.object(name, id, Action::Delete)?.slot;
+
+        state.objects.remove(name);
+        state.released.push((slot.offset, slot.capacity));
+        state.dirty = true;
+        Ok(())
+    }
+
+    pub fn status(&self, name: &str, id: u64) -> Result<(i64, u64)> {
+        let state = self.read_state()?;
+        let slot = state.object(name, id, Action::Status)?.slot;
+        Ok((slot.modified, slot.size))
+    }
+
+    pub fn read(&self, name: &str, id: u64, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        let state = self.read_state()?;
+        let slot = state.object(name, id, Action::Read)?.slot;
+
+        let length = match slot.size.checked_sub(offset) {
+            Some(available) => available.min(buffer.len() as u64),
+            None => return Ok(0),
+        };
+        self.file
+            .read_exact_at(&mut buffer[..length as usize], slot.offset + offset)?;
+        Ok(length)
+    }
+
+    pub fn write(&self, name: &str, id: u64, buffer: &[u8], offset: u64) -> Result<u64> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        let mut state = self.write_state()?;
+        let State {
+            objects,
+            alloc,
+            released,
+            dirty,
+            ..
+        } = &mut *state;
+        let slot = &mut objects
+            .get_mut(name)
+            .filter(|object| object.id == id)
+            .ok_or_else(|| not_found(Action::Write))?
+            .slot;
+
+        let end = offset + buffer.len() as u64;
+        if end > slot.capacity {
+            self.grow(alloc, released, slot, end)?;
+        }
+        // the extent may hold data of removed objects
+        if offset > slot.size {
+            zero(&self.file, slot.offset + slot.size, offset - slot.size)?;
+        }
+        self.file.write_all_at(buffer, slot.offset + offset)?;
+
+        slot.size = slot.size.max(end);
+        slot.modified = now();
+        *dirty = true;
+        Ok(buffer.len() as u64)
+    }
+
+    /// Makes room for at least `end` bytes in the extent of `slot`.
+    fn grow(
+        &self,
+        alloc: &mut Allocator,
+        released: &mut Vec<(u64, u64)>,
+        slot: &mut Slot,
+        end: u64,
+    ) -> Result<()> {
+        let capacity = round_up(end.max(slot.capacity * 2), self.block);
+
+        if slot.capacity > 0 && alloc.extend(slot.offset, slot.capacity, capacity) {
+            slot.capacity = capacity;
+            return Ok(());
+        }
+
+        let offset = alloc.allocate(capacity);
+        copy_data(&self.file, slot.offset, &self.file, offset, slot.size)?;
+        released.push((slot.offset, slot.capacity));
+        trace!(
+            "relocated {} b from {} to {offset}, capacity {capacity} b",
+            slot.size,
+            slot.offset
+        );
+
+        slot.offset = offset;
+        slot.capacity = capacity;
+        Ok(())
+    }
+
+    /// Names in lexicographic order, optionally only those starting with `prefix`.
+    pub fn names(&self, prefix: Option<&str>) -> Result<Vec<String>> {
+        let prefix = prefix.unwrap_or_default();
+        Ok(self
+            .read_state()?
+            .objects
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .map(|(name, _)| name)
+            .take_while(|name| name.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+
+    /// Syncs the object data and writes the index if anything changed since the last flush.
+    pub fn flush(&self) -> Result<()> {
+        let mut state = self.write_state()?;
+        if !state.dirty {
+            return self.file.sync_data().map_err(|e| e.into());
+        }
+        self.file.sync_data()?;
+
+        let raw = index::encode(
+            state
+                .objects
+                .iter()
+                .map(|(name, object)| (name, &object.slot)),
+        );
+        let offset = state.alloc.allocate(round_up(raw.len() as u64, self.block));
+        self.file.write_all_at(&raw, offset)?;
+        self.file.sync_data()?;
+
+        let header = Header {
+            block: self.block,
+            index_offset: offset,
+            index_len: raw.len() as u64,
+            index_crc: crc32c::crc32c(&raw),
+        };
+        self.file.write_all_at(&header.encode(), 0)?;
+        self.file.sync_data()?;
+
+        // neither the previous index nor the released extents are referenced anymore
+        let previous = (
+            state.header.index_offset,
+            round_up(state.header.index_len, self.block),
+        );
+        let released = std::mem::take(&mut state.released);
+        for (offset, len) in released.into_iter().chain(iter::once(previous)) {
+            state.alloc.release(offset, len);
+        }
+        state.header = header;
+        state.dirty = false;
+
+        // free space at the end of the container is given back to the file system
+        self.file.set_len(state.alloc.end().max(self.block))?;
+        trace!("wrote index of {} objects to {offset}", state.objects.len());
+        Ok(())
+    }
+
+    /// Returns the bytes of object data and the length of the container.
+    pub fn usage(&self) -> Result<(u64, u64)> {
+        let state = self.read_state()?;
+        let data = state.objects.values().map(|object| object.slot.size).sum();
+        Ok((data, state.alloc.end().max(self.block)))
+    }
+}
+
+/// The containers of all namespaces of a backend, which are opened on first use.
+pub struct ContainerStore {
+    root: PathBuf,
+    block: u64,
+    containers: Mutex<FxHashMap<String, Arc<Container>>>,
+}
+
+impl ContainerStore {
+    pub fn new(root: &Path, config: &Config) -> Result<Self> {
+        let block = config
+            .get_size("container_block")?
+            .unwrap_or(DEFAULT_BLOCK_SIZE);
+        if block < HEADER_LEN as u64 {
+            return Err(BackendError::new(
+                &format!("container_block must be at least {HEADER_LEN} b"),
+                Action::Init,
+            ));
+        }
+
+        Ok(ContainerStore {
+            root: root.to_path_buf(),
+            block,
+            containers: Mutex::new(FxHashMap::default()),
+        })
+    }
+
+    /// Returns the container of `namespace`. It is created if `create` is set, otherwise
+    /// `None` is returned for namespaces without objects.
+    pub fn container(&self, namespace: &str, create: bool) -> Result<Option<Arc<Container>>> {
+        let mut containers = self
+            .containers
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+        if let Some(container) = containers.get(namespace) {
+            return Ok(Some(container.clone()));
+        }
+
+        let path = self.root.join(format!("{namespace}{CONTAINER_SUFFIX}"));
+        let container = match Container::open(&path, self.block, create)? {
+            Some(container) => Arc::new(container),
+            None => return Ok(None),
+        };
+        containers.insert(String::from(namespace), container.clone());
+        Ok(Some(container))
+    }
+
+    /// Copies an object into a new object, which may be in another namespace.
+    pub fn copy(
+        &self,
+        src_namespace: &str,
+        src_name: &str,
+        dst_namespace: &str,
+        dst_name: &str,
+    ) -> Result<u64> {
+        let src = self
+            .container(src_namespace, false)?
+            .ok_or_else(|| not_found(Action::Copy))?;
+        let src_id = src.lookup(src_name)?;
+        let dst = self
+            .container(dst_namespace, true)?
+            .ok_or_else(|| not_found(Action::Copy))?;
+        let dst_id = dst.create(dst_name)?;
+
+        let (_, size) = src.status(src_name, src_id)?;
+        let mut buffer = vec![0u8; COPY_CHUNK_SIZE.min(size) as usize];
+        let mut copied = 0;
+        while copied < size {
+            let n = src.read(src_name, src_id, &mut buffer, copied)?;
+            if n == 0 {
+                break;
+            }
+            dst.write(dst_name, dst_id, &buffer[..n as usize], copied)?;
+            copied += n;
+        }
+        Ok(copied)
+    }
+
+    /// Flushes all open containers.
+    pub fn flush(&self) -> Result<()> {
+        let containers: Vec<Arc<Container>> = self
+            .containers
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?
+            .values()
+            .cloned()
+            .collect();
+        containers
+            .iter()
+            .try_for_each(|container| container.flush())
+    }
+
+    /// Returns the bytes of object data and the length of all open containers.
+    pub fn usage(&self) -> Result<(u64, u64)> {
+        self.containers
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?
+            .values()
+            .try_fold((0, 0), |(data, len), container| {
+                let (d, l) = container.usage()?;
+                Ok((data + d, len + l))
+            })
+    }
+}
+
+/// Rewrites the container at `path` with its objects packed in name order, dropping
+/// all free space. Must only be run while no backend uses the container. Returns the
+/// number of bytes reclaimed.
+pub fn compact(path: &Path) -> Result<u64> {
+    let container = Container::open(path, DEFAULT_BLOCK_SIZE, false)?.ok_or_else(|| {
+        BackendError::map(&io::Error::from(ErrorKind::NotFound), Action::Internal)
+    })?;
+    let state = container.read_state()?;
+    let block = container.block;
+
+    let name = path.file_name().ok_or(BackendError::new_internal(
+        "Container path has no file name",
+    ))?;
+    let temp = path.with_file_name(format!("{TEMP_PREFIX}{}", name.to_string_lossy()));
+    let file = File::create(&temp)?;
+
+    let mut objects = BTreeMap::new();
+    let mut end = block;
+    for (name, object) in &state.objects {
+        let slot = Slot {
+            offset: end,
+            capacity: round_up(object.slot.size, block),
+            ..object.slot
+        };
+        copy_data(
+            &container.file,
+            object.slot.offset,
+            &file,
+            slot.offset,
+            slot.size,
+        )?;
+        end += slot.capacity;
+        objects.insert(name.clone(), slot);
+    }
+
+    let raw = index::encode(objects.iter());
+    file.write_all_at(&raw, end)?;
+    let header = Header {
+        block,
+        index_offset: end,
+        index_len: raw.len() as u64,
+        index_crc: crc32c::crc32c(&raw),
+    };
+    file.write_all_at(&header.encode(), 0)?;
+    file.sync_data()?;
+
+    let before = container.file.metadata()?.len();
+    let after = file.metadata()?.len();
+    fs::rename(&temp, path)?;
+    if let Some(dir) = path.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+
+    info!(
+        "compacted {path:?} with {} objects from {before} b to {after} b",
+        objects.len()
+    );
+    Ok(before.saturating_sub(after))
+}
+
+/// Copies `len` bytes from `src_offset` in `src` to `dst_offset` in `dst`.
+fn copy_data(src: &File, src_offset: u64, dst: &File, dst_offset: u64, len: u64) -> Result<()> {
+    let mut buffer = vec![0u8; COPY_CHUNK_SIZE.min(len) as usize];
+    let mut done = 0;
+    while done < len {
+        let n = COPY_CHUNK_SIZE.min(len - done) as usize;
+        src.read_exact_at(&mut buffer[..n], src_offset + done)?;
+        dst.write_all_at(&buffer[..n], dst_offset + done)?;
+        done += n as u64;
+    }
+    Ok(())
+}
+
+fn zero(file: &File, offset: u64, len: u64) -> Result<()> {
+    let buffer = vec![0u8; COPY_CHUNK_SIZE.min(len) as usize];
+    let mut done = 0;
+    while done < len {
+        let n = COPY_CHUNK_SIZE.min(len - done) as usize;
+        file.write_all_at(&buffer[..n], offset + done)?;
+        done += n as u64;
+    }
+    Ok(())
+}
+
+fn round_up(n: u64, block: u64) -> u64 {
+    n.div_ceil(block) * block
+}
+
+pub fn not_found(action: Action) -> BackendError {
+    BackendError::map(&io::Error::from(ErrorKind::NotFound), action)
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
@@ -0,0 +1,381 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, create_dir_all, File, OpenOptions},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use log::{error, info, trace, warn};
+
+use io_backends::prelude::*;
+
+use crate::recipe::Recipe;
+use crate::store::{self, ChunkStore, Hash, CHUNK_DIR, STORES};
+
+const DEFAULT_CHUNK_SIZE: u64 = 64 << 10;
+/// Number of modified chunks kept in memory before they are stored.
+const DIRTY_CHUNKS: usize = 16;
+
+type Shared = Arc<Mutex<State>>;
+
+/// States of all open objects, so that every handle of an object sees the same recipe.
+static STATES: SharedStates<State> = SharedStates::new();
+
+/// Returns the state of the object in `file`, reading its recipe unless another handle
+/// has it open already.
+fn shared(file: &File, store: &Arc<ChunkStore>, chunk_size: u64) -> Result<Shared> {
+    STATES.get_or_init(&file.metadata()?, || {
+        let (recipe, changed) = match Recipe::read(file)? {
+            Some(recipe) => (recipe, false),
+            None => (Recipe::new(chunk_size), true),
+        };
+        Ok(State {
+            file: file.try_clone()?,
+            store: store.clone(),
+            recipe,
+            dirty: BTreeMap::new(),
+            added: Vec::new(),
+            released: Vec::new(),
+            changed,
+            deleted: false,
+        })
+    })
+}
+
+/// An object stored as a recipe of fixed-size chunks, each of which is kept once in the
+/// chunk store no matter how many objects contain it. All handles of an object share
+/// its state, so chunk references are only ever taken and dropped once.
+pub struct DedupObject {
+    file: File,
+    state: Shared,
+}
+
+/// The recipe of an open object with its unsaved changes.
+///
+/// Chunks replaced by a write are only released once the recipe without them is on
+/// disk, and chunks stored for a recipe that never made it to disk are released when
+/// the last handle is dropped, so a crash at any point leaves every recipe readable.
+struct State {
+    file: File,
+    store: Arc<ChunkStore>,
+    recipe: Recipe,
+    /// Modified chunks by number, `chunk_size` long.
+    dirty: BTreeMap<u64, Vec<u8>>,
+    /// References taken for chunks that are not in the recipe on disk yet.
+    added: Vec<Hash>,
+    /// References held by the recipe on disk that the current recipe dropped.
+    released: Vec<Hash>,
+    /// Whether the recipe on disk is out of date.
+    changed: bool,
+    /// Whether the object was deleted, its references are gone with it.
+    deleted: bool,
+}
+
+impl State {
+    fn chunk(&self, n: u64) -> Result<Vec<u8>> {
+        if let Some(data) = self.dirty.get(&n) {
+            return Ok(data.clone());
+        }
+
+        let mut data = match self.recipe.chunks.get(n as usize).copied().flatten() {
+            Some(hash) => self.store.get(&hash)?,
+            None => Vec::new(),
+        };
+        data.resize(self.recipe.chunk_size as usize, 0);
+        Ok(data)
+    }
+
+    /// Stores all dirty chunks. Chunks that could not be stored stay dirty.
+    fn flush(&mut self) -> Result<()> {
+        while let Some((n, data)) = self.dirty.pop_first() {
+            if let Err(e) = self.store_chunk(n, &data) {
+                self.dirty.insert(n, data);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn store_chunk(&mut self, n: u64, data: &[u8]) -> Result<()> {
+        let start = n * self.recipe.chunk_size;
+        let len = self
+            .recipe
+            .size
+            .saturating_sub(start)
+            .min(self.recipe.chunk_size);
+        let data = &data[..len as usize];
+
+        let hash = match data.iter().all(|b| *b == 0) {
+            true => None,
+            false => {
+                let hash = self.store.put(data)?;
+                self.added.push(hash);
+                Some(hash)
+            }
+        };
+        trace!("chunk {n} => {:?}", hash.as_ref().map(store::hex));
+
+        if self.recipe.chunks.len() <= n as usize {
+            self.recipe.chunks.resize(n as usize + 1, None);
+        }
+        let old = std::mem::replace(&mut self.recipe.chunks[n as usize], hash);
+        self.released.extend(old);
+        Ok(())
+    }
+
+    /// Writes the recipe, syncs it and releases the chunks it no longer refers to.
+    fn persist(&mut self) -> Result<()> {
+        if self.deleted {
+            return Ok(());
+        }
+        self.flush()?;
+        if !self.changed {
+            return Ok(());
+        }
+
+        self.recipe
+            .write(&self.file)
+            .map_err(|e| e.set_action(Action::Sync))?;
+        self.added.clear();
+        for hash in std::mem::take(&mut self.released) {
+            self.store.release(&hash)?;
+        }
+
+        self.changed = false;
+        Ok(())
+    }
+
+    /// Drops the references of the recipe on disk and all unsaved changes.
+    fn delete(&mut self) -> Result<()> {
+        if std::mem::replace(&mut self.deleted, true) {
+            return Ok(());
+        }
+        self.dirty.clear();
+        self.released.clear();
+        if let Some(recipe) = Recipe::read(&self.file)? {
+            for hash in recipe.hashes() {
+                self.store.release(hash)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&self, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        let chunk_size = self.recipe.chunk_size;
+        let end = self.recipe.size.min(offset + buffer.len() as u64);
+        let mut position = offset;
+
+        while position < end {
+            let n = position / chunk_size;
+            let at = (position % chunk_size) as usize;
+            let len = ((chunk_size - at as u64).min(end - position)) as usize;
+
+            let chunk = self.chunk(n)?;
+            let done = (position - offset) as usize;
+            buffer[done..done + len].copy_from_slice(&chunk[at..at + len]);
+            position += len as u64;
+        }
+
+        Ok(end.saturating_sub(offset))
+    }
+
+    fn write(&mut self, buffer: &[u8], offset: u64) -> Result<u64> {
+        if self.deleted {
+            return Err(BackendError::map(
+                &std::io::Error::from(std::io::ErrorKind::NotFound),
+                Action::Write,
+            ));
+        }
+        let chunk_size = self.recipe.chunk_size;
+        let end = offset + buffer.len() as u64;
+        let mut position = offset;
+
+        while position < end {
+            let n = position / chunk_size;
+            let at = (position % chunk_size) as usize;
+            let len = ((chunk_size - at as u64).min(end - position)) as usize;
+
+            let mut chunk = match self.dirty.remove(&n) {
+                Some(chunk) => chunk,
+                None => self.chunk(n)?,
+            };
+            let done = (position - offset) as usize;
+            chunk[at..at + len].copy_from_slice(&buffer[done..done + len]);
+            self.dirty.insert(n, chunk);
+            position += len as u64;
+        }
+
+        // the former last chunk grows, so it has to be stored again
+        let last = self.recipe.size.div_ceil(chunk_size);
+        if end > self.recipe.size && last > 0 && !self.dirty.contains_key(&(last - 1)) {
+            let chunk = self.chunk(last - 1)?;
+            self.dirty.insert(last - 1, chunk);
+        }
+        self.recipe.size = self.recipe.size.max(end);
+        self.changed = true;
+
+        if self.dirty.len() > DIRTY_CHUNKS {
+            self.flush()?;
+        }
+        Ok(buffer.len() as u64)
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        for hash in std::mem::take(&mut self.added) {
+            if let Err(e) = self.store.release(&hash) {
+                error!("{e}");
+            }
+        }
+    }
+}
+
+impl DedupObject {
+    fn state(&self) -> Result<MutexGuard<'_, State>> {
+        self.state
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))
+    }
+}
+
+impl BackendObject for DedupObject {
+    fn new(file: File, config: &Config) -> Result<Self> {
+        let store = STORES.lookup(config)?;
+        let chunk_size = config
+            .get_size("dedup_chunk")?
+            .unwrap_or(DEFAULT_CHUNK_SIZE);
+        let state = shared(&file, &store, chunk_size)?;
+        Ok(DedupObject { file, state })
+    }
+
+    fn read(&self, buffer: &mut [u8], offset: u64, _length: u64) -> Result<u64> {
+        self.state()?.read(buffer, offset)
+    }
+
+    fn write(&mut self, buffer: &[u8], offset: u64, _length: u64) -> Result<u64> {
+        self.state()?.write(buffer, offset)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.state()?.persist()
+    }
+
+    fn status(&self) -> Result<(i64, u64)> {
+        let metadata = self.file.metadata()?;
+        Ok((metadata.atime(), self.state()?.recipe.size))
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.state()?.persist()
+    }
+}
+
+pub struct Adapter {}
+
+impl JuleaAdapter<DedupObject> for Adapter {
+    unsafe extern "C" fn j_fini(backend_data: gpointer) {
+        let backend_data = Box::from_raw(backend_data.cast::<Backend<DedupObject>>());
+        match STORES.unregister(&backend_data.config) {
+            Ok(Some(store)) => match (store.usage(), store.dedup_ratio()) {
+                (Ok((logical, stored)), Ok(ratio)) => info!(
+                    "Releasing backend, {logical} b of object data in {stored} b of chunks, dedup ratio {ratio:.2}"
+                ),
+                (Err(e), _) | (_, Err(e)) => error!("{e}"),
+            },
+            Err(e) => error!("{e}"),
+            Ok(None) => info!("Releasing backend"),
+        }
+    }
+
+    unsafe fn backend_init(path: *const gchar) -> Result<Backend<DedupObject>> {
+        let path = read_str(path).map_err(|e| e.set_action(Action::Init))?;
+        let (path, mut config) = Config::from_init_path(&path)?;
+        info!("Initializing deduplicating backend in namespace {path}");
+
+        if config.get_size("dedup_chunk")? == Some(0) {
+            return Err(BackendError::new(
+                "Backend option 'dedup_chunk' may not be 0",
+                Action::Init,
+            ));
+        }
+        create_dir_all(&path)?;
+        if config.get_bool("atomic_create")? {
+            sweep_hidden(Path::new(&path))?;
+        }
+
+        // backends in the same root share its chunks
+        let dir = fs::canonicalize(&path)?.join(CHUNK_DIR);
+        let dir = dir.to_str().ok_or(BackendError::new(
+            "Unable to convert chunk path to UTF-8",
+            Action::Init,
+        ))?;
+        STORES.register(&mut config, dir, || {
+            let store =
+                ChunkStore::open(Path::new(&path)).map_err(|e| e.set_action(Action::Init))?;
+            Ok(Arc::new(store))
+        })?;
+
+        Ok(Backend::new(path, config))
+    }
+
+    fn remove_object(backend_data: &Backend<DedupObject>, path: &Path) -> Result<()> {
+        let store = STORES.lookup(&backend_data.config)?;
+        let state = shared(&File::open(path)?, &store, DEFAULT_CHUNK_SIZE)?;
+        state
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?
+            .delete()?;
+        fs::remove_file(path).map_err(|e| BackendError::map(&e, Action::Delete))
+    }
+
+    /// Copies the recipe only, the copy shares all chunks with the original.
+    unsafe fn backend_copy(
+        backend_data: &Backend<DedupObject>,
+        src_namespace: *const gchar,
+        src_path: *const gchar,
+        dst_namespace: *const gchar,
+        dst_path: *const gchar,
+    ) -> Result<u64> {
+        let src = Self::build_path(backend_data, Vec::from([src_namespace, src_path]))?;
+        let dst: PathBuf = Self::build_path(backend_data, Vec::from([dst_namespace, dst_path]))?;
+
+        if let Some(dir) = dst.parent() {
+            create_dir_all(dir)?;
+        }
+
+        // the source is saved first and stays locked until its chunks are acquired
+        let store = STORES.lookup(&backend_data.config)?;
+        let state = shared(&File::open(&src)?, &store, DEFAULT_CHUNK_SIZE)?;
+        let mut src = state
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+        src.persist()?;
+        let recipe = src.recipe.clone();
+        let dst_file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&dst)
+            .map_err(|e| BackendError::map(&e, Action::Copy))?;
+
+        store.acquire(recipe.hashes())?;
+        drop(src);
+        if let Err(e) = recipe.write(&dst_file) {
+            warn!("Copy to {dst:?} failed, releasing its chunks");
+            for hash in recipe.hashes() {
+                store.release(hash)?;
+            }
+            let _ = fs::remove_file(&dst);
+            return Err(e);
+        }
+
+        Ok(recipe.size)
+    }
+
+    fn is_internal(file_name: &str) -> bool {
+        file_name == CHUNK_DIR
+            || file_name.starts_with(TEMP_PREFIX)
+            || file_name.starts_with(CHECKSUM_PREFIX)
+    }
+}
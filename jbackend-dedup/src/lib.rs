@@ -0,0 +1,331 @@
+mod dedup;
+mod recipe;
+mod store;
+
+use io_backends::generate_backend;
+use io_backends::prelude::*;
+use log::debug;
+use log::info;
+
+generate_backend!(dedup);
+
+#[no_mangle]
+pub unsafe extern "C" fn backend_info() -> *mut JBackend {
+    match init_logger() {
+        Ok(_) => info!("logger initialized."),
+        Err(e) => {
+            let _ = println!("Error while initializing logger: {e:?}");
+        }
+    };
+    debug!("backend info called");
+    &mut BACKEND
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CString;
+    use std::fs::{self, OpenOptions};
+    use std::os::unix::fs::FileExt;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use io_backends::prelude::*;
+    use io_backends::test_facility::writes;
+    use io_backends::testing::*;
+    use sha2::{Digest, Sha256};
+
+    use crate::dedup::{Adapter, DedupObject};
+    use crate::recipe::Recipe;
+    use crate::store::{hex, CHUNK_DIR, STORES};
+    use crate::BACKEND;
+
+    fn chunk_files(root: &Path) -> usize {
+        fs::read_dir(root.join(CHUNK_DIR))
+            .unwrap()
+            .map(|shard| fs::read_dir(shard.unwrap().path()).unwrap().count())
+            .sum()
+    }
+
+    fn init(root: &Path) -> Backend<DedupObject> {
+        let path = CString::new(format!("{}?dedup_chunk=16K", root.to_str().unwrap())).unwrap();
+        unsafe { Adapter::backend_init(path.as_ptr()).unwrap() }
+    }
+
+    #[test]
+    fn test_dedup_shared_handles() {
+        let temp = setup();
+        let backend = init(&temp);
+        let backend_data = &backend as *const _ as gpointer;
+        let data = vec![1u8; 32 << 10];
+
+        unsafe {
+            let a =
+                Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "a\0".as_ptr().cast())
+                    .unwrap();
+            backend.write(&a, &data, 0, data.len() as u64).unwrap();
+            backend.sync(&a).unwrap();
+            assert_eq!(chunk_files(&temp), 1);
+
+            // both handles replace the same chunk, it is released only once
+            let b = Adapter::backend_open(&backend, "ns\0".as_ptr().cast(), "a\0".as_ptr().cast())
+                .unwrap();
+            backend.write(&a, &[2u8; 16 << 10], 0, 16 << 10).unwrap();
+            backend
+                .write(&b, &[3u8; 16 << 10], 16 << 10, 16 << 10)
+                .unwrap();
+            for handle in [&a, &b] {
+                assert_eq!(
+                    Adapter::j_close(backend_data, handle as *const _ as gpointer),
+                    TRUE
+                );
+            }
+            assert_eq!(chunk_files(&temp), 2);
+
+            let c = Adapter::backend_open(&backend, "ns\0".as_ptr().cast(), "a\0".as_ptr().cast())
+                .unwrap();
+            let mut buffer = vec![0u8; data.len()];
+            backend.read(&c, &mut buffer, 0, data.len() as u64).unwrap();
+            assert!(buffer[..16 << 10].iter().all(|b| *b == 2));
+            assert!(buffer[16 << 10..].iter().all(|b| *b == 3));
+            Adapter::backend_delete(&backend, &c).unwrap();
+            assert_eq!(chunk_files(&temp), 0);
+
+            let path = CString::new(format!("{}?dedup_chunk=0", temp.to_str().unwrap())).unwrap();
+            assert!(Adapter::backend_init(path.as_ptr()).is_err());
+        }
+
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_dedup_failed_flush() {
+        let temp = setup();
+        let backend = init(&temp);
+        let backend_data = &backend as *const _ as gpointer;
+        let data: Vec<u8> = [7u8, 8].iter().flat_map(|i| vec![*i; 16 << 10]).collect();
+
+        // a file in place of its shard keeps the first chunk from being stored
+        let hash: [u8; 32] = Sha256::digest(&data[..16 << 10]).into();
+        let shard = temp.join(CHUNK_DIR).join(&hex(&hash)[..2]);
+        fs::write(&shard, b"").unwrap();
+
+        unsafe {
+            let a =
+                Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "a\0".as_ptr().cast())
+                    .unwrap();
+            backend.write(&a, &data, 0, data.len() as u64).unwrap();
+            assert!(backend.sync(&a).is_err());
+
+            let mut buffer = vec![0u8; data.len()];
+            backend.read(&a, &mut buffer, 0, data.len() as u64).unwrap();
+            assert_eq!(buffer, data);
+
+            fs::remove_file(&shard).unwrap();
+            assert_eq!(
+                Adapter::j_close(backend_data, &a as *const _ as gpointer),
+                TRUE
+            );
+            assert_eq!(chunk_files(&temp), 2);
+
+            let a = Adapter::backend_open(&backend, "ns\0".as_ptr().cast(), "a\0".as_ptr().cast())
+                .unwrap();
+            buffer.fill(0);
+            backend.read(&a, &mut buffer, 0, data.len() as u64).unwrap();
+            assert_eq!(buffer, data);
+        }
+
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_dedup_unreadable_recipe() {
+        let temp = setup();
+        let backend = init(&temp);
+        let backend_data = &backend as *const _ as gpointer;
+
+        unsafe {
+            for (name, byte) in [("a\0", 1u8), ("b\0", 2)] {
+                let handle =
+                    Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), name.as_ptr().cast())
+                        .unwrap();
+                backend
+                    .write(&handle, &[byte; 16 << 10], 0, 16 << 10)
+                    .unwrap();
+                assert_eq!(
+                    Adapter::j_close(backend_data, &handle as *const _ as gpointer),
+                    TRUE
+                );
+            }
+        }
+        assert_eq!(chunk_files(&temp), 2);
+
+        // a backend in the same root shares the open store
+        let other = init(&temp);
+        assert!(Arc::ptr_eq(
+            &STORES.lookup(&backend.config).unwrap(),
+            &STORES.lookup(&other.config).unwrap()
+        ));
+        for backend in [backend, other] {
+            unsafe { Adapter::j_fini(Box::into_raw(Box::new(backend)).cast()) };
+        }
+
+        // the chunk of 'b' must not be collected just because its recipe is unreadable
+        OpenOptions::new()
+            .write(true)
+            .open(temp.join("ns").join("b"))
+            .unwrap()
+            .set_len(10)
+            .unwrap();
+        let path = CString::new(format!("{}?dedup_chunk=16K", temp.to_str().unwrap())).unwrap();
+        assert!(unsafe { Adapter::backend_init(path.as_ptr()) }.is_err());
+        assert_eq!(chunk_files(&temp), 2);
+
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_dedup_recipe_updates() {
+        let temp = setup();
+        let path = temp.join(CREATE_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .unwrap();
+
+        let mut recipe = Recipe::new(4096);
+        for n in 0..64u8 {
+            recipe.chunks.push(Some([n + 1; 32]));
+            recipe.size += 4096;
+            recipe.write(&file).unwrap();
+            assert_eq!(Recipe::read(&file).unwrap(), Some(recipe.clone()));
+            // the old entries are kept next to the new ones, but not much more
+            assert!(fs::metadata(&path).unwrap().len() < 40 + 3 * 32 * (n as u64 + 1) + 64);
+        }
+
+        // a torn write of the next recipe leaves the current one intact
+        let len = fs::metadata(&path).unwrap().len();
+        file.write_all_at(&[0xff; 4096], len).unwrap();
+        assert_eq!(Recipe::read(&file).unwrap(), Some(recipe.clone()));
+
+        recipe.chunks.truncate(1);
+        recipe.size = 10;
+        recipe.write(&file).unwrap();
+        assert_eq!(Recipe::read(&file).unwrap(), Some(recipe));
+        assert_eq!(fs::metadata(&path).unwrap().len(), 40 + 32);
+
+        shutdown(temp);
+    }
+
+    #[test]
+    fn repeated_writes() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory = |_namespace| {
+            Box::into_raw(Box::new(std::ptr::null_mut() as gpointer)).cast::<gpointer>()
+        };
+
+        writes::test_writes(&backend, data_factory)
+    }
+
+    #[test]
+    fn test_dedup_shared_chunks() {
+        let temp = setup();
+        let backend = init(&temp);
+        let backend_data = &backend as *const _ as gpointer;
+        let ratio = || {
+            STORES
+                .lookup(&backend.config)
+                .unwrap()
+                .dedup_ratio()
+                .unwrap()
+        };
+
+        // 4 distinct chunks of 16K
+        let data: Vec<u8> = (0..4u8).flat_map(|i| vec![i + 1; 16 << 10]).collect();
+
+        unsafe {
+            for name in ["a\0", "b\0"] {
+                let handle =
+                    Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), name.as_ptr().cast())
+                        .unwrap();
+                backend.write(&handle, &data, 0, data.len() as u64).unwrap();
+                assert_eq!(
+                    Adapter::j_close(backend_data, &handle as *const _ as gpointer),
+                    TRUE
+                );
+            }
+            assert_eq!(chunk_files(&temp), 4);
+            assert_eq!(ratio(), 2.0);
+
+            // overwriting part of a chunk stores a new one, the old one is still used by 'a'
+            let b = Adapter::backend_open(&backend, "ns\0".as_ptr().cast(), "b\0".as_ptr().cast())
+                .unwrap();
+            backend.write(&b, b"JULEA", 20_000, 5).unwrap();
+            backend.write(&b, b"!", 100_000, 1).unwrap();
+            assert_eq!(backend.status(&b).unwrap().1, 100_001);
+            backend.sync(&b).unwrap();
+            assert_eq!(chunk_files(&temp), 6);
+
+            let mut buffer = vec![0u8; 100_001];
+            assert_eq!(backend.read(&b, &mut buffer, 0, 100_001).unwrap(), 100_001);
+            assert_eq!(&buffer[..20_000], &data[..20_000]);
+            assert_eq!(&buffer[20_000..20_005], b"JULEA");
+            assert_eq!(&buffer[20_005..data.len()], &data[20_005..]);
+            assert!(buffer[data.len()..100_000].iter().all(|b| *b == 0));
+            assert_eq!(buffer[100_000], b'!');
+
+            // copies share all chunks
+            Adapter::backend_copy(
+                &backend,
+                "ns\0".as_ptr().cast(),
+                "a\0".as_ptr().cast(),
+                "other\0".as_ptr().cast(),
+                "c\0".as_ptr().cast(),
+            )
+            .unwrap();
+            assert_eq!(chunk_files(&temp), 6);
+
+            let a = Adapter::backend_open(&backend, "ns\0".as_ptr().cast(), "a\0".as_ptr().cast())
+                .unwrap();
+            Adapter::backend_delete(&backend, &a).unwrap();
+            Adapter::backend_delete(&backend, &b).unwrap();
+            assert_eq!(chunk_files(&temp), 4);
+
+            let mut names = Vec::new();
+            let mut iter =
+                Adapter::backend_get_iterator(&backend, "\0".as_ptr().cast(), None).unwrap();
+            while let Some(name) = Adapter::backend_iterate(&mut iter).unwrap() {
+                names.push(name.into_string().unwrap());
+            }
+            assert!(names.contains(&String::from("other")));
+            assert!(!names.contains(&String::from(CHUNK_DIR)));
+        }
+
+        // chunks nothing refers to are removed when the store is opened again
+        fs::create_dir_all(temp.join(CHUNK_DIR).join("00")).unwrap();
+        fs::write(
+            temp.join(CHUNK_DIR).join("00").join("0".repeat(64)),
+            b"stray",
+        )
+        .unwrap();
+        unsafe { Adapter::j_fini(Box::into_raw(Box::new(backend)).cast()) };
+        let backend = init(&temp);
+        assert_eq!(chunk_files(&temp), 4);
+
+        unsafe {
+            let c =
+                Adapter::backend_open(&backend, "other\0".as_ptr().cast(), "c\0".as_ptr().cast())
+                    .unwrap();
+            let mut buffer = vec![0u8; data.len()];
+            assert_eq!(
+                backend.read(&c, &mut buffer, 0, data.len() as u64).unwrap(),
+                data.len() as u64
+            );
+            assert_eq!(buffer, data);
+        }
+
+        shutdown(temp);
+    }
+}
@@ -0,0 +1,139 @@
+use std::{fs::File, io, os::unix::fs::FileExt};
+
+use io_backends::prelude::*;
+
+use crate::store::Hash;
+
+const MAGIC: &[u8; 4] = b"JDDP";
+const VERSION: u32 = 2;
+
+/// magic, version, chunk size, logical size, entries offset, entry count
+const HEADER_LEN: usize = 40;
+const ENTRY_LEN: usize = 32;
+
+/// The chunks an object consists of. This is all that is stored in the object's file.
+///
+/// The file starts with a header pointing to the entries. A new recipe never overwrites
+/// the entries the header on disk points to, it is written next to them and synced
+/// before the header is switched over, so a crash leaves either recipe behind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recipe {
+    pub chunk_size: u64,
+    pub size: u64,
+    /// Chunk hashes in order, `None` for chunks that only contain zeros.
+    pub chunks: Vec<Option<Hash>>,
+}
+
+impl Recipe {
+    pub fn new(chunk_size: u64) -> Self {
+        Recipe {
+            chunk_size,
+            size: 0,
+            chunks: Vec::new(),
+        }
+    }
+
+    /// Reads the header in `file` and returns where its entries are, in bytes. Returns
+    /// `None` for empty files.
+    fn entries(file: &File, header: &mut [u8; HEADER_LEN]) -> Result<Option<(u64, u64)>> {
+        let len = file.metadata()?.len();
+        if len == 0 {
+            return Ok(None);
+        }
+
+        let invalid = || BackendError::new("File is not a deduplicated object", Action::Open);
+        if len < HEADER_LEN as u64 {
+            return Err(invalid());
+        }
+        file.read_exact_at(header, 0)?;
+        let offset = u64_at(header, 24);
+        let entries_len = u64_at(header, 32).saturating_mul(ENTRY_LEN as u64);
+        if &header[0..4] != MAGIC
+            || header[4..8] != VERSION.to_le_bytes()
+            || offset < HEADER_LEN as u64
+            || offset.saturating_add(entries_len) > len
+        {
+            return Err(invalid());
+        }
+        Ok(Some((offset, entries_len)))
+    }
+
+    /// Whether `file` starts like a recipe, even if the rest of it cannot be read.
+    pub fn is_recipe(file: &File) -> Result<bool> {
+        let mut magic = [0u8; 4];
+        match file.read_exact_at(&mut magic, 0) {
+            Ok(()) => Ok(&magic == MAGIC),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(BackendError::map(&e, Action::Read)),
+        }
+    }
+
+    /// Reads the recipe in `file`. Returns `None` for empty files.
+    pub fn read(file: &File) -> Result<Option<Self>> {
+        let mut header = [0u8; HEADER_LEN];
+        let (offset, len) = match Self::entries(file, &mut header)? {
+            Some(entries) => entries,
+            None => return Ok(None),
+        };
+
+        let mut raw = vec![0u8; len as usize];
+        file.read_exact_at(&mut raw, offset)?;
+        let chunks = raw
+            .chunks(ENTRY_LEN)
+            .map(|entry| match entry.iter().all(|b| *b == 0) {
+                true => None,
+                false => Some(entry.try_into().unwrap()),
+            })
+            .collect();
+
+        Ok(Some(Recipe {
+            chunk_size: u64_at(&header, 8),
+            size: u64_at(&header, 16),
+            chunks,
+        }))
+    }
+
+    /// Writes the recipe to `file` and syncs it, without touching the recipe on disk
+    /// until the new one is complete.
+    ///
+    /// The entries go to the front of the file if they fit in front of the current ones
+    /// and behind them otherwise, so the file stays a small multiple of the recipe.
+    pub fn write(&self, file: &File) -> Result<()> {
+        let mut header = [0u8; HEADER_LEN];
+        let current = Self::entries(file, &mut header)?;
+
+        let mut raw = Vec::with_capacity(self.chunks.len() * ENTRY_LEN);
+        for chunk in &self.chunks {
+            raw.extend_from_slice(&chunk.unwrap_or_default());
+        }
+        let front = HEADER_LEN as u64;
+        let offset = match current {
+            Some((offset, len)) if front + (raw.len() as u64) > offset => offset + len,
+            _ => front,
+        };
+        file.write_all_at(&raw, offset)?;
+        file.sync_data()?;
+
+        header[0..4].copy_from_slice(MAGIC);
+        header[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        header[8..16].copy_from_slice(&self.chunk_size.to_le_bytes());
+        header[16..24].copy_from_slice(&self.size.to_le_bytes());
+        header[24..32].copy_from_slice(&offset.to_le_bytes());
+        header[32..40].copy_from_slice(&(self.chunks.len() as u64).to_le_bytes());
+        file.write_all_at(&header, 0)?;
+        file.sync_data()?;
+
+        // whatever lies behind the new entries is no longer referenced
+        file.set_len(offset + raw.len() as u64)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    pub fn hashes(&self) -> impl Iterator<Item = &Hash> {
+        self.chunks.iter().flatten()
+    }
+}
+
+fn u64_at(buffer: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(buffer[at..at + 8].try_into().unwrap())
+}
@@ -0,0 +1,242 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+use log::{debug, info, warn};
+use rustc_hash::FxHashMap;
+use sha2::{Digest, Sha256};
+
+use io_backends::prelude::*;
+
+use crate::recipe::Recipe;
+
+/// Directory below the backend root holding the chunks of all namespaces.
+pub const CHUNK_DIR: &str = ".jchunks";
+
+pub type Hash = [u8; 32];
+
+/// Stores of all initialized backends by their chunk directory, recorded in
+/// `dedup_chunks`.
+pub static STORES: Registry<ChunkStore> = Registry::new("dedup_chunks");
+
+pub fn hex(hash: &Hash) -> String {
+    hash.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn parse_hex(name: &str) -> Option<Hash> {
+    if name.len() != 64 {
+        return None;
+    }
+    let mut hash = [0u8; 32];
+    for (byte, i) in hash.iter_mut().zip((0..64).step_by(2)) {
+        *byte = u8::from_str_radix(name.get(i..i + 2)?, 16).ok()?;
+    }
+    Some(hash)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Chunk {
+    refs: u64,
+    len: u64,
+}
+
+/// Chunks stored once under their SHA-256, `<dir>/<first byte>/<hash>`, with the
+/// number of recipes referring to them.
+///
+/// Reference counts are not stored. They are rebuilt from all recipes below the root
+/// when the store is opened, which also removes chunks left behind by a crash. Opening
+/// fails if a recipe cannot be read, instead of removing the chunks it refers to.
+pub struct ChunkStore {
+    dir: PathBuf,
+    chunks: Mutex<FxHashMap<Hash, Chunk>>,
+}
+
+impl ChunkStore {
+    pub fn open(root: &Path) -> Result<Self> {
+        let dir = root.join(CHUNK_DIR);
+        fs::create_dir_all(&dir)?;
+
+        let mut refs = FxHashMap::default();
+        count_refs(root, &mut refs)?;
+
+        let mut chunks = FxHashMap::default();
+        let mut removed = 0;
+        for shard in fs::read_dir(&dir)? {
+            for entry in fs::read_dir(shard?.path())? {
+                let entry = entry?;
+                let name = entry.file_name();
+                match parse_hex(&name.to_string_lossy())
+                    .and_then(|h| refs.remove(&h).map(|r| (h, r)))
+                {
+                    Some((hash, refs)) => {
+                        let len = entry.metadata()?.len();
+                        chunks.insert(hash, Chunk { refs, len });
+                    }
+                    None => {
+                        fs::remove_file(entry.path())?;
+                        removed += 1;
+                    }
+                }
+            }
+        }
+
+        if !refs.is_empty() {
+            warn!("{} chunks referenced by objects are missing", refs.len());
+        }
+        info!(
+            "opened chunk store {dir:?} with {} chunks, removed {removed} unreferenced chunks",
+            chunks.len()
+        );
+
+        Ok(ChunkStore {
+            dir,
+            chunks: Mutex::new(chunks),
+        })
+    }
+
+    fn path(&self, hash: &Hash) -> PathBuf {
+        let name = hex(hash);
+        self.dir.join(&name[..2]).join(name)
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, FxHashMap<Hash, Chunk>>> {
+        self.chunks
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))
+    }
+
+    /// Stores `data` unless an identical chunk exists and takes a reference to it.
+    pub fn put(&self, data: &[u8]) -> Result<Hash> {
+        let hash: Hash = Sha256::digest(data).into();
+        let mut chunks = self.lock()?;
+
+        if let Some(chunk) = chunks.get_mut(&hash) {
+            chunk.refs += 1;
+            return Ok(hash);
+        }
+
+        // readers only ever see complete chunks, and the chunk is on disk before any
+        // recipe referring to it
+        let path = self.path(&hash);
+        let dir = path.parent().unwrap();
+        let temp = dir.join(format!("{TEMP_PREFIX}{}", hex(&hash)));
+        (|| {
+            if !dir.exists() {
+                fs::create_dir(dir)?;
+                File::open(&self.dir)?.sync_all()?;
+            }
+            let mut file = File::create(&temp)?;
+            file.write_all(data)?;
+            file.sync_data()?;
+            fs::rename(&temp, &path)?;
+            File::open(dir)?.sync_all()
+        })()
+        .map_err(|e| BackendError::map(&e, Action::Write))?;
+
+        chunks.insert(
+            hash,
+            Chunk {
+                refs: 1,
+                len: data.len() as u64,
+            },
+        );
+        Ok(hash)
+    }
+
+    pub fn get(&self, hash: &Hash) -> Result<Vec<u8>> {
+        fs::read(self.path(hash))
+            .map_err(|e| BackendError::new(&format!("Chunk {}: {e}", hex(hash)), Action::Read))
+    }
+
+    /// Takes another reference to chunks that are already stored.
+    pub fn acquire<'a>(&self, hashes: impl Iterator<Item = &'a Hash>) -> Result<()> {
+        let mut chunks = self.lock()?;
+        for hash in hashes {
+            chunks
+                .get_mut(hash)
+                .ok_or(BackendError::new(
+                    &format!("Chunk {} is missing", hex(hash)),
+                    Action::Copy,
+                ))?
+                .refs += 1;
+        }
+        Ok(())
+    }
+
+    /// Drops a reference and removes the chunk once it was the last one.
+    pub fn release(&self, hash: &Hash) -> Result<()> {
+        let mut chunks = self.lock()?;
+        let chunk = match chunks.get_mut(hash) {
+            Some(chunk) => chunk,
+            None => return Ok(()),
+        };
+
+        chunk.refs -= 1;
+        if chunk.refs == 0 {
+            chunks.remove(hash);
+            match fs::remove_file(self.path(hash)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    return Err(BackendError::map(&e, Action::Delete))
+                }
+                _ => debug!("removed chunk {}", hex(hash)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the bytes referenced by all objects and the bytes actually stored.
+    pub fn usage(&self) -> Result<(u64, u64)> {
+        let chunks = self.lock()?;
+        Ok(chunks.values().fold((0, 0), |(logical, stored), chunk| {
+            (logical + chunk.refs * chunk.len, stored + chunk.len)
+        }))
+    }
+
+    /// Referenced bytes per stored byte.
+    pub fn dedup_ratio(&self) -> Result<f64> {
+        let (logical, stored) = self.usage()?;
+        Ok(match stored {
+            0 => 1.0,
+            _ => logical as f64 / stored as f64,
+        })
+    }
+}
+
+/// Counts the chunk references of all recipes below `dir`. Fails if any of them cannot
+/// be read, files that are not recipes are skipped.
+fn count_refs(dir: &Path, refs: &mut FxHashMap<Hash, u64>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name == CHUNK_DIR || name.starts_with(TEMP_PREFIX) || name.starts_with(CHECKSUM_PREFIX) {
+            continue;
+        }
+
+        if entry.file_type()?.is_dir() {
+            count_refs(&entry.path(), refs)?;
+            continue;
+        }
+        // the chunks of a recipe that cannot be read would look unreferenced and be
+        // removed, other files are not objects of this backend
+        let unreadable = |e: BackendError| {
+            BackendError::new(
+                &format!("Unable to read recipe {:?}: {e}", entry.path()),
+                Action::Init,
+            )
+        };
+        let file = File::open(entry.path())?;
+        if !Recipe::is_recipe(&file).map_err(unreadable)? {
+            continue;
+        }
+        if let Some(recipe) = Recipe::read(&file).map_err(unreadable)? {
+            for hash in recipe.hashes() {
+                *refs.entry(*hash).or_default() += 1;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use std::{
+    alloc::{self, Layout},
+    fs::{self, File},
+    io::{self, ErrorKind},
+    mem::MaybeUninit,
+    ops::{Deref, DerefMut},
+    os::{fd::AsRawFd, unix::fs::MetadataExt},
+    ptr::NonNull,
+    slice,
+};
+
+use log::debug;
+
+use io_backends::prelude::*;
+
+/// A zeroed heap buffer whose address is a multiple of the given alignment, as O_DIRECT requires.
+pub struct AlignedBuffer {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+impl AlignedBuffer {
+    pub fn new(len: usize, align: usize) -> io::Result<Self> {
+        let layout = Layout::from_size_align(len.max(align), align)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+        let ptr = NonNull::new(unsafe { alloc::alloc_zeroed(layout) })
+            .ok_or(io::Error::from(ErrorKind::OutOfMemory))?;
+
+        Ok(AlignedBuffer { ptr, layout })
+    }
+}
+
+impl Deref for AlignedBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.layout.size()) }
+    }
+}
+
+impl DerefMut for AlignedBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.layout.size()) }
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+/// Determines the alignment O_DIRECT transfers on `file` need.
+///
+/// Prefers what the kernel reports through `statx(STATX_DIOALIGN)` (Linux 6.1+), then the
+/// logical block size of the underlying block device, then the file system's block size.
+pub fn detect_alignment(file: &File) -> Result<u64> {
+    if let Some(align) = dio_alignment(file) {
+        debug!("O_DIRECT alignment from statx: {align} b");
+        return Ok(align);
+    }
+
+    let metadata = file.metadata()?;
+    if let Some(align) = logical_block_size(metadata.dev()) {
+        debug!("O_DIRECT alignment from logical block size: {align} b");
+        return Ok(align);
+    }
+
+    debug!(
+        "O_DIRECT alignment from file system block size: {} b",
+        metadata.blksize()
+    );
+    Ok(metadata.blksize())
+}
+
+fn dio_alignment(file: &File) -> Option<u64> {
+    let mut stx = MaybeUninit::<libc::statx>::zeroed();
+    let ret = unsafe {
+        libc::statx(
+            file.as_raw_fd(),
+            b"\0".as_ptr().cast(),
+            libc::AT_EMPTY_PATH,
+            libc::STATX_DIOALIGN,
+            stx.as_mut_ptr(),
+        )
+    };
+    if ret != 0 {
+        return None;
+    }
+
+    let stx = unsafe { stx.assume_init() };
+    if stx.stx_mask & libc::STATX_DIOALIGN == 0 || stx.stx_dio_offset_align == 0 {
+        return None;
+    }
+
+    Some(stx.stx_dio_offset_align.max(stx.stx_dio_mem_align) as u64)
+}
+
+fn logical_block_size(dev: u64) -> Option<u64> {
+    let (major, minor) = (libc::major(dev), libc::minor(dev));
+    // partitions keep the queue limits in their parent device's directory
+    ["queue", "../queue"].iter().find_map(|queue| {
+        fs::read_to_string(format!(
+            "/sys/dev/block/{major}:{minor}/{queue}/logical_block_size"
+        ))
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+    })
+}
@@ -0,0 +1,234 @@
+use std::{
+    fs::File,
+    io::{self, ErrorKind},
+    os::{
+        fd::AsRawFd,
+        unix::fs::{FileExt, MetadataExt},
+    },
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use log::warn;
+
+use io_backends::prelude::*;
+
+use crate::aligned::{detect_alignment, AlignedBuffer};
+
+pub struct DirectObject {
+    file: File,
+    align: u64,
+    direct: AtomicBool,
+}
+
+impl DirectObject {
+    fn align_down(&self, n: u64) -> u64 {
+        n - n % self.align
+    }
+
+    fn align_up(&self, n: u64) -> u64 {
+        self.align_down(n + self.align - 1)
+    }
+
+    fn is_aligned(&self, buffer: &[u8], offset: u64) -> bool {
+        offset.is_multiple_of(self.align)
+            && (buffer.len() as u64).is_multiple_of(self.align)
+            && (buffer.as_ptr() as u64).is_multiple_of(self.align)
+    }
+
+    /// Switches the object to buffered I/O, for file systems that accept O_DIRECT on open
+    /// but reject the transfers themselves.
+    fn fall_back(&self) -> Result<()> {
+        warn!("file system rejected O_DIRECT transfer, falling back to buffered I/O");
+        set_direct(&self.file, false)?;
+        self.direct.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Reads whole blocks. A read that ends off the block grid has hit EOF and must not be
+    /// continued, the next offset would be unaligned.
+    fn read_blocks(&self, buffer: &mut [u8], offset: u64) -> io::Result<usize> {
+        let mut n_read = 0;
+        while n_read < buffer.len() {
+            match self
+                .file
+                .read_at(&mut buffer[n_read..], offset + n_read as u64)
+            {
+                Ok(0) => break,
+                Ok(n) => {
+                    n_read += n;
+                    if !(n as u64).is_multiple_of(self.align) {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(n_read)
+    }
+
+    fn read_direct(&self, buffer: &mut [u8], offset: u64) -> io::Result<usize> {
+        if self.is_aligned(buffer, offset) {
+            return self.read_blocks(buffer, offset);
+        }
+
+        let start = self.align_down(offset);
+        let end = self.align_up(offset + buffer.len() as u64);
+        let mut bounce = AlignedBuffer::new((end - start) as usize, self.align as usize)?;
+
+        let n_read = self.read_blocks(&mut bounce[..(end - start) as usize], start)?;
+        let skip = (offset - start) as usize;
+        let available = n_read.saturating_sub(skip).min(buffer.len());
+        buffer[..available].copy_from_slice(&bounce[skip..skip + available]);
+
+        Ok(available)
+    }
+
+    /// Writes through an aligned bounce buffer, reading the partially covered edge
+    /// blocks first so that the bytes around the written range are preserved.
+    fn write_direct(&self, buffer: &[u8], offset: u64) -> io::Result<()> {
+        if self.is_aligned(buffer, offset) {
+            return self.file.write_all_at(buffer, offset);
+        }
+
+        let write_end = offset + buffer.len() as u64;
+        let start = self.align_down(offset);
+        let end = self.align_up(write_end);
+        let len = (end - start) as usize;
+        let align = self.align as usize;
+        let size = self.file.metadata()?.len();
+
+        let mut bounce = AlignedBuffer::new(len, align)?;
+        let head_partial = offset != start;
+        let tail_partial = write_end != end;
+
+        if head_partial {
+            self.read_blocks(&mut bounce[..align], start)?;
+        }
+        if tail_partial && !(head_partial && len == align) {
+            self.read_blocks(&mut bounce[len - align..len], end - self.align)?;
+        }
+
+        let skip = (offset - start) as usize;
+        bounce[skip..skip + buffer.len()].copy_from_slice(buffer);
+        self.file.write_all_at(&bounce[..len], start)?;
+
+        // the last block was written in full, cut the file back to its actual size
+        let new_size = size.max(write_end);
+        if end > new_size {
+            self.file.set_len(new_size)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_buffered(&self, buffer: &mut [u8], offset: u64) -> io::Result<usize> {
+        let mut n_read = 0;
+        while n_read < buffer.len() {
+            match self
+                .file
+                .read_at(&mut buffer[n_read..], offset + n_read as u64)
+            {
+                Ok(0) => break,
+                Ok(n) => n_read += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(n_read)
+    }
+}
+
+impl BackendObject for DirectObject {
+    fn new(file: File, _config: &Config) -> Result<Self> {
+        let align = detect_alignment(&file)?;
+
+        let direct = match set_direct(&file, true) {
+            Ok(_) => true,
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+                warn!("file system does not support O_DIRECT, falling back to buffered I/O");
+                false
+            }
+            Err(e) => return Err(BackendError::map(&e, Action::Open)),
+        };
+
+        Ok(DirectObject {
+            file,
+            align,
+            direct: AtomicBool::new(direct),
+        })
+    }
+
+    fn read(&self, buffer: &mut [u8], offset: u64, _length: u64) -> Result<u64> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        let res = match self.direct.load(Ordering::Relaxed) {
+            true => match self.read_direct(buffer, offset) {
+                Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+                    self.fall_back()?;
+                    self.read_buffered(buffer, offset)
+                }
+                res => res,
+            },
+            false => self.read_buffered(buffer, offset),
+        };
+
+        res.map(|n| n as u64)
+            .map_err(|e| BackendError::map(&e, Action::Read))
+    }
+
+    fn write(&mut self, buffer: &[u8], offset: u64, _length: u64) -> Result<u64> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        let res = match self.direct.load(Ordering::Relaxed) {
+            true => match self.write_direct(buffer, offset) {
+                Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+                    self.fall_back()?;
+                    self.file.write_all_at(buffer, offset)
+                }
+                res => res,
+            },
+            false => self.file.write_all_at(buffer, offset),
+        };
+
+        res.map(|_| buffer.len() as u64)
+            .map_err(|e| BackendError::map(&e, Action::Write))
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        // O_DIRECT bypasses the page cache, but neither the device cache nor metadata updates
+        self.file
+            .sync_data()
+            .map_err(|e| BackendError::map(&e, Action::Sync))
+    }
+
+    fn status(&self) -> Result<(i64, u64)> {
+        let metadata = self.file.metadata()?;
+        Ok((metadata.atime(), metadata.size()))
+    }
+}
+
+fn set_direct(file: &File, enable: bool) -> io::Result<()> {
+    let fd = file.as_raw_fd();
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let flags = match enable {
+        true => flags | libc::O_DIRECT,
+        false => flags & !libc::O_DIRECT,
+    };
+    match unsafe { libc::fcntl(fd, libc::F_SETFL, flags) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+pub struct Adapter {}
+
+impl JuleaAdapter<DirectObject> for Adapter {}
@@ -0,0 +1,81 @@
+mod aligned;
+mod direct;
+
+use io_backends::generate_backend;
+use io_backends::prelude::*;
+use log::debug;
+use log::info;
+
+generate_backend!(direct);
+
+#[no_mangle]
+pub unsafe extern "C" fn backend_info() -> *mut JBackend {
+    match init_logger() {
+        Ok(_) => info!("logger initialized."),
+        Err(e) => {
+            let _ = println!("Error while initializing logger: {e:?}");
+        }
+    };
+    debug!("backend info called");
+    &mut BACKEND
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{self, OpenOptions};
+
+    use io_backends::prelude::*;
+    use io_backends::testing::*;
+
+    use crate::direct::DirectObject;
+    use crate::BACKEND;
+
+    #[test]
+    fn test_direct_workflow() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory = |namespace| {
+            let data = Backend::<DirectObject> {
+                object_store: ObjectStore::new(),
+                namespace,
+                config: Config::default(),
+                pending: PendingLinks::new(),
+            };
+            Box::into_raw(Box::new(data)).cast::<gpointer>()
+        };
+        test_workflow(&backend, &data_factory);
+    }
+
+    #[test]
+    fn test_direct_unaligned() {
+        let temp = setup();
+        let path = temp.join(WRITE_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let mut object = DirectObject::new(file, &Config::default()).unwrap();
+
+        let block = vec![b'a'; 8192];
+        assert_eq!(object.write(&block, 0, 8192).unwrap(), 8192);
+        assert_eq!(object.write(b"Hello, world!", 4090, 13).unwrap(), 13);
+        assert_eq!(object.write(b"!", 9000, 1).unwrap(), 1);
+        assert_eq!(object.status().unwrap().1, 9001);
+
+        let mut buffer = [0u8; 15];
+        assert_eq!(object.read(&mut buffer, 4089, 15).unwrap(), 15);
+        assert_eq!(&buffer, b"aHello, world!a");
+
+        let mut buffer = [0u8; 64];
+        assert_eq!(object.read(&mut buffer, 8990, 64).unwrap(), 11);
+        assert_eq!(&buffer[..11], b"\0\0\0\0\0\0\0\0\0\0!");
+
+        let content = fs::read(&path).unwrap();
+        assert_eq!(content.len(), 9001);
+        assert_eq!(&content[4090..4103], b"Hello, world!");
+        assert!(content[..4090].iter().all(|b| *b == b'a'));
+
+        drop(object);
+        shutdown(temp);
+    }
+}
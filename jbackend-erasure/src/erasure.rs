@@ -0,0 +1,325 @@
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, RwLock, Weak,
+    },
+};
+
+use log::{debug, warn};
+use rustc_hash::FxHashMap;
+
+use io_backends::prelude::*;
+
+use crate::fragment::Header;
+use crate::object::ErasureObject;
+
+const DEFAULT_PARITY: u32 = 1;
+const DEFAULT_UNIT: u64 = 64 << 10;
+
+/// An open object and its fragments, shared by all handles of the object so that every
+/// handle sees the size and sequence number of the last write.
+pub struct OpenObject {
+    path: PathBuf,
+    object: Mutex<ErasureObject>,
+    /// The backend's generation the fragments were opened in.
+    generation: AtomicU64,
+}
+
+pub struct ErasureBackend {
+    roots: Vec<PathBuf>,
+    /// Layout of new objects.
+    layout: Header,
+    /// Object I/O holds this shared, a rebuild exclusively.
+    io: RwLock<()>,
+    /// Bumped by every rebuild, open objects reopen their fragments then.
+    generation: AtomicU64,
+    /// Open objects by path.
+    objects: Mutex<FxHashMap<PathBuf, Weak<OpenObject>>>,
+}
+
+impl ErasureBackend {
+    pub fn new(roots: Vec<PathBuf>, config: &Config) -> Result<Self> {
+        let parity = match config.get("erasure_parity") {
+            Some(v) => v.parse().map_err(|_| {
+                BackendError::new(
+                    &format!("Invalid value '{v}' for backend option 'erasure_parity'"),
+                    Action::Init,
+                )
+            })?,
+            None => DEFAULT_PARITY,
+        };
+        let unit = config.get_size("erasure_unit")?.unwrap_or(DEFAULT_UNIT);
+        if parity == 0 || roots.len() <= parity as usize || roots.len() > 256 || unit == 0 {
+            return Err(BackendError::new(
+                &format!(
+                    "Cannot spread {parity} parity fragments of {unit} b over {} roots",
+                    roots.len()
+                ),
+                Action::Init,
+            ));
+        }
+
+        for root in &roots {
+            // a missing failure domain only degrades the objects
+            if let Err(e) = fs::create_dir_all(root) {
+                warn!("Root {root:?} is not accessible: {e}");
+            }
+        }
+
+        Ok(ErasureBackend {
+            layout: Header {
+                data: roots.len() as u32 - parity,
+                parity,
+                index: 0,
+                unit,
+                size: 0,
+                seq: 0,
+            },
+            roots,
+            io: RwLock::new(()),
+            generation: AtomicU64::new(0),
+            objects: Mutex::new(FxHashMap::default()),
+        })
+    }
+
+    /// Returns the open object at `path`, or opens it with `open` if no handle has it open.
+    /// Created objects always replace the open object at their path, which was deleted.
+    fn object(
+        &self,
+        path: PathBuf,
+        create: bool,
+        open: impl FnOnce(&Path) -> Result<ErasureObject>,
+    ) -> Result<Arc<OpenObject>> {
+        let mut objects = self
+            .objects
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+        if !create {
+            if let Some(object) = objects.get(&path).and_then(Weak::upgrade) {
+                return Ok(object);
+            }
+        }
+
+        let object = Arc::new(OpenObject {
+            object: Mutex::new(open(&path)?),
+            generation: AtomicU64::new(self.generation.load(Ordering::SeqCst)),
+            path: path.clone(),
+        });
+        objects.retain(|_, object| object.strong_count() > 0);
+        objects.insert(path, Arc::downgrade(&object));
+        Ok(object)
+    }
+
+    /// Applies `f` to an open object, reopening its fragments if they were rebuilt in
+    /// the meantime.
+    fn with_object<R>(
+        &self,
+        open: &OpenObject,
+        f: impl FnOnce(&mut ErasureObject) -> Result<R>,
+    ) -> Result<R> {
+        let _io = self
+            .io
+            .read()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+        let mut object = lock(&open.object)?;
+
+        let generation = self.generation.load(Ordering::SeqCst);
+        if open.generation.swap(generation, Ordering::SeqCst) != generation {
+            debug!("reopening rebuilt fragments of {:?}", open.path);
+            *object = ErasureObject::open(&self.roots, &open.path)?;
+        }
+        f(&mut object)
+    }
+
+    /// Runs `f` for every root and only fails if it failed for every root, as roots
+    /// that are not accessible only lose fragments.
+    fn on_roots(&self, action: Action, f: impl Fn(&Path) -> io::Result<()>) -> Result<()> {
+        let mut first_error = None;
+        let mut done = 0;
+        for root in &self.roots {
+            match f(root) {
+                Ok(_) => done += 1,
+                Err(e) => {
+                    debug!("{action:?} failed in {root:?}: {e}");
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match (done, first_error) {
+            (0, Some(e)) => Err(BackendError::map(&e, action)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Regenerates the lost fragments of all objects. Object I/O waits until the rebuild
+    /// is done. Returns the number of fragments rebuilt.
+    pub fn rebuild(&self) -> Result<usize> {
+        let _io = self
+            .io
+            .write()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+
+        let mut paths = BTreeSet::new();
+        for root in &self.roots {
+            if let Err(e) = collect_objects(root, Path::new(""), &mut paths) {
+                warn!("Unable to list {root:?}: {e}");
+            }
+        }
+
+        let mut rebuilt = 0;
+        for path in paths {
+            match ErasureObject::open(&self.roots, &path).and_then(|mut o| o.rebuild(&self.roots)) {
+                Ok(n) => rebuilt += n,
+                Err(e) => warn!("Unable to rebuild {path:?}: {e}"),
+            }
+        }
+
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        Ok(rebuilt)
+    }
+}
+
+impl NamespaceBackend for ErasureBackend {
+    type Object = Arc<OpenObject>;
+
+    /// Takes the roots from the path, separated by colons.
+    fn init(path: &str, config: &Config) -> Result<Self> {
+        ErasureBackend::new(split_roots(path)?, config)
+    }
+
+    fn create(&self, namespace: &str, name: &str) -> Result<Arc<OpenObject>> {
+        self.object(Path::new(namespace).join(name), true, |path| {
+            ErasureObject::create(&self.roots, path, self.layout)
+        })
+    }
+
+    fn open(&self, namespace: &str, name: &str) -> Result<Arc<OpenObject>> {
+        self.object(Path::new(namespace).join(name), false, |path| {
+            ErasureObject::open(&self.roots, path)
+        })
+    }
+
+    fn delete(&self, open: &Arc<OpenObject>) -> Result<()> {
+        let mut objects = self
+            .objects
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+        if objects
+            .get(&open.path)
+            .is_some_and(|object| object.as_ptr() == Arc::as_ptr(open))
+        {
+            objects.remove(&open.path);
+        }
+        drop(objects);
+
+        self.on_roots(Action::Delete, |root| {
+            fs::remove_file(root.join(&open.path))
+        })
+    }
+
+    fn status(&self, open: &Arc<OpenObject>) -> Result<(i64, u64)> {
+        self.with_object(open, |object| object.status())
+    }
+
+    fn sync(&self, open: &Arc<OpenObject>) -> Result<()> {
+        self.with_object(open, |object| object.sync())
+    }
+
+    fn read(&self, open: &Arc<OpenObject>, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        self.with_object(open, |object| object.read(buffer, offset))
+    }
+
+    fn write(&self, open: &Arc<OpenObject>, buffer: &[u8], offset: u64) -> Result<u64> {
+        self.with_object(open, |object| object.write(buffer, offset))
+    }
+
+    /// Copies the fragments of an object on every root.
+    fn copy(
+        &self,
+        src_namespace: &str,
+        src_name: &str,
+        dst_namespace: &str,
+        dst_name: &str,
+    ) -> Result<()> {
+        let src = Path::new(src_namespace).join(src_name);
+        let dst = Path::new(dst_namespace).join(dst_name);
+        self.on_roots(Action::Copy, |root| {
+            let dst = root.join(&dst);
+            if let Some(dir) = dst.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let src = fs::File::open(root.join(&src))?;
+            let dst_file = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&dst)?;
+            copy_file(&src, &dst_file).map(|_| ()).map_err(|e| {
+                let _ = fs::remove_file(&dst);
+                io::Error::other(e.to_string())
+            })
+        })
+    }
+
+    /// Lists the namespace from all roots, so objects are found as long as any of their
+    /// fragments is left.
+    fn names(&self, namespace: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+        let mut names = BTreeSet::new();
+        let mut first_error = None;
+        let mut listed = false;
+
+        for root in &self.roots {
+            let entries = match fs::read_dir(root.join(namespace)) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                    continue;
+                }
+            };
+            listed = true;
+            for entry in entries {
+                let name = entry?.file_name().into_string().map_err(|_| {
+                    BackendError::new("Unable to convert file name to UTF-8", Action::Iter)
+                })?;
+                if !name.starts_with(TEMP_PREFIX) && name.starts_with(prefix.unwrap_or_default()) {
+                    names.insert(name);
+                }
+            }
+        }
+
+        match (listed, first_error) {
+            (false, Some(e)) => Err(BackendError::map(&e, Action::Iter)),
+            _ => Ok(names.into_iter().collect()),
+        }
+    }
+}
+
+fn lock(object: &Mutex<ErasureObject>) -> Result<MutexGuard<'_, ErasureObject>> {
+    object
+        .lock()
+        .map_err(|e| BackendError::map(&e, Action::Internal))
+}
+
+/// Collects the paths of all objects below `root`, relative to it.
+fn collect_objects(root: &Path, dir: &Path, paths: &mut BTreeSet<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(root.join(dir))? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with(TEMP_PREFIX) {
+            continue;
+        }
+        let path = dir.join(entry.file_name());
+        match entry.file_type()?.is_dir() {
+            true => collect_objects(root, &path, paths)?,
+            false => {
+                paths.insert(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub struct Adapter {}
+
+impl NamespaceAdapter<ErasureBackend> for Adapter {}
@@ -0,0 +1,81 @@
+use std::{
+    fs::File,
+    io::{self, ErrorKind},
+    os::unix::fs::FileExt,
+};
+
+const MAGIC: &[u8; 4] = b"JERC";
+const VERSION: u32 = 1;
+
+/// magic, version, data fragments, parity fragments, index, padding, unit, size, sequence
+pub const HEADER_LEN: u64 = 48;
+
+/// The start of every fragment file. All fragments of an object carry the same header
+/// apart from their index.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+    pub data: u32,
+    pub parity: u32,
+    /// Position of the fragment, data fragments come first.
+    pub index: u32,
+    /// Bytes of a stripe stored in each fragment.
+    pub unit: u64,
+    /// Logical size of the object.
+    pub size: u64,
+    /// Incremented on every write, fragments with an older sequence number are stale.
+    pub seq: u64,
+}
+
+impl Header {
+    pub fn encode(&self) -> [u8; HEADER_LEN as usize] {
+        let mut raw = [0u8; HEADER_LEN as usize];
+        raw[0..4].copy_from_slice(MAGIC);
+        raw[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        raw[8..12].copy_from_slice(&self.data.to_le_bytes());
+        raw[12..16].copy_from_slice(&self.parity.to_le_bytes());
+        raw[16..20].copy_from_slice(&self.index.to_le_bytes());
+        raw[24..32].copy_from_slice(&self.unit.to_le_bytes());
+        raw[32..40].copy_from_slice(&self.size.to_le_bytes());
+        raw[40..48].copy_from_slice(&self.seq.to_le_bytes());
+        raw
+    }
+
+    pub fn read(file: &File) -> io::Result<Self> {
+        let mut raw = [0u8; HEADER_LEN as usize];
+        file.read_exact_at(&mut raw, 0)?;
+        if &raw[0..4] != MAGIC || raw[4..8] != VERSION.to_le_bytes() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "File is not an erasure-coded fragment",
+            ));
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(raw[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(raw[at..at + 8].try_into().unwrap());
+
+        Ok(Header {
+            data: u32_at(8),
+            parity: u32_at(12),
+            index: u32_at(16),
+            unit: u64_at(24),
+            size: u64_at(32),
+            seq: u64_at(40),
+        })
+    }
+
+    pub fn write(&self, file: &File) -> io::Result<()> {
+        file.write_all_at(&self.encode(), 0)
+    }
+
+    pub fn fragments(&self) -> usize {
+        (self.data + self.parity) as usize
+    }
+
+    pub fn stripe_len(&self) -> u64 {
+        self.data as u64 * self.unit
+    }
+
+    /// Offset of a stripe's unit within every fragment file.
+    pub fn offset(&self, stripe: u64) -> u64 {
+        HEADER_LEN + stripe * self.unit
+    }
+}
@@ -0,0 +1,243 @@
+mod erasure;
+mod fragment;
+mod object;
+
+use io_backends::generate_backend;
+use io_backends::prelude::*;
+use log::debug;
+use log::error;
+use log::info;
+
+use crate::erasure::ErasureBackend;
+
+generate_backend!(erasure);
+
+#[no_mangle]
+pub unsafe extern "C" fn backend_info() -> *mut JBackend {
+    match init_logger() {
+        Ok(_) => info!("logger initialized."),
+        Err(e) => {
+            let _ = println!("Error while initializing logger: {e:?}");
+        }
+    };
+    debug!("backend info called");
+    &mut BACKEND
+}
+
+/// Regenerates the lost fragments of all objects of an initialized backend.
+#[no_mangle]
+pub unsafe extern "C" fn backend_rebuild(backend_data: gpointer) -> gboolean {
+    cast_ptr!(backend_data, NamespaceData<ErasureBackend>);
+
+    match backend_data.rebuild() {
+        Ok(rebuilt) => {
+            info!("Rebuilt {rebuilt} fragments");
+            TRUE
+        }
+        Err(e) => {
+            error!("{e}");
+            FALSE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CString;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::ptr;
+
+    use io_backends::prelude::*;
+    use io_backends::test_facility::writes;
+    use io_backends::testing::*;
+
+    use crate::erasure::{Adapter, ErasureBackend};
+    use crate::fragment::{Header, HEADER_LEN};
+    use crate::{backend_rebuild, BACKEND};
+
+    type Backend = NamespaceData<ErasureBackend>;
+
+    fn init(roots: &[PathBuf]) -> Backend {
+        let roots: Vec<&str> = roots.iter().map(|r| r.to_str().unwrap()).collect();
+        let path = CString::new(format!(
+            "{}?erasure_parity=2&erasure_unit=1K",
+            roots.join(":")
+        ))
+        .unwrap();
+        unsafe { Adapter::backend_init(path.as_ptr()).unwrap() }
+    }
+
+    fn open(backend: &Backend) -> Result<ObjectHandle> {
+        unsafe { Adapter::backend_open(backend, "ns\0".as_ptr().cast(), "obj\0".as_ptr().cast()) }
+    }
+
+    fn read_all(backend: &Backend, handle: &ObjectHandle) -> Vec<u8> {
+        let mut buffer = vec![0u8; 16384];
+        let mut read = 0;
+        let ret = unsafe {
+            Adapter::j_read(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+                buffer.as_mut_ptr().cast(),
+                buffer.len() as u64,
+                0,
+                &mut read,
+            )
+        };
+        assert_eq!(ret, TRUE);
+        buffer.truncate(read as usize);
+        buffer
+    }
+
+    fn write(backend: &Backend, handle: &ObjectHandle, data: &[u8], offset: u64) {
+        let mut written = 0;
+        let ret = unsafe {
+            Adapter::j_write(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+                data.as_ptr().cast(),
+                data.len() as u64,
+                offset,
+                &mut written,
+            )
+        };
+        assert_eq!((ret, written), (TRUE, data.len() as u64));
+    }
+
+    fn close(backend: &Backend, handle: &ObjectHandle) {
+        let ret = unsafe {
+            Adapter::j_close(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+            )
+        };
+        assert_eq!(ret, TRUE);
+    }
+
+    #[test]
+    fn repeated_writes() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory =
+            |_namespace| Box::into_raw(Box::new(ptr::null_mut() as gpointer)).cast::<gpointer>();
+
+        // two more roots are appended to the path of the first
+        let other = setup();
+        let other_path = other.to_str().unwrap();
+        writes::test_writes_with_options(
+            &backend,
+            data_factory,
+            &format!(":{other_path}/1:{other_path}/2"),
+        );
+        shutdown(other)
+    }
+
+    #[test]
+    fn test_erasure_reconstruction() {
+        let temp = setup();
+        let roots: Vec<PathBuf> = (0..4).map(|i| temp.join(format!("disk{i}"))).collect();
+        let fragment = |i: usize| roots[i].join("ns/obj");
+        let backend = init(&roots);
+
+        let mut expected: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let handle = unsafe {
+            Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "obj\0".as_ptr().cast())
+                .unwrap()
+        };
+        write(&backend, &handle, &expected, 0);
+        // a partial overwrite across a stripe boundary
+        write(&backend, &handle, b"0123456789", 2045);
+        expected[2045..2055].copy_from_slice(b"0123456789");
+        assert_eq!(read_all(&backend, &handle), expected);
+
+        // 5 stripes of 2 data units, every fragment stores 1K of each
+        for i in 0..4 {
+            assert_eq!(
+                fs::metadata(fragment(i)).unwrap().len(),
+                HEADER_LEN + 5 * 1024
+            );
+        }
+
+        // a fragment that missed the last write is stale, only the parity is left
+        let stale = fs::read(fragment(1)).unwrap();
+        write(&backend, &handle, b"!", 0);
+        expected[0] = b'!';
+        close(&backend, &handle);
+        fs::write(fragment(1), stale).unwrap();
+        fs::remove_file(fragment(0)).unwrap();
+
+        let degraded = open(&backend).unwrap();
+        assert_eq!(read_all(&backend, &degraded), expected);
+        let (mut modified, mut size) = (0, 0);
+        let ret = unsafe {
+            Adapter::j_status(
+                &backend as *const _ as gpointer,
+                &degraded as *const _ as gpointer,
+                &mut modified,
+                &mut size,
+            )
+        };
+        assert_eq!((ret, size), (TRUE, 10_000));
+
+        // degraded writes only update the remaining fragments
+        write(&backend, &degraded, b"degraded", 9_000);
+        expected[9_000..9_008].copy_from_slice(b"degraded");
+        assert_eq!(read_all(&backend, &degraded), expected);
+
+        // a third lost fragment is one too many
+        close(&backend, &degraded);
+        let moved = temp.join("moved");
+        fs::rename(fragment(2), &moved).unwrap();
+        assert!(open(&backend).is_err());
+        fs::rename(&moved, fragment(2)).unwrap();
+        let degraded = open(&backend).unwrap();
+
+        assert_eq!(
+            unsafe { backend_rebuild(&backend as *const _ as gpointer) },
+            TRUE
+        );
+        let headers: Vec<Header> = (0..4)
+            .map(|i| Header::read(&fs::File::open(fragment(i)).unwrap()).unwrap())
+            .collect();
+        for (i, header) in headers.iter().enumerate() {
+            assert_eq!(header.index as usize, i);
+            assert_eq!((header.seq, header.size), (headers[0].seq, 10_000));
+        }
+
+        // the rebuilt data fragments suffice, also for handles opened before the rebuild
+        fs::remove_file(fragment(2)).unwrap();
+        fs::remove_file(fragment(3)).unwrap();
+        assert_eq!(read_all(&backend, &degraded), expected);
+        assert_eq!(read_all(&backend, &open(&backend).unwrap()), expected);
+
+        shutdown(temp)
+    }
+
+    #[test]
+    fn test_erasure_shared_handles() {
+        let temp = setup();
+        let roots: Vec<PathBuf> = (0..4).map(|i| temp.join(format!("disk{i}"))).collect();
+        let backend = init(&roots);
+
+        let first = unsafe {
+            Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "obj\0".as_ptr().cast())
+                .unwrap()
+        };
+        write(&backend, &first, b"0123456789", 0);
+
+        // a handle opened before the object grew does not overwrite the new data
+        let second = open(&backend).unwrap();
+        write(&backend, &first, &[b'a'; 3000], 10);
+        write(&backend, &second, b"end", 3010);
+        let mut expected = b"0123456789".to_vec();
+        expected.extend_from_slice(&[b'a'; 3000]);
+        expected.extend_from_slice(b"end");
+        assert_eq!(read_all(&backend, &first), expected);
+
+        close(&backend, &first);
+        close(&backend, &second);
+        assert_eq!(read_all(&backend, &open(&backend).unwrap()), expected);
+
+        shutdown(temp)
+    }
+}
@@ -0,0 +1,422 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind},
+    ops::Range,
+    os::unix::fs::{FileExt, MetadataExt},
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info, warn};
+use reed_solomon_erasure::galois_8::ReedSolomon;
+
+use io_backends::prelude::*;
+
+use crate::fragment::Header;
+
+type Shards = Vec<Option<Vec<u8>>>;
+
+/// An object striped over one fragment file per root, `<root>/<namespace>/<name>`.
+///
+/// Every stripe of `data * unit` bytes is split into `data` units, which are extended
+/// by `parity` Reed-Solomon units, and unit `i` of every stripe is stored in fragment
+/// `i`. Any `data` fragments suffice to read the object. Fragments that are missing,
+/// fail or are stale are treated as lost until they are rebuilt.
+pub struct ErasureObject {
+    path: PathBuf,
+    /// Fragment files by index, `None` for lost fragments.
+    files: Vec<Option<File>>,
+    header: Header,
+    codec: ReedSolomon,
+}
+
+impl ErasureObject {
+    /// Creates the fragments of a new object with the layout of `header`. Fails unless
+    /// at least `data` fragments could be created.
+    pub fn create(roots: &[PathBuf], path: &Path, header: Header) -> Result<Self> {
+        let mut files = Vec::with_capacity(roots.len());
+        let mut first_error = None;
+
+        for (index, root) in roots.iter().enumerate() {
+            let header = Header {
+                index: index as u32,
+                ..header
+            };
+            let res = create_fragment(&root.join(path)).and_then(|file| {
+                header.write(&file)?;
+                Ok(file)
+            });
+            match res {
+                Ok(file) => files.push(Some(file)),
+                Err(e) => {
+                    warn!("Unable to create fragment {index} of {path:?}: {e}");
+                    first_error.get_or_insert(e);
+                    files.push(None);
+                }
+            }
+        }
+
+        if files.iter().flatten().count() < header.data as usize {
+            for (root, file) in roots.iter().zip(&files) {
+                if file.is_some() {
+                    let _ = fs::remove_file(root.join(path));
+                }
+            }
+            return Err(match first_error {
+                Some(e) => BackendError::map(&e, Action::Create),
+                None => BackendError::new_internal("No fragments were created"),
+            });
+        }
+
+        Self::new(path, files, header)
+    }
+
+    /// Opens the fragments of an existing object. Fails unless at least `data` of them
+    /// are current.
+    pub fn open(roots: &[PathBuf], path: &Path) -> Result<Self> {
+        let mut found = Vec::new();
+        let mut first_error = None;
+
+        for (index, root) in roots.iter().enumerate() {
+            match open_fragment(&root.join(path), index) {
+                Ok(fragment) => found.push(fragment),
+                Err(e) => {
+                    debug!("fragment {index} of {path:?} is not available: {e}");
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        let header = match found
+            .iter()
+            .map(|(_, header)| *header)
+            .max_by_key(|h| h.seq)
+        {
+            Some(header) => header,
+            None => {
+                return Err(match first_error {
+                    Some(e) => BackendError::map(&e, Action::Open),
+                    None => BackendError::new_internal("No fragments were found"),
+                })
+            }
+        };
+        if header.fragments() != roots.len() {
+            return Err(BackendError::new(
+                &format!(
+                    "{path:?} has {} fragments, but the backend {} roots",
+                    header.fragments(),
+                    roots.len()
+                ),
+                Action::Open,
+            ));
+        }
+
+        let mut files: Vec<Option<File>> = (0..roots.len()).map(|_| None).collect();
+        for (file, fragment) in found {
+            if fragment.seq < header.seq || fragment.unit != header.unit {
+                warn!("Fragment {} of {path:?} is stale", fragment.index);
+                continue;
+            }
+            files[fragment.index as usize] = Some(file);
+        }
+
+        let object = Self::new(path, files, header)?;
+        let available = object.files.iter().flatten().count();
+        if available < header.data as usize {
+            return Err(BackendError::new(
+                &format!(
+                    "Only {available} of {} fragments of {path:?} are available, {} are needed",
+                    header.fragments(),
+                    header.data
+                ),
+                Action::Open,
+            ));
+        }
+        if !object.lost().is_empty() {
+            warn!("{path:?} is degraded, lost fragments {:?}", object.lost());
+        }
+        Ok(object)
+    }
+
+    fn new(path: &Path, files: Vec<Option<File>>, header: Header) -> Result<Self> {
+        let codec = ReedSolomon::new(header.data as usize, header.parity as usize)
+            .map_err(|e| BackendError::new(&format!("{e:?}"), Action::Internal))?;
+
+        Ok(ErasureObject {
+            path: path.to_path_buf(),
+            files,
+            header,
+            codec,
+        })
+    }
+
+    /// Indices of the fragments that are lost.
+    pub fn lost(&self) -> Vec<usize> {
+        (0..self.files.len())
+            .filter(|index| self.files[*index].is_none())
+            .collect()
+    }
+
+    pub fn status(&self) -> Result<(i64, u64)> {
+        let file = self
+            .files
+            .iter()
+            .flatten()
+            .next()
+            .ok_or(BackendError::new_internal("All fragments are lost"))?;
+        Ok((file.metadata()?.atime(), self.header.size))
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        for index in 0..self.files.len() {
+            if let Some(Err(e)) = self.files[index].as_ref().map(|file| file.sync_data()) {
+                self.lose(index, &e);
+            }
+        }
+        self.check_available(Action::Sync)
+    }
+
+    pub fn read(&mut self, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        let length = match self.header.size.checked_sub(offset) {
+            Some(available) => available.min(buffer.len() as u64),
+            None => return Ok(0),
+        };
+        let (unit, stripe_len) = (self.header.unit, self.header.stripe_len());
+
+        let mut done = 0;
+        while done < length {
+            let position = offset + done;
+            let stripe = position / stripe_len;
+            let at = position % stripe_len;
+            let n = (stripe_len - at).min(length - done);
+
+            // only the units covering the requested range are read, unless some are lost
+            let wanted = (at / unit) as usize..(at + n).div_ceil(unit) as usize;
+            let data = self.stripe(stripe, wanted.clone())?;
+            let first = wanted.start as u64 * unit;
+            buffer[done as usize..(done + n) as usize]
+                .copy_from_slice(&data[(at - first) as usize..(at - first + n) as usize]);
+            done += n;
+        }
+
+        Ok(length)
+    }
+
+    /// Writes `buffer` at `offset`, recomputing the parity of every stripe it touches.
+    /// Stripes that are only partially overwritten are read first.
+    pub fn write(&mut self, buffer: &[u8], offset: u64) -> Result<u64> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        let (data, unit) = (self.header.data as usize, self.header.unit as usize);
+        let stripe_len = self.header.stripe_len();
+        let end = offset + buffer.len() as u64;
+
+        for stripe in offset / stripe_len..=(end - 1) / stripe_len {
+            let start = stripe * stripe_len;
+            let (from, to) = (offset.max(start), end.min(start + stripe_len));
+
+            let mut content =
+                match (from == start && to == start + stripe_len) || start >= self.header.size {
+                    true => vec![0u8; stripe_len as usize],
+                    false => self.stripe(stripe, 0..data)?,
+                };
+            content[(from - start) as usize..(to - start) as usize]
+                .copy_from_slice(&buffer[(from - offset) as usize..(to - offset) as usize]);
+
+            let mut shards: Vec<Vec<u8>> = content.chunks(unit).map(|c| c.to_vec()).collect();
+            shards.resize(self.files.len(), vec![0u8; unit]);
+            self.codec
+                .encode(&mut shards)
+                .map_err(|e| BackendError::new(&format!("{e:?}"), Action::Write))?;
+
+            for (index, shard) in shards.iter().enumerate() {
+                let offset = self.header.offset(stripe);
+                if let Some(Err(e)) = self.files[index]
+                    .as_ref()
+                    .map(|file| file.write_all_at(shard, offset))
+                {
+                    self.lose(index, &e);
+                }
+            }
+            self.check_available(Action::Write)?;
+        }
+
+        self.header.size = self.header.size.max(end);
+        self.header.seq += 1;
+        for index in 0..self.files.len() {
+            let header = Header {
+                index: index as u32,
+                ..self.header
+            };
+            if let Some(Err(e)) = self.files[index].as_ref().map(|file| header.write(file)) {
+                self.lose(index, &e);
+            }
+        }
+        self.check_available(Action::Write)?;
+
+        Ok(buffer.len() as u64)
+    }
+
+    /// Regenerates the lost fragments from the available ones. Every fragment is first
+    /// written to a temporary file next to its final name. Returns the number of
+    /// fragments rebuilt.
+    pub fn rebuild(&mut self, roots: &[PathBuf]) -> Result<usize> {
+        let mut targets = Vec::new();
+        for index in self.lost() {
+            let path = roots[index].join(&self.path);
+            let temp = match path.file_name() {
+                Some(name) => {
+                    path.with_file_name(format!("{TEMP_PREFIX}{}", name.to_string_lossy()))
+                }
+                None => continue,
+            };
+            match path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| File::create(&temp))
+            {
+                Ok(file) => targets.push((index, path, temp, file)),
+                Err(e) => warn!("Unable to rebuild fragment {index} of {:?}: {e}", self.path),
+            }
+        }
+        if targets.is_empty() {
+            return Ok(0);
+        }
+
+        let stripes = self.header.size.div_ceil(self.header.stripe_len());
+        for stripe in 0..stripes {
+            let mut shards = self.shards(stripe, 0..self.files.len())?;
+            self.codec
+                .reconstruct(&mut shards)
+                .map_err(|e| BackendError::new(&format!("{e:?}"), Action::Internal))?;
+
+            for (index, _, _, file) in &targets {
+                let shard = shards[*index].as_ref().unwrap();
+                file.write_all_at(shard, self.header.offset(stripe))?;
+            }
+        }
+
+        let rebuilt = targets.len();
+        for (index, path, temp, file) in targets {
+            Header {
+                index: index as u32,
+                ..self.header
+            }
+            .write(&file)?;
+            file.sync_all()?;
+            fs::rename(&temp, &path)?;
+            self.files[index] = Some(file);
+        }
+
+        info!("Rebuilt {rebuilt} fragments of {:?}", self.path);
+        Ok(rebuilt)
+    }
+
+    /// Returns the data units of a stripe concatenated, starting with the first one in
+    /// `wanted`. Lost units are reconstructed, which is logged as a degraded read.
+    fn stripe(&mut self, stripe: u64, wanted: Range<usize>) -> Result<Vec<u8>> {
+        let mut shards = self.shards(stripe, wanted.clone())?;
+
+        if wanted.clone().any(|index| shards[index].is_none()) {
+            let available = shards.iter().flatten().count();
+            warn!(
+                "Degraded read of stripe {stripe} of {:?}, reconstructing from {available} fragments",
+                self.path
+            );
+            self.codec
+                .reconstruct_data(&mut shards)
+                .map_err(|e| BackendError::new(&format!("{e:?}"), Action::Read))?;
+        }
+
+        Ok(shards[wanted].iter().flatten().flatten().copied().collect())
+    }
+
+    /// Reads the units `wanted` of a stripe. If any of them is lost, all other units
+    /// are read as well, so the result suffices to reconstruct the stripe.
+    fn shards(&mut self, stripe: u64, wanted: Range<usize>) -> Result<Shards> {
+        let mut shards: Shards = vec![None; self.files.len()];
+
+        let mut complete = true;
+        for index in wanted.clone() {
+            shards[index] = self.unit(index, stripe);
+            complete &= shards[index].is_some();
+        }
+        if complete {
+            return Ok(shards);
+        }
+
+        for index in (0..self.files.len()).filter(|index| !wanted.contains(index)) {
+            shards[index] = self.unit(index, stripe);
+        }
+        let available = shards.iter().flatten().count();
+        if available < self.header.data as usize {
+            return Err(BackendError::new(
+                &format!(
+                    "Only {available} fragments of stripe {stripe} of {:?} are readable, {} are needed",
+                    self.path, self.header.data
+                ),
+                Action::Read,
+            ));
+        }
+        Ok(shards)
+    }
+
+    fn unit(&mut self, index: usize, stripe: u64) -> Option<Vec<u8>> {
+        let mut unit = vec![0u8; self.header.unit as usize];
+        match self.files[index]
+            .as_ref()?
+            .read_exact_at(&mut unit, self.header.offset(stripe))
+        {
+            Ok(_) => Some(unit),
+            Err(e) => {
+                self.lose(index, &e);
+                None
+            }
+        }
+    }
+
+    fn lose(&mut self, index: usize, error: &io::Error) {
+        warn!(
+            "Fragment {index} of {:?} failed and is lost: {error}",
+            self.path
+        );
+        self.files[index] = None;
+    }
+
+    fn check_available(&self, action: Action) -> Result<()> {
+        let available = self.files.iter().flatten().count();
+        match available < self.header.data as usize {
+            true => Err(BackendError::new(
+                &format!(
+                    "Only {available} fragments of {:?} are left, {} are needed",
+                    self.path, self.header.data
+                ),
+                action,
+            )),
+            false => Ok(()),
+        }
+    }
+}
+
+fn create_fragment(path: &Path) -> io::Result<File> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(path)
+}
+
+fn open_fragment(path: &Path, index: usize) -> io::Result<(File, Header)> {
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    let header = Header::read(&file)?;
+    if header.index as usize != index {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Fragment has index {}", header.index),
+        ));
+    }
+    Ok((file, header))
+}
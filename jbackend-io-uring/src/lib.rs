@@ -22,6 +22,7 @@ pub unsafe extern "C" fn backend_info() -> *mut JBackend {
 #[cfg(test)]
 mod test {
     use io_backends::prelude::*;
+    use io_backends::test_facility::writes;
     use io_backends::testing::*;
 
     use crate::uring::UringObject;
@@ -31,12 +32,30 @@ mod test {
     fn test_uring_workflow() {
         let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
         let data_factory = |namespace| {
-            let data = Backend::<UringObject> {
+            let data = Backend::<CoalescingObject<EncryptedObject<ChecksummedObject<UringObject>>>> {
                 object_store: ObjectStore::new(),
                 namespace,
+                config: Config::default(),
+                pending: PendingLinks::new(),
             };
             Box::into_raw(Box::new(data)).cast::<gpointer>()
         };
         test_workflow(&backend, &data_factory);
     }
+
+    #[test]
+    fn repeated_writes() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory = |namespace| {
+            let data = Backend::<CoalescingObject<EncryptedObject<ChecksummedObject<UringObject>>>> {
+                object_store: ObjectStore::new(),
+                namespace,
+                config: Config::default(),
+                pending: PendingLinks::new(),
+            };
+            Box::into_raw(Box::new(data)).cast::<gpointer>()
+        };
+
+        writes::test_writes(&backend, data_factory)
+    }
 }
@@ -101,12 +101,20 @@ impl Uring {
 pub struct UringObject {
     file: File,
     fd: RawFd,
+    prealloc: Preallocator,
 }
 
 impl BackendObject for UringObject {
-    fn new(file: File) -> Result<Self> {
+    fn new(file: File, config: &Config) -> Result<Self> {
         let fd = file.as_raw_fd();
-        Ok(UringObject { file, fd })
+        let prealloc = Preallocator::new(&file, config)?;
+        Ok(UringObject { file, fd, prealloc })
+    }
+
+    fn create(file: File, config: &Config) -> Result<Self> {
+        let mut object = Self::new(file, config)?;
+        object.prealloc.on_create(&object.file)?;
+        Ok(object)
     }
 
     fn read(&self, buffer: &mut [u8], offset: u64, _length: u64) -> Result<u64> {
@@ -116,6 +124,9 @@ impl BackendObject for UringObject {
     }
 
     fn write(&mut self, buffer: &[u8], offset: u64, _length: u64) -> Result<u64> {
+        self.prealloc
+            .before_write(&self.file, offset, buffer.len() as u64)?;
+
         THREAD_URING
             .try_with(|ring| ring.borrow_mut().write(buffer, self.fd, offset))
             .map_err(|e| BackendError::map(&e, Action::Read))?
@@ -130,8 +141,12 @@ impl BackendObject for UringObject {
         let metadata = self.file.metadata()?;
         Ok((metadata.atime(), metadata.size() as _))
     }
+
+    fn close(&mut self) -> Result<()> {
+        self.prealloc.trim(&self.file)
+    }
 }
 
 pub struct Adapter {}
 
-impl JuleaAdapter<UringObject> for Adapter {}
+impl JuleaAdapter<CoalescingObject<EncryptedObject<ChecksummedObject<UringObject>>>> for Adapter {}
@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+
+/// Where a piece of object data lives in the log.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Extent {
+    pub segment: u32,
+    pub offset: u64,
+    pub len: u64,
+}
+
+/// Maps the logical byte ranges of an object to extents in the log. Ranges without an
+/// extent read as zeros.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExtentMap {
+    pub size: u64,
+    /// Non-overlapping extents by their logical start.
+    pub extents: BTreeMap<u64, Extent>,
+}
+
+impl ExtentMap {
+    /// Maps `start..start + extent.len` to `extent`. `dead` is called with the segment
+    /// and length of every piece of data that is no longer referenced.
+    pub fn insert(&mut self, start: u64, extent: Extent, mut dead: impl FnMut(u32, u64)) {
+        let end = start + extent.len;
+
+        let overlapping: Vec<u64> = self
+            .extents
+            .range(..end)
+            .rev()
+            .take_while(|(key, e)| *key + e.len > start)
+            .map(|(key, _)| *key)
+            .collect();
+
+        for key in overlapping {
+            let old = self.extents.remove(&key).unwrap();
+            let old_end = key + old.len;
+            dead(old.segment, old_end.min(end) - key.max(start));
+
+            if key < start {
+                self.extents.insert(
+                    key,
+                    Extent {
+                        len: start - key,
+                        ..old
+                    },
+                );
+            }
+            if old_end > end {
+                self.extents.insert(
+                    end,
+                    Extent {
+                        segment: old.segment,
+                        offset: old.offset + (end - key),
+                        len: old_end - end,
+                    },
+                );
+            }
+        }
+
+        self.extents.insert(start, extent);
+        self.size = self.size.max(end);
+    }
+
+    /// Returns the pieces of `start..end` that are backed by extents, as the offset
+    /// into the range and the part of the extent covering it.
+    pub fn lookup(&self, start: u64, end: u64) -> Vec<(u64, Extent)> {
+        let first = self
+            .extents
+            .range(..=start)
+            .next_back()
+            .map_or(start, |(key, _)| *key);
+
+        self.extents
+            .range(first..end)
+            .filter(|(key, e)| *key + e.len > start)
+            .map(|(key, e)| {
+                let from = start.max(*key);
+                let to = end.min(key + e.len);
+                (
+                    from - start,
+                    Extent {
+                        segment: e.segment,
+                        offset: e.offset + (from - key),
+                        len: to - from,
+                    },
+                )
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fs::{self, create_dir_all, File, OpenOptions},
+    os::unix::fs::MetadataExt,
+    path::Path,
+    sync::Arc,
+};
+
+use log::{error, info};
+
+use io_backends::prelude::*;
+
+use crate::segments::{LogStore, LOG_DIR, STORES};
+
+/// Size of the pieces objects are copied in.
+const COPY_CHUNK_SIZE: u64 = 1 << 20;
+
+/// An object whose data lives in the log of its backend. Its file in the namespace only
+/// provides the identity and the timestamps.
+pub struct LfsObject {
+    file: File,
+    ino: u64,
+    store: Arc<LogStore>,
+}
+
+impl BackendObject for LfsObject {
+    fn new(file: File, config: &Config) -> Result<Self> {
+        let ino = file.metadata()?.ino();
+        Ok(LfsObject {
+            file,
+            ino,
+            store: STORES.lookup(config)?,
+        })
+    }
+
+    fn create(file: File, config: &Config) -> Result<Self> {
+        // the inode may have belonged to an object deleted behind the backend's back
+        let object = Self::new(file, config)?;
+        object.store.reset(object.ino)?;
+        Ok(object)
+    }
+
+    fn read(&self, buffer: &mut [u8], offset: u64, _length: u64) -> Result<u64> {
+        self.store.read(self.ino, buffer, offset)
+    }
+
+    fn write(&mut self, buffer: &[u8], offset: u64, _length: u64) -> Result<u64> {
+        self.store.write(self.ino, buffer, offset)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.store.sync().map_err(|e| e.set_action(Action::Sync))
+    }
+
+    fn status(&self) -> Result<(i64, u64)> {
+        let metadata = self.file.metadata()?;
+        Ok((metadata.atime(), self.store.size(self.ino)?))
+    }
+}
+
+pub struct Adapter {}
+
+impl JuleaAdapter<LfsObject> for Adapter {
+    unsafe extern "C" fn j_fini(backend_data: gpointer) {
+        let backend_data = Box::from_raw(backend_data.cast::<Backend<LfsObject>>());
+        let store = match STORES.unregister(&backend_data.config) {
+            Ok(Some(store)) => store,
+            Err(e) => return error!("{e}"),
+            Ok(None) => return info!("Releasing backend"),
+        };
+
+        match store
+            .shutdown()
+            .and_then(|_| store.sync())
+            .and_then(|_| store.usage())
+        {
+            Ok((live, len)) => {
+                info!("Releasing backend, {live} b of live data in {len} b of segments")
+            }
+            Err(e) => error!("{}", e.set_action(Action::Fini)),
+        }
+    }
+
+    unsafe fn backend_init(path: *const gchar) -> Result<Backend<LfsObject>> {
+        let path = read_str(path).map_err(|e| e.set_action(Action::Init))?;
+        let (path, mut config) = Config::from_init_path(&path)?;
+        info!("Initializing log-structured backend in namespace {path}");
+
+        LogStore::check_config(&config)?;
+        create_dir_all(&path)?;
+        if config.get_bool("atomic_create")? {
+            sweep_hidden(Path::new(&path))?;
+        }
+
+        // backends in the same root share its log
+        let dir = fs::canonicalize(&path)?.join(LOG_DIR);
+        let dir = dir.to_str().ok_or(BackendError::new(
+            "Unable to convert log path to UTF-8",
+            Action::Init,
+        ))?;
+        let options = config.clone();
+        STORES.register(&mut config, dir, || {
+            let store = Arc::new(
+                LogStore::open(Path::new(&path), &options)
+                    .map_err(|e| e.set_action(Action::Init))?,
+            );
+            store.start_compactor(&options)?;
+            Ok(store)
+        })?;
+
+        Ok(Backend::new(path, config))
+    }
+
+    fn remove_object(backend_data: &Backend<LfsObject>, path: &Path) -> Result<()> {
+        let ino = fs::metadata(path)?.ino();
+        STORES.lookup(&backend_data.config)?.remove(ino)?;
+        fs::remove_file(path).map_err(|e| BackendError::map(&e, Action::Delete))
+    }
+
+    /// Copies the data through the log, the copy does not share extents with the original.
+    unsafe fn backend_copy(
+        backend_data: &Backend<LfsObject>,
+        src_namespace: *const gchar,
+        src_path: *const gchar,
+        dst_namespace: *const gchar,
+        dst_path: *const gchar,
+    ) -> Result<u64> {
+        let src = Self::build_path(backend_data, Vec::from([src_namespace, src_path]))?;
+        let dst = Self::build_path(backend_data, Vec::from([dst_namespace, dst_path]))?;
+
+        if let Some(dir) = dst.parent() {
+            create_dir_all(dir)?;
+        }
+
+        let store = STORES.lookup(&backend_data.config)?;
+        let src_ino = fs::metadata(&src)
+            .map_err(|e| BackendError::map(&e, Action::Copy))?
+            .ino();
+        let dst_ino = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&dst)
+            .map_err(|e| BackendError::map(&e, Action::Copy))?
+            .metadata()?
+            .ino();
+        store.reset(dst_ino)?;
+
+        let size = store.size(src_ino)?;
+        let mut buffer = vec![0u8; COPY_CHUNK_SIZE.min(size) as usize];
+        let mut copied = 0;
+        while copied < size {
+            let n = store.read(src_ino, &mut buffer, copied)?;
+            store.write(dst_ino, &buffer[..n as usize], copied)?;
+            copied += n;
+        }
+
+        Ok(copied)
+    }
+
+    fn is_internal(file_name: &str) -> bool {
+        file_name == LOG_DIR
+            || file_name.starts_with(TEMP_PREFIX)
+            || file_name.starts_with(CHECKSUM_PREFIX)
+    }
+}
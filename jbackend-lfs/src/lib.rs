@@ -0,0 +1,221 @@
+mod extents;
+mod lfs;
+mod segments;
+
+use io_backends::generate_backend;
+use io_backends::prelude::*;
+use log::debug;
+use log::info;
+
+generate_backend!(lfs);
+
+#[no_mangle]
+pub unsafe extern "C" fn backend_info() -> *mut JBackend {
+    match init_logger() {
+        Ok(_) => info!("logger initialized."),
+        Err(e) => {
+            let _ = println!("Error while initializing logger: {e:?}");
+        }
+    };
+    debug!("backend info called");
+    &mut BACKEND
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CString;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use io_backends::prelude::*;
+    use io_backends::test_facility::writes;
+    use io_backends::testing::*;
+
+    use crate::lfs::{Adapter, LfsObject};
+    use crate::segments::{LOG_DIR, STORES};
+    use crate::BACKEND;
+
+    fn init(root: &Path) -> Backend<LfsObject> {
+        let path = CString::new(format!(
+            "{}?lfs_segment=4K&lfs_compact=0",
+            root.to_str().unwrap()
+        ))
+        .unwrap();
+        unsafe { Adapter::backend_init(path.as_ptr()).unwrap() }
+    }
+
+    fn segment_files(root: &Path) -> usize {
+        fs::read_dir(root.join(LOG_DIR))
+            .unwrap()
+            .filter(|e| e.as_ref().unwrap().path().extension().is_some())
+            .count()
+    }
+
+    #[test]
+    fn repeated_writes() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory = |_namespace| {
+            Box::into_raw(Box::new(std::ptr::null_mut() as gpointer)).cast::<gpointer>()
+        };
+
+        writes::test_writes(&backend, data_factory)
+    }
+
+    #[test]
+    fn test_lfs_shared_log() {
+        let temp = setup();
+        let (a, b) = (init(&temp), init(&temp));
+        assert!(Arc::ptr_eq(
+            &STORES.lookup(&a.config).unwrap(),
+            &STORES.lookup(&b.config).unwrap()
+        ));
+
+        // the log stays open until the last backend in the root is released
+        unsafe { Adapter::j_fini(Box::into_raw(Box::new(a)).cast()) };
+        unsafe {
+            let handle =
+                Adapter::backend_create(&b, "ns\0".as_ptr().cast(), "obj\0".as_ptr().cast())
+                    .unwrap();
+            b.write(&handle, b"Hello", 0, 5).unwrap();
+            b.sync(&handle).unwrap();
+            let mut buffer = [0u8; 5];
+            assert_eq!(b.read(&handle, &mut buffer, 0, 5).unwrap(), 5);
+            assert_eq!(&buffer, b"Hello");
+        }
+        let config = b.config.clone();
+        unsafe { Adapter::j_fini(Box::into_raw(Box::new(b)).cast()) };
+        assert!(STORES.lookup(&config).is_err());
+
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_lfs_recovery() {
+        let temp = setup();
+        let backend = init(&temp);
+
+        unsafe {
+            let handle =
+                Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "obj\0".as_ptr().cast())
+                    .unwrap();
+            backend.write(&handle, &[b'a'; 4096], 0, 4096).unwrap();
+            backend.write(&handle, &[b'b'; 1000], 500, 1000).unwrap();
+            backend.sync(&handle).unwrap();
+
+            // lost in the crash
+            backend.write(&handle, &[b'c'; 100], 10_000, 100).unwrap();
+            backend.write(&handle, &[b'c'; 100], 0, 100).unwrap();
+            assert_eq!(backend.status(&handle).unwrap().1, 10_100);
+        }
+
+        // stop the old log without a checkpoint
+        let store = STORES.lookup(&backend.config).unwrap();
+        STORES.unregister(&backend.config).unwrap();
+        store.shutdown().unwrap();
+        drop(store);
+        drop(backend);
+
+        let backend = init(&temp);
+        unsafe {
+            let handle =
+                Adapter::backend_open(&backend, "ns\0".as_ptr().cast(), "obj\0".as_ptr().cast())
+                    .unwrap();
+            assert_eq!(backend.status(&handle).unwrap().1, 4096);
+
+            let mut buffer = vec![0u8; 5000];
+            assert_eq!(backend.read(&handle, &mut buffer, 0, 5000).unwrap(), 4096);
+            assert!(buffer[..500].iter().all(|b| *b == b'a'));
+            assert!(buffer[500..1500].iter().all(|b| *b == b'b'));
+            assert!(buffer[1500..4096].iter().all(|b| *b == b'a'));
+        }
+
+        let invalid = CString::new(format!("{}?lfs_segment=0", temp.to_str().unwrap())).unwrap();
+        assert!(unsafe { Adapter::backend_init(invalid.as_ptr()) }.is_err());
+
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_lfs_compaction() {
+        let temp = setup();
+        let backend = init(&temp);
+        let store = STORES.lookup(&backend.config).unwrap();
+
+        unsafe {
+            let keep =
+                Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "keep\0".as_ptr().cast())
+                    .unwrap();
+            let churn = Adapter::backend_create(
+                &backend,
+                "ns\0".as_ptr().cast(),
+                "churn\0".as_ptr().cast(),
+            )
+            .unwrap();
+
+            // every segment holds 1K of 'keep' and 3K of 'churn' that is overwritten later
+            for i in 0..8u8 {
+                backend
+                    .write(&keep, &[i; 1024], i as u64 * 1024, 1024)
+                    .unwrap();
+                backend.write(&churn, &[i; 3072], 0, 3072).unwrap();
+            }
+            backend.write(&churn, b"hole", 100_000, 4).unwrap();
+            backend.sync(&keep).unwrap();
+            assert_eq!(segment_files(&temp), 9);
+            let (live, len) = store.usage().unwrap();
+            assert_eq!(live, 8 * 1024 + 3072 + 4);
+            assert_eq!(len, 8 * 4096 + 4);
+
+            assert_eq!(store.compact().unwrap(), 7 * 3072);
+            let (live_after, len_after) = store.usage().unwrap();
+            assert_eq!(live_after, live);
+            assert!(len_after < len / 2);
+            assert!(segment_files(&temp) <= 4);
+
+            let mut buffer = vec![0u8; 8192];
+            assert_eq!(backend.read(&keep, &mut buffer, 0, 8192).unwrap(), 8192);
+            for i in 0..8u8 {
+                let at = i as usize * 1024;
+                assert!(buffer[at..at + 1024].iter().all(|b| *b == i));
+            }
+            assert_eq!(backend.status(&churn).unwrap().1, 100_004);
+            assert_eq!(
+                backend.read(&churn, &mut buffer[..4096], 0, 4096).unwrap(),
+                4096
+            );
+            assert!(buffer[..3072].iter().all(|b| *b == 7));
+            assert!(buffer[3072..4096].iter().all(|b| *b == 0));
+
+            // the copy has its own extents, deleting the original frees its data
+            Adapter::backend_copy(
+                &backend,
+                "ns\0".as_ptr().cast(),
+                "keep\0".as_ptr().cast(),
+                "other\0".as_ptr().cast(),
+                "copy\0".as_ptr().cast(),
+            )
+            .unwrap();
+            Adapter::backend_delete(&backend, &keep).unwrap();
+            Adapter::backend_delete(&backend, &churn).unwrap();
+            assert_eq!(store.usage().unwrap().0, 8192);
+
+            let copy = Adapter::backend_open(
+                &backend,
+                "other\0".as_ptr().cast(),
+                "copy\0".as_ptr().cast(),
+            )
+            .unwrap();
+            assert_eq!(backend.read(&copy, &mut buffer, 0, 8192).unwrap(), 8192);
+            assert!(buffer[7 * 1024..].iter().all(|b| *b == 7));
+
+            let mut iter =
+                Adapter::backend_get_iterator(&backend, "\0".as_ptr().cast(), None).unwrap();
+            while let Some(name) = Adapter::backend_iterate(&mut iter).unwrap() {
+                assert_ne!(name.to_str().unwrap(), LOG_DIR);
+            }
+        }
+
+        shutdown(temp);
+    }
+}
@@ -0,0 +1,705 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    os::unix::fs::{FileExt, MetadataExt},
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, RwLock, Weak},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use log::{debug, error, info, trace, warn};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use io_backends::prelude::*;
+
+use crate::extents::{Extent, ExtentMap};
+
+/// Directory below the backend root holding the segments and the checkpoint.
+pub const LOG_DIR: &str = ".jlog";
+const CHECKPOINT: &str = "checkpoint";
+
+const MAGIC: &[u8; 4] = b"JLFS";
+const VERSION: u32 = 1;
+/// magic, version, objects
+const HEADER_LEN: usize = 16;
+/// logical offset, segment, offset, length
+const EXTENT_LEN: usize = 28;
+
+const DEFAULT_SEGMENT_SIZE: u64 = 64 << 20;
+/// Sealed segments in which less than this share of the data is still referenced are
+/// compacted.
+const COMPACT_LIVE_RATIO: f64 = 0.5;
+const DEFAULT_COMPACT_INTERVAL: u64 = 5;
+
+/// Logs of all initialized backends by their directory, recorded in `lfs_dir`.
+pub static STORES: Registry<LogStore> = Registry::new("lfs_dir");
+
+struct Segment {
+    file: File,
+    /// Bytes appended so far.
+    len: u64,
+    /// Bytes still referenced by an extent map.
+    live: u64,
+}
+
+struct State {
+    /// Extent maps by the inode of the object's file.
+    objects: FxHashMap<u64, ExtentMap>,
+    segments: BTreeMap<u32, Segment>,
+    head: u32,
+    /// Segments appended to since the last checkpoint.
+    unsynced: FxHashSet<u32>,
+}
+
+impl State {
+    fn segment(&self, id: u32) -> Result<&Segment> {
+        self.segments
+            .get(&id)
+            .ok_or(BackendError::new_internal(&format!(
+                "Segment {id} is missing"
+            )))
+    }
+
+    fn release(segments: &mut BTreeMap<u32, Segment>, id: u32, len: u64) {
+        if let Some(segment) = segments.get_mut(&id) {
+            segment.live -= len;
+        }
+    }
+
+    /// Adds an empty segment behind all others.
+    fn add_segment(&mut self, dir: &Path) -> Result<u32> {
+        let id = self.segments.keys().next_back().map_or(0, |id| id + 1);
+        self.segments.insert(
+            id,
+            Segment {
+                file: create_segment(dir, id)?,
+                len: 0,
+                live: 0,
+            },
+        );
+        Ok(id)
+    }
+}
+
+/// Tells the compactor thread to stop. The thread only holds this while it waits, so
+/// that the store can be dropped in the meantime.
+#[derive(Default)]
+struct Stop {
+    stopped: Mutex<bool>,
+    wake: Condvar,
+}
+
+/// A log of segment files shared by all objects of a backend, `<root>/.jlog/<n>.seg`.
+///
+/// Every write is appended to the head segment, and the extent map of its object is
+/// updated to point there. All extent maps are checkpointed together on sync, after
+/// the segments were synced, so a crash loses nothing that was synced. Objects are
+/// identified by the inode of their file in the namespace, which otherwise stays empty.
+/// A background thread compacts sealed segments that mostly hold overwritten data.
+pub struct LogStore {
+    dir: PathBuf,
+    segment_size: u64,
+    state: RwLock<State>,
+    /// Held for the whole of a compaction, so that only one runs at a time.
+    compacting: Mutex<()>,
+    /// Held from taking a checkpoint until it is written, so that checkpoints are
+    /// written in the order they were taken. Locked before the state.
+    checkpointing: Mutex<()>,
+    stop: Arc<Stop>,
+    compactor: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// The extent maps of all objects as of one point in time, with the segments that
+/// have to be synced before they may be written.
+struct Checkpoint {
+    unsynced: Vec<(u32, File)>,
+    raw: Vec<u8>,
+    objects: usize,
+}
+
+/// Returns the segment size set by `lfs_segment`.
+fn segment_size(config: &Config) -> Result<u64> {
+    match config.get_size("lfs_segment")? {
+        Some(0) => Err(BackendError::new(
+            "Backend option 'lfs_segment' may not be 0",
+            Action::Init,
+        )),
+        Some(size) => Ok(size),
+        None => Ok(DEFAULT_SEGMENT_SIZE),
+    }
+}
+
+/// Returns the compactor interval in seconds set by `lfs_compact`.
+fn compact_interval(config: &Config) -> Result<u64> {
+    match config.get("lfs_compact") {
+        Some(v) => v.parse::<u64>().map_err(|_| {
+            BackendError::new(
+                &format!("Invalid value '{v}' for backend option 'lfs_compact'"),
+                Action::Init,
+            )
+        }),
+        None => Ok(DEFAULT_COMPACT_INTERVAL),
+    }
+}
+
+impl LogStore {
+    /// Checks the options of the log in `config`, also for backends that share the log
+    /// of another one and whose options therefore go unused.
+    pub fn check_config(config: &Config) -> Result<()> {
+        segment_size(config)?;
+        compact_interval(config)?;
+        Ok(())
+    }
+
+    /// Opens the log below `root`, keeping the extent maps of the objects that still exist.
+    pub fn open(root: &Path, config: &Config) -> Result<Self> {
+        let dir = root.join(LOG_DIR);
+        fs::create_dir_all(&dir)?;
+        let segment_size = segment_size(config)?;
+
+        let mut inodes = FxHashSet::default();
+        collect_inodes(root, &mut inodes)?;
+
+        let mut objects = load_checkpoint(&dir.join(CHECKPOINT))?;
+        let stale = objects.len();
+        objects.retain(|ino, _| inodes.contains(ino));
+        if stale > objects.len() {
+            debug!("dropped {} maps of removed objects", stale - objects.len());
+        }
+
+        let mut live: FxHashMap<u32, u64> = FxHashMap::default();
+        for extent in objects.values().flat_map(|map| map.extents.values()) {
+            *live.entry(extent.segment).or_default() += extent.len;
+        }
+
+        let mut segments = BTreeMap::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let id = match path.extension().and_then(|e| e.to_str()) {
+                Some("seg") => path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .and_then(|s| s.parse::<u32>().ok()),
+                _ => None,
+            };
+
+            match id.map(|id| (id, live.remove(&id))) {
+                Some((id, Some(live))) => {
+                    let file = OpenOptions::new().read(true).write(true).open(&path)?;
+                    let len = file.metadata()?.len();
+                    segments.insert(id, Segment { file, len, live });
+                }
+                Some((_, None)) => {
+                    debug!("removing unreferenced segment {path:?}");
+                    fs::remove_file(&path)?;
+                }
+                None => (),
+            }
+        }
+        if !live.is_empty() {
+            warn!("{} segments referenced by objects are missing", live.len());
+        }
+
+        // writes never go to a segment that may end in data that was not synced
+        let head = segments.keys().next_back().map_or(0, |id| id + 1);
+        segments.insert(
+            head,
+            Segment {
+                file: create_segment(&dir, head)?,
+                len: 0,
+                live: 0,
+            },
+        );
+        info!(
+            "opened log {dir:?} with {} objects in {} segments",
+            objects.len(),
+            segments.len()
+        );
+
+        Ok(LogStore {
+            dir,
+            segment_size,
+            state: RwLock::new(State {
+                objects,
+                segments,
+                head,
+                unsynced: FxHashSet::default(),
+            }),
+            compacting: Mutex::new(()),
+            checkpointing: Mutex::new(()),
+            stop: Arc::new(Stop::default()),
+            compactor: Mutex::new(None),
+        })
+    }
+
+    fn read_state(&self) -> Result<std::sync::RwLockReadGuard<'_, State>> {
+        self.state
+            .read()
+            .map_err(|e| BackendError::map(&e, Action::Internal))
+    }
+
+    fn write_state(&self) -> Result<std::sync::RwLockWriteGuard<'_, State>> {
+        self.state
+            .write()
+            .map_err(|e| BackendError::map(&e, Action::Internal))
+    }
+
+    /// Appends `data` to the head segment, starting a new one once it is full.
+    fn append(&self, state: &mut State, data: &[u8]) -> Result<(u32, u64)> {
+        let head = state.segment(state.head)?;
+        if head.len > 0 && head.len + data.len() as u64 > self.segment_size {
+            let id = state.add_segment(&self.dir)?;
+            trace!("sealing segment {}, starting {id}", state.head);
+            state.head = id;
+        }
+
+        let id = state.head;
+        let head = state.segments.get_mut(&id).unwrap();
+        let offset = head.len;
+        head.file
+            .write_all_at(data, offset)
+            .map_err(|e| BackendError::map(&e, Action::Write))?;
+        head.len += data.len() as u64;
+        head.live += data.len() as u64;
+        state.unsynced.insert(id);
+
+        Ok((id, offset))
+    }
+
+    /// Starts an empty extent map for a new object.
+    pub fn reset(&self, ino: u64) -> Result<()> {
+        let mut state = self.write_state()?;
+        if let Some(old) = state.objects.insert(ino, ExtentMap::default()) {
+            for extent in old.extents.values() {
+                State::release(&mut state.segments, extent.segment, extent.len);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove(&self, ino: u64) -> Result<()> {
+        let mut state = self.write_state()?;
+        if let Some(old) = state.objects.remove(&ino) {
+            for extent in old.extents.values() {
+                State::release(&mut state.segments, extent.segment, extent.len);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn size(&self, ino: u64) -> Result<u64> {
+        Ok(self
+            .read_state()?
+            .objects
+            .get(&ino)
+            .map_or(0, |map| map.size))
+    }
+
+    pub fn read(&self, ino: u64, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        let state = self.read_state()?;
+        let map = match state.objects.get(&ino) {
+            Some(map) => map,
+            None => return Ok(0),
+        };
+
+        let end = map.size.min(offset + buffer.len() as u64);
+        if offset >= end {
+            return Ok(0);
+        }
+        let n = (end - offset) as usize;
+        buffer[..n].fill(0);
+
+        for (at, extent) in map.lookup(offset, end) {
+            let at = at as usize;
+            state
+                .segment(extent.segment)?
+                .file
+                .read_exact_at(&mut buffer[at..at + extent.len as usize], extent.offset)
+                .map_err(|e| BackendError::map(&e, Action::Read))?;
+        }
+        Ok(n as u64)
+    }
+
+    pub fn write(&self, ino: u64, buffer: &[u8], offset: u64) -> Result<u64> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        let mut state = self.write_state()?;
+        let (segment, at) = self.append(&mut state, buffer)?;
+
+        let State {
+            objects, segments, ..
+        } = &mut *state;
+        objects.entry(ino).or_default().insert(
+            offset,
+            Extent {
+                segment,
+                offset: at,
+                len: buffer.len() as u64,
+            },
+            |id, len| State::release(segments, id, len),
+        );
+        Ok(buffer.len() as u64)
+    }
+
+    /// Syncs all segments written to and checkpoints the extent maps of all objects.
+    pub fn sync(&self) -> Result<()> {
+        let _checkpointing = self
+            .checkpointing
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+
+        let checkpoint = self.take_checkpoint(&mut *self.write_state()?)?;
+        self.write_checkpoint(checkpoint)
+    }
+
+    /// Serializes the extent maps. Only this runs under the state lock, the syncs and
+    /// writes are left to `write_checkpoint`.
+    fn take_checkpoint(&self, state: &mut State) -> Result<Checkpoint> {
+        let mut unsynced = Vec::with_capacity(state.unsynced.len());
+        for id in &state.unsynced {
+            if let Some(segment) = state.segments.get(id) {
+                unsynced.push((*id, segment.file.try_clone()?));
+            }
+        }
+        state.unsynced.clear();
+
+        let mut raw = Vec::with_capacity(HEADER_LEN);
+        raw.extend_from_slice(MAGIC);
+        raw.extend_from_slice(&VERSION.to_le_bytes());
+        raw.extend_from_slice(&(state.objects.len() as u64).to_le_bytes());
+        for (ino, map) in &state.objects {
+            raw.extend_from_slice(&ino.to_le_bytes());
+            raw.extend_from_slice(&map.size.to_le_bytes());
+            raw.extend_from_slice(&(map.extents.len() as u64).to_le_bytes());
+            for (start, extent) in &map.extents {
+                raw.extend_from_slice(&start.to_le_bytes());
+                raw.extend_from_slice(&extent.segment.to_le_bytes());
+                raw.extend_from_slice(&extent.offset.to_le_bytes());
+                raw.extend_from_slice(&extent.len.to_le_bytes());
+            }
+        }
+
+        Ok(Checkpoint {
+            unsynced,
+            raw,
+            objects: state.objects.len(),
+        })
+    }
+
+    /// Syncs the segments of `checkpoint` and then writes it. Must be called with the
+    /// checkpointing lock held since `take_checkpoint`.
+    fn write_checkpoint(&self, checkpoint: Checkpoint) -> Result<()> {
+        let synced = checkpoint
+            .unsynced
+            .iter()
+            .try_for_each(|(_, file)| file.sync_data())
+            .map_err(|e| BackendError::map(&e, Action::Sync));
+        if let Err(e) = synced {
+            // the next checkpoint has to sync them again
+            let ids = checkpoint.unsynced.iter().map(|(id, _)| *id);
+            self.write_state()?.unsynced.extend(ids);
+            return Err(e);
+        }
+
+        // the previous checkpoint stays intact until the new one is complete
+        let temp = self.dir.join(format!("{TEMP_PREFIX}{CHECKPOINT}"));
+        let file = File::create(&temp)?;
+        file.write_all_at(&checkpoint.raw, 0)?;
+        file.sync_data()?;
+        fs::rename(&temp, self.dir.join(CHECKPOINT))?;
+        File::open(&self.dir)?.sync_all()?;
+
+        trace!(
+            "checkpointed {} objects, {} b",
+            checkpoint.objects,
+            checkpoint.raw.len()
+        );
+        Ok(())
+    }
+
+    /// Moves the live data out of sealed segments that are mostly garbage and removes
+    /// them. Returns the number of bytes reclaimed.
+    ///
+    /// The live data is copied into a new segment without holding the state lock, so
+    /// reads and writes go on meanwhile. Only extents that did not change during the
+    /// copy are then pointed to the new segment, segments that still hold live data
+    /// after that are left for the next compaction.
+    pub fn compact(&self) -> Result<u64> {
+        let _compacting = self
+            .compacting
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+
+        let (victims, moving, target, output) = {
+            let mut state = self.write_state()?;
+            let mut victims = FxHashMap::default();
+            for (id, segment) in &state.segments {
+                if *id != state.head
+                    && (segment.live as f64) < segment.len as f64 * COMPACT_LIVE_RATIO
+                {
+                    victims.insert(*id, segment.file.try_clone()?);
+                }
+            }
+            if victims.is_empty() {
+                return Ok(0);
+            }
+
+            let moving: Vec<(u64, u64, Extent)> = state
+                .objects
+                .iter()
+                .flat_map(|(ino, map)| {
+                    map.extents
+                        .iter()
+                        .filter(|(_, e)| victims.contains_key(&e.segment))
+                        .map(|(start, e)| (*ino, *start, *e))
+                })
+                .collect();
+
+            let target = state.add_segment(&self.dir)?;
+            let output = state.segment(target)?.file.try_clone()?;
+            (victims, moving, target, output)
+        };
+
+        let copied = copy_extents(&victims, &moving, target, &output);
+        let _checkpointing = self
+            .checkpointing
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+        let mut state = self.write_state()?;
+        let moved = match copied {
+            Ok(moved) => moved,
+            Err(e) => {
+                state.segments.remove(&target);
+                fs::remove_file(segment_path(&self.dir, target))?;
+                return Err(e);
+            }
+        };
+
+        let State {
+            objects, segments, ..
+        } = &mut *state;
+        let mut len = 0;
+        for ((ino, start, old), new) in moving.iter().zip(moved) {
+            len = new.offset + new.len;
+            match objects
+                .get_mut(ino)
+                .and_then(|map| map.extents.get_mut(start))
+            {
+                Some(extent) if extent == old => *extent = new,
+                _ => continue,
+            }
+            State::release(segments, old.segment, old.len);
+            segments.get_mut(&target).unwrap().live += old.len;
+        }
+        segments.get_mut(&target).unwrap().len = len;
+
+        // sealed segments never gain live data again, so these stay unreferenced
+        let mut unused: Vec<u32> = victims
+            .keys()
+            .filter(|id| segments.get(id).is_some_and(|s| s.live == 0))
+            .copied()
+            .collect();
+        if len == 0 {
+            unused.push(target);
+        }
+        let checkpoint = self.take_checkpoint(&mut state)?;
+        drop(state);
+
+        // the old segments are referenced until the new checkpoint is written
+        self.write_checkpoint(checkpoint)?;
+        let mut state = self.write_state()?;
+        let mut reclaimed = 0;
+        for id in unused {
+            if let Some(segment) = state.segments.remove(&id) {
+                reclaimed += segment.len;
+                fs::remove_file(segment_path(&self.dir, id))?;
+            }
+        }
+
+        let reclaimed = reclaimed.saturating_sub(len);
+        debug!("compaction reclaimed {reclaimed} b");
+        Ok(reclaimed)
+    }
+
+    /// Returns the bytes still referenced and the bytes held by all segments.
+    pub fn usage(&self) -> Result<(u64, u64)> {
+        let state = self.read_state()?;
+        Ok(state
+            .segments
+            .values()
+            .fold((0, 0), |(live, len), s| (live + s.live, len + s.len)))
+    }
+
+    /// Runs the compactor every `lfs_compact` seconds until `shutdown` is called.
+    /// A value of 0 disables it.
+    pub fn start_compactor(self: &Arc<Self>, config: &Config) -> Result<()> {
+        let interval = compact_interval(config)?;
+        if interval == 0 {
+            return Ok(());
+        }
+
+        let interval = Duration::from_secs(interval);
+        let store: Weak<LogStore> = Arc::downgrade(self);
+        let stop = self.stop.clone();
+        let handle = thread::Builder::new()
+            .name(String::from("lfs-compactor"))
+            .spawn(move || loop {
+                let stopped = match stop.stopped.lock() {
+                    Ok(stopped) => stopped,
+                    Err(_) => return,
+                };
+                match stop.wake.wait_timeout(stopped, interval) {
+                    Ok((stopped, _)) if !*stopped => (),
+                    _ => return,
+                }
+                let store = match store.upgrade() {
+                    Some(store) => store,
+                    None => return,
+                };
+                if let Err(e) = store.compact() {
+                    error!("{e}");
+                }
+            })?;
+
+        *self
+            .compactor
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Init))? = Some(handle);
+        Ok(())
+    }
+
+    /// Stops the compactor.
+    pub fn shutdown(&self) -> Result<()> {
+        *self
+            .stop
+            .stopped
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Fini))? = true;
+        self.stop.wake.notify_all();
+
+        let handle = self
+            .compactor
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Fini))?
+            .take();
+        if let Some(handle) = handle {
+            let _ = handle.join();
+        }
+        Ok(())
+    }
+}
+
+/// Copies the data of `extents` from the `victims` to the start of the `target`
+/// segment in `output` and syncs it. Returns where each of them was copied to.
+fn copy_extents(
+    victims: &FxHashMap<u32, File>,
+    extents: &[(u64, u64, Extent)],
+    target: u32,
+    output: &File,
+) -> Result<Vec<Extent>> {
+    let mut moved = Vec::with_capacity(extents.len());
+    let mut offset = 0;
+    for (_, _, extent) in extents {
+        let mut data = vec![0u8; extent.len as usize];
+        victims[&extent.segment].read_exact_at(&mut data, extent.offset)?;
+        output
+            .write_all_at(&data, offset)
+            .map_err(|e| BackendError::map(&e, Action::Write))?;
+        moved.push(Extent {
+            segment: target,
+            offset,
+            len: extent.len,
+        });
+        offset += extent.len;
+    }
+    output
+        .sync_data()
+        .map_err(|e| BackendError::map(&e, Action::Sync))?;
+    Ok(moved)
+}
+
+fn segment_path(dir: &Path, id: u32) -> PathBuf {
+    dir.join(format!("{id:08}.seg"))
+}
+
+fn create_segment(dir: &Path, id: u32) -> Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(segment_path(dir, id))
+        .map_err(|e| BackendError::map(&e, Action::Write))
+}
+
+fn load_checkpoint(path: &Path) -> Result<FxHashMap<u64, ExtentMap>> {
+    let raw = match fs::read(path) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(FxHashMap::default()),
+        Err(e) => return Err(BackendError::map(&e, Action::Init)),
+    };
+    let invalid =
+        || BackendError::new(&format!("{path:?} is not a valid checkpoint"), Action::Init);
+
+    if raw.len() < HEADER_LEN || &raw[0..4] != MAGIC || raw[4..8] != VERSION.to_le_bytes() {
+        return Err(invalid());
+    }
+    let u64_at = |at: usize| -> Result<u64> {
+        raw.get(at..at + 8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+            .ok_or_else(invalid)
+    };
+
+    let mut objects = FxHashMap::default();
+    let mut at = HEADER_LEN;
+    for _ in 0..u64_at(8)? {
+        let ino = u64_at(at)?;
+        let mut map = ExtentMap {
+            size: u64_at(at + 8)?,
+            ..Default::default()
+        };
+        let count = u64_at(at + 16)? as usize;
+        at += 24;
+
+        for _ in 0..count {
+            let segment = raw
+                .get(at + 8..at + 12)
+                .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+                .ok_or_else(invalid)?;
+            map.extents.insert(
+                u64_at(at)?,
+                Extent {
+                    segment,
+                    offset: u64_at(at + 12)?,
+                    len: u64_at(at + 20)?,
+                },
+            );
+            at += EXTENT_LEN;
+        }
+        objects.insert(ino, map);
+    }
+    Ok(objects)
+}
+
+/// Collects the inodes of all objects below `dir`.
+fn collect_inodes(dir: &Path, inodes: &mut FxHashSet<u64>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name == LOG_DIR || name.starts_with(TEMP_PREFIX) || name.starts_with(CHECKSUM_PREFIX) {
+            continue;
+        }
+
+        let metadata = entry.metadata()?;
+        match metadata.is_dir() {
+            true => collect_inodes(&entry.path(), inodes)?,
+            false => {
+                inodes.insert(metadata.ino());
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,187 @@
+mod memory;
+mod store;
+
+use io_backends::generate_backend;
+use io_backends::prelude::*;
+use log::debug;
+use log::info;
+
+generate_backend!(memory);
+
+#[no_mangle]
+pub unsafe extern "C" fn backend_info() -> *mut JBackend {
+    match init_logger() {
+        Ok(_) => info!("logger initialized."),
+        Err(e) => {
+            let _ = println!("Error while initializing logger: {e:?}");
+        }
+    };
+    debug!("backend info called");
+    &mut BACKEND
+}
+
+#[cfg(test)]
+mod test {
+    use std::ptr;
+
+    use io_backends::prelude::*;
+    use io_backends::test_facility::writes;
+    use io_backends::testing;
+
+    use crate::memory::Adapter;
+    use crate::store::{MemoryStore, PAGE_SIZE};
+    use crate::BACKEND;
+
+    #[test]
+    fn repeated_writes() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory =
+            |_namespace| Box::into_raw(Box::new(ptr::null_mut() as gpointer)).cast::<gpointer>();
+
+        writes::test_writes(&backend, data_factory)
+    }
+
+    #[test]
+    fn test_memory_sparse() {
+        let store = MemoryStore::new(Some(2 * PAGE_SIZE as u64));
+        let shared = store.create("ns", "sparse").unwrap();
+        let mut object = shared.write().unwrap();
+
+        object.write(b"Hello", PAGE_SIZE as u64 * 100 - 2).unwrap();
+        assert_eq!(store.usage().used(), 2 * PAGE_SIZE as u64);
+        assert_eq!(object.status().1, PAGE_SIZE as u64 * 100 + 3);
+
+        let mut buffer = [1u8; 8];
+        assert_eq!(object.read(&mut buffer, PAGE_SIZE as u64 * 100 - 5), 8);
+        assert_eq!(&buffer, b"\0\0\0Hello");
+        assert_eq!(object.read(&mut buffer, 10), 8);
+        assert_eq!(buffer, [0u8; 8]);
+        assert_eq!(object.read(&mut buffer, PAGE_SIZE as u64 * 100 + 1), 2);
+
+        // the cap is reached, a write into a new page fails and changes nothing
+        let err = object.write(b"x", 0).unwrap_err();
+        assert!(err.to_string().contains("No space left on device"));
+        assert_eq!(store.usage().used(), 2 * PAGE_SIZE as u64);
+        assert_eq!(object.read(&mut buffer, 0), 8);
+        assert_eq!(buffer, [0u8; 8]);
+
+        // existing pages can still be overwritten
+        object.write(b"J", PAGE_SIZE as u64 * 100).unwrap();
+
+        drop(object);
+        store.remove("ns", "sparse", &shared).unwrap();
+        assert_eq!(store.usage().used(), 2 * PAGE_SIZE as u64);
+        drop(shared);
+        assert_eq!(store.usage().used(), 0);
+    }
+
+    #[test]
+    fn test_memory_namespaces() {
+        let mut config = Config::default();
+        config.set("memory_cap", "1M");
+        let backend = NamespaceData::new(MemoryStore::init("", &config).unwrap());
+        let backend_data = &backend as *const _ as gpointer;
+        let object = unsafe { BACKEND.anon1.object };
+        let names =
+            |namespace, prefix| unsafe { testing::names(&object, backend_data, namespace, prefix) };
+
+        unsafe {
+            for name in ["b-2\0", "a-1\0", "b-1\0"] {
+                let handle = Adapter::backend_create(
+                    &backend,
+                    "one\0".as_ptr().cast(),
+                    name.as_ptr().cast(),
+                )
+                .unwrap();
+                assert_eq!(
+                    Adapter::j_close(backend_data, &handle as *const _ as gpointer),
+                    TRUE
+                );
+            }
+            let handle =
+                Adapter::backend_create(&backend, "two\0".as_ptr().cast(), "a-1\0".as_ptr().cast())
+                    .unwrap();
+            let exists =
+                Adapter::backend_create(&backend, "two\0".as_ptr().cast(), "a-1\0".as_ptr().cast());
+            assert!(exists.is_err());
+
+            let mut written = 0;
+            let ret = Adapter::j_write(
+                backend_data,
+                &handle as *const _ as gpointer,
+                "Hello, world!".as_ptr().cast(),
+                13,
+                0,
+                &mut written,
+            );
+            assert_eq!((ret, written), (TRUE, 13));
+
+            assert_eq!(
+                Adapter::j_copy(
+                    backend_data,
+                    "two\0".as_ptr().cast(),
+                    "a-1\0".as_ptr().cast(),
+                    "one\0".as_ptr().cast(),
+                    "c-1\0".as_ptr().cast(),
+                ),
+                TRUE
+            );
+
+            assert_eq!(names("one", None), ["a-1", "b-1", "b-2", "c-1"]);
+            assert_eq!(names("one", Some("b-")), ["b-1", "b-2"]);
+            assert_eq!(names("two", None), ["a-1"]);
+            assert!(names("three", None).is_empty());
+
+            let copy =
+                Adapter::backend_open(&backend, "one\0".as_ptr().cast(), "c-1\0".as_ptr().cast())
+                    .unwrap();
+            let (mut modified, mut size) = (0, 0);
+            let ret = Adapter::j_status(
+                backend_data,
+                &copy as *const _ as gpointer,
+                &mut modified,
+                &mut size,
+            );
+            assert_eq!((ret, size), (TRUE, 13));
+            assert!(modified > 0);
+
+            // a deleted object stays readable through handles that are still open
+            assert_eq!(
+                Adapter::j_delete(backend_data, &handle as *const _ as gpointer),
+                TRUE
+            );
+            assert!(names("two", None).is_empty());
+            let other =
+                Adapter::backend_open(&backend, "two\0".as_ptr().cast(), "a-1\0".as_ptr().cast());
+            assert!(other.is_err());
+
+            // deleting through a stale handle leaves an object created under the same name
+            let new =
+                Adapter::backend_create(&backend, "two\0".as_ptr().cast(), "a-1\0".as_ptr().cast())
+                    .unwrap();
+            assert_eq!(
+                Adapter::j_delete(backend_data, &handle as *const _ as gpointer),
+                FALSE
+            );
+            assert_eq!(names("two", None), ["a-1"]);
+            assert_eq!(
+                Adapter::j_delete(backend_data, &new as *const _ as gpointer),
+                TRUE
+            );
+
+            let mut buffer = [0u8; 16];
+            let mut read = 0;
+            let ret = Adapter::j_read(
+                backend_data,
+                &copy as *const _ as gpointer,
+                buffer.as_mut_ptr().cast(),
+                16,
+                0,
+                &mut read,
+            );
+            assert_eq!((ret, read), (TRUE, 13));
+            assert_eq!(&buffer[..13], b"Hello, world!");
+            assert_eq!(backend.usage().used(), PAGE_SIZE as u64);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use log::info;
+
+use io_backends::prelude::*;
+
+use crate::store::{MemoryStore, SharedObject};
+
+/// An open object, with its name so that it can be deleted.
+pub struct MemoryHandle {
+    namespace: String,
+    name: String,
+    object: SharedObject,
+}
+
+impl NamespaceBackend for MemoryStore {
+    type Object = MemoryHandle;
+
+    fn init(_path: &str, config: &Config) -> Result<Self> {
+        Ok(MemoryStore::new(config.get_size("memory_cap")?))
+    }
+
+    fn fini(&self) -> Result<()> {
+        info!("Releasing {} b of object data", self.usage().used());
+        Ok(())
+    }
+
+    fn create(&self, namespace: &str, name: &str) -> Result<MemoryHandle> {
+        Ok(MemoryHandle {
+            namespace: String::from(namespace),
+            name: String::from(name),
+            object: MemoryStore::create(self, namespace, name)?,
+        })
+    }
+
+    fn open(&self, namespace: &str, name: &str) -> Result<MemoryHandle> {
+        Ok(MemoryHandle {
+            namespace: String::from(namespace),
+            name: String::from(name),
+            object: MemoryStore::open(self, namespace, name)?,
+        })
+    }
+
+    fn delete(&self, handle: &MemoryHandle) -> Result<()> {
+        self.remove(&handle.namespace, &handle.name, &handle.object)
+    }
+
+    fn status(&self, handle: &MemoryHandle) -> Result<(i64, u64)> {
+        Ok(handle
+            .object
+            .read()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?
+            .status())
+    }
+
+    /// There is nothing to persist.
+    fn sync(&self, _handle: &MemoryHandle) -> Result<()> {
+        Ok(())
+    }
+
+    fn read(&self, handle: &MemoryHandle, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        Ok(handle
+            .object
+            .read()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?
+            .read(buffer, offset))
+    }
+
+    fn write(&self, handle: &MemoryHandle, buffer: &[u8], offset: u64) -> Result<u64> {
+        handle
+            .object
+            .write()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?
+            .write(buffer, offset)
+    }
+
+    fn copy(
+        &self,
+        src_namespace: &str,
+        src_name: &str,
+        dst_namespace: &str,
+        dst_name: &str,
+    ) -> Result<()> {
+        MemoryStore::copy(self, src_namespace, src_name, dst_namespace, dst_name).map(|_| ())
+    }
+
+    fn names(&self, namespace: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+        MemoryStore::names(self, namespace, prefix)
+    }
+}
+
+pub struct Adapter {}
+
+impl NamespaceAdapter<MemoryStore> for Adapter {}
@@ -0,0 +1,273 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, ErrorKind},
+    ops::Bound,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rustc_hash::FxHashMap;
+
+use io_backends::prelude::*;
+
+pub const PAGE_SIZE: usize = 4096;
+
+/// Page memory held by all objects of a backend, limited by the `memory_cap` option.
+pub struct Usage {
+    used: AtomicU64,
+    cap: Option<u64>,
+}
+
+impl Usage {
+    fn reserve(&self, n: u64) -> Result<()> {
+        self.used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| match self.cap {
+                Some(cap) if used + n > cap => None,
+                _ => Some(used + n),
+            })
+            .map(|_| ())
+            .map_err(|_| {
+                BackendError::map(&io::Error::from_raw_os_error(libc::ENOSPC), Action::Write)
+            })
+    }
+
+    fn release(&self, n: u64) {
+        self.used.fetch_sub(n, Ordering::SeqCst);
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::SeqCst)
+    }
+}
+
+/// A sparse object. Pages are only allocated once they are written, holes read as zeros.
+pub struct MemoryObject {
+    pages: BTreeMap<u64, Box<[u8]>>,
+    size: u64,
+    modified: i64,
+    usage: Arc<Usage>,
+}
+
+impl MemoryObject {
+    fn new(usage: Arc<Usage>) -> Self {
+        MemoryObject {
+            pages: BTreeMap::new(),
+            size: 0,
+            modified: now(),
+            usage,
+        }
+    }
+
+    pub fn read(&self, buffer: &mut [u8], offset: u64) -> u64 {
+        let length = match self.size.checked_sub(offset) {
+            Some(available) => available.min(buffer.len() as u64) as usize,
+            None => return 0,
+        };
+
+        let mut done = 0;
+        while done < length {
+            let position = offset + done as u64;
+            let at = (position % PAGE_SIZE as u64) as usize;
+            let n = (PAGE_SIZE - at).min(length - done);
+
+            match self.pages.get(&(position / PAGE_SIZE as u64)) {
+                Some(page) => buffer[done..done + n].copy_from_slice(&page[at..at + n]),
+                None => buffer[done..done + n].fill(0),
+            }
+            done += n;
+        }
+
+        length as u64
+    }
+
+    /// Writes `buffer` at `offset`. All missing pages are reserved up front, so a write
+    /// that would exceed the memory cap fails without modifying the object.
+    pub fn write(&mut self, buffer: &[u8], offset: u64) -> Result<u64> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        let end = offset + buffer.len() as u64;
+        let pages = offset / PAGE_SIZE as u64..=(end - 1) / PAGE_SIZE as u64;
+        let missing = pages
+            .clone()
+            .filter(|index| !self.pages.contains_key(index))
+            .count();
+        self.usage.reserve((missing * PAGE_SIZE) as u64)?;
+
+        let mut done = 0;
+        for index in pages {
+            let position = offset + done as u64;
+            let at = (position % PAGE_SIZE as u64) as usize;
+            let n = (PAGE_SIZE - at).min(buffer.len() - done);
+
+            let page = self
+                .pages
+                .entry(index)
+                .or_insert_with(|| vec![0u8; PAGE_SIZE].into_boxed_slice());
+            page[at..at + n].copy_from_slice(&buffer[done..done + n]);
+            done += n;
+        }
+
+        self.size = self.size.max(end);
+        self.modified = now();
+        Ok(buffer.len() as u64)
+    }
+
+    pub fn status(&self) -> (i64, u64) {
+        (self.modified, self.size)
+    }
+
+    fn duplicate(&self) -> Result<MemoryObject> {
+        self.usage
+            .reserve((self.pages.len() * PAGE_SIZE) as u64)
+            .map_err(|e| e.set_action(Action::Copy))?;
+
+        Ok(MemoryObject {
+            pages: self.pages.clone(),
+            size: self.size,
+            modified: now(),
+            usage: self.usage.clone(),
+        })
+    }
+}
+
+impl Drop for MemoryObject {
+    fn drop(&mut self) {
+        self.usage.release((self.pages.len() * PAGE_SIZE) as u64);
+    }
+}
+
+pub type SharedObject = Arc<RwLock<MemoryObject>>;
+
+/// All objects of a backend by namespace and name. Deleted objects stay readable
+/// through handles that are still open and release their memory once those are closed.
+pub struct MemoryStore {
+    namespaces: RwLock<FxHashMap<String, BTreeMap<String, SharedObject>>>,
+    usage: Arc<Usage>,
+}
+
+impl MemoryStore {
+    pub fn new(cap: Option<u64>) -> Self {
+        MemoryStore {
+            namespaces: RwLock::new(FxHashMap::default()),
+            usage: Arc::new(Usage {
+                used: AtomicU64::new(0),
+                cap,
+            }),
+        }
+    }
+
+    pub fn usage(&self) -> &Usage {
+        &self.usage
+    }
+
+    pub fn create(&self, namespace: &str, name: &str) -> Result<SharedObject> {
+        let mut namespaces = self
+            .namespaces
+            .write()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+        let objects = namespaces.entry(String::from(namespace)).or_default();
+
+        if objects.contains_key(name) {
+            return Err(BackendError::map(
+                &io::Error::from(ErrorKind::AlreadyExists),
+                Action::Create,
+            ));
+        }
+        let object = Arc::new(RwLock::new(MemoryObject::new(self.usage.clone())));
+        objects.insert(String::from(name), object.clone());
+
+        Ok(object)
+    }
+
+    pub fn open(&self, namespace: &str, name: &str) -> Result<SharedObject> {
+        self.namespaces
+            .read()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?
+            .get(namespace)
+            .and_then(|objects| objects.get(name))
+            .cloned()
+            .ok_or(BackendError::map(
+                &io::Error::from(ErrorKind::NotFound),
+                Action::Open,
+            ))
+    }
+
+    /// Removes `object` from its namespace, unless `name` refers to another object by now.
+    pub fn remove(&self, namespace: &str, name: &str, object: &SharedObject) -> Result<()> {
+        let mut namespaces = self
+            .namespaces
+            .write()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+        let objects = namespaces.get_mut(namespace).ok_or(BackendError::map(
+            &io::Error::from(ErrorKind::NotFound),
+            Action::Delete,
+        ))?;
+
+        match objects.get(name) {
+            Some(current) if Arc::ptr_eq(current, object) => objects.remove(name),
+            _ => {
+                return Err(BackendError::map(
+                    &io::Error::from(ErrorKind::NotFound),
+                    Action::Delete,
+                ))
+            }
+        };
+        if objects.is_empty() {
+            namespaces.remove(namespace);
+        }
+        Ok(())
+    }
+
+    /// Copies an object into a new object, which may be in another namespace.
+    pub fn copy(
+        &self,
+        src_namespace: &str,
+        src_name: &str,
+        dst_namespace: &str,
+        dst_name: &str,
+    ) -> Result<u64> {
+        let duplicate = self
+            .open(src_namespace, src_name)?
+            .read()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?
+            .duplicate()?;
+        let size = duplicate.size;
+
+        let dst = self.create(dst_namespace, dst_name)?;
+        *dst.write()
+            .map_err(|e| BackendError::map(&e, Action::Internal))? = duplicate;
+
+        Ok(size)
+    }
+
+    /// Names in `namespace` in lexicographic order, optionally only those starting with `prefix`.
+    pub fn names(&self, namespace: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+        let namespaces = self
+            .namespaces
+            .read()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+        let objects = match namespaces.get(namespace) {
+            Some(objects) => objects,
+            None => return Ok(Vec::new()),
+        };
+
+        let prefix = prefix.unwrap_or_default();
+        Ok(objects
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .map(|(name, _)| name)
+            .take_while(|name| name.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
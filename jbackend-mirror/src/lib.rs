@@ -0,0 +1,197 @@
+mod mirror;
+mod replicas;
+
+use io_backends::generate_backend;
+use io_backends::prelude::*;
+use log::debug;
+use log::error;
+use log::info;
+
+use crate::mirror::MirrorBackend;
+
+generate_backend!(mirror);
+
+#[no_mangle]
+pub unsafe extern "C" fn backend_info() -> *mut JBackend {
+    match init_logger() {
+        Ok(_) => info!("logger initialized."),
+        Err(e) => {
+            let _ = println!("Error while initializing logger: {e:?}");
+        }
+    };
+    debug!("backend info called");
+    &mut BACKEND
+}
+
+/// Repairs all degraded replicas of an initialized backend from a healthy one.
+#[no_mangle]
+pub unsafe extern "C" fn backend_resync(backend_data: gpointer) -> gboolean {
+    cast_ptr!(backend_data, NamespaceData<MirrorBackend>);
+
+    match backend_data.replicas.resync() {
+        Ok(repaired) => {
+            info!("Resynced {repaired} replicas");
+            TRUE
+        }
+        Err(e) => {
+            error!("{e}");
+            FALSE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CString;
+    use std::fs;
+    use std::path::Path;
+    use std::ptr;
+
+    use io_backends::prelude::*;
+    use io_backends::test_facility::writes;
+    use io_backends::testing::*;
+
+    use crate::mirror::{Adapter, MirrorBackend};
+    use crate::replicas::STATE_FILE;
+    use crate::{backend_resync, BACKEND};
+
+    type Backend = NamespaceData<MirrorBackend>;
+
+    fn init(roots: &[&Path]) -> Backend {
+        let roots: Vec<&str> = roots.iter().map(|r| r.to_str().unwrap()).collect();
+        let path = CString::new(roots.join(":")).unwrap();
+        unsafe { Adapter::backend_init(path.as_ptr()).unwrap() }
+    }
+
+    #[test]
+    fn repeated_writes() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory =
+            |_namespace| Box::into_raw(Box::new(ptr::null_mut() as gpointer)).cast::<gpointer>();
+
+        // the second replica root is appended to the path of the first
+        let other = setup();
+        writes::test_writes_with_options(
+            &backend,
+            data_factory,
+            &format!(":{}", other.to_str().unwrap()),
+        );
+        shutdown(other)
+    }
+
+    #[test]
+    fn test_mirror_failover() {
+        let temp = setup();
+        let (a, b) = (temp.join("a"), temp.join("b"));
+        let backend = init(&[&a, &b]);
+
+        unsafe {
+            let handle =
+                Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "obj\0".as_ptr().cast())
+                    .unwrap();
+            let object = backend.get(&handle).unwrap();
+            backend.write(&object, b"Hello", 0).unwrap();
+            backend.sync(&object).unwrap();
+            assert_eq!(fs::read(a.join("ns/obj")).unwrap(), b"Hello");
+            assert_eq!(fs::read(b.join("ns/obj")).unwrap(), b"Hello");
+            Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "gone\0".as_ptr().cast())
+                .unwrap();
+
+            // a missing object is no failure of the replica
+            assert!(Adapter::backend_open(
+                &backend,
+                "ns\0".as_ptr().cast(),
+                "missing\0".as_ptr().cast()
+            )
+            .is_err());
+            assert_eq!(backend.replicas.healthy().count(), 2);
+        }
+
+        // the object cannot be opened on the first replica anymore
+        fs::remove_file(a.join("ns/obj")).unwrap();
+        fs::create_dir(a.join("ns/obj")).unwrap();
+
+        let object = unsafe {
+            let handle =
+                Adapter::backend_open(&backend, "ns\0".as_ptr().cast(), "obj\0".as_ptr().cast())
+                    .unwrap();
+            let object = backend.get(&handle).unwrap();
+            let mut buffer = [0u8; 16];
+            assert_eq!(backend.read(&object, &mut buffer, 0).unwrap(), 5);
+            assert_eq!(&buffer[..5], b"Hello");
+            backend.write(&object, b", world!", 5).unwrap();
+            assert_eq!(backend.status(&object).unwrap().1, 13);
+
+            let gone =
+                Adapter::backend_open(&backend, "ns\0".as_ptr().cast(), "gone\0".as_ptr().cast())
+                    .unwrap();
+            Adapter::backend_delete(&backend, &gone).unwrap();
+            object
+        };
+        assert!(a.join("ns/obj").is_dir());
+        assert!(a.join("ns/gone").exists());
+        assert_eq!(
+            fs::read_to_string(b.join(STATE_FILE)).unwrap(),
+            format!("{}\n", a.to_str().unwrap())
+        );
+
+        // the degraded replica stays degraded after a restart
+        let restarted = init(&[&a, &b]);
+        assert_eq!(restarted.replicas.healthy().count(), 1);
+        drop(restarted);
+
+        let backend_data = &backend as *const _ as gpointer;
+        assert_eq!(unsafe { backend_resync(backend_data) }, TRUE);
+        assert_eq!(backend.replicas.healthy().count(), 2);
+        assert_eq!(fs::read(a.join("ns/obj")).unwrap(), b"Hello, world!");
+        assert!(!a.join("ns/gone").exists());
+        assert!(!b.join(STATE_FILE).exists());
+
+        // open objects reopen their file on the repaired replica
+        backend.write(&object, b"!", 13).unwrap();
+        assert_eq!(fs::read(a.join("ns/obj")).unwrap(), b"Hello, world!!");
+        assert_eq!(fs::read(b.join("ns/obj")).unwrap(), b"Hello, world!!");
+
+        let names = backend.names("", None).unwrap();
+        assert_eq!(names, ["ns"]);
+
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_mirror_disagreement() {
+        let temp = setup();
+        let (a, b, c) = (temp.join("a"), temp.join("b"), temp.join("c"));
+        let backend = init(&[&a, &b, &c]);
+
+        // a stale object on a single replica is outvoted
+        fs::create_dir_all(a.join("ns")).unwrap();
+        fs::write(a.join("ns/stale"), b"old").unwrap();
+        unsafe {
+            assert!(Adapter::backend_open(
+                &backend,
+                "ns\0".as_ptr().cast(),
+                "stale\0".as_ptr().cast()
+            )
+            .is_err());
+        }
+        assert_eq!(backend.replicas.healthy().count(), 2);
+        assert!(backend.replicas.get(0).is_degraded());
+
+        // on a tie, the replica that failed is degraded
+        fs::create_dir_all(c.join("ns")).unwrap();
+        fs::write(c.join("ns/obj"), b"old").unwrap();
+        unsafe {
+            Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "obj\0".as_ptr().cast())
+                .unwrap();
+        }
+        assert_eq!(backend.replicas.healthy().count(), 1);
+        assert!(backend.replicas.get(2).is_degraded());
+
+        backend.replicas.resync().unwrap();
+        assert!(!a.join("ns/stale").exists());
+        assert_eq!(fs::read(c.join("ns/obj")).unwrap(), b"");
+
+        shutdown(temp);
+    }
+}
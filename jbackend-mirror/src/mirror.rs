@@ -0,0 +1,313 @@
+use std::{
+    fs::{self, create_dir_all, File, OpenOptions},
+    io::{self, ErrorKind},
+    os::unix::fs::{FileExt, MetadataExt},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use log::{info, warn};
+
+use io_backends::prelude::*;
+
+use crate::replicas::{self, Replica, ReplicaSet};
+
+/// A file of an open object and the generation of the replica it was opened in.
+type ReplicaFile = Option<(u64, Arc<File>)>;
+
+/// An open object, with its file on every replica that was healthy.
+pub struct OpenObject {
+    /// Path of the object relative to the roots.
+    path: PathBuf,
+    /// The object's file by replica.
+    files: Mutex<Vec<ReplicaFile>>,
+}
+
+impl OpenObject {
+    /// Returns the object's file on a replica, reopening it if the replica was resynced.
+    fn file(&self, index: usize, replica: &Replica) -> io::Result<Arc<File>> {
+        let mut files = self
+            .files
+            .lock()
+            .map_err(|e| io::Error::other(e.to_string()))?;
+
+        match &files[index] {
+            Some((generation, file)) if *generation == replica.generation() => Ok(file.clone()),
+            _ => {
+                let file = Arc::new(
+                    OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .open(replica.root.join(&self.path))?,
+                );
+                files[index] = Some((replica.generation(), file.clone()));
+                Ok(file)
+            }
+        }
+    }
+}
+
+/// Tells errors of the replica's file system apart from errors about the object, like
+/// a missing or already existing one, which all replicas report alike.
+fn is_io_error(e: &io::Error) -> bool {
+    !matches!(
+        e.kind(),
+        ErrorKind::NotFound | ErrorKind::AlreadyExists | ErrorKind::InvalidInput
+    )
+}
+
+pub struct MirrorBackend {
+    pub replicas: ReplicaSet,
+}
+
+impl MirrorBackend {
+    pub fn new(replicas: ReplicaSet) -> Self {
+        MirrorBackend { replicas }
+    }
+
+    /// Runs `f` on every healthy replica. Replicas on which it fails with an I/O error
+    /// while it succeeds on others are degraded. If it fails everywhere, the first error
+    /// is returned and no replica is degraded.
+    ///
+    /// Errors about the object, like a missing one, mean that the replicas disagree on
+    /// it. The side with fewer replicas is degraded and repaired by the next resync,
+    /// the successful one on a tie. If that is the successful side, the first error about
+    /// the object is returned.
+    fn on_healthy<R>(
+        &self,
+        action: Action,
+        mut f: impl FnMut(usize, &Replica) -> io::Result<R>,
+    ) -> Result<Vec<(usize, R)>> {
+        let mut done = Vec::new();
+        let mut failed = Vec::new();
+        for (index, replica) in self.replicas.healthy() {
+            match f(index, replica) {
+                Ok(r) => done.push((index, r)),
+                Err(e) => failed.push((index, e)),
+            }
+        }
+
+        if done.is_empty() {
+            return Err(match failed.into_iter().next() {
+                Some((_, e)) => BackendError::map(&e, action),
+                None => BackendError::new_internal("No healthy replica left"),
+            });
+        }
+
+        let disagreeing = failed.iter().filter(|(_, e)| !is_io_error(e)).count();
+        if disagreeing > done.len() {
+            for (index, _) in done {
+                let e = io::Error::other("Object differs from the other replicas");
+                self.replicas.degrade(index, &e);
+            }
+            let mut error = None;
+            for (index, e) in failed {
+                match is_io_error(&e) {
+                    true => self.replicas.degrade(index, &e),
+                    false => {
+                        error.get_or_insert(e);
+                    }
+                }
+            }
+            return Err(BackendError::map(&error.unwrap(), action));
+        }
+
+        for (index, e) in failed {
+            self.replicas.degrade(index, &e);
+        }
+        Ok(done)
+    }
+
+    /// Runs `f` on the fastest healthy replica, failing over to the next one on I/O
+    /// errors. Replicas that failed are degraded once another one succeeded. Other
+    /// errors are returned right away.
+    fn on_fastest<R>(
+        &self,
+        action: Action,
+        mut f: impl FnMut(usize, &Replica) -> io::Result<R>,
+    ) -> Result<R> {
+        let mut failed = Vec::new();
+        for index in self.replicas.read_order() {
+            let replica = self.replicas.get(index);
+            let start = Instant::now();
+            match f(index, replica) {
+                Ok(r) => {
+                    replica.record_latency(start.elapsed());
+                    for (index, e) in failed {
+                        self.replicas.degrade(index, &e);
+                    }
+                    return Ok(r);
+                }
+                Err(e) if !is_io_error(&e) => return Err(BackendError::map(&e, action)),
+                Err(e) => {
+                    warn!("Replica {:?} failed, failing over: {e}", replica.root);
+                    failed.push((index, e));
+                }
+            }
+        }
+
+        Err(match failed.into_iter().next() {
+            Some((_, e)) => BackendError::map(&e, action),
+            None => BackendError::new_internal("No healthy replica left"),
+        })
+    }
+
+    /// Wraps the files opened on the replicas into an object.
+    fn object(&self, path: PathBuf, opened: Vec<(usize, File)>) -> OpenObject {
+        let mut files = vec![None; self.replicas.len()];
+        for (index, file) in opened {
+            files[index] = Some((self.replicas.get(index).generation(), Arc::new(file)));
+        }
+        OpenObject {
+            path,
+            files: Mutex::new(files),
+        }
+    }
+}
+
+impl NamespaceBackend for MirrorBackend {
+    type Object = OpenObject;
+
+    /// Takes the replica roots from the path, separated by colons.
+    fn init(path: &str, config: &Config) -> Result<Self> {
+        let replicas = ReplicaSet::open(split_roots(path)?)?;
+        if config.get_bool("mirror_resync")? {
+            let repaired = replicas.resync()?;
+            info!("Resynced {repaired} replicas");
+        }
+        Ok(MirrorBackend::new(replicas))
+    }
+
+    fn fini(&self) -> Result<()> {
+        let degraded = self.replicas.len() - self.replicas.healthy().count();
+        info!("{degraded} replicas degraded");
+        Ok(())
+    }
+
+    fn create(&self, namespace: &str, name: &str) -> Result<OpenObject> {
+        let path = Path::new(namespace).join(name);
+        let _io = self.replicas.io()?;
+        let opened = self.on_healthy(Action::Create, |_, replica| {
+            let path = replica.root.join(&path);
+            if let Some(dir) = path.parent() {
+                create_dir_all(dir)?;
+            }
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(path)
+        })?;
+        Ok(self.object(path, opened))
+    }
+
+    fn open(&self, namespace: &str, name: &str) -> Result<OpenObject> {
+        let path = Path::new(namespace).join(name);
+        let _io = self.replicas.io()?;
+        let opened = self.on_healthy(Action::Open, |_, replica| {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(replica.root.join(&path))
+        })?;
+        Ok(self.object(path, opened))
+    }
+
+    fn delete(&self, object: &OpenObject) -> Result<()> {
+        let _io = self.replicas.io()?;
+        self.on_healthy(Action::Delete, |_, replica| {
+            fs::remove_file(replica.root.join(&object.path))
+        })?;
+        Ok(())
+    }
+
+    fn copy(
+        &self,
+        src_namespace: &str,
+        src_name: &str,
+        dst_namespace: &str,
+        dst_name: &str,
+    ) -> Result<()> {
+        let src = Path::new(src_namespace).join(src_name);
+        let dst = Path::new(dst_namespace).join(dst_name);
+        let _io = self.replicas.io()?;
+        self.on_healthy(Action::Copy, |_, replica| {
+            let dst = replica.root.join(&dst);
+            if let Some(dir) = dst.parent() {
+                create_dir_all(dir)?;
+            }
+            let src = File::open(replica.root.join(&src))?;
+            let dst_file = OpenOptions::new().write(true).create_new(true).open(&dst)?;
+            copy_file(&src, &dst_file).map_err(|e| {
+                let _ = fs::remove_file(&dst);
+                io::Error::other(e.to_string())
+            })
+        })?;
+        Ok(())
+    }
+
+    fn status(&self, object: &OpenObject) -> Result<(i64, u64)> {
+        let _io = self.replicas.io()?;
+        self.on_fastest(Action::Status, |index, replica| {
+            let metadata = object.file(index, replica)?.metadata()?;
+            Ok((metadata.atime(), metadata.size()))
+        })
+    }
+
+    fn sync(&self, object: &OpenObject) -> Result<()> {
+        let _io = self.replicas.io()?;
+        self.on_healthy(Action::Sync, |index, replica| {
+            object.file(index, replica)?.sync_data()
+        })?;
+        Ok(())
+    }
+
+    fn read(&self, object: &OpenObject, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        let _io = self.replicas.io()?;
+        self.on_fastest(Action::Read, |index, replica| {
+            let file = object.file(index, replica)?;
+            let mut done = 0;
+            while done < buffer.len() {
+                match file.read_at(&mut buffer[done..], offset + done as u64)? {
+                    0 => break,
+                    n => done += n,
+                }
+            }
+            Ok(done as u64)
+        })
+    }
+
+    /// Writes `buffer` to every healthy replica before returning.
+    fn write(&self, object: &OpenObject, buffer: &[u8], offset: u64) -> Result<u64> {
+        let _io = self.replicas.io()?;
+        self.on_healthy(Action::Write, |index, replica| {
+            object.file(index, replica)?.write_all_at(buffer, offset)
+        })?;
+        Ok(buffer.len() as u64)
+    }
+
+    /// Lists the namespace from the fastest healthy replica.
+    fn names(&self, namespace: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+        let _io = self.replicas.io()?;
+        self.on_fastest(Action::Iter, |_, replica| {
+            let mut names = Vec::new();
+            for entry in fs::read_dir(replica.root.join(namespace))? {
+                let name = entry?.file_name().into_string().map_err(|_| {
+                    io::Error::new(
+                        ErrorKind::InvalidData,
+                        "Unable to convert file name to UTF-8",
+                    )
+                })?;
+                if !replicas::is_internal(&name) && name.starts_with(prefix.unwrap_or_default()) {
+                    names.push(name);
+                }
+            }
+            Ok(names)
+        })
+    }
+}
+
+pub struct Adapter {}
+
+impl NamespaceAdapter<MirrorBackend> for Adapter {}
@@ -0,0 +1,270 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        RwLock, RwLockReadGuard,
+    },
+    time::Duration,
+};
+
+use log::{debug, error, info, warn};
+use rustc_hash::FxHashSet;
+
+use io_backends::prelude::*;
+
+/// File in every healthy root that lists the degraded roots, one per line.
+pub const STATE_FILE: &str = ".jmirror";
+
+pub struct Replica {
+    pub root: PathBuf,
+    degraded: AtomicBool,
+    /// Bumped whenever the replica was resynced. Open objects reopen their file then.
+    generation: AtomicU64,
+    /// Moving average of the read latency in microseconds.
+    latency: AtomicU64,
+}
+
+impl Replica {
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::SeqCst)
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    pub fn record_latency(&self, latency: Duration) {
+        let sample = latency.as_micros() as u64;
+        let _ = self
+            .latency
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |average| {
+                Some((average * 7 + sample) / 8)
+            });
+    }
+}
+
+/// The root directories every object is mirrored to.
+///
+/// A replica that fails while others succeed is marked degraded and skipped from then
+/// on, which is recorded in the healthy roots so it survives a restart. [`resync`]
+/// copies a healthy replica over the degraded ones.
+///
+/// [`resync`]: ReplicaSet::resync
+pub struct ReplicaSet {
+    replicas: Vec<Replica>,
+    /// Object I/O holds this shared, a resync exclusively.
+    io: RwLock<()>,
+}
+
+impl ReplicaSet {
+    pub fn open(roots: Vec<PathBuf>) -> Result<Self> {
+        if roots.len() < 2 {
+            return Err(BackendError::new(
+                "The mirror backend needs at least two roots",
+                Action::Init,
+            ));
+        }
+
+        let mut degraded: Vec<PathBuf> = Vec::new();
+        for root in &roots {
+            match fs::create_dir_all(root).and_then(|_| fs::read_to_string(root.join(STATE_FILE))) {
+                Ok(state) => degraded.extend(state.lines().map(PathBuf::from)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => {
+                    warn!("Replica {root:?} is not accessible: {e}");
+                    degraded.push(root.clone());
+                }
+            }
+        }
+
+        let replicas: Vec<Replica> = roots
+            .into_iter()
+            .map(|root| Replica {
+                degraded: AtomicBool::new(degraded.contains(&root)),
+                root,
+                generation: AtomicU64::new(0),
+                latency: AtomicU64::new(0),
+            })
+            .collect();
+        for replica in replicas.iter().filter(|r| r.is_degraded()) {
+            warn!("Replica {:?} is degraded", replica.root);
+        }
+        if replicas.iter().all(|r| r.is_degraded()) {
+            return Err(BackendError::new("All replicas are degraded", Action::Init));
+        }
+
+        Ok(ReplicaSet {
+            replicas,
+            io: RwLock::new(()),
+        })
+    }
+
+    pub fn io(&self) -> Result<RwLockReadGuard<'_, ()>> {
+        self.io
+            .read()
+            .map_err(|e| BackendError::map(&e, Action::Internal))
+    }
+
+    pub fn get(&self, index: usize) -> &Replica {
+        &self.replicas[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.replicas.len()
+    }
+
+    pub fn healthy(&self) -> impl Iterator<Item = (usize, &Replica)> {
+        self.replicas
+            .iter()
+            .enumerate()
+            .filter(|(_, replica)| !replica.is_degraded())
+    }
+
+    /// Healthy replicas, fastest first.
+    pub fn read_order(&self) -> Vec<usize> {
+        let mut order: Vec<(u64, usize)> = self
+            .healthy()
+            .map(|(index, replica)| (replica.latency.load(Ordering::Relaxed), index))
+            .collect();
+        order.sort_unstable();
+        order.into_iter().map(|(_, index)| index).collect()
+    }
+
+    /// Marks the replica degraded after it failed with `error`.
+    pub fn degrade(&self, index: usize, error: &io::Error) {
+        let replica = &self.replicas[index];
+        if replica.degraded.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        error!(
+            "Replica {:?} failed, marking it degraded: {error}",
+            replica.root
+        );
+        if let Err(e) = self.persist() {
+            error!("Unable to record the degraded replica: {e}");
+        }
+    }
+
+    /// Writes the degraded roots into every healthy root.
+    fn persist(&self) -> io::Result<()> {
+        let state: String = self
+            .replicas
+            .iter()
+            .filter(|replica| replica.is_degraded())
+            .map(|replica| format!("{}\n", replica.root.to_string_lossy()))
+            .collect();
+
+        for (_, replica) in self.healthy() {
+            let path = replica.root.join(STATE_FILE);
+            if state.is_empty() {
+                match fs::remove_file(&path) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => continue,
+                }
+            }
+
+            let temp = replica.root.join(format!("{TEMP_PREFIX}{STATE_FILE}"));
+            fs::write(&temp, &state)?;
+            File::open(&temp)?.sync_all()?;
+            fs::rename(&temp, &path)?;
+        }
+        Ok(())
+    }
+
+    /// Copies the fastest healthy replica over every degraded one and marks them
+    /// healthy again. Object I/O waits until the resync is done. Returns the number
+    /// of repaired replicas.
+    pub fn resync(&self) -> Result<usize> {
+        let _io = self
+            .io
+            .write()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+
+        let source = match self.read_order().first() {
+            Some(index) => &self.replicas[*index],
+            None => return Err(BackendError::new_internal("No healthy replica left")),
+        };
+
+        let mut repaired = 0;
+        for replica in self.replicas.iter().filter(|r| r.is_degraded()) {
+            info!("Resyncing {:?} from {:?}", replica.root, source.root);
+            match sync_tree(&source.root, &replica.root) {
+                Ok(files) => {
+                    debug!("copied {files} files to {:?}", replica.root);
+                    replica.generation.fetch_add(1, Ordering::SeqCst);
+                    replica.degraded.store(false, Ordering::SeqCst);
+                    repaired += 1;
+                }
+                Err(e) => error!("Unable to resync {:?}: {e}", replica.root),
+            }
+        }
+
+        self.persist()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+        Ok(repaired)
+    }
+}
+
+pub fn is_internal(file_name: &str) -> bool {
+    file_name == STATE_FILE || file_name.starts_with(TEMP_PREFIX)
+}
+
+/// Makes the tree below `dst` a copy of the tree below `src`. Returns the number of
+/// files copied.
+fn sync_tree(src: &Path, dst: &Path) -> io::Result<u64> {
+    if !dst.is_dir() {
+        let _ = fs::remove_file(dst);
+        fs::create_dir_all(dst)?;
+    }
+
+    let mut copied = 0;
+    let mut names = FxHashSet::default();
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if is_internal(&name.to_string_lossy()) {
+            continue;
+        }
+
+        let target = dst.join(&name);
+        if entry.file_type()?.is_dir() {
+            copied += sync_tree(&entry.path(), &target)?;
+        } else {
+            if target.is_dir() {
+                fs::remove_dir_all(&target)?;
+            }
+            // the old file stays in place until the copy is complete
+            let temp = dst.join(format!("{TEMP_PREFIX}{}", name.to_string_lossy()));
+            let file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&temp)?;
+            copy_file(&File::open(entry.path())?, &file)
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            file.sync_all()?;
+            fs::rename(&temp, &target)?;
+            copied += 1;
+        }
+        names.insert(name);
+    }
+
+    // objects deleted while the replica was degraded
+    for entry in fs::read_dir(dst)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if names.contains(&name) || is_internal(&name.to_string_lossy()) {
+            continue;
+        }
+        match entry.file_type()?.is_dir() {
+            true => fs::remove_dir_all(entry.path())?,
+            false => fs::remove_file(entry.path())?,
+        }
+    }
+
+    File::open(dst)?.sync_all()?;
+    Ok(copied)
+}
@@ -20,22 +20,202 @@ pub unsafe extern "C" fn backend_info() -> *mut JBackend {
 
 #[cfg(test)]
 mod test {
+    use std::fs::{self, File, OpenOptions};
+    use std::os::unix::fs::FileExt;
+
     use io_backends::prelude::*;
+    use io_backends::test_facility::writes;
     use io_backends::testing::*;
 
-    use crate::mmap::MmapObject;
     use crate::BACKEND;
 
     #[test]
     fn test_mmap_workflow() {
         let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
         let data_factory = |namespace| {
-            let data = Backend::<MmapObject> {
+            let data = Backend::<ChecksummedObject<MmapObject>> {
                 object_store: ObjectStore::new(),
                 namespace,
+                config: Config::default(),
+                pending: PendingLinks::new(),
             };
             Box::into_raw(Box::new(data)).cast::<gpointer>()
         };
         test_workflow(&backend, &data_factory);
     }
+
+    #[test]
+    fn repeated_writes() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory = |namespace| {
+            let data = Backend::<ChecksummedObject<MmapObject>> {
+                object_store: ObjectStore::new(),
+                namespace,
+                config: Config::default(),
+                pending: PendingLinks::new(),
+            };
+            Box::into_raw(Box::new(data)).cast::<gpointer>()
+        };
+
+        writes::test_writes(&backend, data_factory)
+    }
+
+    #[test]
+    fn test_mmap_truncated() {
+        let temp = setup();
+        let path = temp.join(WRITE_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let mut object = MmapObject::new(file, &Config::default()).unwrap();
+
+        let content = [1u8; 64 << 10];
+        assert_eq!(object.write(&content, 0, 64 << 10).unwrap(), 64 << 10);
+
+        // shrink the file behind the object's back, the mapped pages past the new end
+        // raise SIGBUS when they are touched
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(100)
+            .unwrap();
+
+        let mut buffer = vec![0u8; 64 << 10];
+        assert_eq!(object.read(&mut buffer, 0, 64 << 10).unwrap(), 100);
+        assert!(buffer[..100].iter().all(|b| *b == 1));
+        assert_eq!(object.read(&mut buffer, 32 << 10, 4096).unwrap(), 0);
+        assert_eq!(object.status().unwrap().1, 100);
+
+        assert_eq!(object.write(&content[..10], 6000, 10).unwrap(), 10);
+        assert_eq!(object.status().unwrap().1, 6010);
+        assert_eq!(object.read(&mut buffer, 6000, 10).unwrap(), 10);
+
+        // a write past a truncation that happens after the object was mapped again
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(10)
+            .unwrap();
+        assert_eq!(object.write(b"Hello", 5000, 5).unwrap(), 5);
+        assert_eq!(object.read(&mut buffer, 5000, 5).unwrap(), 5);
+        assert_eq!(&buffer[..5], b"Hello");
+        assert_eq!(fs::metadata(&path).unwrap().len(), 5005);
+
+        drop(object);
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_mmap_near_eof() {
+        let temp = setup();
+        let path = temp.join(WRITE_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let mut object = MmapObject::new(file, &Config::default()).unwrap();
+
+        let content: Vec<u8> = (0..3 << 20).map(|i: u32| i as u8).collect();
+        assert_eq!(object.write(&content, 0, 3 << 20).unwrap(), 3 << 20);
+        assert_eq!(object.write(b"Hello", 4096, 5).unwrap(), 5);
+        assert_eq!(fs::read(&path).unwrap().len(), 3 << 20);
+
+        // reads see what was written to the mapping
+        let mut buffer = vec![0u8; 2 << 20];
+        assert_eq!(object.read(&mut buffer, 1 << 20, 2 << 20).unwrap(), 2 << 20);
+        assert_eq!(buffer, content[1 << 20..]);
+        object.write(b"world", (3 << 20) - 5, 5).unwrap();
+        assert_eq!(object.read(&mut buffer[..5], 0, 5).unwrap(), 5);
+        assert_eq!(&buffer[..5], &content[..5]);
+
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(3 << 19)
+            .unwrap();
+        assert_eq!(object.read(&mut buffer, 4096, 5).unwrap(), 5);
+        assert_eq!(&buffer[..5], b"Hello");
+        assert_eq!(object.read(&mut buffer, 1 << 20, 2 << 20).unwrap(), 1 << 19);
+        assert_eq!(buffer[..1 << 19], content[1 << 20..3 << 19]);
+
+        drop(object);
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_mmap_read_only_upgrade() {
+        let temp = setup();
+        let path = temp.join(READ_FILE);
+        let mut config = Config::default();
+        config.set("populate", "true");
+        let mut object = MmapObject::new(File::open(&path).unwrap(), &config).unwrap();
+
+        let mut buffer = [0u8; 13];
+        assert_eq!(object.read(&mut buffer, 0, 13).unwrap(), 13);
+        assert_eq!(&buffer, b"Hello, world!");
+
+        assert_eq!(object.write(b"Jello", 0, 5).unwrap(), 5);
+        object.sync().unwrap();
+        assert_eq!(object.read(&mut buffer, 0, 13).unwrap(), 13);
+        assert_eq!(&buffer, b"Jello, world!");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "Jello, world!");
+
+        drop(object);
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_mmap_checksums() {
+        let temp = setup();
+        let path = temp.join(WRITE_FILE);
+        let mut config = Config::default();
+        config.set("checksum", "true");
+        let open = || {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap();
+            ChecksummedObject::<MmapObject>::new(file, &config).unwrap()
+        };
+
+        let mut object = open();
+        object.write(&[1u8; 8192], 0, 8192).unwrap();
+        object.write(b"Hello", 10_000, 5).unwrap();
+        object.sync().unwrap();
+        drop(object);
+
+        // corrupt the first two blocks, the third one stays intact
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.write_all_at(&[2u8; 2], 4095).unwrap();
+
+        let object = open();
+        let mut buffer = [0u8; 13];
+        assert_eq!(object.read(&mut buffer, 10_002, 13).unwrap(), 3);
+        assert_eq!(&buffer[..3], b"llo");
+
+        let e = object.read(&mut buffer, 8000, 13).unwrap_err();
+        assert!(
+            matches!(e.kind(), ErrorKind::ChecksumMismatch { blocks, .. } if blocks == &(1..=1))
+        );
+        let e = object.read(&mut buffer, 0, 13).unwrap_err();
+        assert!(
+            matches!(e.kind(), ErrorKind::ChecksumMismatch { blocks, .. } if blocks == &(0..=0))
+        );
+
+        let mut buffer = vec![0u8; 10_005];
+        let e = object.read(&mut buffer, 0, 10_005).unwrap_err();
+        assert!(
+            matches!(e.kind(), ErrorKind::ChecksumMismatch { blocks, .. } if blocks == &(0..=1))
+        );
+
+        drop(object);
+        shutdown(temp);
+    }
 }
@@ -0,0 +1,201 @@
+mod pack;
+mod packed;
+
+use io_backends::generate_backend;
+use io_backends::prelude::*;
+use log::debug;
+use log::error;
+use log::info;
+
+use crate::packed::PackBackend;
+
+generate_backend!(packed);
+
+#[no_mangle]
+pub unsafe extern "C" fn backend_info() -> *mut JBackend {
+    match init_logger() {
+        Ok(_) => info!("logger initialized."),
+        Err(e) => {
+            let _ = println!("Error while initializing logger: {e:?}");
+        }
+    };
+    debug!("backend info called");
+    &mut BACKEND
+}
+
+/// Reclaims the holes left by deleted and promoted objects in all packs of an
+/// initialized backend.
+#[no_mangle]
+pub unsafe extern "C" fn backend_repack(backend_data: gpointer) -> gboolean {
+    cast_ptr!(backend_data, NamespaceData<PackBackend>);
+
+    match backend_data.repack() {
+        Ok(reclaimed) => {
+            info!("Repacked, reclaimed {reclaimed} b");
+            TRUE
+        }
+        Err(e) => {
+            error!("{e}");
+            FALSE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CString;
+    use std::fs;
+    use std::path::Path;
+    use std::ptr;
+
+    use io_backends::prelude::*;
+    use io_backends::test_facility::writes;
+    use io_backends::testing::*;
+
+    use crate::packed::{Adapter, PackBackend};
+    use crate::{backend_repack, BACKEND};
+
+    type Backend = NamespaceData<PackBackend>;
+
+    fn init(root: &Path, options: &str) -> Backend {
+        let path = CString::new(format!("{}{options}", root.to_str().unwrap())).unwrap();
+        unsafe { Adapter::backend_init(path.as_ptr()).unwrap() }
+    }
+
+    fn create(backend: &Backend, name: &str) -> ObjectHandle {
+        let name = CString::new(name).unwrap();
+        unsafe { Adapter::backend_create(backend, "ns\0".as_ptr().cast(), name.as_ptr()).unwrap() }
+    }
+
+    fn open(backend: &Backend, name: &str) -> ObjectHandle {
+        let name = CString::new(name).unwrap();
+        unsafe { Adapter::backend_open(backend, "ns\0".as_ptr().cast(), name.as_ptr()).unwrap() }
+    }
+
+    fn read_all(backend: &Backend, handle: &ObjectHandle) -> Vec<u8> {
+        let mut buffer = vec![0u8; 32768];
+        let mut read = 0;
+        let ret = unsafe {
+            Adapter::j_read(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+                buffer.as_mut_ptr().cast(),
+                buffer.len() as u64,
+                0,
+                &mut read,
+            )
+        };
+        assert_eq!(ret, TRUE);
+        buffer.truncate(read as usize);
+        buffer
+    }
+
+    fn write(backend: &Backend, handle: &ObjectHandle, data: &[u8], offset: u64) {
+        let mut written = 0;
+        let ret = unsafe {
+            Adapter::j_write(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+                data.as_ptr().cast(),
+                data.len() as u64,
+                offset,
+                &mut written,
+            )
+        };
+        assert_eq!((ret, written), (TRUE, data.len() as u64));
+    }
+
+    fn files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn repeated_writes() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory =
+            |_namespace| Box::into_raw(Box::new(ptr::null_mut() as gpointer)).cast::<gpointer>();
+
+        writes::test_writes_with_options(&backend, data_factory, "?pack_threshold=4K")
+    }
+
+    #[test]
+    fn test_pack_promotion() {
+        let temp = setup();
+        let dir = temp.join("ns");
+        let backend = init(&temp, "?pack_threshold=4K");
+
+        // small objects share the pack, none of them has a file of its own
+        let a = create(&backend, "a");
+        let b = create(&backend, "b");
+        assert!(unsafe {
+            Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "a\0".as_ptr().cast())
+        }
+        .is_err());
+        write(&backend, &a, b"small", 0);
+        write(&backend, &b, &[7u8; 1000], 0);
+        write(&backend, &b, b"tail", 2000);
+        let mut expected_b = vec![7u8; 1000];
+        expected_b.resize(2000, 0);
+        expected_b.extend_from_slice(b"tail");
+        assert_eq!(read_all(&backend, &b), expected_b);
+        assert_eq!(files(&dir), [".jpack.0"]);
+
+        let ret = unsafe {
+            Adapter::j_sync(&backend as *const _ as gpointer, &a as *const _ as gpointer)
+        };
+        assert_eq!(ret, TRUE);
+        let index = fs::read(dir.join(".jpack-index")).unwrap();
+
+        // growing past the threshold promotes the object for every open handle
+        let other = open(&backend, "a");
+        let large: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+        write(&backend, &a, &large, 5);
+        let mut expected_a = b"small".to_vec();
+        expected_a.extend_from_slice(&large);
+        assert_eq!(read_all(&backend, &other), expected_a);
+        assert_eq!(fs::read(dir.join("a")).unwrap(), expected_a);
+
+        // the promotion is on disk right away, as if the backend had crashed here
+        assert_ne!(fs::read(dir.join(".jpack-index")).unwrap(), index);
+        let crashed = init(&temp, "?pack_threshold=4K");
+        assert_eq!(read_all(&crashed, &open(&crashed, "a")), expected_a);
+        drop(crashed);
+
+        let ret = unsafe {
+            Adapter::j_copy(
+                &backend as *const _ as gpointer,
+                "ns\0".as_ptr().cast(),
+                "b\0".as_ptr().cast(),
+                "ns\0".as_ptr().cast(),
+                "c\0".as_ptr().cast(),
+            )
+        };
+        assert_eq!(ret, TRUE);
+        let ret = unsafe {
+            Adapter::j_delete(&backend as *const _ as gpointer, &b as *const _ as gpointer)
+        };
+        assert_eq!(ret, TRUE);
+        assert_eq!(backend.names("ns", None).unwrap(), ["a", "c"]);
+
+        // the holes of the promoted and the deleted object are reclaimed
+        let before = fs::metadata(dir.join(".jpack.0")).unwrap().len();
+        let ret = unsafe { backend_repack(&backend as *const _ as gpointer) };
+        assert_eq!(ret, TRUE);
+        let after = fs::metadata(dir.join(".jpack.1")).unwrap().len();
+        assert!(after < before);
+        assert_eq!(files(&dir), [".jpack-index", ".jpack.1", "a"]);
+        drop(backend);
+
+        let backend = init(&temp, "");
+        assert_eq!(backend.names("ns", None).unwrap(), ["a", "c"]);
+        assert_eq!(read_all(&backend, &open(&backend, "c")), expected_b);
+        assert_eq!(read_all(&backend, &open(&backend, "a")), expected_a);
+
+        shutdown(temp)
+    }
+}
@@ -0,0 +1,304 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::ErrorKind,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info};
+
+use io_backends::prelude::*;
+
+/// Prefix of the pack files in every namespace directory, which are not objects.
+pub const PACK_PREFIX: &str = ".jpack";
+const INDEX_FILE: &str = ".jpack-index";
+
+const MAGIC: &[u8; 4] = b"JPCK";
+const VERSION: u32 = 1;
+/// Smallest extent allocated for an object, so tiny appends do not relocate it.
+const MIN_CAPACITY: u64 = 512;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Extent {
+    pub offset: u64,
+    pub len: u64,
+    pub capacity: u64,
+}
+
+/// The packed objects of one namespace, stored back to back in a single data file
+/// with an index recording their extents. Deleted and relocated objects leave holes
+/// until the pack is repacked.
+pub struct Pack {
+    dir: PathBuf,
+    /// Every repack writes a new data file, `.jpack.<generation>`, and switches to it
+    /// by replacing the index.
+    generation: u64,
+    data: File,
+    index: BTreeMap<String, Extent>,
+    /// End of the last extent, new extents are appended there.
+    end: u64,
+    /// Bytes of the data file that no object uses.
+    holes: u64,
+    /// Whether the index changed since it was last written.
+    dirty: bool,
+}
+
+impl Pack {
+    /// Opens the pack in the namespace directory `dir`, creating it on first use.
+    /// Objects that also exist as standalone files were promoted before the index was
+    /// written and are dropped from it.
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let (generation, mut index) = match fs::read(dir.join(INDEX_FILE)) {
+            Ok(raw) => decode(&raw).ok_or(BackendError::new(
+                &format!("{:?} is corrupted", dir.join(INDEX_FILE)),
+                Action::Open,
+            ))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => (0, BTreeMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        // data files of other generations are left over from interrupted repacks
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with(&format!("{PACK_PREFIX}.")) && *name != data_file(generation) {
+                debug!("removing stale pack data {name}");
+                fs::remove_file(dir.join(&*name))?;
+            }
+        }
+        let data = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join(data_file(generation)))?;
+
+        let promoted = index.len();
+        index.retain(|name, _| !dir.join(name).exists());
+        let dirty = index.len() != promoted;
+
+        let end = index
+            .values()
+            .map(|extent| extent.offset + extent.capacity)
+            .max()
+            .unwrap_or(0);
+        let holes = end - index.values().map(|extent| extent.capacity).sum::<u64>();
+        debug!(
+            "opened pack {dir:?} with {} objects, {holes} of {end} b are holes",
+            index.len()
+        );
+
+        Ok(Pack {
+            dir: dir.to_path_buf(),
+            generation,
+            data,
+            index,
+            end,
+            holes,
+            dirty,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<Extent> {
+        self.index.get(name).copied()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.index.keys()
+    }
+
+    pub fn data(&self) -> &File {
+        &self.data
+    }
+
+    pub fn insert(&mut self, name: &str) {
+        self.index.entry(name.to_string()).or_insert(Extent {
+            offset: self.end,
+            len: 0,
+            capacity: 0,
+        });
+        self.dirty = true;
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Extent> {
+        let extent = self.index.remove(name)?;
+        self.holes += extent.capacity;
+        self.dirty = true;
+        Some(extent)
+    }
+
+    pub fn read(&self, name: &str, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        let extent = self.lookup(name)?;
+        let length = match extent.len.checked_sub(offset) {
+            Some(available) => available.min(buffer.len() as u64),
+            None => return Ok(0),
+        };
+        self.data
+            .read_exact_at(&mut buffer[..length as usize], extent.offset + offset)
+            .map_err(|e| BackendError::map(&e, Action::Read))?;
+        Ok(length)
+    }
+
+    /// Writes to a packed object that stays below `threshold`, relocating it to a
+    /// larger extent at the end of the pack if it outgrows its own.
+    pub fn write(&mut self, name: &str, buffer: &[u8], offset: u64, threshold: u64) -> Result<()> {
+        let mut extent = self.lookup(name)?;
+        let end = offset + buffer.len() as u64;
+
+        if end > extent.capacity {
+            let capacity = end
+                .next_power_of_two()
+                .max(MIN_CAPACITY)
+                .min(threshold.max(end));
+            let mut content = vec![0u8; extent.len as usize];
+            self.data.read_exact_at(&mut content, extent.offset)?;
+            self.data.write_all_at(&content, self.end)?;
+            // reserve the whole extent, so the data file always covers the index
+            self.data.set_len(self.end + capacity)?;
+
+            self.holes += extent.capacity;
+            extent.offset = self.end;
+            extent.capacity = capacity;
+            self.end += capacity;
+        }
+        if offset > extent.len {
+            // the extent may hold stale bytes of a previous object
+            let gap = vec![0u8; (offset - extent.len) as usize];
+            self.data.write_all_at(&gap, extent.offset + extent.len)?;
+        }
+        self.data
+            .write_all_at(buffer, extent.offset + offset)
+            .map_err(|e| BackendError::map(&e, Action::Write))?;
+
+        extent.len = extent.len.max(end);
+        self.index.insert(name.to_string(), extent);
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Returns the content of a packed object.
+    pub fn content(&self, name: &str) -> Result<Vec<u8>> {
+        let extent = self.lookup(name)?;
+        let mut content = vec![0u8; extent.len as usize];
+        self.data.read_exact_at(&mut content, extent.offset)?;
+        Ok(content)
+    }
+
+    /// Syncs the data file and replaces the index if it changed.
+    pub fn flush(&mut self) -> Result<()> {
+        self.data.sync_data()?;
+        if !self.dirty {
+            return Ok(());
+        }
+        let temp = self.dir.join(format!("{TEMP_PREFIX}{INDEX_FILE}"));
+        let index = encode(self.generation, &self.index);
+        let file = File::create(&temp)?;
+        file.write_all_at(&index, 0)?;
+        file.sync_all()?;
+        fs::rename(&temp, self.dir.join(INDEX_FILE))?;
+        File::open(&self.dir)?.sync_all()?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Rewrites the data file without holes into the next generation, which the
+    /// replaced index switches to. Returns the number of bytes reclaimed.
+    pub fn repack(&mut self) -> Result<u64> {
+        if self.holes == 0 {
+            return Ok(0);
+        }
+        let generation = self.generation + 1;
+        let data = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(self.dir.join(data_file(generation)))?;
+
+        let mut index = BTreeMap::new();
+        let mut end = 0;
+        for (name, extent) in &self.index {
+            let mut content = vec![0u8; extent.len as usize];
+            self.data.read_exact_at(&mut content, extent.offset)?;
+            data.write_all_at(&content, end)?;
+            let capacity = match extent.len {
+                0 => 0,
+                len => len.next_power_of_two().max(MIN_CAPACITY),
+            };
+            index.insert(
+                name.clone(),
+                Extent {
+                    offset: end,
+                    len: extent.len,
+                    capacity,
+                },
+            );
+            end += capacity;
+        }
+        data.set_len(end)?;
+
+        let reclaimed = self.end - end;
+        let old = data_file(self.generation);
+        (self.generation, self.data, self.index, self.end, self.holes) =
+            (generation, data, index, end, 0);
+        self.dirty = true;
+        self.flush()?;
+        fs::remove_file(self.dir.join(old))?;
+
+        info!("Repacked {:?}, reclaimed {reclaimed} b", self.dir);
+        Ok(reclaimed)
+    }
+
+    fn lookup(&self, name: &str) -> Result<Extent> {
+        self.get(name).ok_or(BackendError::new_internal(&format!(
+            "{name} is not packed in {:?}",
+            self.dir
+        )))
+    }
+}
+
+fn data_file(generation: u64) -> String {
+    format!("{PACK_PREFIX}.{generation}")
+}
+
+/// Header of magic, version and generation, followed by offset, length, capacity,
+/// name length and name of every object.
+fn encode(generation: u64, index: &BTreeMap<String, Extent>) -> Vec<u8> {
+    let mut raw = Vec::with_capacity(16 + index.len() * 64);
+    raw.extend_from_slice(MAGIC);
+    raw.extend_from_slice(&VERSION.to_le_bytes());
+    raw.extend_from_slice(&generation.to_le_bytes());
+    for (name, extent) in index {
+        raw.extend_from_slice(&extent.offset.to_le_bytes());
+        raw.extend_from_slice(&extent.len.to_le_bytes());
+        raw.extend_from_slice(&extent.capacity.to_le_bytes());
+        raw.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        raw.extend_from_slice(name.as_bytes());
+    }
+    raw
+}
+
+fn decode(raw: &[u8]) -> Option<(u64, BTreeMap<String, Extent>)> {
+    if raw.get(0..4)? != MAGIC || raw.get(4..8)? != VERSION.to_le_bytes() {
+        return None;
+    }
+    let u64_at = |at: usize| Some(u64::from_le_bytes(raw.get(at..at + 8)?.try_into().ok()?));
+
+    let generation = u64_at(8)?;
+    let mut index = BTreeMap::new();
+    let mut at = 16;
+    while at < raw.len() {
+        let extent = Extent {
+            offset: u64_at(at)?,
+            len: u64_at(at + 8)?,
+            capacity: u64_at(at + 16)?,
+        };
+        let name_len = u32::from_le_bytes(raw.get(at + 24..at + 28)?.try_into().ok()?) as usize;
+        let name = String::from_utf8(raw.get(at + 28..at + 28 + name_len)?.to_vec()).ok()?;
+        index.insert(name, extent);
+        at += 28 + name_len;
+    }
+    Some((generation, index))
+}
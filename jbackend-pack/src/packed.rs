@@ -0,0 +1,393 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind},
+    os::unix::fs::{FileExt, MetadataExt},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use log::{error, info};
+use rustc_hash::FxHashMap;
+
+use io_backends::prelude::*;
+
+use crate::pack::{Pack, PACK_PREFIX};
+
+const DEFAULT_THRESHOLD: u64 = 64 << 10;
+
+/// An open object, either packed or standalone. A packed object may be promoted
+/// through another handle, so its file is only opened once the pack no longer holds
+/// it.
+pub struct OpenObject {
+    dir: PathBuf,
+    name: String,
+    standalone: Mutex<Option<File>>,
+}
+
+/// Where an object is stored while it is accessed.
+enum Target<'a> {
+    /// In the pack, with the slot for the standalone file in case it is promoted.
+    Packed(&'a mut Pack, &'a mut Option<File>),
+    Standalone(&'a File),
+}
+
+pub struct PackBackend {
+    root: PathBuf,
+    /// Objects up to this size are packed, 0 disables packing.
+    threshold: u64,
+    /// The packs of all namespaces used so far, by namespace directory.
+    packs: Mutex<FxHashMap<PathBuf, Arc<Mutex<Pack>>>>,
+}
+
+impl PackBackend {
+    pub fn new(root: PathBuf, config: &Config) -> Result<Self> {
+        fs::create_dir_all(&root)?;
+        Ok(PackBackend {
+            root,
+            threshold: config
+                .get_size("pack_threshold")?
+                .unwrap_or(DEFAULT_THRESHOLD),
+            packs: Mutex::new(FxHashMap::default()),
+        })
+    }
+
+    /// Returns the pack of the namespace directory `dir`, opening it on first use.
+    fn pack(&self, dir: &Path) -> Result<Arc<Mutex<Pack>>> {
+        let mut packs = lock(&self.packs)?;
+        if let Some(pack) = packs.get(dir) {
+            return Ok(pack.clone());
+        }
+        let pack = Arc::new(Mutex::new(Pack::open(dir)?));
+        packs.insert(dir.to_path_buf(), pack.clone());
+        Ok(pack)
+    }
+
+    /// Splits the path of an object into its namespace directory and name.
+    fn locate(&self, namespace: &str, name: &str) -> Result<(PathBuf, String)> {
+        let path = self.root.join(namespace).join(name);
+        match (path.parent(), path.file_name().and_then(|n| n.to_str())) {
+            (Some(dir), Some(name)) => Ok((dir.to_path_buf(), name.to_string())),
+            _ => Err(BackendError::new_internal(&format!(
+                "{path:?} is not a valid object path"
+            ))),
+        }
+    }
+
+    /// Runs `f` on an object wherever it is stored.
+    fn with_object<R>(
+        &self,
+        object: &OpenObject,
+        f: impl FnOnce(&OpenObject, Target) -> Result<R>,
+    ) -> Result<R> {
+        let mut standalone = lock(&object.standalone)?;
+        if let Some(file) = standalone.as_ref() {
+            return f(object, Target::Standalone(file));
+        }
+        let pack = self.pack(&object.dir)?;
+        let mut pack = lock(&pack)?;
+        if pack.get(&object.name).is_some() {
+            return f(object, Target::Packed(&mut pack, &mut standalone));
+        }
+        drop(pack);
+
+        // promoted through another handle in the meantime
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(object.dir.join(&object.name))?;
+        f(object, Target::Standalone(standalone.insert(file)))
+    }
+
+    /// Reclaims the holes of every pack below the root, returns the bytes reclaimed.
+    pub fn repack(&self) -> Result<u64> {
+        let mut dirs = Vec::new();
+        collect_packs(&self.root, &mut dirs)?;
+
+        let mut reclaimed = 0;
+        for dir in dirs {
+            reclaimed += lock(&*self.pack(&dir)?)?.repack()?;
+        }
+        Ok(reclaimed)
+    }
+
+    /// Writes the indexes of all packs.
+    pub fn flush(&self) -> Result<()> {
+        for (dir, pack) in lock(&self.packs)?.iter() {
+            if let Err(e) = lock(pack)?.flush() {
+                error!("Unable to flush pack {dir:?}: {e}");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl NamespaceBackend for PackBackend {
+    type Object = OpenObject;
+
+    fn init(path: &str, config: &Config) -> Result<Self> {
+        PackBackend::new(PathBuf::from(path), config)
+    }
+
+    fn fini(&self) -> Result<()> {
+        self.flush()
+    }
+
+    /// Creates new objects in the pack of their namespace, unless packing is disabled.
+    /// Fails if the object exists, packed or standalone.
+    fn create(&self, namespace: &str, name: &str) -> Result<OpenObject> {
+        let (dir, name) = self.locate(namespace, name)?;
+        let file = dir.join(&name);
+
+        // the pack stays locked so that the object cannot be created in between
+        let pack = self.pack(&dir)?;
+        let mut pack = lock(&pack)?;
+        if pack.get(&name).is_some() || file.try_exists()? {
+            return Err(BackendError::map(
+                &io::Error::from(ErrorKind::AlreadyExists),
+                Action::Create,
+            ));
+        }
+        let standalone = match self.threshold {
+            0 => {
+                fs::create_dir_all(&dir)?;
+                Some(
+                    OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .create_new(true)
+                        .open(&file)?,
+                )
+            }
+            _ => {
+                pack.insert(&name);
+                None
+            }
+        };
+        Ok(OpenObject {
+            dir,
+            name,
+            standalone: Mutex::new(standalone),
+        })
+    }
+
+    fn open(&self, namespace: &str, name: &str) -> Result<OpenObject> {
+        let (dir, name) = self.locate(namespace, name)?;
+        let standalone = match lock(&*self.pack(&dir)?)?.get(&name) {
+            Some(_) => None,
+            None => Some(
+                OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(dir.join(&name))?,
+            ),
+        };
+        Ok(OpenObject {
+            dir,
+            name,
+            standalone: Mutex::new(standalone),
+        })
+    }
+
+    fn delete(&self, object: &OpenObject) -> Result<()> {
+        self.with_object(object, |object, target| {
+            match target {
+                Target::Packed(pack, _) => {
+                    pack.remove(&object.name);
+                }
+                Target::Standalone(_) => fs::remove_file(object.dir.join(&object.name))?,
+            }
+            Ok(())
+        })
+    }
+
+    fn status(&self, object: &OpenObject) -> Result<(i64, u64)> {
+        self.with_object(object, |object, target| match target {
+            Target::Packed(pack, _) => {
+                let extent = pack.get(&object.name).unwrap_or_default();
+                Ok((pack.data().metadata()?.atime(), extent.len))
+            }
+            Target::Standalone(file) => {
+                let metadata = file.metadata()?;
+                Ok((metadata.atime(), metadata.size()))
+            }
+        })
+    }
+
+    /// Syncs a standalone object, or the pack of a packed one with its index.
+    fn sync(&self, object: &OpenObject) -> Result<()> {
+        self.with_object(object, |_, target| match target {
+            Target::Packed(pack, _) => pack.flush(),
+            Target::Standalone(file) => file
+                .sync_data()
+                .map_err(|e| BackendError::map(&e, Action::Sync)),
+        })
+    }
+
+    fn read(&self, object: &OpenObject, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        self.with_object(object, |object, target| match target {
+            Target::Packed(pack, _) => pack.read(&object.name, buffer, offset),
+            Target::Standalone(file) => {
+                let mut n_read = 0;
+                while n_read < buffer.len() {
+                    match file.read_at(&mut buffer[n_read..], offset + n_read as u64) {
+                        Ok(0) => break,
+                        Ok(n) => n_read += n,
+                        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                        Err(e) => return Err(BackendError::map(&e, Action::Read)),
+                    }
+                }
+                Ok(n_read as u64)
+            }
+        })
+    }
+
+    /// Writes to an object, promoting it to a standalone file if it grows past the
+    /// threshold.
+    fn write(&self, object: &OpenObject, buffer: &[u8], offset: u64) -> Result<u64> {
+        let end = offset + buffer.len() as u64;
+        self.with_object(object, |object, target| {
+            let file = match target {
+                Target::Packed(pack, _) if end <= self.threshold => {
+                    pack.write(&object.name, buffer, offset, self.threshold)?;
+                    return Ok(buffer.len() as u64);
+                }
+                Target::Packed(pack, slot) => slot.insert(promote(pack, object)?),
+                Target::Standalone(file) => file,
+            };
+            file.write_all_at(buffer, offset)
+                .map_err(|e| BackendError::map(&e, Action::Write))?;
+            Ok(buffer.len() as u64)
+        })
+    }
+
+    /// Copies an object into the pack of the destination namespace if it is small
+    /// enough, or into a standalone file otherwise.
+    fn copy(
+        &self,
+        src_namespace: &str,
+        src_name: &str,
+        dst_namespace: &str,
+        dst_name: &str,
+    ) -> Result<()> {
+        let (src_dir, src_name) = self.locate(src_namespace, src_name)?;
+        let (dst_dir, dst_name) = self.locate(dst_namespace, dst_name)?;
+        if dst_dir.join(&dst_name).exists()
+            || lock(&*self.pack(&dst_dir)?)?.get(&dst_name).is_some()
+        {
+            return Err(BackendError::new(
+                &format!("{:?} already exists", dst_dir.join(&dst_name)),
+                Action::Copy,
+            ));
+        }
+
+        let packed = {
+            let pack = self.pack(&src_dir)?;
+            let pack = lock(&pack)?;
+            match pack.get(&src_name) {
+                Some(_) => Some(pack.content(&src_name)?),
+                None => None,
+            }
+        };
+        let content = match packed {
+            Some(content) if content.len() as u64 <= self.threshold => {
+                let pack = self.pack(&dst_dir)?;
+                let mut pack = lock(&pack)?;
+                pack.insert(&dst_name);
+                return pack.write(&dst_name, &content, 0, self.threshold);
+            }
+            Some(content) => Some(content),
+            None => None,
+        };
+
+        fs::create_dir_all(&dst_dir)?;
+        let dst_file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(dst_dir.join(&dst_name))?;
+        let res = match content {
+            Some(content) => dst_file
+                .write_all_at(&content, 0)
+                .map_err(BackendError::from),
+            None => copy_file(&File::open(src_dir.join(&src_name))?, &dst_file).map(|_| ()),
+        };
+        if res.is_err() {
+            let _ = fs::remove_file(dst_dir.join(&dst_name));
+        }
+        res
+    }
+
+    /// Lists the standalone objects of a namespace together with its packed ones.
+    fn names(&self, namespace: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+        let dir = self.root.join(namespace);
+        let mut names = BTreeSet::new();
+        for entry in fs::read_dir(&dir)? {
+            let name = entry?.file_name().into_string().map_err(|_| {
+                BackendError::new("Unable to convert file name to UTF-8", Action::Iter)
+            })?;
+            if !name.starts_with(TEMP_PREFIX) && !name.starts_with(PACK_PREFIX) {
+                names.insert(name);
+            }
+        }
+        names.extend(lock(&*self.pack(&dir)?)?.names().cloned());
+
+        Ok(names
+            .into_iter()
+            .filter(|name| name.starts_with(prefix.unwrap_or_default()))
+            .collect())
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> Result<MutexGuard<'_, T>> {
+    mutex
+        .lock()
+        .map_err(|e| BackendError::map(&e, Action::Internal))
+}
+
+/// Moves a packed object into a standalone file, which is complete before it replaces
+/// the object in the pack.
+fn promote(pack: &mut Pack, object: &OpenObject) -> Result<File> {
+    let content = pack.content(&object.name)?;
+    let temp = object.dir.join(format!("{TEMP_PREFIX}{}", object.name));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&temp)?;
+    file.write_all_at(&content, 0)?;
+    file.sync_all()?;
+    fs::rename(&temp, object.dir.join(&object.name))?;
+    File::open(&object.dir)?.sync_all()?;
+    // the packed copy would shadow the file after a crash
+    pack.remove(&object.name);
+    pack.flush()?;
+
+    info!(
+        "Promoted {:?} ({} b) to a standalone file",
+        object.dir.join(&object.name),
+        content.len()
+    );
+    Ok(file)
+}
+
+/// Collects the directories below `dir` that hold a pack.
+fn collect_packs(dir: &Path, dirs: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut packed = false;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            collect_packs(&entry.path(), dirs)?;
+        } else if entry.file_name().to_string_lossy().starts_with(PACK_PREFIX) {
+            packed = true;
+        }
+    }
+    if packed {
+        dirs.push(dir.to_path_buf());
+    }
+    Ok(())
+}
+
+pub struct Adapter {}
+
+impl NamespaceAdapter<PackBackend> for Adapter {}
@@ -0,0 +1,144 @@
+use std::{collections::VecDeque, fs::File, io, os::fd::AsRawFd};
+
+use log::debug;
+
+use io_backends::common::prelude::*;
+
+/// Number of recent reads the detector looks at.
+const HISTORY_LEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    Unknown,
+    Sequential,
+    Strided(i64),
+    Random,
+}
+
+/// Tracks the recent accesses to an object and hints the page cache accordingly.
+pub struct AccessTracker {
+    history: VecDeque<(u64, u64)>,
+    pattern: Pattern,
+    n_reads: u64,
+    written: Option<(u64, u64)>,
+}
+
+impl AccessTracker {
+    pub fn new() -> Self {
+        AccessTracker {
+            history: VecDeque::with_capacity(HISTORY_LEN),
+            pattern: Pattern::Unknown,
+            n_reads: 0,
+            written: None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn pattern(&self) -> Pattern {
+        self.pattern
+    }
+
+    pub fn on_read(&mut self, file: &File, offset: u64, length: u64) -> Result<()> {
+        self.n_reads += 1;
+
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back((offset, length));
+
+        let pattern = self.classify();
+        if pattern != self.pattern {
+            debug!(
+                "access pattern of fd {} changed: {:?} => {pattern:?}",
+                file.as_raw_fd(),
+                self.pattern
+            );
+            self.pattern = pattern;
+
+            match pattern {
+                Pattern::Sequential => advise(file, 0, 0, libc::POSIX_FADV_SEQUENTIAL)?,
+                Pattern::Random => advise(file, 0, 0, libc::POSIX_FADV_RANDOM)?,
+                Pattern::Strided(_) | Pattern::Unknown => {
+                    advise(file, 0, 0, libc::POSIX_FADV_NORMAL)?
+                }
+            }
+        }
+
+        // read-ahead does not help strided access, so prefetch the next stride explicitly
+        if let Pattern::Strided(stride) = self.pattern {
+            let next = offset as i64 + stride;
+            if next >= 0 {
+                debug!("prefetching {length} b at {next} for strided access");
+                advise(file, next as u64, length, libc::POSIX_FADV_WILLNEED)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn on_write(&mut self, offset: u64, length: u64) {
+        let end = offset + length;
+        self.written = match self.written {
+            Some((start, stop)) => Some((start.min(offset), stop.max(end))),
+            None => Some((offset, end)),
+        };
+    }
+
+    #[allow(dead_code)]
+    pub fn written(&self) -> Option<(u64, u64)> {
+        self.written
+    }
+
+    /// Drops data that was only written, never read, from the page cache.
+    /// `POSIX_FADV_DONTNEED` starts the write-back of dirty pages without waiting for
+    /// it and only drops the pages that are clean already, so this never blocks on I/O.
+    pub fn on_sync(&mut self, file: &File) -> Result<()> {
+        let (start, end) = match self.written.take() {
+            Some(range) if self.n_reads == 0 => range,
+            _ => return Ok(()),
+        };
+
+        debug!(
+            "dropping write-once range {start}..{end} of fd {} from page cache",
+            file.as_raw_fd()
+        );
+        advise(file, start, end - start, libc::POSIX_FADV_DONTNEED)
+    }
+
+    fn classify(&self) -> Pattern {
+        if self.history.len() < HISTORY_LEN {
+            return self.pattern;
+        }
+
+        let pairs = || self.history.iter().zip(self.history.iter().skip(1));
+
+        if pairs().all(|((offset, length), (next, _))| offset + length == *next) {
+            return Pattern::Sequential;
+        }
+
+        let stride = self.history[1].0 as i64 - self.history[0].0 as i64;
+        if stride != 0
+            && pairs().all(|((offset, _), (next, _))| *next as i64 - *offset as i64 == stride)
+        {
+            return Pattern::Strided(stride);
+        }
+
+        Pattern::Random
+    }
+}
+
+fn advise(file: &File, offset: u64, length: u64, advice: libc::c_int) -> Result<()> {
+    let ret = unsafe {
+        libc::posix_fadvise(
+            file.as_raw_fd(),
+            offset as libc::off_t,
+            length as libc::off_t,
+            advice,
+        )
+    };
+
+    match ret {
+        0 => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno).into()),
+    }
+}
@@ -1,3 +1,4 @@
+mod fadvise;
 mod posix;
 
 use io_backends::generate_backend;
@@ -21,20 +22,30 @@ pub unsafe extern "C" fn backend_info() -> *mut JBackend {
 
 #[cfg(test)]
 mod test {
+    use std::ffi::CString;
+    use std::fs::{self, File, OpenOptions};
+    use std::io::{IoSlice, IoSliceMut};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::{FileExt, MetadataExt};
+    use std::process::Command;
+
     use io_backends::prelude::*;
     use io_backends::test_facility::writes;
     use io_backends::testing::*;
 
-    use crate::posix::PosixObject;
+    use crate::fadvise::{AccessTracker, Pattern};
+    use crate::posix::{Adapter, PosixObject};
     use crate::BACKEND;
 
     // #[test]
     fn _test_posix_workflow() {
         let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
         let data_factory = |namespace| {
-            let data = Backend::<PosixObject> {
+            let data = Backend::<CoalescingObject<EncryptedObject<ChecksummedObject<PosixObject>>>> {
                 object_store: ObjectStore::new(),
                 namespace,
+                config: Config::default(),
+                pending: PendingLinks::new(),
             };
             Box::into_raw(Box::new(data)).cast::<gpointer>()
         };
@@ -45,13 +56,650 @@ mod test {
     fn repeated_writes() {
         let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
         let data_factory = |namespace| {
-            let data = Backend::<PosixObject> {
+            let data = Backend::<CoalescingObject<EncryptedObject<ChecksummedObject<PosixObject>>>> {
                 object_store: ObjectStore::new(),
                 namespace,
+                config: Config::default(),
+                pending: PendingLinks::new(),
             };
             Box::into_raw(Box::new(data)).cast::<gpointer>()
         };
 
         writes::test_writes(&backend, data_factory)
     }
+
+    #[test]
+    fn coalesced_writes() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory = |namespace| {
+            let data = Backend::<CoalescingObject<EncryptedObject<ChecksummedObject<PosixObject>>>> {
+                object_store: ObjectStore::new(),
+                namespace,
+                config: Config::default(),
+                pending: PendingLinks::new(),
+            };
+            Box::into_raw(Box::new(data)).cast::<gpointer>()
+        };
+
+        writes::test_writes_with_options(&backend, data_factory, "?coalesce=1M")
+    }
+
+    #[test]
+    fn test_posix_vectored() {
+        let temp = setup();
+        let path = temp.join(WRITE_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let mut object = PosixObject::new(file, &Config::default()).unwrap();
+
+        let segments = [
+            IoSlice::new(b"Hello"),
+            IoSlice::new(b", "),
+            IoSlice::new(b"world!"),
+        ];
+        assert_eq!(object.write_vectored(&segments, 2).unwrap(), 13);
+        assert_eq!(fs::read(&path).unwrap(), b"\0\0Hello, world!");
+
+        let (mut a, mut b) = ([0u8; 4], [0u8; 16]);
+        let mut buffers = [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)];
+        assert_eq!(object.read_vectored(&mut buffers, 3).unwrap(), 12);
+        assert_eq!(&a, b"ello");
+        assert_eq!(&b[..8], b", world!");
+
+        let mut buffer = [0u8; 64];
+        assert_eq!(object.read(&mut buffer, 0, 64).unwrap(), 15);
+
+        let empty = [IoSlice::new(b""), IoSlice::new(b"")];
+        assert_eq!(object.write_vectored(&empty, 4).unwrap(), 0);
+
+        drop(object);
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_posix_access_patterns() {
+        let temp = setup();
+        let file = File::open(temp.join(READ_FILE)).unwrap();
+        let mut tracker = AccessTracker::new();
+
+        for i in 0..4 {
+            tracker.on_read(&file, i * 3, 3).unwrap();
+        }
+        assert_eq!(tracker.pattern(), Pattern::Sequential);
+
+        for i in 0..4 {
+            tracker.on_read(&file, i * 4096, 16).unwrap();
+        }
+        assert_eq!(tracker.pattern(), Pattern::Strided(4096));
+
+        for offset in [7, 1, 12, 3] {
+            tracker.on_read(&file, offset, 1).unwrap();
+        }
+        assert_eq!(tracker.pattern(), Pattern::Random);
+
+        tracker.on_write(0, 13);
+        tracker.on_sync(&file).unwrap();
+        assert_eq!(tracker.written(), None);
+
+        drop(file);
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_posix_write_once() {
+        let temp = setup();
+        let path = temp.join(CREATE_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .unwrap();
+        let mut tracker = AccessTracker::new();
+
+        let content = [1u8; 8192];
+        file.write_all_at(&content, 4096).unwrap();
+        tracker.on_write(4096, 4096);
+        tracker.on_write(8192, 4096);
+        assert_eq!(tracker.written(), Some((4096, 12288)));
+
+        // the write-once range is dropped without waiting for its write-back
+        tracker.on_sync(&file).unwrap();
+        assert_eq!(tracker.written(), None);
+        tracker.on_sync(&file).unwrap();
+
+        let mut buffer = [0u8; 8192];
+        file.read_exact_at(&mut buffer, 4096).unwrap();
+        assert_eq!(buffer, content);
+
+        drop(file);
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_posix_preallocation() {
+        let temp = setup();
+        let path = temp.join(CREATE_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .unwrap();
+        let mut config = Config::default();
+        config.set("prealloc", "1M");
+        config.set("prealloc_growth", "true");
+
+        let mut object = PosixObject::create(file, &config).unwrap();
+        let allocated = || fs::metadata(&path).unwrap().blocks() * 512;
+        assert!(allocated() >= 1 << 20);
+
+        let content = [1u8; 4096];
+        object.write(&content, (1 << 20) + 1, 4096).unwrap();
+        assert!(allocated() >= 2 << 20);
+        assert_eq!(object.status().unwrap().1, (1 << 20) + 4097);
+
+        object.close().unwrap();
+        assert!(allocated() < 2 << 20);
+        assert_eq!(fs::metadata(&path).unwrap().len(), (1 << 20) + 4097);
+
+        drop(object);
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_posix_coalescing() {
+        let temp = setup();
+        let path = temp.join(WRITE_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let mut config = Config::default();
+        config.set("coalesce", "64");
+        let mut object =
+            CoalescingObject::<EncryptedObject<ChecksummedObject<PosixObject>>>::new(file, &config)
+                .unwrap();
+
+        object.write(b"Hello", 0, 5).unwrap();
+        object.write(b", wor", 5, 5).unwrap();
+        object.write(b"ld!", 10, 3).unwrap();
+        object.write(b"J", 0, 1).unwrap();
+        object.write(b"?", 20, 1).unwrap();
+        object.write(b"", 50, 0).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        assert_eq!(object.status().unwrap().1, 21);
+
+        // a read behind all buffered data does not flush
+        let mut buffer = [0u8; 4];
+        assert_eq!(object.read(&mut buffer, 30, 4).unwrap(), 0);
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+
+        let mut buffer = [0u8; 13];
+        assert_eq!(object.read(&mut buffer, 0, 13).unwrap(), 13);
+        assert_eq!(&buffer, b"Jello, world!");
+
+        // vectored writes are buffered as well and flushed before an overlapping read
+        let segments = [IoSlice::new(b"ab"), IoSlice::new(b""), IoSlice::new(b"cd")];
+        assert_eq!(object.write_vectored(&segments, 40).unwrap(), 4);
+        assert_eq!(fs::metadata(&path).unwrap().len(), 21);
+        let (mut a, mut b) = ([0u8; 1], [0u8; 3]);
+        let mut buffers = [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)];
+        assert_eq!(object.read_vectored(&mut buffers, 40).unwrap(), 4);
+        assert_eq!((&a, &b), (b"a", b"bcd"));
+
+        object.write(&[b'x'; 64], 100, 64).unwrap();
+        object.write(b"y", 200, 1).unwrap();
+        object.close().unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 201);
+
+        drop(object);
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_posix_coalescing_shared() {
+        let temp = setup();
+        let path = temp.join(WRITE_FILE);
+        let mut config = Config::default();
+        config.set("coalesce", "64");
+        let open = || {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap();
+            CoalescingObject::<EncryptedObject<ChecksummedObject<PosixObject>>>::new(file, &config)
+                .unwrap()
+        };
+        let (mut a, mut b) = (open(), open());
+
+        // writes buffered by one handle are seen by the other
+        a.write(b"Hello", 0, 5).unwrap();
+        assert_eq!(b.status().unwrap().1, 5);
+        let mut buffer = [0u8; 5];
+        assert_eq!(b.read(&mut buffer, 0, 5).unwrap(), 5);
+        assert_eq!(&buffer, b"Hello");
+
+        b.write(b"J", 0, 1).unwrap();
+        a.write(b"!", 5, 1).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"Hello");
+        b.close().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"Jello!");
+
+        drop((a, b));
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_posix_copy() {
+        let temp = setup();
+        let backend =
+            Backend::<CoalescingObject<EncryptedObject<ChecksummedObject<PosixObject>>>>::new(
+                String::from(temp.to_str().unwrap()),
+                Config::default(),
+            );
+
+        unsafe {
+            let copied = Adapter::backend_copy(
+                &backend,
+                "\0".as_ptr().cast(),
+                "read.txt\0".as_ptr().cast(),
+                "other\0".as_ptr().cast(),
+                "copy.txt\0".as_ptr().cast(),
+            )
+            .unwrap();
+            assert_eq!(copied, 13);
+
+            let exists = Adapter::backend_copy(
+                &backend,
+                "\0".as_ptr().cast(),
+                "read.txt\0".as_ptr().cast(),
+                "other\0".as_ptr().cast(),
+                "copy.txt\0".as_ptr().cast(),
+            );
+            assert!(exists.is_err());
+        }
+
+        assert_eq!(
+            fs::read_to_string(temp.join("other/copy.txt")).unwrap(),
+            "Hello, world!"
+        );
+
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_posix_atomic_create() {
+        let temp = setup();
+        let mut config = Config::default();
+        config.set("atomic_create", "true");
+        let backend =
+            Backend::<CoalescingObject<EncryptedObject<ChecksummedObject<PosixObject>>>>::new(
+                String::from(temp.to_str().unwrap()),
+                config,
+            );
+        let backend_data = &backend as *const _ as gpointer;
+        let names = || unsafe {
+            let mut iter =
+                Adapter::backend_get_iterator(&backend, "\0".as_ptr().cast(), None).unwrap();
+            let mut names = Vec::new();
+            while let Some(name) = Adapter::backend_iterate(&mut iter).unwrap() {
+                names.push(name.into_string().unwrap());
+            }
+            names
+        };
+
+        unsafe {
+            // O_TMPFILE, linked on sync
+            let handle = Adapter::backend_create(
+                &backend,
+                "\0".as_ptr().cast(),
+                "tmpfile\0".as_ptr().cast(),
+            )
+            .unwrap();
+            backend.write(&handle, b"Hello", 0, 5).unwrap();
+            assert!(!handle.path.exists());
+            assert_eq!(
+                Adapter::j_sync(backend_data, &handle as *const _ as gpointer),
+                TRUE
+            );
+            assert_eq!(fs::read(&handle.path).unwrap(), b"Hello");
+            assert_eq!(
+                Adapter::j_close(backend_data, &handle as *const _ as gpointer),
+                TRUE
+            );
+
+            // hidden temporary name, renamed on close
+            let path = temp.join("hidden");
+            let (file, link) = create_hidden(&path).unwrap();
+            let fd = file.as_raw_fd();
+            let object =
+                CoalescingObject::<EncryptedObject<ChecksummedObject<PosixObject>>>::create(
+                    file,
+                    &backend.config,
+                )
+                .unwrap();
+            backend.object_store.insert(object, fd).unwrap();
+            backend.pending.insert(fd, link).unwrap();
+            let handle = ObjectHandle { raw_fd: fd, path };
+
+            backend.write(&handle, b"world", 0, 5).unwrap();
+            assert!(!names().contains(&String::from("hidden")));
+            assert!(names().iter().all(|name| !name.starts_with(TEMP_PREFIX)));
+            assert_eq!(
+                Adapter::j_close(backend_data, &handle as *const _ as gpointer),
+                TRUE
+            );
+            assert_eq!(fs::read(&handle.path).unwrap(), b"world");
+            assert_eq!(fs::read_dir(&temp).unwrap().count(), names().len());
+
+            // deleted before it was ever visible
+            let handle =
+                Adapter::backend_create(&backend, "\0".as_ptr().cast(), "gone\0".as_ptr().cast())
+                    .unwrap();
+            Adapter::backend_delete(&backend, &handle).unwrap();
+            assert!(!handle.path.exists());
+
+            // an existing object is never replaced, publishing its twin fails
+            let handle = Adapter::backend_create(
+                &backend,
+                "\0".as_ptr().cast(),
+                "tmpfile\0".as_ptr().cast(),
+            )
+            .unwrap();
+            backend.write(&handle, b"Jello", 0, 5).unwrap();
+            assert_eq!(
+                Adapter::j_close(backend_data, &handle as *const _ as gpointer),
+                FALSE
+            );
+            assert_eq!(fs::read(&handle.path).unwrap(), b"Hello");
+        }
+
+        // never published before the backend is released
+        let (file, link) = create_hidden(&temp.join("unclosed")).unwrap();
+        backend.pending.insert(file.as_raw_fd(), link).unwrap();
+        drop(file);
+        drop(backend);
+        assert!(fs::read_dir(&temp).unwrap().all(|entry| !entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with(TEMP_PREFIX)));
+
+        // left behind by processes that exited, swept on init
+        let mut child = Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        fs::create_dir(temp.join("ns")).unwrap();
+        let dead = temp.join(format!("ns/{TEMP_PREFIX}{}-obj", child.id()));
+        let alive = temp.join(format!("ns/{TEMP_PREFIX}{}-obj", std::process::id()));
+        fs::write(&dead, b"").unwrap();
+        fs::write(&alive, b"").unwrap();
+        let path = CString::new(format!("{}?atomic_create", temp.to_str().unwrap())).unwrap();
+        unsafe { Adapter::backend_init(path.as_ptr()).unwrap() };
+        assert!(!dead.exists());
+        assert!(alive.exists());
+
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_posix_readonly() {
+        let temp = setup();
+        let mut config = Config::default();
+        config.set("readonly", "true");
+        let backend =
+            Backend::<CoalescingObject<EncryptedObject<ChecksummedObject<PosixObject>>>>::new(
+                String::from(temp.to_str().unwrap()),
+                config,
+            );
+        let backend_data = &backend as *const _ as gpointer;
+
+        unsafe {
+            let handle =
+                Adapter::backend_create(&backend, "\0".as_ptr().cast(), "obj\0".as_ptr().cast())
+                    .unwrap();
+            backend.write(&handle, b"Hello", 0, 5).unwrap();
+            assert_eq!(
+                Adapter::j_close(backend_data, &handle as *const _ as gpointer),
+                TRUE
+            );
+
+            // opened read-only, reopened for writing on the first write
+            let handle =
+                Adapter::backend_open(&backend, "\0".as_ptr().cast(), "obj\0".as_ptr().cast())
+                    .unwrap();
+            assert!(!is_writable(handle.raw_fd).unwrap());
+            backend.write(&handle, b"J", 0, 1).unwrap();
+            assert!(is_writable(handle.raw_fd).unwrap());
+            assert_eq!(
+                Adapter::j_close(backend_data, &handle as *const _ as gpointer),
+                TRUE
+            );
+            assert_eq!(fs::read(temp.join("obj")).unwrap(), b"Jello");
+        }
+
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_posix_encryption() {
+        let temp = setup();
+        let path = temp.join(CREATE_FILE);
+        let key = temp.join("master.key");
+        fs::write(
+            &key,
+            "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff\n",
+        )
+        .unwrap();
+
+        let mut config = Config::default();
+        config.set("encrypt_key", key.to_str().unwrap());
+        for size in ["0", "4G"] {
+            config.set("encrypt_block", size);
+            assert!(EncryptedObject::<PosixObject>::init(&config).is_err());
+        }
+        config.set("encrypt_block", "1K");
+        EncryptedObject::<PosixObject>::init(&config).unwrap();
+
+        let open = |config: &Config| {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)
+                .unwrap();
+            EncryptedObject::<PosixObject>::new(file, config).unwrap()
+        };
+
+        let text = b"All work and no play makes Jack a dull boy. ".repeat(100);
+        let mut object = open(&config);
+        object.write(&text, 0, text.len() as u64).unwrap();
+        object.write(b"JULEA", 2_000, 5).unwrap();
+        object.write(b"end", 10_000, 3).unwrap();
+        object.sync().unwrap();
+        assert_eq!(object.status().unwrap().1, 10_003);
+        drop(object);
+
+        // neither the text nor any part of it ends up on disk
+        let stored = fs::read(&path).unwrap();
+        assert!(stored.len() > 10_003);
+        assert!(!stored.windows(16).any(|w| text.windows(16).any(|t| t == w)));
+        assert!(!stored.windows(5).any(|w| w == b"JULEA"));
+
+        let object = open(&config);
+        assert_eq!(object.status().unwrap().1, 10_003);
+        let mut buffer = vec![0u8; 12];
+        assert_eq!(object.read(&mut buffer, 1_998, 12).unwrap(), 12);
+        assert_eq!(&buffer[2..7], b"JULEA");
+        assert_eq!(object.read(&mut buffer, 9_994, 12).unwrap(), 9);
+        assert_eq!(&buffer[..9], b"\0\0\0\0\0\0end");
+        // the blocks between the text and the end are holes
+        assert_eq!(object.read(&mut buffer, 6_000, 12).unwrap(), 12);
+        assert_eq!(buffer, [0u8; 12]);
+        drop(object);
+
+        // a modified byte is detected, other blocks stay readable
+        let mut tampered = stored.clone();
+        tampered[96 + 3 * (24 + 1024 + 16) + 100] ^= 1;
+        fs::write(&path, &tampered).unwrap();
+        let object = open(&config);
+        let mut buffer = vec![0u8; 1024];
+        assert!(object.read(&mut buffer, 3 * 1024, 1024).is_err());
+        assert_eq!(object.read(&mut buffer, 0, 1024).unwrap(), 1024);
+        assert_eq!(&buffer[..], &text[..1024]);
+        drop(object);
+
+        // dropping whole blocks from the end is detected as well
+        fs::write(&path, &stored[..stored.len() - (24 + 787 + 16)]).unwrap();
+        let object = open(&config);
+        assert_eq!(object.status().unwrap().1, 10_003);
+        assert!(object.read(&mut buffer, 9_216, 1024).is_err());
+        drop(object);
+        fs::write(&path, &stored).unwrap();
+
+        // all handles of an object share its size, so none of them zeroes the blocks
+        // another one wrote
+        let mut first = open(&config);
+        let mut second = open(&config);
+        first.write(b"first", 12_000, 5).unwrap();
+        assert_eq!(second.status().unwrap().1, 12_005);
+        second.write(b"second", 11_000, 6).unwrap();
+        let mut buffer = vec![0u8; 5];
+        assert_eq!(first.read(&mut buffer, 12_000, 5).unwrap(), 5);
+        assert_eq!(&buffer, b"first");
+        drop((first, second));
+
+        // a write far behind the end does not seal the blocks it skips
+        let mut object = open(&config);
+        object.write(b"!", 1 << 30, 1).unwrap();
+        assert!(fs::metadata(&path).unwrap().blocks() < 1 << 10);
+        let mut buffer = vec![1u8; 4096];
+        assert_eq!(object.read(&mut buffer, 1 << 29, 4096).unwrap(), 4096);
+        assert!(buffer.iter().all(|b| *b == 0));
+        assert_eq!(object.read(&mut buffer, 1 << 30, 4096).unwrap(), 1);
+        assert_eq!(buffer[0], b'!');
+        drop(object);
+
+        // without the key, the ciphertext is all there is
+        let stored = fs::metadata(&path).unwrap().len();
+        let object = open(&Config::default());
+        assert_eq!(object.status().unwrap().1, stored);
+
+        shutdown(temp);
+    }
+
+    #[test]
+    fn test_posix_checksums() {
+        let temp = setup();
+        let mut config = Config::default();
+        config.set("checksum", "true");
+        for size in ["0", "4G"] {
+            config.set("checksum_block", size);
+            assert!(ChecksummedObject::<PosixObject>::init(&config).is_err());
+        }
+        config.set("checksum_block", "1K");
+        let mut rebuild = config.clone();
+        rebuild.set("checksum_rebuild", "true");
+        let backend =
+            Backend::<CoalescingObject<EncryptedObject<ChecksummedObject<PosixObject>>>>::new(
+                String::from(temp.to_str().unwrap()),
+                config,
+            );
+        let backend_data = &backend as *const _ as gpointer;
+        let sidecar = temp.join(".jsum-sums");
+
+        unsafe {
+            let handle =
+                Adapter::backend_create(&backend, "\0".as_ptr().cast(), "sums\0".as_ptr().cast())
+                    .unwrap();
+            backend.write(&handle, &[b'a'; 3000], 0, 3000).unwrap();
+            backend.write(&handle, b"end", 5000, 3).unwrap();
+            assert_eq!(
+                Adapter::j_close(backend_data, &handle as *const _ as gpointer),
+                TRUE
+            );
+            assert_eq!(fs::metadata(&sidecar).unwrap().len(), 16 + 5 * 4);
+
+            let mut iter =
+                Adapter::backend_get_iterator(&backend, "\0".as_ptr().cast(), None).unwrap();
+            while let Some(name) = Adapter::backend_iterate(&mut iter).unwrap() {
+                assert!(!name.to_str().unwrap().starts_with(CHECKSUM_PREFIX));
+            }
+
+            // flip a bit in the second block behind the backend's back
+            let file = OpenOptions::new().write(true).open(&handle.path).unwrap();
+            file.write_all_at(b"b", 1500).unwrap();
+
+            let handle =
+                Adapter::backend_open(&backend, "\0".as_ptr().cast(), "sums\0".as_ptr().cast())
+                    .unwrap();
+            let mut buffer = vec![0u8; 1024];
+            assert_eq!(backend.read(&handle, &mut buffer, 0, 1024).unwrap(), 1024);
+            assert_eq!(backend.read(&handle, &mut buffer, 4990, 1024).unwrap(), 13);
+            assert_eq!(&buffer[10..13], b"end");
+
+            let e = backend.read(&handle, &mut buffer, 500, 1024).unwrap_err();
+            assert_eq!(
+                e.kind(),
+                &ErrorKind::ChecksumMismatch {
+                    path: handle.path.clone(),
+                    blocks: 1..=1,
+                }
+            );
+            assert!(backend.write(&handle, b"x", 1100, 1).is_err());
+
+            // rewriting the whole block repairs it
+            backend.write(&handle, &[b'c'; 1024], 1024, 1024).unwrap();
+            assert_eq!(
+                backend.read(&handle, &mut buffer, 1024, 1024).unwrap(),
+                1024
+            );
+            assert!(buffer.iter().all(|b| *b == b'c'));
+
+            // all handles of an object share its checksums
+            let other =
+                Adapter::backend_open(&backend, "\0".as_ptr().cast(), "sums\0".as_ptr().cast())
+                    .unwrap();
+            backend.write(&handle, &[b'd'; 1100], 4990, 1100).unwrap();
+            assert_eq!(backend.read(&other, &mut buffer, 5000, 1024).unwrap(), 1024);
+            assert!(buffer.iter().all(|b| *b == b'd'));
+            for handle in [&handle, &other] {
+                assert_eq!(
+                    Adapter::j_close(backend_data, handle as *const _ as gpointer),
+                    TRUE
+                );
+            }
+
+            // a corrupt sidecar fails the open, unless it is rebuilt on request
+            let sums = fs::read(&sidecar).unwrap();
+            fs::write(&sidecar, &sums[..sums.len() - 4]).unwrap();
+            let e = Adapter::backend_open(&backend, "\0".as_ptr().cast(), "sums\0".as_ptr().cast())
+                .err()
+                .unwrap();
+            assert_eq!(
+                e.kind(),
+                &ErrorKind::CorruptChecksums {
+                    path: sidecar.clone()
+                }
+            );
+            let rebuilt = Backend::<
+                CoalescingObject<EncryptedObject<ChecksummedObject<PosixObject>>>,
+            >::new(String::from(temp.to_str().unwrap()), rebuild);
+            let handle =
+                Adapter::backend_open(&rebuilt, "\0".as_ptr().cast(), "sums\0".as_ptr().cast())
+                    .unwrap();
+            assert_eq!(fs::read(&sidecar).unwrap(), sums);
+
+            Adapter::backend_delete(&rebuilt, &handle).unwrap();
+            assert!(!sidecar.exists());
+        }
+
+        shutdown(temp);
+    }
 }
@@ -1,47 +1,206 @@
 use std::{
+    cmp::min,
     fs::File,
-    io::Write,
-    os::unix::fs::{FileExt, MetadataExt},
+    io::{self, ErrorKind, IoSlice, IoSliceMut, Write},
+    os::{
+        fd::AsRawFd,
+        unix::fs::{FileExt, MetadataExt},
+    },
+    sync::Mutex,
 };
 
 use io_backends::common::prelude::*;
+use log::warn;
+
+use crate::fadvise::AccessTracker;
 
 // INIT
 pub struct Adapter {}
 
 pub struct PosixObject {
     file: File,
+    tracker: Option<Mutex<AccessTracker>>,
+    prealloc: Preallocator,
+}
+
+impl PosixObject {
+    fn track(&self, op: impl FnOnce(&mut AccessTracker) -> Result<()>) -> Result<()> {
+        match &self.tracker {
+            Some(tracker) => op(&mut *tracker
+                .lock()
+                .map_err(|e| BackendError::map(&e, Action::Internal))?),
+            None => Ok(()),
+        }
+    }
+
+    /// Issues `preadv`/`pwritev` until all segments are transferred, the file ends
+    /// (reads only) or an error other than EINTR occurs.
+    fn transfer_vectored(
+        &self,
+        mut iovecs: Vec<libc::iovec>,
+        offset: u64,
+        write: bool,
+    ) -> io::Result<u64> {
+        // empty segments transfer nothing, and a call with only empty segments returns 0
+        iovecs.retain(|iovec| iovec.iov_len > 0);
+
+        let fd = self.file.as_raw_fd();
+        let mut transferred: u64 = 0;
+        let mut first = 0;
+
+        while first < iovecs.len() {
+            let segments = &iovecs[first..];
+            let count = min(segments.len(), libc::UIO_MAXIOV as usize) as libc::c_int;
+            let pos = (offset + transferred) as libc::off_t;
+
+            let ret = unsafe {
+                if write {
+                    libc::pwritev(fd, segments.as_ptr(), count, pos)
+                } else {
+                    libc::preadv(fd, segments.as_ptr(), count, pos)
+                }
+            };
+
+            let mut n = match ret {
+                n if n < 0 => {
+                    let e = io::Error::last_os_error();
+                    if e.kind() == ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(e);
+                }
+                0 if write => return Err(ErrorKind::WriteZero.into()),
+                0 => break,
+                n => n as usize,
+            };
+            transferred += n as u64;
+
+            // skip the segments that were transferred completely and trim the first partial one
+            while first < iovecs.len() && n >= iovecs[first].iov_len {
+                n -= iovecs[first].iov_len;
+                first += 1;
+            }
+            if n > 0 {
+                let iovec = &mut iovecs[first];
+                iovec.iov_base = unsafe { iovec.iov_base.cast::<u8>().add(n).cast() };
+                iovec.iov_len -= n;
+            }
+        }
+
+        Ok(transferred)
+    }
 }
 
 impl BackendObject for PosixObject {
-    fn new(file: File) -> Result<Self> {
-        return Ok(PosixObject { file });
+    fn new(file: File, config: &Config) -> Result<Self> {
+        let tracker = match config.get_bool_or("fadvise", true)? {
+            true => Some(Mutex::new(AccessTracker::new())),
+            false => None,
+        };
+        let prealloc = Preallocator::new(&file, config)?;
+        return Ok(PosixObject {
+            file,
+            tracker,
+            prealloc,
+        });
     }
 
-    fn read(&self, buffer: &mut [u8], offset: u64, _length: u64) -> Result<u64> {
-        self.file
-            .read_at(buffer, offset)
-            .map_err(|e| BackendError::map(&e, Action::Read))
-            .map(|n| n as u64)
+    fn create(file: File, config: &Config) -> Result<Self> {
+        let mut object = Self::new(file, config)?;
+        object.prealloc.on_create(&object.file)?;
+        Ok(object)
+    }
+
+    fn read(&self, buffer: &mut [u8], offset: u64, length: u64) -> Result<u64> {
+        // hints are best effort and must not fail the read itself
+        if let Err(e) = self.track(|t| t.on_read(&self.file, offset, length)) {
+            warn!("{e}");
+        }
+
+        let mut n_read = 0;
+        while n_read < buffer.len() {
+            match self
+                .file
+                .read_at(&mut buffer[n_read..], offset + n_read as u64)
+            {
+                Ok(0) => break,
+                Ok(n) => n_read += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(BackendError::map(&e, Action::Read)),
+            }
+        }
+        Ok(n_read as u64)
     }
 
     fn write(&mut self, buffer: &[u8], offset: u64, _length: u64) -> Result<u64> {
+        self.track(|t| {
+            t.on_write(offset, buffer.len() as u64);
+            Ok(())
+        })?;
+        self.prealloc
+            .before_write(&self.file, offset, buffer.len() as u64)?;
+
         self.file
-            .write_at(buffer, offset)
+            .write_all_at(buffer, offset)
+            .map_err(|e| BackendError::map(&e, Action::Write))
+            .map(|_| buffer.len() as u64)
+    }
+
+    fn read_vectored(&self, buffers: &mut [IoSliceMut], offset: u64) -> Result<u64> {
+        let length = buffers.iter().map(|b| b.len() as u64).sum();
+        if let Err(e) = self.track(|t| t.on_read(&self.file, offset, length)) {
+            warn!("{e}");
+        }
+
+        let iovecs = buffers
+            .iter_mut()
+            .map(|b| libc::iovec {
+                iov_base: b.as_mut_ptr().cast(),
+                iov_len: b.len(),
+            })
+            .collect();
+
+        self.transfer_vectored(iovecs, offset, false)
+            .map_err(|e| BackendError::map(&e, Action::Read))
+    }
+
+    fn write_vectored(&mut self, buffers: &[IoSlice], offset: u64) -> Result<u64> {
+        let length = buffers.iter().map(|b| b.len() as u64).sum();
+        self.track(|t| {
+            t.on_write(offset, length);
+            Ok(())
+        })?;
+        self.prealloc.before_write(&self.file, offset, length)?;
+
+        let iovecs = buffers
+            .iter()
+            .map(|b| libc::iovec {
+                iov_base: b.as_ptr().cast_mut().cast(),
+                iov_len: b.len(),
+            })
+            .collect();
+
+        self.transfer_vectored(iovecs, offset, true)
             .map_err(|e| BackendError::map(&e, Action::Write))
-            .map(|n| n as u64)
     }
 
     fn sync(&mut self) -> Result<()> {
         self.file
             .flush()
-            .map_err(|e| BackendError::map(&e, Action::Sync))
+            .map_err(|e| BackendError::map(&e, Action::Sync))?;
+
+        self.track(|t| t.on_sync(&self.file))
+            .map_err(|e| e.set_action(Action::Sync))
     }
 
     fn status(&self) -> Result<(i64, u64)> {
         let metadata = self.file.metadata()?;
         Ok((metadata.atime(), metadata.size()))
     }
+
+    fn close(&mut self) -> Result<()> {
+        self.prealloc.trim(&self.file)
+    }
 }
 
-impl JuleaAdapter<PosixObject> for Adapter {}
+impl JuleaAdapter<CoalescingObject<EncryptedObject<ChecksummedObject<PosixObject>>>> for Adapter {}
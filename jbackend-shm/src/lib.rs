@@ -0,0 +1,108 @@
+mod shm;
+
+use io_backends::generate_backend;
+use io_backends::prelude::*;
+use log::debug;
+use log::info;
+
+generate_backend!(shm);
+
+#[no_mangle]
+pub unsafe extern "C" fn backend_info() -> *mut JBackend {
+    match init_logger() {
+        Ok(_) => info!("logger initialized."),
+        Err(e) => {
+            let _ = println!("Error while initializing logger: {e:?}");
+        }
+    };
+    debug!("backend info called");
+    &mut BACKEND
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{self, File};
+    use std::path::Path;
+
+    use io_backends::prelude::*;
+    use io_backends::testing::*;
+    use memmap2::Mmap;
+
+    use crate::shm::{segment_root, Adapter, SHM_ROOT};
+    use crate::BACKEND;
+
+    #[test]
+    fn test_shm_workflow() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory = |namespace| {
+            let data = Backend::<MmapObject> {
+                object_store: ObjectStore::new(),
+                namespace,
+                config: Config::default(),
+                pending: PendingLinks::new(),
+            };
+            Box::into_raw(Box::new(data)).cast::<gpointer>()
+        };
+        test_workflow_in(Path::new(SHM_ROOT), &backend, &data_factory);
+    }
+
+    #[test]
+    fn test_shm_lifetime() {
+        let name = format!("jbackend-shm-test-{}", std::process::id());
+        let root = segment_root(&name);
+
+        unsafe {
+            let backend =
+                Adapter::backend_init(format!("{name}?shm_lifetime=reboot\0").as_ptr().cast())
+                    .unwrap();
+            let handle =
+                Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "obj\0".as_ptr().cast())
+                    .unwrap();
+            assert_eq!(handle.path, root.join("ns/obj"));
+            backend.write(&handle, b"Hello", 0, 5).unwrap();
+
+            // another process maps the published segment
+            let segment = File::open(root.join("ns/obj")).unwrap();
+            let mapping = Mmap::map(&segment).unwrap();
+            assert_eq!(&mapping[..], b"Hello");
+
+            Adapter::backend_delete(&backend, &handle).unwrap();
+            assert!(!root.join("ns/obj").exists());
+            assert_eq!(&mapping[..], b"Hello");
+            assert_eq!(fs::read(root.join(".retired/ns/obj")).unwrap(), b"Hello");
+
+            Adapter::j_fini(Box::into_raw(Box::new(backend)).cast());
+            assert!(root.is_dir());
+
+            let backend =
+                Adapter::backend_init(format!("{name}?shm_lifetime=fini\0").as_ptr().cast())
+                    .unwrap();
+            Adapter::j_fini(Box::into_raw(Box::new(backend)).cast());
+            assert!(!root.exists());
+
+            let invalid =
+                Adapter::backend_init(format!("{name}?shm_lifetime=never\0").as_ptr().cast());
+            assert!(invalid.is_err());
+
+            // segments are only ever removed below /dev/shm
+            let outside = Path::new("/tmp").join(&name);
+            for path in [
+                outside.to_str().unwrap(),
+                "../tmp",
+                &format!("{name}/../.."),
+                "",
+                ".",
+                SHM_ROOT,
+                &format!("{SHM_ROOT}/"),
+            ] {
+                for lifetime in ["delete", "fini"] {
+                    let refused = Adapter::backend_init(
+                        format!("{path}?shm_lifetime={lifetime}\0").as_ptr().cast(),
+                    );
+                    assert!(refused.is_err());
+                }
+            }
+            assert!(!outside.exists());
+        }
+    }
+}
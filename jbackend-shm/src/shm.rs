@@ -0,0 +1,150 @@
+use std::{
+    ffi::CString,
+    fs, io, mem,
+    os::unix::ffi::OsStrExt,
+    path::{Component, Path, PathBuf},
+};
+
+use log::{debug, error, info, warn};
+
+use io_backends::prelude::*;
+
+/// Directory relative init paths are resolved against. Every process on the node can
+/// map an object by opening `<root>/<namespace>/<name>` below it.
+pub const SHM_ROOT: &str = "/dev/shm";
+
+/// Directory below the root that deleted objects are moved to with `shm_lifetime=reboot`.
+const RETIRED_DIR: &str = ".retired";
+
+/// When the segments of a backend disappear, set with the option `shm_lifetime`.
+#[derive(Debug, PartialEq)]
+pub enum Lifetime {
+    /// All segments are removed when the backend is released.
+    Fini,
+    /// Segments outlive the backend and are removed when their object is deleted.
+    Delete,
+    /// The backend never removes segments, deleted objects are moved out of their
+    /// namespace into `.retired`. Everything is gone once tmpfs is, at the next reboot.
+    Reboot,
+}
+
+impl Lifetime {
+    fn from_config(config: &Config) -> Result<Self> {
+        match config.get("shm_lifetime") {
+            None | Some("delete") => Ok(Lifetime::Delete),
+            Some("fini") => Ok(Lifetime::Fini),
+            Some("reboot") => Ok(Lifetime::Reboot),
+            Some(v) => Err(BackendError::new(
+                &format!("Invalid value '{v}' for backend option 'shm_lifetime'"),
+                Action::Init,
+            )),
+        }
+    }
+}
+
+/// Resolves the init path to the directory holding the segments.
+pub fn segment_root(path: &str) -> PathBuf {
+    // joining an absolute path replaces the shm root
+    Path::new(SHM_ROOT).join(path)
+}
+
+/// Refuses roots the backend must not remove segments from. Unless segments are kept
+/// until reboot, the backend deletes files below its root, which is therefore limited
+/// to tmpfs below `/dev/shm`, never `/dev/shm` itself.
+fn check_root(root: &Path, lifetime: &Lifetime) -> Result<()> {
+    if *lifetime == Lifetime::Reboot {
+        return Ok(());
+    }
+
+    let below = match root.strip_prefix(SHM_ROOT) {
+        Ok(rest) => {
+            rest.components().next().is_some()
+                && rest.components().all(|c| matches!(c, Component::Normal(_)))
+        }
+        Err(_) => false,
+    };
+    if !below {
+        return Err(BackendError::new(
+            &format!("{root:?} is not below {SHM_ROOT}, refusing to remove segments there"),
+            Action::Init,
+        ));
+    }
+    Ok(())
+}
+
+fn is_tmpfs(path: &Path) -> io::Result<bool> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statfs = unsafe { mem::zeroed() };
+
+    match unsafe { libc::statfs(path.as_ptr(), &mut stat) } {
+        0 => Ok(stat.f_type == libc::TMPFS_MAGIC),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+pub struct Adapter {}
+
+impl JuleaAdapter<MmapObject> for Adapter {
+    unsafe extern "C" fn j_fini(backend_data: gpointer) {
+        let backend_data = Box::from_raw(backend_data.cast::<Backend<MmapObject>>());
+
+        match Lifetime::from_config(&backend_data.config) {
+            Ok(Lifetime::Fini) => {
+                info!("Releasing backend, removing {}", backend_data.namespace);
+                if let Err(e) = fs::remove_dir_all(&backend_data.namespace) {
+                    error!("{}", BackendError::map(&e, Action::Fini));
+                }
+            }
+            _ => info!("Releasing backend, keeping {}", backend_data.namespace),
+        }
+    }
+
+    unsafe fn backend_init(path: *const gchar) -> Result<Backend<MmapObject>> {
+        let path = read_str(path).map_err(|e| e.set_action(Action::Init))?;
+        let (path, config) = Config::from_init_path(&path)?;
+        let lifetime = Lifetime::from_config(&config)?;
+        MmapObject::init(&config)?;
+
+        let root = segment_root(&path);
+        info!("Initializing shared memory backend in {root:?}, segments live until {lifetime:?}");
+
+        check_root(&root, &lifetime)?;
+        fs::create_dir_all(&root)?;
+        if !is_tmpfs(&root)? {
+            if lifetime != Lifetime::Reboot {
+                return Err(BackendError::new(
+                    &format!("{root:?} is not on tmpfs, refusing to remove segments there"),
+                    Action::Init,
+                ));
+            }
+            warn!("{root:?} is not on tmpfs, segments are backed by a disk");
+        }
+        if config.get_bool("atomic_create")? {
+            sweep_hidden(&root)?;
+        }
+
+        let root = root.to_str().ok_or(BackendError::new(
+            "Unable to convert segment path to UTF-8",
+            Action::Init,
+        ))?;
+        Ok(Backend::new(String::from(root), config))
+    }
+
+    fn remove_object(backend_data: &Backend<MmapObject>, path: &Path) -> Result<()> {
+        if Lifetime::from_config(&backend_data.config)? != Lifetime::Reboot {
+            return fs::remove_file(path).map_err(|e| BackendError::map(&e, Action::Delete));
+        }
+
+        let root = Path::new(&backend_data.namespace);
+        let retired = root.join(RETIRED_DIR).join(
+            path.strip_prefix(root)
+                .map_err(|e| BackendError::map(&e, Action::Delete))?,
+        );
+        debug!("Retiring {path:?} to {retired:?}");
+
+        if let Some(dir) = retired.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::rename(path, retired).map_err(|e| BackendError::map(&e, Action::Delete))
+    }
+}
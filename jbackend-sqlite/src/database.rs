@@ -0,0 +1,318 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+    sync::{Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::debug;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use io_backends::prelude::*;
+
+/// File name suffix of the database holding the objects of a namespace.
+pub const DATABASE_SUFFIX: &str = ".sqlite";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS meta (
+        key TEXT PRIMARY KEY,
+        value INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS objects (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE,
+        size INTEGER NOT NULL,
+        modified INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS pages (
+        object INTEGER NOT NULL,
+        page INTEGER NOT NULL,
+        data BLOB NOT NULL,
+        PRIMARY KEY (object, page)
+    ) WITHOUT ROWID;
+";
+
+/// A SQLite database holding all objects of a namespace, `<root>/<namespace>.sqlite`.
+///
+/// Page `i` of an object, bytes `i * page_size..(i + 1) * page_size`, is a row of the
+/// `pages` table. Pages are only stored once they are written to and may be shorter
+/// than the page size, missing bytes read as zeros. Changes are collected in a
+/// transaction that is committed on sync, so a crash loses nothing that was synced.
+pub struct Database {
+    page_size: u64,
+    conn: Mutex<Connection>,
+}
+
+impl Database {
+    /// Opens the database at `path`, or creates it with pages of `page_size` bytes if
+    /// `create` is set. Returns `None` if it does not exist and is not created.
+    pub fn open(path: &Path, page_size: u64, create: bool) -> Result<Option<Self>> {
+        if !create && !path.exists() {
+            return Ok(None);
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let conn = Connection::open(path).map_err(sql)?;
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .and_then(|_| conn.pragma_update(None, "synchronous", "FULL"))
+            .and_then(|_| conn.execute_batch(SCHEMA))
+            .and_then(|_| {
+                conn.execute(
+                    "INSERT OR IGNORE INTO meta (key, value) VALUES ('page_size', ?1)",
+                    [page_size],
+                )
+            })
+            .map_err(sql)?;
+        // existing databases keep the page size they were created with
+        let page_size: u64 = conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'page_size'",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(sql)?;
+        if page_size == 0 {
+            return Err(BackendError::new(
+                &format!("{path:?} is corrupted"),
+                Action::Open,
+            ));
+        }
+        debug!("opened {path:?} with pages of {page_size} b");
+
+        Ok(Some(Database {
+            page_size,
+            conn: Mutex::new(conn),
+        }))
+    }
+
+    /// Locks the connection and starts a transaction unless one is running already.
+    fn begin(&self) -> Result<MutexGuard<'_, Connection>> {
+        let conn = self.lock()?;
+        if conn.is_autocommit() {
+            conn.execute_batch("BEGIN IMMEDIATE").map_err(sql)?;
+        }
+        Ok(conn)
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Connection>> {
+        self.conn
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))
+    }
+
+    /// Creates the object called `name` and returns its id. An existing object is
+    /// returned instead, unless `exclusive` is set.
+    pub fn create(&self, name: &str, exclusive: bool) -> Result<i64> {
+        let conn = self.begin()?;
+        let inserted = conn
+            .prepare_cached(
+                "INSERT OR IGNORE INTO objects (name, size, modified) VALUES (?1, 0, ?2)",
+            )
+            .and_then(|mut stmt| stmt.execute(params![name, now()]))
+            .map_err(sql)?;
+        if inserted == 0 && exclusive {
+            return Err(BackendError::map(
+                &io::Error::from(ErrorKind::AlreadyExists),
+                Action::Create,
+            ));
+        }
+        lookup(&conn, name)?.ok_or_else(|| not_found(Action::Create))
+    }
+
+    /// Returns the id of the object called `name`.
+    pub fn lookup(&self, name: &str) -> Result<i64> {
+        lookup(&*self.lock()?, name)?.ok_or_else(|| not_found(Action::Open))
+    }
+
+    pub fn remove(&self, id: i64) -> Result<()> {
+        let mut conn = self.begin()?;
+        let tx = conn.savepoint().map_err(sql)?;
+        tx.execute("DELETE FROM pages WHERE object = ?1", [id])
+            .map_err(sql)?;
+        if tx
+            .execute("DELETE FROM objects WHERE id = ?1", [id])
+            .map_err(sql)?
+            == 0
+        {
+            return Err(not_found(Action::Delete));
+        }
+        tx.commit().map_err(sql)
+    }
+
+    pub fn status(&self, id: i64) -> Result<(i64, u64)> {
+        let conn = self.lock()?;
+        let (size, modified) = object(&conn, id, Action::Status)?;
+        Ok((modified, size))
+    }
+
+    pub fn read(&self, id: i64, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        let conn = self.lock()?;
+        let (size, _) = object(&conn, id, Action::Read)?;
+        let length = match size.checked_sub(offset) {
+            Some(available) => available.min(buffer.len() as u64),
+            None => return Ok(0),
+        };
+        if length == 0 {
+            return Ok(0);
+        }
+        let buffer = &mut buffer[..length as usize];
+        buffer.fill(0);
+
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT page, data FROM pages WHERE object = ?1 AND page BETWEEN ?2 AND ?3",
+            )
+            .map_err(sql)?;
+        let mut rows = stmt
+            .query(params![
+                id,
+                offset / self.page_size,
+                (offset + length - 1) / self.page_size
+            ])
+            .map_err(sql)?;
+        while let Some(row) = rows.next().map_err(sql)? {
+            let start = row.get::<_, u64>(0).map_err(sql)? * self.page_size;
+            let data = row.get_ref(1).and_then(|v| Ok(v.as_blob()?)).map_err(sql)?;
+
+            let from = start.max(offset);
+            let to = (start + data.len() as u64).min(offset + length);
+            if from < to {
+                buffer[(from - offset) as usize..(to - offset) as usize]
+                    .copy_from_slice(&data[(from - start) as usize..(to - start) as usize]);
+            }
+        }
+        Ok(length)
+    }
+
+    /// Writes into the pages the data falls into, partially covered pages are read,
+    /// patched and written back. A failed write is rolled back to a savepoint, so it
+    /// leaves the object unchanged.
+    pub fn write(&self, id: i64, buffer: &[u8], offset: u64) -> Result<u64> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        let mut conn = self.begin()?;
+        let tx = conn.savepoint().map_err(sql)?;
+        object(&tx, id, Action::Write)?;
+
+        let mut done = 0;
+        for (page, at, n) in pieces(offset, buffer.len() as u64, self.page_size) {
+            let piece = &buffer[done..done + n];
+            done += n;
+
+            let data = match at == 0 && n as u64 == self.page_size {
+                true => piece.to_vec(),
+                false => {
+                    let mut data: Vec<u8> = tx
+                        .prepare_cached("SELECT data FROM pages WHERE object = ?1 AND page = ?2")
+                        .and_then(|mut stmt| {
+                            stmt.query_row(params![id, page], |row| row.get(0))
+                                .optional()
+                        })
+                        .map_err(sql)?
+                        .unwrap_or_default();
+                    let at = at as usize;
+                    if data.len() < at + n {
+                        data.resize(at + n, 0);
+                    }
+                    data[at..at + n].copy_from_slice(piece);
+                    data
+                }
+            };
+            tx.prepare_cached(
+                "INSERT OR REPLACE INTO pages (object, page, data) VALUES (?1, ?2, ?3)",
+            )
+            .and_then(|mut stmt| stmt.execute(params![id, page, data]))
+            .map_err(sql)?;
+        }
+        tx.execute(
+            "UPDATE objects SET size = max(size, ?1), modified = ?2 WHERE id = ?3",
+            params![offset + buffer.len() as u64, now(), id],
+        )
+        .map_err(sql)?;
+        tx.commit().map_err(sql)?;
+        Ok(buffer.len() as u64)
+    }
+
+    /// Returns the names starting with `prefix`, from a range scan of the name index.
+    pub fn names(&self, prefix: Option<&str>) -> Result<Vec<String>> {
+        let prefix = prefix.unwrap_or_default();
+        let conn = self.lock()?;
+        let mut stmt = conn
+            .prepare_cached("SELECT name FROM objects WHERE name >= ?1 ORDER BY name")
+            .map_err(sql)?;
+        let mut rows = stmt.query([prefix]).map_err(sql)?;
+
+        let mut names = Vec::new();
+        while let Some(row) = rows.next().map_err(sql)? {
+            let name: String = row.get(0).map_err(sql)?;
+            // names compare bytewise, so all matches come before the first mismatch
+            if !name.starts_with(prefix) {
+                break;
+            }
+            names.push(name);
+        }
+        Ok(names)
+    }
+
+    /// Commits the running transaction.
+    pub fn commit(&self) -> Result<()> {
+        let conn = self.lock()?;
+        if !conn.is_autocommit() {
+            conn.execute_batch("COMMIT")
+                .map_err(|e| BackendError::map(&e, Action::Sync))?;
+        }
+        Ok(())
+    }
+}
+
+fn lookup(conn: &Connection, name: &str) -> Result<Option<i64>> {
+    conn.prepare_cached("SELECT id FROM objects WHERE name = ?1")
+        .and_then(|mut stmt| stmt.query_row([name], |row| row.get(0)).optional())
+        .map_err(sql)
+}
+
+/// Returns the size and modification time of the object `id`.
+fn object(conn: &Connection, id: i64, action: Action) -> Result<(u64, i64)> {
+    conn.prepare_cached("SELECT size, modified FROM objects WHERE id = ?1")
+        .and_then(|mut stmt| {
+            stmt.query_row([id], |row| Ok((row.get(0)?, row.get(1)?)))
+                .optional()
+        })
+        .map_err(sql)?
+        .ok_or_else(|| not_found(action))
+}
+
+/// Splits the `length` bytes at `offset` into `(page, offset in page, length)`.
+fn pieces(offset: u64, length: u64, page_size: u64) -> impl Iterator<Item = (u64, u64, usize)> {
+    let end = offset + length;
+
+    let mut position = offset;
+    std::iter::from_fn(move || {
+        if position >= end {
+            return None;
+        }
+        let at = position % page_size;
+        let n = (page_size - at).min(end - position);
+        let piece = (position / page_size, at, n as usize);
+        position += n;
+        Some(piece)
+    })
+}
+
+fn sql(e: rusqlite::Error) -> BackendError {
+    BackendError::map(&e, Action::Internal)
+}
+
+fn not_found(action: Action) -> BackendError {
+    BackendError::map(&io::Error::from(ErrorKind::NotFound), action)
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
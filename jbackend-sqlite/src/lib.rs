@@ -0,0 +1,184 @@
+mod database;
+mod sqlite;
+
+use io_backends::generate_backend;
+use io_backends::prelude::*;
+use log::debug;
+use log::info;
+
+generate_backend!(sqlite);
+
+#[no_mangle]
+pub unsafe extern "C" fn backend_info() -> *mut JBackend {
+    match init_logger() {
+        Ok(_) => info!("logger initialized."),
+        Err(e) => {
+            let _ = println!("Error while initializing logger: {e:?}");
+        }
+    };
+    debug!("backend info called");
+    &mut BACKEND
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CString;
+    use std::path::Path;
+    use std::ptr;
+
+    use io_backends::prelude::*;
+    use io_backends::test_facility::writes;
+    use io_backends::testing::{self, *};
+
+    use crate::database::DATABASE_SUFFIX;
+    use crate::sqlite::{Adapter, SqliteBackend};
+    use crate::BACKEND;
+
+    type Backend = NamespaceData<SqliteBackend>;
+
+    fn init(root: &Path, options: &str) -> Backend {
+        let path = CString::new(format!("{}{options}", root.to_str().unwrap())).unwrap();
+        unsafe { Adapter::backend_init(path.as_ptr()).unwrap() }
+    }
+
+    fn create(backend: &Backend, name: &str) -> ObjectHandle {
+        let name = CString::new(name).unwrap();
+        unsafe { Adapter::backend_create(backend, "ns\0".as_ptr().cast(), name.as_ptr()).unwrap() }
+    }
+
+    fn open(backend: &Backend, name: &str) -> Result<ObjectHandle> {
+        let name = CString::new(name).unwrap();
+        unsafe { Adapter::backend_open(backend, "ns\0".as_ptr().cast(), name.as_ptr()) }
+    }
+
+    fn read_all(backend: &Backend, handle: &ObjectHandle) -> Vec<u8> {
+        let mut buffer = vec![0u8; 32768];
+        let mut read = 0;
+        let ret = unsafe {
+            Adapter::j_read(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+                buffer.as_mut_ptr().cast(),
+                buffer.len() as u64,
+                0,
+                &mut read,
+            )
+        };
+        assert_eq!(ret, TRUE);
+        buffer.truncate(read as usize);
+        buffer
+    }
+
+    fn write(backend: &Backend, handle: &ObjectHandle, data: &[u8], offset: u64) {
+        let mut written = 0;
+        let ret = unsafe {
+            Adapter::j_write(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+                data.as_ptr().cast(),
+                data.len() as u64,
+                offset,
+                &mut written,
+            )
+        };
+        assert_eq!((ret, written), (TRUE, data.len() as u64));
+    }
+
+    fn sync(backend: &Backend, handle: &ObjectHandle) {
+        let ret = unsafe {
+            Adapter::j_sync(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+            )
+        };
+        assert_eq!(ret, TRUE);
+    }
+
+    fn names(backend: &Backend, prefix: Option<&str>) -> Vec<String> {
+        let object = unsafe { BACKEND.anon1.object };
+        unsafe { testing::names(&object, backend as *const _ as gpointer, "ns", prefix) }
+    }
+
+    #[test]
+    fn repeated_writes() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory =
+            |_namespace| Box::into_raw(Box::new(ptr::null_mut() as gpointer)).cast::<gpointer>();
+
+        writes::test_writes_with_options(&backend, data_factory, "?sqlite_page=1K")
+    }
+
+    #[test]
+    fn test_sqlite_transactions() {
+        let temp = setup();
+        let backend = init(&temp, "?sqlite_page=1K");
+
+        // writes across pages, with a hole of pages that are never stored
+        let handle = create(&backend, "obj");
+        let data: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+        write(&backend, &handle, &data, 100);
+        write(&backend, &handle, b"end", 6000);
+        let mut expected = vec![0u8; 100];
+        expected.extend_from_slice(&data);
+        expected.resize(6000, 0);
+        expected.extend_from_slice(b"end");
+        assert_eq!(read_all(&backend, &handle), expected);
+        sync(&backend, &handle);
+
+        // changes after the last sync are rolled back if the backend goes away
+        let lost = create(&backend, "lost");
+        write(&backend, &lost, b"gone", 0);
+        write(&backend, &handle, b"overwritten", 0);
+        drop(backend);
+
+        // the database keeps the page size it was created with
+        let backend = init(&temp, "?sqlite_page=64K");
+        assert!(temp.join(format!("ns{DATABASE_SUFFIX}")).exists());
+        assert!(open(&backend, "lost").is_err());
+        let handle = open(&backend, "obj").unwrap();
+        assert_eq!(read_all(&backend, &handle), expected);
+
+        // prefixes are matched literally, without LIKE wildcards
+        for name in ["a_1", "a%2", "ab", "b"] {
+            create(&backend, name);
+        }
+        assert_eq!(names(&backend, None), ["a%2", "a_1", "ab", "b", "obj"]);
+        assert_eq!(names(&backend, Some("a_")), ["a_1"]);
+        assert_eq!(names(&backend, Some("a")), ["a%2", "a_1", "ab"]);
+        assert!(names(&backend, Some("c")).is_empty());
+
+        let ret = unsafe {
+            Adapter::j_copy(
+                &backend as *const _ as gpointer,
+                "ns\0".as_ptr().cast(),
+                "obj\0".as_ptr().cast(),
+                "other\0".as_ptr().cast(),
+                "copy\0".as_ptr().cast(),
+            )
+        };
+        assert_eq!(ret, TRUE);
+        unsafe { Adapter::j_fini(Box::into_raw(Box::new(backend)).cast()) };
+
+        let backend = init(&temp, "");
+        let copy = unsafe {
+            Adapter::backend_open(
+                &backend,
+                "other\0".as_ptr().cast(),
+                "copy\0".as_ptr().cast(),
+            )
+            .unwrap()
+        };
+        assert_eq!(read_all(&backend, &copy), expected);
+        let handle = open(&backend, "obj").unwrap();
+        let ret = unsafe {
+            Adapter::j_delete(
+                &backend as *const _ as gpointer,
+                &handle as *const _ as gpointer,
+            )
+        };
+        assert_eq!(ret, TRUE);
+        assert!(open(&backend, "obj").is_err());
+
+        shutdown(temp)
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    fs::create_dir_all,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use rustc_hash::FxHashMap;
+
+use io_backends::prelude::*;
+
+use crate::database::{Database, DATABASE_SUFFIX};
+
+const DEFAULT_PAGE_SIZE: u64 = 16 << 10;
+/// Size of the pieces data is moved in when objects are copied.
+const COPY_CHUNK_SIZE: u64 = 1 << 20;
+
+/// An open object, the row of the `objects` table in its namespace's database.
+pub struct OpenObject {
+    database: Arc<Database>,
+    id: i64,
+}
+
+pub struct SqliteBackend {
+    root: PathBuf,
+    page_size: u64,
+    /// The databases of all namespaces used so far, which are opened on first use.
+    databases: Mutex<FxHashMap<String, Arc<Database>>>,
+}
+
+impl SqliteBackend {
+    pub fn new(root: &Path, config: &Config) -> Result<Self> {
+        let page_size = config.get_size("sqlite_page")?.unwrap_or(DEFAULT_PAGE_SIZE);
+        if page_size == 0 {
+            return Err(BackendError::new(
+                "Invalid value '0' for backend option 'sqlite_page'",
+                Action::Init,
+            ));
+        }
+
+        Ok(SqliteBackend {
+            root: root.to_path_buf(),
+            page_size,
+            databases: Mutex::new(FxHashMap::default()),
+        })
+    }
+
+    /// Returns the database of `namespace`. It is created if `create` is set, otherwise
+    /// `None` is returned for namespaces without objects.
+    pub fn database(&self, namespace: &str, create: bool) -> Result<Option<Arc<Database>>> {
+        let mut databases = self
+            .databases
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+        if let Some(database) = databases.get(namespace) {
+            return Ok(Some(database.clone()));
+        }
+
+        let path = self.root.join(format!("{namespace}{DATABASE_SUFFIX}"));
+        let database = match Database::open(&path, self.page_size, create)? {
+            Some(database) => Arc::new(database),
+            None => return Ok(None),
+        };
+        databases.insert(String::from(namespace), database.clone());
+        Ok(Some(database))
+    }
+
+    /// Commits the transactions of all open databases.
+    pub fn commit(&self) -> Result<()> {
+        let databases: Vec<Arc<Database>> = self
+            .databases
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?
+            .values()
+            .cloned()
+            .collect();
+        databases.iter().try_for_each(|database| database.commit())
+    }
+}
+
+impl NamespaceBackend for SqliteBackend {
+    type Object = OpenObject;
+
+    fn init(path: &str, config: &Config) -> Result<Self> {
+        create_dir_all(path)?;
+        SqliteBackend::new(Path::new(path), config)
+    }
+
+    /// Commits what was written since the last sync.
+    fn fini(&self) -> Result<()> {
+        self.commit()
+    }
+
+    fn create(&self, namespace: &str, name: &str) -> Result<OpenObject> {
+        let database = self
+            .database(namespace, true)?
+            .ok_or(BackendError::new_internal("Database was not created"))?;
+        let id = database.create(name, true)?;
+        Ok(OpenObject { database, id })
+    }
+
+    fn open(&self, namespace: &str, name: &str) -> Result<OpenObject> {
+        let database = self
+            .database(namespace, false)?
+            .ok_or_else(|| not_found(Action::Open))?;
+        let id = database.lookup(name)?;
+        Ok(OpenObject { database, id })
+    }
+
+    fn delete(&self, object: &OpenObject) -> Result<()> {
+        object.database.remove(object.id)
+    }
+
+    fn status(&self, object: &OpenObject) -> Result<(i64, u64)> {
+        object.database.status(object.id)
+    }
+
+    /// Commits the transaction of the object's database, and with it all changes in
+    /// its namespace.
+    fn sync(&self, object: &OpenObject) -> Result<()> {
+        object.database.commit()
+    }
+
+    fn read(&self, object: &OpenObject, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        object.database.read(object.id, buffer, offset)
+    }
+
+    fn write(&self, object: &OpenObject, buffer: &[u8], offset: u64) -> Result<u64> {
+        object.database.write(object.id, buffer, offset)
+    }
+
+    /// Copies an object into a new object, which may be in another namespace.
+    fn copy(
+        &self,
+        src_namespace: &str,
+        src_name: &str,
+        dst_namespace: &str,
+        dst_name: &str,
+    ) -> Result<()> {
+        let src = self
+            .database(src_namespace, false)?
+            .ok_or_else(|| not_found(Action::Copy))?;
+        let src_id = src.lookup(src_name)?;
+        let dst = self
+            .database(dst_namespace, true)?
+            .ok_or_else(|| not_found(Action::Copy))?;
+        let dst_id = dst.create(dst_name, true)?;
+
+        let (_, size) = src.status(src_id)?;
+        let mut buffer = vec![0u8; COPY_CHUNK_SIZE.min(size) as usize];
+        let mut copied = 0;
+        while copied < size {
+            let n = src.read(src_id, &mut buffer, copied)?;
+            if n == 0 {
+                break;
+            }
+            dst.write(dst_id, &buffer[..n as usize], copied)?;
+            copied += n;
+        }
+        Ok(())
+    }
+
+    fn names(&self, namespace: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+        match self.database(namespace, false)? {
+            Some(database) => database.names(prefix),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+fn not_found(action: Action) -> BackendError {
+    BackendError::map(&io::Error::from(ErrorKind::NotFound), action)
+}
+
+pub struct Adapter {}
+
+impl NamespaceAdapter<SqliteBackend> for Adapter {}
@@ -0,0 +1,228 @@
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{self, ErrorKind},
+    os::{fd::AsRawFd, unix::fs::FileExt},
+    thread,
+};
+
+use io_uring::{opcode, squeue, types, IoUring};
+
+use io_backends::prelude::*;
+
+const RING_ENTRIES: u32 = 64;
+
+thread_local! {
+    static RING: RefCell<Option<IoUring>> = const { RefCell::new(None) };
+}
+
+/// How the pieces of a request are transferred to and from the stripe files. Either
+/// way, the pieces of all roots are in flight at the same time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataPath {
+    /// `pread`/`pwrite` with one thread per root.
+    Posix,
+    /// One io_uring submission for all pieces.
+    Uring,
+}
+
+impl DataPath {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        match config.get("stripe_io") {
+            None | Some("posix") => Ok(DataPath::Posix),
+            Some("uring") => Ok(DataPath::Uring),
+            Some(v) => Err(BackendError::new(
+                &format!("Invalid value '{v}' for backend option 'stripe_io'"),
+                Action::Init,
+            )),
+        }
+    }
+
+    /// Fills the pieces of every root from `files`. Bytes past the end of a stripe
+    /// file are zeroed, as the object may continue in another root.
+    pub fn read(&self, files: &[File], pieces: Vec<Vec<(u64, &mut [u8])>>) -> io::Result<()> {
+        match self {
+            DataPath::Posix => in_parallel(files, pieces, |file, (offset, buffer)| {
+                read_at(file, buffer, *offset)
+            }),
+            DataPath::Uring => {
+                let mut flat: Vec<(&File, u64, &mut [u8])> = pieces
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(|(root, pieces)| {
+                        pieces
+                            .into_iter()
+                            .map(move |(offset, buffer)| (&files[root], offset, buffer))
+                    })
+                    .collect();
+                let entries: Vec<_> = flat
+                    .iter_mut()
+                    .map(|(file, offset, buffer)| {
+                        opcode::Read::new(
+                            types::Fd(file.as_raw_fd()),
+                            buffer.as_mut_ptr(),
+                            buffer.len() as _,
+                        )
+                        .offset(*offset)
+                        .build()
+                    })
+                    .collect();
+
+                for ((file, offset, buffer), n) in flat.into_iter().zip(submit(entries)?) {
+                    // short reads are finished or zeroed synchronously
+                    let n = n? as usize;
+                    if n < buffer.len() {
+                        read_at(file, &mut buffer[n..], offset + n as u64)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the pieces of every root to `files`.
+    pub fn write(&self, files: &[File], pieces: Vec<Vec<(u64, &[u8])>>) -> io::Result<()> {
+        match self {
+            DataPath::Posix => in_parallel(files, pieces, |file, (offset, buffer)| {
+                file.write_all_at(buffer, *offset)
+            }),
+            DataPath::Uring => {
+                let flat: Vec<(&File, u64, &[u8])> = pieces
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(|(root, pieces)| {
+                        pieces
+                            .into_iter()
+                            .map(move |(offset, buffer)| (&files[root], offset, buffer))
+                    })
+                    .collect();
+                let entries: Vec<_> = flat
+                    .iter()
+                    .map(|(file, offset, buffer)| {
+                        opcode::Write::new(
+                            types::Fd(file.as_raw_fd()),
+                            buffer.as_ptr(),
+                            buffer.len() as _,
+                        )
+                        .offset(*offset)
+                        .build()
+                    })
+                    .collect();
+
+                for ((file, offset, buffer), n) in flat.into_iter().zip(submit(entries)?) {
+                    let n = n? as usize;
+                    if n < buffer.len() {
+                        file.write_all_at(&buffer[n..], offset + n as u64)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Runs `op` for the pieces of every root, each root in a thread of its own unless
+/// only one root is involved.
+fn in_parallel<P: Send>(
+    files: &[File],
+    pieces: Vec<Vec<P>>,
+    op: impl Fn(&File, &mut P) -> io::Result<()> + Sync,
+) -> io::Result<()> {
+    let mut involved: Vec<(&File, Vec<P>)> = files
+        .iter()
+        .zip(pieces)
+        .filter(|(_, pieces)| !pieces.is_empty())
+        .collect();
+    let run = |(file, pieces): &mut (&File, Vec<P>)| {
+        pieces.iter_mut().try_for_each(|piece| op(file, piece))
+    };
+
+    if involved.len() == 1 {
+        return run(&mut involved[0]);
+    }
+    thread::scope(|scope| {
+        let threads: Vec<_> = involved
+            .iter_mut()
+            .map(|root| scope.spawn(|| run(root)))
+            .collect();
+        threads
+            .into_iter()
+            .try_for_each(|thread| thread.join().unwrap_or(Err(ErrorKind::Other.into())))
+    })
+}
+
+/// Submits `entries` to this thread's ring in batches and returns their results in
+/// order. The buffers of the entries must stay valid until this returns.
+fn submit(entries: Vec<squeue::Entry>) -> io::Result<Vec<io::Result<u32>>> {
+    RING.with(|ring| {
+        let mut ring = ring.borrow_mut();
+        if ring.is_none() {
+            *ring = Some(IoUring::new(RING_ENTRIES)?);
+        }
+
+        let mut results: Vec<io::Result<u32>> = Vec::with_capacity(entries.len());
+        for entries in entries.chunks(RING_ENTRIES as usize) {
+            let first = results.len();
+            results.extend((0..entries.len()).map(|_| Ok(0)));
+            if let Err(e) = submit_batch(ring.as_mut().unwrap(), entries, &mut results[first..]) {
+                // entries the ring failed to submit or wait for may still be queued or in
+                // flight, so the ring is dropped with them and created anew next time
+                *ring = None;
+                return Err(e);
+            }
+        }
+        Ok(results)
+    })
+}
+
+/// Submits at most `RING_ENTRIES` entries and waits until every entry that was pushed
+/// completed, even if pushing the rest failed.
+fn submit_batch(
+    ring: &mut IoUring,
+    entries: &[squeue::Entry],
+    results: &mut [io::Result<u32>],
+) -> io::Result<()> {
+    let mut pushed = 0;
+    let mut error = None;
+    for (i, entry) in entries.iter().enumerate() {
+        let entry = entry.clone().user_data(i as u64);
+        match unsafe { ring.submission().push(&entry) } {
+            Ok(_) => pushed += 1,
+            Err(e) => {
+                error = Some(io::Error::other(e.to_string()));
+                break;
+            }
+        }
+    }
+
+    let mut completed = 0;
+    while completed < pushed {
+        match ring.submit_and_wait(pushed - completed) {
+            Err(e) if e.kind() != ErrorKind::Interrupted => return Err(e),
+            _ => (),
+        }
+        for cqe in ring.completion() {
+            results[cqe.user_data() as usize] = match cqe.result() {
+                n if n < 0 => Err(io::Error::from_raw_os_error(-n)),
+                n => Ok(n as u32),
+            };
+            completed += 1;
+        }
+    }
+    error.map_or(Ok(()), Err)
+}
+
+/// Reads until `buffer` is full and zeroes whatever lies past the end of `file`.
+fn read_at(file: &File, buffer: &mut [u8], offset: u64) -> io::Result<()> {
+    let mut n_read = 0;
+    while n_read < buffer.len() {
+        match file.read_at(&mut buffer[n_read..], offset + n_read as u64) {
+            Ok(0) => break,
+            Ok(n) => n_read += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    buffer[n_read..].fill(0);
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use log::info;
+
+use io_backends::prelude::*;
+
+/// File in every root that records the layout the objects were striped with.
+pub const LAYOUT_FILE: &str = ".jstripe";
+
+/// Places stripe `s` of every object, bytes `s * unit..(s + 1) * unit`, in root
+/// `s % roots` at offset `s / roots * unit` of the object's stripe file there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Layout {
+    pub roots: usize,
+    pub unit: u64,
+}
+
+impl Layout {
+    /// Checks `unit` against the layout recorded in every root and records it in roots
+    /// that were not used before. Without `unit` the recorded layout is used, so the
+    /// stripe unit only needs to be given on the first `backend_init`.
+    pub fn load_or_store(roots: &[PathBuf], unit: Option<u64>, default: u64) -> Result<Self> {
+        let mut recorded = None;
+        let mut unused = Vec::new();
+
+        for (index, root) in roots.iter().enumerate() {
+            fs::create_dir_all(root)?;
+            let layout = match fs::read_to_string(root.join(LAYOUT_FILE)) {
+                Ok(content) => parse(&content, root)?,
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    unused.push((index, root));
+                    continue;
+                }
+                Err(e) => return Err(BackendError::map(&e, Action::Init)),
+            };
+            if layout.1 != index || layout.0.roots != roots.len() {
+                return Err(BackendError::new(
+                    &format!(
+                        "{root:?} is root {} of {}, but was given as root {index} of {}",
+                        layout.1,
+                        layout.0.roots,
+                        roots.len()
+                    ),
+                    Action::Init,
+                ));
+            }
+            match recorded {
+                Some(other) if other != layout.0 => {
+                    return Err(BackendError::new(
+                        &format!("The roots record different layouts, {other:?} and {layout:?}"),
+                        Action::Init,
+                    ))
+                }
+                _ => recorded = Some(layout.0),
+            }
+        }
+
+        let layout = match (recorded, unit) {
+            (Some(layout), Some(unit)) if layout.unit != unit => {
+                return Err(BackendError::new(
+                    &format!(
+                        "Objects were striped in units of {} b, cannot change 'stripe_unit' to {unit} b",
+                        layout.unit
+                    ),
+                    Action::Init,
+                ))
+            }
+            (Some(layout), _) => layout,
+            (None, unit) => Layout {
+                roots: roots.len(),
+                unit: unit.unwrap_or(default),
+            },
+        };
+
+        for (index, root) in unused {
+            info!("Recording {layout:?} in {root:?}");
+            layout
+                .store(root, index)
+                .map_err(|e| BackendError::map(&e, Action::Init))?;
+        }
+        Ok(layout)
+    }
+
+    fn store(&self, root: &Path, index: usize) -> io::Result<()> {
+        let temp = root.join(format!("{TEMP_PREFIX}{LAYOUT_FILE}"));
+        fs::write(&temp, format!("{} {} {index}\n", self.unit, self.roots))?;
+        fs::File::open(&temp)?.sync_all()?;
+        fs::rename(&temp, root.join(LAYOUT_FILE))
+    }
+
+    /// Maps the `length` bytes at logical `offset` to `(root, local offset, length)`
+    /// extents, one per stripe they touch.
+    fn extents(&self, offset: u64, length: u64) -> impl Iterator<Item = (usize, u64, usize)> {
+        let (roots, unit) = (self.roots as u64, self.unit);
+        let end = offset + length;
+
+        let mut position = offset;
+        std::iter::from_fn(move || {
+            if position >= end {
+                return None;
+            }
+            let stripe = position / unit;
+            let at = position % unit;
+            let n = (unit - at).min(end - position);
+            position += n;
+            Some((
+                (stripe % roots) as usize,
+                stripe / roots * unit + at,
+                n as usize,
+            ))
+        })
+    }
+
+    /// Splits `buffer`, which holds the bytes at logical `offset`, into the pieces that
+    /// belong to every root, with their local offsets.
+    pub fn split<'a>(&self, mut buffer: &'a [u8], offset: u64) -> Vec<Vec<(u64, &'a [u8])>> {
+        let mut pieces = vec![Vec::new(); self.roots];
+        for (root, local, n) in self.extents(offset, buffer.len() as u64) {
+            let (piece, rest) = buffer.split_at(n);
+            pieces[root].push((local, piece));
+            buffer = rest;
+        }
+        pieces
+    }
+
+    /// Like [`Layout::split`], for buffers that are read into.
+    pub fn split_mut<'a>(
+        &self,
+        mut buffer: &'a mut [u8],
+        offset: u64,
+    ) -> Vec<Vec<(u64, &'a mut [u8])>> {
+        let mut pieces: Vec<Vec<(u64, &mut [u8])>> = (0..self.roots).map(|_| Vec::new()).collect();
+        for (root, local, n) in self.extents(offset, buffer.len() as u64) {
+            let (piece, rest) = buffer.split_at_mut(n);
+            pieces[root].push((local, piece));
+            buffer = rest;
+        }
+        pieces
+    }
+
+    /// Reconstructs the logical size of an object from the sizes of its stripe files,
+    /// as the end of the last stripe any of them holds.
+    pub fn logical_size(&self, sizes: &[u64]) -> u64 {
+        let (roots, unit) = (self.roots as u64, self.unit);
+        sizes
+            .iter()
+            .enumerate()
+            .filter(|(_, size)| **size > 0)
+            .map(|(root, size)| {
+                let last = size - 1;
+                (last / unit * roots + root as u64) * unit + last % unit + 1
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Parses `<unit> <roots> <index>`.
+fn parse(content: &str, root: &Path) -> Result<(Layout, usize)> {
+    let fields: Vec<u64> = content
+        .split_whitespace()
+        .map(|field| field.parse())
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| {
+            BackendError::new(
+                &format!("{:?} is corrupted", root.join(LAYOUT_FILE)),
+                Action::Init,
+            )
+        })?;
+    match fields[..] {
+        [unit, roots, index] if unit > 0 => Ok((
+            Layout {
+                roots: roots as usize,
+                unit,
+            },
+            index as usize,
+        )),
+        _ => Err(BackendError::new(
+            &format!("{:?} is corrupted", root.join(LAYOUT_FILE)),
+            Action::Init,
+        )),
+    }
+}
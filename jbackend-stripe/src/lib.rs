@@ -0,0 +1,160 @@
+mod io;
+mod layout;
+mod stripe;
+
+use io_backends::generate_backend;
+use io_backends::prelude::*;
+use log::debug;
+use log::info;
+
+generate_backend!(stripe);
+
+#[no_mangle]
+pub unsafe extern "C" fn backend_info() -> *mut JBackend {
+    match init_logger() {
+        Ok(_) => info!("logger initialized."),
+        Err(e) => {
+            let _ = println!("Error while initializing logger: {e:?}");
+        }
+    };
+    debug!("backend info called");
+    &mut BACKEND
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CString;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::ptr;
+
+    use io_backends::prelude::*;
+    use io_backends::test_facility::writes;
+    use io_backends::testing::*;
+
+    use crate::stripe::{Adapter, StripeBackend};
+    use crate::BACKEND;
+
+    type Backend = NamespaceData<StripeBackend>;
+
+    fn init(roots: &[PathBuf], options: &str) -> Result<Backend> {
+        let roots: Vec<&str> = roots.iter().map(|r| r.to_str().unwrap()).collect();
+        let path = CString::new(format!("{}{options}", roots.join(":"))).unwrap();
+        unsafe { Adapter::backend_init(path.as_ptr()) }
+    }
+
+    fn read_all(backend: &Backend, handle: &ObjectHandle) -> Vec<u8> {
+        let mut buffer = vec![0u8; 64];
+        let mut read = 0;
+        let ret = unsafe {
+            Adapter::j_read(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+                buffer.as_mut_ptr().cast(),
+                buffer.len() as u64,
+                0,
+                &mut read,
+            )
+        };
+        assert_eq!(ret, TRUE);
+        buffer.truncate(read as usize);
+        buffer
+    }
+
+    fn write(backend: &Backend, handle: &ObjectHandle, data: &[u8], offset: u64) {
+        let mut written = 0;
+        let ret = unsafe {
+            Adapter::j_write(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+                data.as_ptr().cast(),
+                data.len() as u64,
+                offset,
+                &mut written,
+            )
+        };
+        assert_eq!((ret, written), (TRUE, data.len() as u64));
+    }
+
+    #[test]
+    fn repeated_writes() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory =
+            |_namespace| Box::into_raw(Box::new(ptr::null_mut() as gpointer)).cast::<gpointer>();
+
+        // two more roots are appended to the path of the first
+        let other = setup();
+        let other_path = other.to_str().unwrap();
+        writes::test_writes_with_options(
+            &backend,
+            data_factory,
+            &format!(":{other_path}/1:{other_path}/2?stripe_unit=4K"),
+        );
+        shutdown(other)
+    }
+
+    #[test]
+    fn test_stripe_layout() {
+        let temp = setup();
+        let roots: Vec<PathBuf> = (0..3).map(|i| temp.join(format!("nvme{i}"))).collect();
+        let stripe_file = |i: usize| fs::read(roots[i].join("ns/obj")).unwrap();
+        let backend = init(&roots, "?stripe_unit=4").unwrap();
+
+        let handle = unsafe {
+            Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "obj\0".as_ptr().cast())
+                .unwrap()
+        };
+        write(&backend, &handle, b"abcdefghijklmnopqrstuvwxyz", 0);
+        assert_eq!(stripe_file(0), b"abcdmnopyz");
+        assert_eq!(stripe_file(1), b"efghqrst");
+        assert_eq!(stripe_file(2), b"ijkluvwx");
+
+        // the logical size is the end of stripe 10, which lies in the second root
+        write(&backend, &handle, b"!", 40);
+        let mut expected = b"abcdefghijklmnopqrstuvwxyz".to_vec();
+        expected.resize(40, 0);
+        expected.push(b'!');
+        let (mut modified, mut size) = (0, 0);
+        let ret = unsafe {
+            Adapter::j_status(
+                &backend as *const _ as gpointer,
+                &handle as *const _ as gpointer,
+                &mut modified,
+                &mut size,
+            )
+        };
+        assert_eq!((ret, size), (TRUE, 41));
+        assert_eq!(read_all(&backend, &handle), expected);
+
+        // the io_uring data path stripes the same way, with the recorded stripe unit
+        let uring = init(&roots, "?stripe_io=uring").unwrap();
+        let uring_handle = unsafe {
+            Adapter::backend_open(&uring, "ns\0".as_ptr().cast(), "obj\0".as_ptr().cast()).unwrap()
+        };
+        assert_eq!(read_all(&uring, &uring_handle), expected);
+        write(&uring, &uring_handle, b"0123456789", 2);
+        expected[2..12].copy_from_slice(b"0123456789");
+        assert_eq!(read_all(&backend, &handle), expected);
+
+        // the layout cannot change once objects were striped with it
+        assert!(init(&roots, "?stripe_unit=8").is_err());
+        assert!(init(&[roots[1].clone(), roots[0].clone(), roots[2].clone()], "").is_err());
+        assert!(init(&roots[..2], "").is_err());
+
+        // creating an existing object fails without touching its stripe files
+        let create = |name: &str| unsafe {
+            Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), name.as_ptr().cast())
+        };
+        assert!(create("obj\0").is_err());
+        assert_eq!(read_all(&backend, &handle), expected);
+
+        // only the stripe files this create made are removed when a later root fails
+        fs::write(roots[2].join("ns/other"), b"foreign").unwrap();
+        assert!(create("other\0").is_err());
+        assert!(!roots[0].join("ns/other").exists());
+        assert!(!roots[1].join("ns/other").exists());
+        assert_eq!(fs::read(roots[2].join("ns/other")).unwrap(), b"foreign");
+
+        shutdown(temp)
+    }
+}
@@ -0,0 +1,241 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+
+use io_backends::prelude::*;
+
+use crate::io::DataPath;
+use crate::layout::Layout;
+
+const DEFAULT_UNIT: u64 = 1 << 20;
+
+/// An object striped over one file per root, `<root>/<namespace>/<name>`.
+pub struct StripedObject {
+    path: PathBuf,
+    files: Vec<File>,
+    layout: Layout,
+    data_path: DataPath,
+}
+
+impl StripedObject {
+    pub fn status(&self) -> Result<(i64, u64)> {
+        let mut last_mod = 0;
+        let mut sizes = Vec::with_capacity(self.files.len());
+        for file in &self.files {
+            let metadata = file.metadata()?;
+            last_mod = last_mod.max(metadata.atime());
+            sizes.push(metadata.size());
+        }
+        Ok((last_mod, self.layout.logical_size(&sizes)))
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        for file in &self.files {
+            file.sync_data()
+                .map_err(|e| BackendError::map(&e, Action::Sync))?;
+        }
+        Ok(())
+    }
+
+    pub fn read(&self, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        let (_, size) = self.status()?;
+        let length = match size.checked_sub(offset) {
+            Some(available) => available.min(buffer.len() as u64),
+            None => return Ok(0),
+        };
+
+        let pieces = self
+            .layout
+            .split_mut(&mut buffer[..length as usize], offset);
+        self.data_path
+            .read(&self.files, pieces)
+            .map_err(|e| BackendError::map(&e, Action::Read))?;
+        Ok(length)
+    }
+
+    pub fn write(&self, buffer: &[u8], offset: u64) -> Result<u64> {
+        let pieces = self.layout.split(buffer, offset);
+        self.data_path
+            .write(&self.files, pieces)
+            .map_err(|e| BackendError::map(&e, Action::Write))?;
+        Ok(buffer.len() as u64)
+    }
+}
+
+pub struct StripeBackend {
+    roots: Vec<PathBuf>,
+    layout: Layout,
+    data_path: DataPath,
+}
+
+impl StripeBackend {
+    pub fn new(roots: Vec<PathBuf>, config: &Config) -> Result<Self> {
+        let unit = config.get_size("stripe_unit")?;
+        if roots.len() > 1 << 16 || unit == Some(0) {
+            return Err(BackendError::new(
+                &format!(
+                    "Cannot stripe over {} roots in units of {unit:?} b",
+                    roots.len()
+                ),
+                Action::Init,
+            ));
+        }
+
+        Ok(StripeBackend {
+            layout: Layout::load_or_store(&roots, unit, DEFAULT_UNIT)?,
+            data_path: DataPath::from_config(config)?,
+            roots,
+        })
+    }
+
+    fn object(&self, path: PathBuf, files: Vec<File>) -> StripedObject {
+        StripedObject {
+            path,
+            files,
+            layout: self.layout,
+            data_path: self.data_path,
+        }
+    }
+}
+
+impl NamespaceBackend for StripeBackend {
+    type Object = StripedObject;
+
+    /// Takes the roots from the path, separated by colons.
+    fn init(path: &str, config: &Config) -> Result<Self> {
+        StripeBackend::new(split_roots(path)?, config)
+    }
+
+    /// Creates the stripe files of a new object in every root, failing if any of them
+    /// exists already. Files that were created are removed again if any root fails.
+    fn create(&self, namespace: &str, name: &str) -> Result<StripedObject> {
+        let path = Path::new(namespace).join(name);
+        let mut files = Vec::with_capacity(self.roots.len());
+        for root in &self.roots {
+            let target = root.join(&path);
+            let res = target
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| {
+                    OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .create_new(true)
+                        .open(&target)
+                });
+            match res {
+                Ok(file) => files.push(file),
+                Err(e) => {
+                    for root in &self.roots[..files.len()] {
+                        let _ = fs::remove_file(root.join(&path));
+                    }
+                    return Err(BackendError::map(&e, Action::Create));
+                }
+            }
+        }
+        Ok(self.object(path, files))
+    }
+
+    fn open(&self, namespace: &str, name: &str) -> Result<StripedObject> {
+        let path = Path::new(namespace).join(name);
+        let files = self
+            .roots
+            .iter()
+            .map(|root| {
+                OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(root.join(&path))
+            })
+            .collect::<io::Result<Vec<File>>>()
+            .map_err(|e| BackendError::map(&e, Action::Open))?;
+        Ok(self.object(path, files))
+    }
+
+    /// Removes the stripe files from every root, even if some of them fail.
+    fn delete(&self, object: &StripedObject) -> Result<()> {
+        let mut first_error = None;
+        for root in &self.roots {
+            if let Err(e) = fs::remove_file(root.join(&object.path)) {
+                warn!("Unable to remove stripe file in {root:?}: {e}");
+                first_error.get_or_insert(e);
+            }
+        }
+        match first_error {
+            Some(e) => Err(BackendError::map(&e, Action::Delete)),
+            None => Ok(()),
+        }
+    }
+
+    fn status(&self, object: &StripedObject) -> Result<(i64, u64)> {
+        object.status()
+    }
+
+    fn sync(&self, object: &StripedObject) -> Result<()> {
+        object.sync()
+    }
+
+    fn read(&self, object: &StripedObject, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        object.read(buffer, offset)
+    }
+
+    fn write(&self, object: &StripedObject, buffer: &[u8], offset: u64) -> Result<u64> {
+        object.write(buffer, offset)
+    }
+
+    /// Copies the stripe files of an object within every root, as the copy is striped
+    /// the same way. Copies that were made are removed again if any root fails.
+    fn copy(
+        &self,
+        src_namespace: &str,
+        src_name: &str,
+        dst_namespace: &str,
+        dst_name: &str,
+    ) -> Result<()> {
+        let src = Path::new(src_namespace).join(src_name);
+        let dst = Path::new(dst_namespace).join(dst_name);
+        for (index, root) in self.roots.iter().enumerate() {
+            let mut created = false;
+            let res = (|| {
+                let dst = root.join(&dst);
+                if let Some(dir) = dst.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                let src_file = File::open(root.join(&src))?;
+                let dst_file = OpenOptions::new().write(true).create_new(true).open(&dst)?;
+                created = true;
+                copy_file(&src_file, &dst_file)
+            })();
+            if let Err(e) = res {
+                for root in &self.roots[..index + created as usize] {
+                    let _ = fs::remove_file(root.join(&dst));
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Every object has a stripe file in every root, so the first root is listed.
+    fn names(&self, namespace: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(self.roots[0].join(namespace))? {
+            let name = entry?.file_name().into_string().map_err(|_| {
+                BackendError::new("Unable to convert file name to UTF-8", Action::Iter)
+            })?;
+            if !name.starts_with(TEMP_PREFIX) && name.starts_with(prefix.unwrap_or_default()) {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+}
+
+pub struct Adapter {}
+
+impl NamespaceAdapter<StripeBackend> for Adapter {}
@@ -0,0 +1,190 @@
+mod policy;
+mod tier;
+
+use std::path::PathBuf;
+
+use io_backends::generate_backend;
+use io_backends::prelude::*;
+use log::debug;
+use log::error;
+use log::info;
+
+use crate::tier::TierBackend;
+
+generate_backend!(tier);
+
+#[no_mangle]
+pub unsafe extern "C" fn backend_info() -> *mut JBackend {
+    match init_logger() {
+        Ok(_) => info!("logger initialized."),
+        Err(e) => {
+            let _ = println!("Error while initializing logger: {e:?}");
+        }
+    };
+    debug!("backend info called");
+    &mut BACKEND
+}
+
+/// Returns the tier an object lives on, 0 for the fast and 1 for the slow one, or -1
+/// if it is not found.
+#[no_mangle]
+pub unsafe extern "C" fn backend_tier(
+    backend_data: gpointer,
+    namespace: *const gchar,
+    path: *const gchar,
+) -> gint {
+    cast_ptr!(backend_data, NamespaceData<TierBackend>);
+
+    let res = (|| backend_data.tier(&PathBuf::from(read_str(namespace)?).join(read_str(path)?)))();
+    match res {
+        Ok(tier) => tier as gint,
+        Err(e) => {
+            error!("{}", e.set_action(Action::Status));
+            -1
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CString;
+    use std::path::PathBuf;
+    use std::ptr;
+
+    use io_backends::prelude::*;
+    use io_backends::test_facility::writes;
+    use io_backends::testing::*;
+
+    use crate::tier::{Adapter, TierBackend};
+    use crate::{backend_tier, BACKEND};
+
+    type Backend = NamespaceData<TierBackend>;
+
+    fn init(roots: &[PathBuf], options: &str) -> Backend {
+        let roots: Vec<&str> = roots.iter().map(|r| r.to_str().unwrap()).collect();
+        let path = CString::new(format!("{}{options}", roots.join(":"))).unwrap();
+        unsafe { Adapter::backend_init(path.as_ptr()).unwrap() }
+    }
+
+    fn create(backend: &Backend, name: &str) -> ObjectHandle {
+        let name = CString::new(name).unwrap();
+        unsafe { Adapter::backend_create(backend, "ns\0".as_ptr().cast(), name.as_ptr()).unwrap() }
+    }
+
+    fn tier(backend: &Backend, name: &str) -> gint {
+        let name = CString::new(name).unwrap();
+        unsafe {
+            backend_tier(
+                backend as *const _ as gpointer,
+                "ns\0".as_ptr().cast(),
+                name.as_ptr(),
+            )
+        }
+    }
+
+    fn read_all(backend: &Backend, handle: &ObjectHandle) -> Vec<u8> {
+        let mut buffer = vec![0u8; 8192];
+        let mut read = 0;
+        let ret = unsafe {
+            Adapter::j_read(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+                buffer.as_mut_ptr().cast(),
+                buffer.len() as u64,
+                0,
+                &mut read,
+            )
+        };
+        assert_eq!(ret, TRUE);
+        buffer.truncate(read as usize);
+        buffer
+    }
+
+    fn write(backend: &Backend, handle: &ObjectHandle, data: &[u8]) {
+        let mut written = 0;
+        let ret = unsafe {
+            Adapter::j_write(
+                backend as *const _ as gpointer,
+                handle as *const _ as gpointer,
+                data.as_ptr().cast(),
+                data.len() as u64,
+                0,
+                &mut written,
+            )
+        };
+        assert_eq!((ret, written), (TRUE, data.len() as u64));
+    }
+
+    #[test]
+    fn repeated_writes() {
+        let backend: ObjectBackend = unsafe { BACKEND.anon1.object };
+        let data_factory =
+            |_namespace| Box::into_raw(Box::new(ptr::null_mut() as gpointer)).cast::<gpointer>();
+
+        // the slow root is appended to the path of the fast one
+        let other = setup();
+        writes::test_writes_with_options(
+            &backend,
+            data_factory,
+            &format!(":{}?tier_high=1M", other.to_str().unwrap()),
+        );
+        shutdown(other)
+    }
+
+    #[test]
+    fn test_tier_migration() {
+        let temp = setup();
+        let roots = [temp.join("ssd"), temp.join("pfs")];
+        let backend = init(&roots, "?tier_high=10K&tier_low=6K");
+
+        let handles: Vec<ObjectHandle> = ["a", "b", "c"]
+            .iter()
+            .map(|name| {
+                let handle = create(&backend, name);
+                write(&backend, &handle, &name.repeat(4096).into_bytes());
+                handle
+            })
+            .collect();
+        backend.settle().unwrap();
+        // 12K passed the high watermark, the least recently used objects were demoted
+        assert_eq!(["a", "b", "c"].map(|name| tier(&backend, name)), [1, 1, 0]);
+        assert!(roots[1].join("ns/a").exists() && !roots[0].join("ns/a").exists());
+        assert_eq!(tier(&backend, "missing"), -1);
+        // the demoted object still exists
+        assert!(unsafe {
+            Adapter::backend_create(&backend, "ns\0".as_ptr().cast(), "a\0".as_ptr().cast())
+        }
+        .is_err());
+
+        // accessing a demoted object through its open handle promotes it
+        assert_eq!(read_all(&backend, &handles[0]), b"a".repeat(4096));
+        assert_eq!(tier(&backend, "a"), 0);
+        write(&backend, &handles[1], b"B");
+        backend.settle().unwrap();
+        assert_eq!(["a", "b", "c"].map(|name| tier(&backend, name)), [1, 0, 1]);
+        let mut expected = b"b".repeat(4096);
+        expected[0] = b'B';
+        assert_eq!(read_all(&backend, &handles[1]), expected);
+        assert_eq!(read_all(&backend, &handles[2]), b"c".repeat(4096));
+        for handle in &handles {
+            unsafe {
+                Adapter::j_close(
+                    &backend as *const _ as gpointer,
+                    handle as *const _ as gpointer,
+                );
+            }
+        }
+        drop(backend);
+
+        // the age policy keeps recently accessed objects on the fast tier
+        let backend = init(&roots, "?tier_high=10K&tier_low=6K&tier_policy=age");
+        for name in ["d", "e"] {
+            let handle = create(&backend, name);
+            write(&backend, &handle, &name.repeat(4096).into_bytes());
+        }
+        backend.settle().unwrap();
+        assert_eq!(["b", "d", "e"].map(|name| tier(&backend, name)), [0, 0, 0]);
+
+        shutdown(temp)
+    }
+}
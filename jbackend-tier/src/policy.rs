@@ -0,0 +1,194 @@
+use std::{
+    ffi::CString,
+    fs, io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use log::info;
+use rustc_hash::FxHashMap;
+
+use io_backends::prelude::*;
+
+const DEFAULT_AGE: u64 = 3600;
+
+/// Chooses the objects that are demoted once the fast tier passes its high watermark.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    /// Demotes the least recently accessed objects until the low watermark is reached.
+    Lru,
+    /// Like `Lru`, but only demotes objects that were not accessed for the given time.
+    Age(Duration),
+}
+
+struct Usage {
+    size: u64,
+    accessed: SystemTime,
+    /// Orders the accesses, as the clock may not tell them apart.
+    tick: u64,
+}
+
+/// Accounts for the objects on the fast tier.
+pub struct FastTier {
+    objects: FxHashMap<PathBuf, Usage>,
+    used: u64,
+    tick: u64,
+    policy: Policy,
+    high: u64,
+    low: u64,
+}
+
+impl FastTier {
+    /// Reads the policy and watermarks from `config` and accounts for the objects that
+    /// are already stored below `root`, in the order they were last modified.
+    pub fn new(root: &Path, config: &Config) -> Result<Self> {
+        let policy = match config.get("tier_policy") {
+            None | Some("lru") => Policy::Lru,
+            Some("age") => Policy::Age(Duration::from_secs(match config.get("tier_age") {
+                Some(v) => v.parse().map_err(|_| {
+                    BackendError::new(
+                        &format!("Invalid value '{v}' for backend option 'tier_age'"),
+                        Action::Init,
+                    )
+                })?,
+                None => DEFAULT_AGE,
+            })),
+            Some(v) => {
+                return Err(BackendError::new(
+                    &format!("Invalid value '{v}' for backend option 'tier_policy'"),
+                    Action::Init,
+                ))
+            }
+        };
+        let high = match config.get_size("tier_high")? {
+            Some(high) => high,
+            None => capacity(root)? / 10 * 9,
+        };
+        let low = config.get_size("tier_low")?.unwrap_or(high / 4 * 3);
+        if low > high {
+            return Err(BackendError::new(
+                &format!("'tier_low' ({low} b) is above 'tier_high' ({high} b)"),
+                Action::Init,
+            ));
+        }
+
+        let mut found = Vec::new();
+        collect(root, Path::new(""), &mut found)?;
+        found.sort_by_key(|(_, _, modified)| *modified);
+
+        let mut tier = FastTier {
+            objects: FxHashMap::default(),
+            used: 0,
+            tick: 0,
+            policy,
+            high,
+            low,
+        };
+        for (path, size, modified) in found {
+            tier.record(&path, size);
+            if let Some(usage) = tier.objects.get_mut(&path) {
+                usage.accessed = modified;
+            }
+        }
+        info!(
+            "Fast tier holds {} objects with {} b, watermarks {low} b to {high} b, {policy:?}",
+            tier.objects.len(),
+            tier.used
+        );
+        Ok(tier)
+    }
+
+    /// Records an access to an object on the fast tier and its current size.
+    pub fn record(&mut self, path: &Path, size: u64) {
+        self.tick += 1;
+        let usage = self.objects.entry(path.to_path_buf()).or_insert(Usage {
+            size: 0,
+            accessed: SystemTime::UNIX_EPOCH,
+            tick: 0,
+        });
+        self.used = self.used - usage.size + size;
+        usage.size = size;
+        usage.accessed = SystemTime::now();
+        usage.tick = self.tick;
+    }
+
+    /// Records an access without a change in size.
+    pub fn touch(&mut self, path: &Path) {
+        if let Some(size) = self.objects.get(path).map(|usage| usage.size) {
+            self.record(path, size);
+        }
+    }
+
+    /// Stops accounting for an object that left the fast tier.
+    pub fn forget(&mut self, path: &Path) {
+        if let Some(usage) = self.objects.remove(path) {
+            self.used -= usage.size;
+        }
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    /// Objects larger than the low watermark are not promoted, they would be the
+    /// first to be demoted again.
+    pub fn fits(&self, size: u64) -> bool {
+        size <= self.low
+    }
+
+    /// Returns the objects to demote, in order, if the high watermark is passed.
+    pub fn victims(&self) -> Vec<PathBuf> {
+        if self.used <= self.high {
+            return Vec::new();
+        }
+
+        let mut candidates: Vec<(&PathBuf, &Usage)> = match self.policy {
+            Policy::Lru => self.objects.iter().collect(),
+            Policy::Age(age) => self
+                .objects
+                .iter()
+                .filter(|(_, usage)| usage.accessed.elapsed().unwrap_or_default() >= age)
+                .collect(),
+        };
+        candidates.sort_by_key(|(_, usage)| usage.tick);
+
+        let mut used = self.used;
+        candidates
+            .into_iter()
+            .take_while(|(_, usage)| {
+                let take = used > self.low;
+                used -= usage.size;
+                take
+            })
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+}
+
+/// Size of the file system holding `root`.
+fn capacity(root: &Path) -> io::Result<u64> {
+    let path = CString::new(root.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stat.f_blocks as u64 * stat.f_frsize as u64)
+}
+
+/// Collects the path, size and modification time of every object below `root`.
+fn collect(root: &Path, dir: &Path, found: &mut Vec<(PathBuf, u64, SystemTime)>) -> io::Result<()> {
+    for entry in fs::read_dir(root.join(dir))? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with(TEMP_PREFIX) {
+            continue;
+        }
+        let path = dir.join(entry.file_name());
+        let metadata = entry.metadata()?;
+        match metadata.is_dir() {
+            true => collect(root, &path, found)?,
+            false => found.push((path, metadata.len(), metadata.modified()?)),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,553 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind},
+    os::unix::fs::{FileExt, MetadataExt},
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, MutexGuard, RwLock, Weak},
+    thread::{self, JoinHandle},
+};
+
+use log::{debug, info, warn};
+use rustc_hash::FxHashMap;
+
+use io_backends::prelude::*;
+
+use crate::policy::FastTier;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tier {
+    Fast = 0,
+    Slow = 1,
+}
+
+struct Placement {
+    tier: Tier,
+    file: File,
+}
+
+/// An object shared by all handles to it, so moving it between the tiers swaps the
+/// file behind every handle at once.
+pub struct TieredObject {
+    path: PathBuf,
+    placement: RwLock<Placement>,
+}
+
+impl TieredObject {
+    fn placement(&self) -> Result<std::sync::RwLockReadGuard<'_, Placement>> {
+        self.placement
+            .read()
+            .map_err(|e| BackendError::map(&e, Action::Internal))
+    }
+
+    pub fn tier(&self) -> Result<Tier> {
+        Ok(self.placement()?.tier)
+    }
+}
+
+/// Work for the demoter thread.
+#[derive(Default)]
+struct Demand {
+    pending: bool,
+    running: bool,
+    stop: bool,
+}
+
+/// The state shared by the backend and its demoter thread.
+struct Tiers {
+    /// The fast and the slow root, indexed by [`Tier`].
+    roots: [PathBuf; 2],
+    fast: Mutex<FastTier>,
+    /// All objects that are open or being moved.
+    objects: Mutex<FxHashMap<PathBuf, Weak<TieredObject>>>,
+    demand: Mutex<Demand>,
+    /// Signals changes of `demand`.
+    wake: Condvar,
+}
+
+impl Tiers {
+    fn fast(&self) -> Result<MutexGuard<'_, FastTier>> {
+        self.fast
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))
+    }
+
+    fn objects(&self) -> Result<MutexGuard<'_, FxHashMap<PathBuf, Weak<TieredObject>>>> {
+        self.objects
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))
+    }
+
+    fn demand(&self) -> Result<MutexGuard<'_, Demand>> {
+        self.demand
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))
+    }
+
+    /// Asks the demoter thread to check the fast tier.
+    fn schedule(&self) -> Result<()> {
+        self.demand()?.pending = true;
+        self.wake.notify_all();
+        Ok(())
+    }
+
+    /// Returns the shared object for `path`, opening it on the tier it lives on.
+    fn object(&self, path: &Path) -> Result<Arc<TieredObject>> {
+        let mut objects = self.objects()?;
+        if let Some(object) = objects.get(path).and_then(Weak::upgrade) {
+            return Ok(object);
+        }
+
+        let object = Arc::new(TieredObject {
+            path: path.to_path_buf(),
+            placement: RwLock::new(self.locate(path)?),
+        });
+        objects.insert(path.to_path_buf(), Arc::downgrade(&object));
+        Ok(object)
+    }
+
+    /// Creates a new object on the fast tier. Fails if it exists on either tier.
+    fn create(&self, path: &Path) -> Result<Arc<TieredObject>> {
+        let mut objects = self.objects()?;
+        self.absent(Tier::Slow, path)?;
+
+        let target = self.roots[Tier::Fast as usize].join(path);
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&target)?;
+        // a demotion may have moved the object away from the fast tier in between
+        if let Err(e) = self.absent(Tier::Slow, path) {
+            let _ = fs::remove_file(&target);
+            return Err(e);
+        }
+        self.fast()?.record(path, 0);
+
+        let object = Arc::new(TieredObject {
+            path: path.to_path_buf(),
+            placement: RwLock::new(Placement {
+                tier: Tier::Fast,
+                file,
+            }),
+        });
+        // replaces a deleted object that is still open
+        objects.insert(path.to_path_buf(), Arc::downgrade(&object));
+        Ok(object)
+    }
+
+    /// Fails with `AlreadyExists` if `path` exists on `tier`.
+    fn absent(&self, tier: Tier, path: &Path) -> Result<()> {
+        match self.roots[tier as usize].join(path).try_exists()? {
+            true => Err(BackendError::map(
+                &io::Error::from(ErrorKind::AlreadyExists),
+                Action::Create,
+            )),
+            false => Ok(()),
+        }
+    }
+
+    /// Forgets a shared object if `object` is the last reference to it, which the
+    /// caller drops right after.
+    fn release(&self, object: &Arc<TieredObject>) -> Result<()> {
+        let mut objects = self.objects()?;
+        let known = objects
+            .get(&object.path)
+            .is_some_and(|o| o.as_ptr() == Arc::as_ptr(object));
+        if known && Arc::strong_count(object) == 1 {
+            objects.remove(&object.path);
+        }
+        Ok(())
+    }
+
+    /// Opens an object on the fast tier, or on the slow tier if it is not there.
+    fn locate(&self, path: &Path) -> Result<Placement> {
+        let open = |tier: Tier| {
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(self.roots[tier as usize].join(path))
+                .map(|file| Placement { tier, file })
+        };
+        match open(Tier::Fast) {
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(open(Tier::Slow)?),
+            res => Ok(res?),
+        }
+    }
+
+    /// Moves an object to `target`. The copy is written to a temporary file and renamed
+    /// before the original is removed, so the object is never lost in between.
+    fn move_to(&self, object: &TieredObject, target: Tier) -> Result<()> {
+        let mut placement = object
+            .placement
+            .write()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+        if placement.tier == target {
+            return Ok(());
+        }
+
+        let src = self.roots[placement.tier as usize].join(&object.path);
+        let dst = self.roots[target as usize].join(&object.path);
+        let temp = match dst.file_name() {
+            Some(name) => dst.with_file_name(format!("{TEMP_PREFIX}{}", name.to_string_lossy())),
+            None => return Err(BackendError::new_internal("Object path has no file name")),
+        };
+        if let Some(dir) = dst.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let res = (|| -> Result<File> {
+            let copy = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&temp)?;
+            copy_file(&placement.file, &copy)?;
+            copy.sync_all()?;
+            fs::rename(&temp, &dst)?;
+            Ok(copy)
+        })();
+        let copy = res.inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })?;
+        let size = copy.metadata()?.len();
+        fs::remove_file(&src)?;
+        *placement = Placement {
+            tier: target,
+            file: copy,
+        };
+        drop(placement);
+
+        let mut fast = self.fast()?;
+        match target {
+            Tier::Fast => fast.record(&object.path, size),
+            Tier::Slow => fast.forget(&object.path),
+        }
+        info!(
+            "Moved {:?} ({size} b) to the {target:?} tier, fast tier holds {} b",
+            object.path,
+            fast.used()
+        );
+        Ok(())
+    }
+
+    /// Demotes objects as chosen by the policy if the fast tier passed its high
+    /// watermark. Failures only delay the demotion.
+    fn demote(&self) -> Result<()> {
+        let victims = self.fast()?.victims();
+
+        for path in victims {
+            let res = self.object(&path).and_then(|object| {
+                let res = self.move_to(&object, Tier::Slow);
+                self.release(&object)?;
+                res
+            });
+            if let Err(e) = res {
+                warn!("Unable to demote {path:?}: {e}");
+                if !self.roots[Tier::Fast as usize].join(&path).exists() {
+                    self.fast()?.forget(&path);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves an object on the slow tier to the fast tier unless it is too large.
+    /// Failures are only logged, the object stays usable on the slow tier.
+    fn promote(&self, object: &TieredObject) -> Result<()> {
+        let size = {
+            let placement = object.placement()?;
+            if placement.tier == Tier::Fast {
+                return Ok(());
+            }
+            placement.file.metadata()?.len()
+        };
+        if !self.fast()?.fits(size) {
+            debug!("{:?} is too large to be promoted", object.path);
+            return Ok(());
+        }
+
+        match self.move_to(object, Tier::Fast) {
+            Ok(_) => self.schedule(),
+            Err(e) => {
+                warn!("Unable to promote {:?}: {e}", object.path);
+                Ok(())
+            }
+        }
+    }
+
+    /// Runs `f` on the file of an object, promoting it first if needed, and records
+    /// the access.
+    fn access<R>(
+        &self,
+        object: &TieredObject,
+        write: bool,
+        f: impl FnOnce(&File) -> Result<R>,
+    ) -> Result<R> {
+        self.promote(object)?;
+
+        let (res, tier, size) = {
+            let placement = object.placement()?;
+            let res = f(&placement.file)?;
+            let size = match write {
+                true => Some(placement.file.metadata()?.len()),
+                false => None,
+            };
+            (res, placement.tier, size)
+        };
+        if tier == Tier::Fast {
+            let mut fast = self.fast()?;
+            match size {
+                Some(size) => fast.record(&object.path, size),
+                None => fast.touch(&object.path),
+            }
+        }
+        if write {
+            self.schedule()?;
+        }
+        Ok(res)
+    }
+}
+
+/// Demotes objects whenever the backend asks for it, until it is stopped.
+fn demoter(tiers: Arc<Tiers>) {
+    loop {
+        {
+            let Ok(mut demand) = tiers.demand.lock() else {
+                return;
+            };
+            demand.running = false;
+            tiers.wake.notify_all();
+            demand = match tiers
+                .wake
+                .wait_while(demand, |demand| !demand.pending && !demand.stop)
+            {
+                Ok(demand) => demand,
+                Err(_) => return,
+            };
+            if demand.stop {
+                return;
+            }
+            demand.pending = false;
+            demand.running = true;
+        }
+        if let Err(e) = tiers.demote() {
+            warn!("Demotion failed: {e}");
+        }
+    }
+}
+
+/// Keeps new objects on the fast tier. Objects are demoted by a thread of their own,
+/// so requests do not wait for the copies.
+pub struct TierBackend {
+    tiers: Arc<Tiers>,
+    demoter: Option<JoinHandle<()>>,
+}
+
+impl TierBackend {
+    pub fn new(roots: Vec<PathBuf>, config: &Config) -> Result<Self> {
+        let [fast, slow]: [PathBuf; 2] = roots.try_into().map_err(|roots: Vec<PathBuf>| {
+            BackendError::new(
+                &format!("Expected a fast and a slow root, got {}", roots.len()),
+                Action::Init,
+            )
+        })?;
+        fs::create_dir_all(&fast)?;
+        fs::create_dir_all(&slow)?;
+
+        let tiers = Arc::new(Tiers {
+            fast: Mutex::new(FastTier::new(&fast, config)?),
+            roots: [fast, slow],
+            objects: Mutex::new(FxHashMap::default()),
+            demand: Mutex::new(Demand::default()),
+            wake: Condvar::new(),
+        });
+        let shared = tiers.clone();
+        let demoter = thread::Builder::new()
+            .name(String::from("tier-demoter"))
+            .spawn(move || demoter(shared))?;
+
+        // the fast tier may have filled up since the last run
+        tiers.schedule()?;
+        Ok(TierBackend {
+            tiers,
+            demoter: Some(demoter),
+        })
+    }
+
+    /// Returns the tier an object lives on.
+    pub fn tier(&self, path: &Path) -> Result<Tier> {
+        let object = self.tiers.object(path)?;
+        let tier = object.tier();
+        self.tiers.release(&object)?;
+        tier
+    }
+
+    /// Waits until the demoter is done with the demotions asked for so far.
+    pub fn settle(&self) -> Result<()> {
+        let demand = self.tiers.demand()?;
+        self.tiers
+            .wake
+            .wait_while(demand, |demand| demand.pending || demand.running)
+            .map(drop)
+            .map_err(|e| BackendError::map(&e, Action::Internal))
+    }
+}
+
+impl Drop for TierBackend {
+    fn drop(&mut self) {
+        if let Ok(mut demand) = self.tiers.demand() {
+            demand.stop = true;
+        }
+        self.tiers.wake.notify_all();
+        if let Some(demoter) = self.demoter.take() {
+            let _ = demoter.join();
+        }
+    }
+}
+
+impl NamespaceBackend for TierBackend {
+    type Object = Arc<TieredObject>;
+
+    /// Takes the fast and the slow root from the path, separated by a colon.
+    fn init(path: &str, config: &Config) -> Result<Self> {
+        TierBackend::new(split_roots(path)?, config)
+    }
+
+    /// Lets pending demotions finish, the demoter is stopped when the backend is dropped.
+    fn fini(&self) -> Result<()> {
+        self.settle()
+    }
+
+    fn create(&self, namespace: &str, name: &str) -> Result<Arc<TieredObject>> {
+        let object = self.tiers.create(&Path::new(namespace).join(name))?;
+        self.tiers.schedule()?;
+        Ok(object)
+    }
+
+    /// Opens an object, promoting it if it lives on the slow tier.
+    fn open(&self, namespace: &str, name: &str) -> Result<Arc<TieredObject>> {
+        let object = self.tiers.object(&Path::new(namespace).join(name))?;
+        self.tiers.promote(&object)?;
+        Ok(object)
+    }
+
+    fn delete(&self, object: &Arc<TieredObject>) -> Result<()> {
+        let res = {
+            let placement = object.placement()?;
+            if placement.tier == Tier::Fast {
+                self.tiers.fast()?.forget(&object.path);
+            }
+            fs::remove_file(self.tiers.roots[placement.tier as usize].join(&object.path))
+        };
+        self.tiers.release(object)?;
+        res.map_err(|e| BackendError::map(&e, Action::Delete))
+    }
+
+    fn close(&self, object: &Arc<TieredObject>) -> Result<()> {
+        self.tiers.release(object)
+    }
+
+    fn status(&self, object: &Arc<TieredObject>) -> Result<(i64, u64)> {
+        let metadata = object.placement()?.file.metadata()?;
+        Ok((metadata.atime(), metadata.size()))
+    }
+
+    fn sync(&self, object: &Arc<TieredObject>) -> Result<()> {
+        let res = object.placement()?.file.sync_data();
+        res.map_err(|e| BackendError::map(&e, Action::Sync))
+    }
+
+    fn read(&self, object: &Arc<TieredObject>, buffer: &mut [u8], offset: u64) -> Result<u64> {
+        self.tiers.access(object, false, |file| {
+            let mut n_read = 0;
+            while n_read < buffer.len() {
+                match file.read_at(&mut buffer[n_read..], offset + n_read as u64) {
+                    Ok(0) => break,
+                    Ok(n) => n_read += n,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(BackendError::map(&e, Action::Read)),
+                }
+            }
+            Ok(n_read as u64)
+        })
+    }
+
+    fn write(&self, object: &Arc<TieredObject>, buffer: &[u8], offset: u64) -> Result<u64> {
+        self.tiers.access(object, true, |file| {
+            file.write_all_at(buffer, offset)
+                .map_err(|e| BackendError::map(&e, Action::Write))?;
+            Ok(buffer.len() as u64)
+        })
+    }
+
+    /// Copies an object within the tier it lives on.
+    fn copy(
+        &self,
+        src_namespace: &str,
+        src_name: &str,
+        dst_namespace: &str,
+        dst_name: &str,
+    ) -> Result<()> {
+        let dst = Path::new(dst_namespace).join(dst_name);
+        let object = self
+            .tiers
+            .object(&Path::new(src_namespace).join(src_name))?;
+        let res = (|| -> Result<()> {
+            let placement = object.placement()?;
+            let other = match placement.tier {
+                Tier::Fast => Tier::Slow,
+                Tier::Slow => Tier::Fast,
+            };
+            self.tiers.absent(other, &dst)?;
+
+            let dst_path = self.tiers.roots[placement.tier as usize].join(&dst);
+            if let Some(dir) = dst_path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let dst_file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&dst_path)?;
+            let size = copy_file(&placement.file, &dst_file).inspect_err(|_| {
+                let _ = fs::remove_file(&dst_path);
+            })?;
+            if placement.tier == Tier::Fast {
+                self.tiers.fast()?.record(&dst, size);
+            }
+            Ok(())
+        })();
+        self.tiers.release(&object)?;
+        res?;
+        self.tiers.schedule()
+    }
+
+    /// Lists the namespace on both tiers.
+    fn names(&self, namespace: &str, prefix: Option<&str>) -> Result<Vec<String>> {
+        let mut names = BTreeSet::new();
+        for root in &self.tiers.roots {
+            let entries = match fs::read_dir(root.join(namespace)) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(BackendError::map(&e, Action::Iter)),
+            };
+            for entry in entries {
+                let name = entry?.file_name().into_string().map_err(|_| {
+                    BackendError::new("Unable to convert file name to UTF-8", Action::Iter)
+                })?;
+                if !name.starts_with(TEMP_PREFIX) && name.starts_with(prefix.unwrap_or_default()) {
+                    names.insert(name);
+                }
+            }
+        }
+        Ok(names.into_iter().collect())
+    }
+}
+
+pub struct Adapter {}
+
+impl NamespaceAdapter<TierBackend> for Adapter {}
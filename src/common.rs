@@ -1,16 +1,36 @@
 mod adapter;
+mod atomic;
 mod backend;
+mod checksum;
+mod coalesce;
+mod config;
+mod copy;
+mod encrypt;
 mod error;
 mod init;
 mod io_handler;
+mod mmap;
+mod prealloc;
+mod registry;
+mod shared;
 mod util_c;
 
 pub mod prelude {
     pub use crate::common::adapter::*;
+    pub use crate::common::atomic::*;
     pub use crate::common::backend::*;
+    pub use crate::common::checksum::*;
+    pub use crate::common::coalesce::*;
+    pub use crate::common::config::*;
+    pub use crate::common::copy::*;
+    pub use crate::common::encrypt::*;
     pub use crate::common::error::*;
     pub use crate::common::init::*;
     pub use crate::common::io_handler::*;
+    pub use crate::common::mmap::*;
+    pub use crate::common::prealloc::*;
+    pub use crate::common::registry::*;
+    pub use crate::common::shared::*;
     pub use crate::common::util_c::util_macro::cast_ptr;
     pub use crate::common::util_c::*;
 }
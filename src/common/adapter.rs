@@ -3,12 +3,19 @@ use std::{
     ffi::CString,
     fmt::Display,
     fs::{self, create_dir_all, File, OpenOptions},
+    io::ErrorKind,
+    ops::Deref,
     os::fd::{AsRawFd, RawFd},
     path::{Path, PathBuf},
     ptr, slice,
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc, RwLock,
+    },
 };
 
 use log::{debug, error, info, trace};
+use rustc_hash::FxHashMap;
 
 use crate::prelude::*;
 
@@ -25,14 +32,19 @@ pub trait JuleaAdapter<T: BackendObject> {
 
     unsafe fn backend_init(path: *const gchar) -> Result<Backend<T>> {
         let path = read_str(path).map_err(|e| e.set_action(Action::Init))?;
+        let (path, config) = Config::from_init_path(&path)?;
         info!("Initializing backend in namespace {path}");
+        T::init(&config)?;
 
         if !Path::new(path.as_str()).is_dir() {
             trace!("Creating namespace directory");
             create_dir_all(path.as_str())?;
         }
+        if config.get_bool("atomic_create")? {
+            sweep_hidden(Path::new(&path))?;
+        }
 
-        Ok(Backend::new(path))
+        Ok(Backend::new(path, config))
     }
 
     // FINI
@@ -72,21 +84,29 @@ pub trait JuleaAdapter<T: BackendObject> {
 
         debug!("Create new file: {path:?}");
 
-        // setting O_APPEND will cause the posix backend to break: https://bugzilla.kernel.org/show_bug.cgi?id=43178
-        let f: File = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create_new(true)
-            .open(&path)
-            .map_err(|e| BackendError::map(&e, Action::Create))?;
+        // with atomic_create, the object only becomes visible on its first sync or close
+        let (f, pending) = match backend_data.config.get_bool("atomic_create")? {
+            true => create_atomic(&path).map(|(f, link)| (f, Some(link)))?,
+            // setting O_APPEND will cause the posix backend to break: https://bugzilla.kernel.org/show_bug.cgi?id=43178
+            false => OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .map(|f| (f, None))
+                .map_err(|e| BackendError::map(&e, Action::Create))?,
+        };
         let fd = f.as_raw_fd();
 
-        let handle: T = T::new(f)?;
+        let handle: T = T::create(f, &backend_data.config)?;
 
         backend_data
             .object_store
             .insert(handle, fd)
             .map_err(|e| e.set_action(Action::Create))?;
+        if let Some(link) = pending {
+            backend_data.pending.insert(fd, link)?;
+        }
 
         Ok(ObjectHandle { raw_fd: fd, path })
     }
@@ -116,14 +136,10 @@ pub trait JuleaAdapter<T: BackendObject> {
 
         debug!("Open path: {path:?}");
 
-        let f: File = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(&path)
-            .map_err(|e| BackendError::map(&e, Action::Open))?;
+        let f: File = Self::open_file(backend_data, &path)?;
         let fd = f.as_raw_fd();
 
-        let handle: T = T::new(f)?;
+        let handle: T = T::new(f, &backend_data.config)?;
 
         backend_data
             .object_store
@@ -151,8 +167,78 @@ pub trait JuleaAdapter<T: BackendObject> {
             .object_store
             .remove(backend_object.raw_fd)
             .map_err(|e| e.set_action(Action::Delete))?;
-        Ok(fs::remove_file(&backend_object.path)
-            .map_err(|e| BackendError::map(&e, Action::Delete))?)
+        if backend_data
+            .pending
+            .discard(backend_object.raw_fd)
+            .map_err(|e| e.set_action(Action::Delete))?
+        {
+            // never linked into the namespace, nothing left to remove
+            return Ok(());
+        }
+        Self::remove_object(backend_data, &backend_object.path)
+            .map_err(|e| e.set_action(Action::Delete))?;
+        match backend_data.config.get_bool("checksum")? {
+            true => remove_checksums(&backend_object.path),
+            false => Ok(()),
+        }
+    }
+
+    /// Removes the file of a deleted object. Backends that keep the data around
+    /// after a delete override this.
+    fn remove_object(_backend_data: &Backend<T>, path: &Path) -> Result<()> {
+        fs::remove_file(path).map_err(|e| BackendError::map(&e, Action::Delete))
+    }
+
+    // COPY
+    unsafe extern "C" fn j_copy(
+        backend_data: gpointer,
+        src_namespace: *const gchar,
+        src_path: *const gchar,
+        dst_namespace: *const gchar,
+        dst_path: *const gchar,
+    ) -> gboolean {
+        cast_ptr!(backend_data, Backend<T>);
+
+        match Self::backend_copy(
+            backend_data,
+            src_namespace,
+            src_path,
+            dst_namespace,
+            dst_path,
+        ) {
+            Ok(_) => TRUE,
+            Err(e) => handle_error(e.set_action(Action::Copy)),
+        }
+    }
+
+    /// Copies an object into a new object, which may be in another namespace.
+    /// Data still buffered by an open source object is not included, sync it first.
+    unsafe fn backend_copy(
+        backend_data: &Backend<T>,
+        src_namespace: *const gchar,
+        src_path: *const gchar,
+        dst_namespace: *const gchar,
+        dst_path: *const gchar,
+    ) -> Result<u64> {
+        let src = Self::build_path(backend_data, Vec::from([src_namespace, src_path]))?;
+        let dst = Self::build_path(backend_data, Vec::from([dst_namespace, dst_path]))?;
+
+        if let Some(dir) = dst.parent() {
+            create_dir_all(dir)?;
+        }
+
+        debug!("Copy {src:?} to {dst:?}");
+
+        let src_file = File::open(&src).map_err(|e| BackendError::map(&e, Action::Copy))?;
+        let dst_file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&dst)
+            .map_err(|e| BackendError::map(&e, Action::Copy))?;
+
+        copy_file(&src_file, &dst_file).inspect_err(|_| {
+            let _ = fs::remove_file(&dst);
+        })
     }
 
     // CLOSE
@@ -160,7 +246,21 @@ pub trait JuleaAdapter<T: BackendObject> {
         cast_ptr!(backend_data, Backend<T>);
         cast_ptr!(backend_object, ObjectHandle);
 
-        match backend_data.object_store.remove(backend_object.raw_fd) {
+        // pending objects are linked after the last flush, but while their file is still open
+        let closed = backend_data
+            .object_store
+            .remove(backend_object.raw_fd)
+            .and_then(|mut object| {
+                object.close()?;
+                backend_data.pending.publish(backend_object.raw_fd)
+            });
+        // the descriptor is gone either way, a link left behind would be taken by its next user
+        if closed.is_err() {
+            if let Err(e) = backend_data.pending.discard(backend_object.raw_fd) {
+                error!("{e}");
+            }
+        }
+        match closed {
             Ok(_) => TRUE,
             Err(e) => handle_error(e.set_action(Action::Close)),
         }
@@ -191,7 +291,10 @@ pub trait JuleaAdapter<T: BackendObject> {
         cast_ptr!(backend_data, Backend<T>);
         cast_ptr!(backend_object, ObjectHandle);
 
-        match backend_data.sync(backend_object) {
+        match backend_data
+            .sync(backend_object)
+            .and_then(|_| backend_data.pending.publish(backend_object.raw_fd))
+        {
             Ok(_) => TRUE,
             Err(e) => handle_error(e.set_action(Action::Sync)),
         }
@@ -346,6 +449,10 @@ pub trait JuleaAdapter<T: BackendObject> {
                 BackendError::new("Unable to convert file name to UTF-8", Action::Iter),
             )?);
 
+            if Self::is_internal(&file_name) {
+                continue;
+            }
+
             let matching = match &backend_iterator.prefix {
                 Some(prefix) => file_name.starts_with(prefix),
                 None => true,
@@ -359,6 +466,28 @@ pub trait JuleaAdapter<T: BackendObject> {
         Ok(None)
     }
 
+    /// Whether an entry of a namespace directory belongs to the backend rather than
+    /// being an object. Such entries are skipped when iterating.
+    fn is_internal(file_name: &str) -> bool {
+        file_name.starts_with(TEMP_PREFIX) || file_name.starts_with(CHECKSUM_PREFIX)
+    }
+
+    /// Opens an existing object for reading and writing. Objects are opened
+    /// read-only if the namespace was configured with `readonly` or if the file
+    /// is not writable; the backend object has to upgrade itself on write.
+    fn open_file(backend_data: &Backend<T>, path: &Path) -> Result<File> {
+        let read_only = backend_data.config.get_bool("readonly")?;
+
+        match OpenOptions::new().read(true).write(!read_only).open(path) {
+            Err(e) if !read_only && e.kind() == ErrorKind::PermissionDenied => {
+                debug!("{path:?} is not writable, opening read-only");
+                OpenOptions::new().read(true).open(path)
+            }
+            res => res,
+        }
+        .map_err(|e| BackendError::map(&e, Action::Open))
+    }
+
     unsafe fn build_path(backend_data: &Backend<T>, appends: Vec<*const gchar>) -> Result<PathBuf> {
         appends.iter().map(|p| read_str(*p)).fold(
             Ok(PathBuf::new().join(&backend_data.namespace)),
@@ -367,6 +496,432 @@ pub trait JuleaAdapter<T: BackendObject> {
     }
 }
 
+/// A backend whose objects are no files of their own, for example because they live
+/// in memory, inside a larger file or spread across several roots. It only maps
+/// namespaces and names to its objects; the handle table, the iterators and the FFI
+/// glue are provided by [`NamespaceAdapter`].
+pub trait NamespaceBackend: Sized {
+    /// What the handle of an open object refers to.
+    type Object;
+
+    /// Called by `backend_init` with the path JULEA passed, without its options.
+    fn init(path: &str, config: &Config) -> Result<Self>;
+
+    /// Called when JULEA releases the backend, to persist what is still buffered.
+    fn fini(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Creates a new object, fails if it exists already.
+    fn create(&self, namespace: &str, name: &str) -> Result<Self::Object>;
+
+    fn open(&self, namespace: &str, name: &str) -> Result<Self::Object>;
+
+    /// Removes the object. Its handle is already closed, others may still be open.
+    fn delete(&self, object: &Self::Object) -> Result<()>;
+
+    /// Called when JULEA closes the object, right before it is dropped.
+    fn close(&self, _object: &Self::Object) -> Result<()> {
+        Ok(())
+    }
+
+    fn status(&self, object: &Self::Object) -> Result<(i64, u64)>;
+
+    fn sync(&self, object: &Self::Object) -> Result<()>;
+
+    fn read(&self, object: &Self::Object, buffer: &mut [u8], offset: u64) -> Result<u64>;
+
+    fn write(&self, object: &Self::Object, buffer: &[u8], offset: u64) -> Result<u64>;
+
+    /// Copies an object into a new object, which may be in another namespace.
+    fn copy(
+        &self,
+        src_namespace: &str,
+        src_name: &str,
+        dst_namespace: &str,
+        dst_name: &str,
+    ) -> Result<()>;
+
+    /// Returns the names of the objects in `namespace` that start with `prefix`.
+    fn names(&self, namespace: &str, prefix: Option<&str>) -> Result<Vec<String>>;
+}
+
+/// The `backend_data` JULEA holds for a [`NamespaceBackend`]: the backend and the
+/// objects opened through it. The id of a handle takes the place of the file
+/// descriptor in its [`ObjectHandle`].
+pub struct NamespaceData<B: NamespaceBackend> {
+    pub backend: B,
+    handles: RwLock<FxHashMap<i32, Arc<B::Object>>>,
+    next_handle: AtomicI32,
+}
+
+impl<B: NamespaceBackend> NamespaceData<B> {
+    pub fn new(backend: B) -> Self {
+        NamespaceData {
+            backend,
+            handles: RwLock::new(FxHashMap::default()),
+            next_handle: AtomicI32::new(0),
+        }
+    }
+
+    fn insert(&self, namespace: &str, name: &str, object: B::Object) -> Result<ObjectHandle> {
+        let raw_fd = self.next_handle.fetch_add(1, Ordering::Relaxed);
+
+        self.handles
+            .write()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?
+            .insert(raw_fd, Arc::new(object));
+        Ok(ObjectHandle {
+            raw_fd,
+            path: PathBuf::from(namespace).join(name),
+        })
+    }
+
+    /// Returns the object behind `handle`. The handle table is not locked while the
+    /// object is used.
+    pub fn get(&self, handle: &ObjectHandle) -> Result<Arc<B::Object>> {
+        self.handles
+            .read()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?
+            .get(&handle.raw_fd)
+            .cloned()
+            .ok_or(BackendError::new_internal(
+                "Backend doesn't know a matching object.",
+            ))
+    }
+
+    fn remove(&self, handle: &ObjectHandle) -> Result<Arc<B::Object>> {
+        self.handles
+            .write()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?
+            .remove(&handle.raw_fd)
+            .ok_or(BackendError::new_internal(
+                "Cannot remove object, backend does not know a matching object.",
+            ))
+    }
+}
+
+impl<B: NamespaceBackend> Deref for NamespaceData<B> {
+    type Target = B;
+
+    fn deref(&self) -> &B {
+        &self.backend
+    }
+}
+
+/// The JULEA object backend interface on top of a [`NamespaceBackend`], which is
+/// independent of the file system, unlike [`JuleaAdapter`].
+pub trait NamespaceAdapter<B: NamespaceBackend> {
+    // INIT
+    unsafe extern "C" fn j_init(path: *const gchar, backend_data: *mut gpointer) -> gboolean {
+        finish(Self::backend_init(path), backend_data)
+    }
+
+    unsafe fn backend_init(path: *const gchar) -> Result<NamespaceData<B>> {
+        let path = read_str(path).map_err(|e| e.set_action(Action::Init))?;
+        let (path, config) = Config::from_init_path(&path)?;
+        info!("Initializing backend for {path}");
+
+        B::init(&path, &config)
+            .map(NamespaceData::new)
+            .map_err(|e| e.set_action(Action::Init))
+    }
+
+    // FINI
+    unsafe extern "C" fn j_fini(backend_data: gpointer) {
+        let backend_data = Box::from_raw(backend_data.cast::<NamespaceData<B>>());
+
+        match backend_data.fini() {
+            Ok(_) => info!("Releasing backend"),
+            Err(e) => error!("{}", e.set_action(Action::Fini)),
+        }
+    }
+
+    // CREATE
+    unsafe extern "C" fn j_create(
+        backend_data: gpointer,
+        namespace: *const gchar,
+        path: *const gchar,
+        backend_object: *mut gpointer,
+    ) -> gboolean {
+        cast_ptr!(backend_data, NamespaceData<B>);
+
+        finish(
+            Self::backend_create(backend_data, namespace, path),
+            backend_object,
+        )
+    }
+
+    unsafe fn backend_create(
+        backend_data: &NamespaceData<B>,
+        namespace: *const gchar,
+        path: *const gchar,
+    ) -> Result<ObjectHandle> {
+        let (namespace, name) = (read_str(namespace)?, read_str(path)?);
+        debug!("Create new object: {namespace}/{name}");
+
+        let object = backend_data
+            .create(&namespace, &name)
+            .map_err(|e| e.set_action(Action::Create))?;
+        backend_data
+            .insert(&namespace, &name, object)
+            .map_err(|e| e.set_action(Action::Create))
+    }
+
+    // OPEN
+    unsafe extern "C" fn j_open(
+        backend_data: gpointer,
+        namespace: *const gchar,
+        path: *const gchar,
+        backend_object: *mut gpointer,
+    ) -> gboolean {
+        cast_ptr!(backend_data, NamespaceData<B>);
+
+        finish(
+            Self::backend_open(backend_data, namespace, path),
+            backend_object,
+        )
+    }
+
+    unsafe fn backend_open(
+        backend_data: &NamespaceData<B>,
+        namespace: *const gchar,
+        path: *const gchar,
+    ) -> Result<ObjectHandle> {
+        let (namespace, name) = (read_str(namespace)?, read_str(path)?);
+        debug!("Open object: {namespace}/{name}");
+
+        let object = backend_data
+            .open(&namespace, &name)
+            .map_err(|e| e.set_action(Action::Open))?;
+        backend_data
+            .insert(&namespace, &name, object)
+            .map_err(|e| e.set_action(Action::Open))
+    }
+
+    // DELETE
+    unsafe extern "C" fn j_delete(backend_data: gpointer, backend_object: gpointer) -> gboolean {
+        cast_ptr!(backend_data, NamespaceData<B>);
+        cast_ptr!(backend_object, ObjectHandle);
+
+        match Self::backend_delete(backend_data, backend_object) {
+            Ok(_) => TRUE,
+            Err(e) => handle_error(e.set_action(Action::Delete)),
+        }
+    }
+
+    unsafe fn backend_delete(
+        backend_data: &NamespaceData<B>,
+        backend_object: &ObjectHandle,
+    ) -> Result<()> {
+        let object = backend_data.remove(backend_object)?;
+        backend_data.delete(&object)
+    }
+
+    // COPY
+    unsafe extern "C" fn j_copy(
+        backend_data: gpointer,
+        src_namespace: *const gchar,
+        src_path: *const gchar,
+        dst_namespace: *const gchar,
+        dst_path: *const gchar,
+    ) -> gboolean {
+        cast_ptr!(backend_data, NamespaceData<B>);
+
+        let res = (|| {
+            backend_data.copy(
+                &read_str(src_namespace)?,
+                &read_str(src_path)?,
+                &read_str(dst_namespace)?,
+                &read_str(dst_path)?,
+            )
+        })();
+        match res {
+            Ok(_) => TRUE,
+            Err(e) => handle_error(e.set_action(Action::Copy)),
+        }
+    }
+
+    // CLOSE
+    unsafe extern "C" fn j_close(backend_data: gpointer, backend_object: gpointer) -> gboolean {
+        cast_ptr!(backend_data, NamespaceData<B>);
+        cast_ptr!(backend_object, ObjectHandle);
+
+        match backend_data
+            .remove(backend_object)
+            .and_then(|object| backend_data.close(&object))
+        {
+            Ok(_) => TRUE,
+            Err(e) => handle_error(e.set_action(Action::Close)),
+        }
+    }
+
+    // STATUS
+    unsafe extern "C" fn j_status(
+        backend_data: gpointer,
+        backend_object: gpointer,
+        modification_time: *mut gint64,
+        size: *mut guint64,
+    ) -> gboolean {
+        cast_ptr!(backend_data, NamespaceData<B>);
+        cast_ptr!(backend_object, ObjectHandle);
+
+        match backend_data
+            .get(backend_object)
+            .and_then(|object| backend_data.status(&object))
+        {
+            Ok((last_mod, s)) => {
+                *modification_time = last_mod;
+                *size = s;
+                TRUE
+            }
+            Err(e) => handle_error(e.set_action(Action::Status)),
+        }
+    }
+
+    // SYNC
+    unsafe extern "C" fn j_sync(backend_data: gpointer, backend_object: gpointer) -> gboolean {
+        cast_ptr!(backend_data, NamespaceData<B>);
+        cast_ptr!(backend_object, ObjectHandle);
+
+        match backend_data
+            .get(backend_object)
+            .and_then(|object| backend_data.sync(&object))
+        {
+            Ok(_) => TRUE,
+            Err(e) => handle_error(e.set_action(Action::Sync)),
+        }
+    }
+
+    // READ
+    unsafe extern "C" fn j_read(
+        backend_data: gpointer,
+        backend_object: gpointer,
+        buffer: gpointer,
+        length: guint64,
+        offset: guint64,
+        bytes_read: *mut guint64,
+    ) -> gboolean {
+        cast_ptr!(backend_data, NamespaceData<B>);
+        cast_ptr!(backend_object, ObjectHandle);
+
+        let buffer = slice::from_raw_parts_mut(buffer.cast::<u8>(), length as _);
+
+        match backend_data
+            .get(backend_object)
+            .and_then(|object| backend_data.read(&object, buffer, offset))
+        {
+            Ok(n_read) => {
+                *bytes_read = n_read;
+                trace!("Read {n_read}/{length} b, offset {offset} b");
+                TRUE
+            }
+            Err(e) => handle_error(e.set_action(Action::Read)),
+        }
+    }
+
+    // WRITE
+    unsafe extern "C" fn j_write(
+        backend_data: gpointer,
+        backend_object: gpointer,
+        buffer: gconstpointer,
+        length: guint64,
+        offset: guint64,
+        bytes_written: *mut guint64,
+    ) -> gboolean {
+        cast_ptr!(backend_data, NamespaceData<B>);
+        cast_ptr!(backend_object, ObjectHandle);
+
+        let buffer = slice::from_raw_parts(buffer.cast::<u8>(), length as _);
+
+        match backend_data
+            .get(backend_object)
+            .and_then(|object| backend_data.write(&object, buffer, offset))
+        {
+            Ok(n_written) => {
+                *bytes_written += n_written;
+                trace!("Wrote {n_written}/{length} b, offset {offset} b");
+                TRUE
+            }
+            Err(e) => handle_error(e.set_action(Action::Write)),
+        }
+    }
+
+    // ITERATE
+    unsafe extern "C" fn j_get_all(
+        backend_data: gpointer,
+        namespace: *const gchar,
+        backend_iterator: *mut gpointer,
+    ) -> gboolean {
+        cast_ptr!(backend_data, NamespaceData<B>);
+
+        finish(
+            Self::backend_get_iterator(backend_data, namespace, None)
+                .map_err(|e| e.set_action(Action::CreateIterAll)),
+            backend_iterator,
+        )
+    }
+
+    unsafe extern "C" fn j_get_by_prefix(
+        backend_data: gpointer,
+        namespace: *const gchar,
+        prefix: *const gchar,
+        backend_iterator: *mut gpointer,
+    ) -> gboolean {
+        cast_ptr!(backend_data, NamespaceData<B>);
+
+        finish(
+            Self::backend_get_iterator(backend_data, namespace, Some(prefix))
+                .map_err(|e| e.set_action(Action::CreateIterPrefix)),
+            backend_iterator,
+        )
+    }
+
+    /// Takes a snapshot of the matching names, objects created later are not listed.
+    unsafe fn backend_get_iterator(
+        backend_data: &NamespaceData<B>,
+        namespace: *const gchar,
+        prefix: Option<*const gchar>,
+    ) -> Result<NameIterator> {
+        let prefix = match prefix {
+            Some(cs) => Some(read_str(cs)?),
+            None => None,
+        };
+        let names = backend_data.names(&read_str(namespace)?, prefix.as_deref())?;
+
+        Ok(NameIterator {
+            names: names.into_iter(),
+            current_name: CString::default(),
+        })
+    }
+
+    unsafe extern "C" fn j_iterate(
+        _backend_data: gpointer,
+        backend_iterator: gpointer,
+        name: *mut *const gchar,
+    ) -> gboolean {
+        let backend_iterator: &mut NameIterator = &mut *backend_iterator.cast();
+
+        match backend_iterator.names.next().map(CString::new) {
+            Some(Ok(n)) => {
+                backend_iterator.current_name = n;
+                write_str(name, &backend_iterator.current_name);
+                TRUE
+            }
+            Some(Err(e)) => {
+                drop(Box::from_raw(backend_iterator));
+                info!("An error occured. Releasing iterator.");
+                handle_error(BackendError::map(&e, Action::Iter))
+            }
+            None => {
+                info!("End of iterator reached. Releasing iterator.");
+                drop(Box::from_raw(backend_iterator));
+                FALSE
+            }
+        }
+    }
+}
+
 unsafe fn finish<T, E: Display>(res: std::result::Result<T, E>, out: *mut gpointer) -> gboolean {
     match res {
         Ok(r) => {
@@ -0,0 +1,233 @@
+use std::{
+    ffi::CString,
+    fs::{self, File, OpenOptions},
+    io,
+    os::unix::{ffi::OsStrExt, fs::OpenOptionsExt},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use log::{debug, warn};
+use rustc_hash::FxHashMap;
+
+use crate::common::error::{Action, BackendError, Result};
+
+/// Prefix of the hidden names objects are created under when O_TMPFILE is not available.
+/// Iterators skip files starting with it.
+pub const TEMP_PREFIX: &str = ".jtmp-";
+
+/// An object that was created with `atomic_create` but is not yet visible under its name.
+pub enum PendingLink {
+    /// An anonymous O_TMPFILE inode, linked into place via `/proc/self/fd`.
+    Anonymous { target: PathBuf },
+    /// A file with a hidden temporary name, hard linked into place and then unlinked.
+    Hidden { temp: PathBuf, target: PathBuf },
+}
+
+/// Creates the file for a new object without making it visible at `target`.
+/// An existing object is only detected when the file is published, as linking it into
+/// place never replaces an object.
+pub fn create_atomic(target: &Path) -> Result<(File, PendingLink)> {
+    let dir = target.parent().ok_or(BackendError::new(
+        "Object path has no parent",
+        Action::Create,
+    ))?;
+
+    match OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_TMPFILE)
+        .open(dir)
+    {
+        Ok(f) => Ok((
+            f,
+            PendingLink::Anonymous {
+                target: target.to_path_buf(),
+            },
+        )),
+        Err(e)
+            if matches!(
+                e.raw_os_error(),
+                Some(libc::EOPNOTSUPP | libc::EISDIR | libc::EINVAL)
+            ) =>
+        {
+            debug!("O_TMPFILE not supported ({e}), using a hidden temporary name");
+            create_hidden(target)
+        }
+        Err(e) => Err(BackendError::map(&e, Action::Create)),
+    }
+}
+
+/// Fallback for file systems without O_TMPFILE.
+pub fn create_hidden(target: &Path) -> Result<(File, PendingLink)> {
+    let name = target
+        .file_name()
+        .ok_or(BackendError::new("Object path has no name", Action::Create))?;
+    let mut temp_name = format!("{TEMP_PREFIX}{}-", std::process::id()).into_bytes();
+    temp_name.extend_from_slice(name.as_bytes());
+    let temp = target.with_file_name(std::ffi::OsStr::from_bytes(&temp_name));
+
+    let f = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .map_err(|e| BackendError::map(&e, Action::Create))?;
+
+    Ok((
+        f,
+        PendingLink::Hidden {
+            temp,
+            target: target.to_path_buf(),
+        },
+    ))
+}
+
+/// Removes the hidden temporary files below `root` that processes which have exited
+/// left behind. Directories starting with a dot hold backend internals and are skipped.
+pub fn sweep_hidden(root: &Path) -> Result<()> {
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() && !name.starts_with('.') {
+            sweep_hidden(&entry.path())?;
+            continue;
+        }
+
+        let pid = match name
+            .strip_prefix(TEMP_PREFIX)
+            .and_then(|rest| rest.split_once('-'))
+            .and_then(|(pid, _)| pid.parse::<libc::pid_t>().ok())
+        {
+            Some(pid) if file_type.is_file() && pid > 0 => pid,
+            _ => continue,
+        };
+        let exited = unsafe { libc::kill(pid, 0) } != 0
+            && io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH);
+        if exited {
+            debug!("Removing {name} of exited process {pid}");
+            fs::remove_file(entry.path()).map_err(|e| BackendError::map(&e, Action::Init))?;
+        }
+    }
+    Ok(())
+}
+
+impl PendingLink {
+    /// Links the object into place once its data is on disk, and syncs the directory
+    /// so that the name survives a crash as well.
+    fn publish(&self, fd: i32) -> io::Result<()> {
+        if unsafe { libc::fdatasync(fd) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let target = match self {
+            PendingLink::Anonymous { target } => {
+                debug!("Linking anonymous object to {target:?}");
+                let proc_path = CString::new(format!("/proc/self/fd/{fd}"))?;
+                let c_target = CString::new(target.as_os_str().as_bytes())?;
+                let ret = unsafe {
+                    libc::linkat(
+                        libc::AT_FDCWD,
+                        proc_path.as_ptr(),
+                        libc::AT_FDCWD,
+                        c_target.as_ptr(),
+                        libc::AT_SYMLINK_FOLLOW,
+                    )
+                };
+                if ret != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                target
+            }
+            PendingLink::Hidden { temp, target } => {
+                debug!("Moving {temp:?} to {target:?}");
+                // unlike rename, link refuses to replace an object created in the meantime
+                fs::hard_link(temp, target)?;
+                fs::remove_file(temp)?;
+                target
+            }
+        };
+
+        match target.parent() {
+            Some(dir) => File::open(dir)?.sync_all(),
+            None => Ok(()),
+        }
+    }
+
+    fn discard(&self) -> io::Result<()> {
+        match self {
+            PendingLink::Anonymous { .. } => Ok(()),
+            PendingLink::Hidden { temp, .. } => fs::remove_file(temp),
+        }
+    }
+}
+
+/// Objects created with `atomic_create` that still wait for their first sync or close.
+#[derive(Default)]
+pub struct PendingLinks {
+    links: Mutex<FxHashMap<i32, PendingLink>>,
+}
+
+impl PendingLinks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, fd: i32, link: PendingLink) -> Result<()> {
+        self.links
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?
+            .insert(fd, link);
+        Ok(())
+    }
+
+    /// Makes the object behind `fd` visible under its name, if it is still pending.
+    pub fn publish(&self, fd: i32) -> Result<()> {
+        let mut links = self
+            .links
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?;
+
+        if let Some(link) = links.get(&fd) {
+            link.publish(fd)?;
+            links.remove(&fd);
+        }
+        Ok(())
+    }
+
+    /// Drops the object behind `fd` before it was published.
+    /// Returns whether the object was pending at all.
+    pub fn discard(&self, fd: i32) -> Result<bool> {
+        let link = self
+            .links
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))?
+            .remove(&fd);
+
+        match link {
+            Some(link) => {
+                link.discard()?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+/// Objects that were never published when the backend is released are discarded.
+impl Drop for PendingLinks {
+    fn drop(&mut self) {
+        let links = match self.links.get_mut() {
+            Ok(links) => links,
+            Err(e) => e.into_inner(),
+        };
+        for (_, link) in links.drain() {
+            if let Err(e) = link.discard() {
+                warn!("{e}");
+            }
+        }
+    }
+}
@@ -8,3 +8,10 @@ pub struct BackendIterator {
     pub prefix: Option<String>,
     pub current_name: CString,
 }
+
+/// Iterates over the names a [`NamespaceBackend`](super::adapter::NamespaceBackend)
+/// listed when the iterator was created.
+pub struct NameIterator {
+    pub names: std::vec::IntoIter<String>,
+    pub current_name: CString,
+}
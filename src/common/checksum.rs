@@ -0,0 +1,423 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, IoSlice, IoSliceMut},
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::fs::FileExt,
+    },
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use log::{debug, warn};
+
+use crate::common::{
+    atomic::TEMP_PREFIX,
+    config::Config,
+    error::{Action, BackendError, ErrorKind, Result},
+    io_handler::{read_each, write_each, BackendObject},
+    shared::{lock, SharedStates},
+};
+
+/// Prefix of the sidecar files holding the checksums of an object.
+pub const CHECKSUM_PREFIX: &str = ".jsum-";
+
+const MAGIC: &[u8; 4] = b"JSUM";
+const VERSION: u32 = 1;
+
+/// magic, version, block size, reserved
+const HEADER_LEN: u64 = 16;
+const ENTRY_LEN: u64 = 4;
+
+const DEFAULT_BLOCK_SIZE: u64 = 4096;
+
+static CHECKSUMS: SharedStates<Checksums> = SharedStates::new();
+
+/// Returns the block size set by `checksum_block`. The sidecar records it in 32 bits.
+fn block_size(config: &Config) -> Result<u64> {
+    let block_size = config
+        .get_size("checksum_block")?
+        .unwrap_or(DEFAULT_BLOCK_SIZE);
+    if block_size == 0 || block_size > u32::MAX as u64 {
+        return Err(BackendError::new(
+            &format!("Invalid block size {block_size} for backend option 'checksum_block'"),
+            Action::Init,
+        ));
+    }
+    Ok(block_size)
+}
+
+/// Returns the sidecar of the object at `path`.
+pub fn checksum_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{CHECKSUM_PREFIX}{name}"))
+}
+
+/// Removes the sidecar of a deleted object, if there is one.
+pub fn remove_checksums(path: &Path) -> Result<()> {
+    match fs::remove_file(checksum_path(path)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(BackendError::map(&e, Action::Delete)),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the path of an open object, or `None` while it is not linked into its
+/// namespace under its final name.
+fn object_path(fd: RawFd) -> Result<Option<PathBuf>> {
+    let path = fs::read_link(format!("/proc/self/fd/{fd}"))?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+    match name.starts_with(TEMP_PREFIX) || name.ends_with(" (deleted)") {
+        true => Ok(None),
+        false => Ok(Some(path)),
+    }
+}
+
+/// Verifies object data against a checksum per block.
+///
+/// Enabled with the backend option `checksum`. A CRC32C of every `checksum_block` bytes
+/// is kept in a sidecar file next to the object (`.jsum-<name>`), which is updated on
+/// every write. Reads verify all blocks they touch and fail with
+/// `ErrorKind::ChecksumMismatch` if any of them changed. Objects without a sidecar,
+/// like copies or objects written without the option, are checksummed when they are
+/// opened. Opening an object whose sidecar is corrupt fails with
+/// `ErrorKind::CorruptChecksums`, unless `checksum_rebuild` is set, which checksums the
+/// object again. Without the option, all operations are passed through unchanged.
+pub struct ChecksummedObject<T: BackendObject> {
+    inner: T,
+    sums: Option<Arc<Mutex<Checksums>>>,
+}
+
+/// The checksums of an object, shared by all its handles.
+struct Checksums {
+    /// The object, to find its path once it is linked.
+    file: File,
+    /// Where the object was opened, or where it will be visible once it is linked.
+    path: PathBuf,
+    /// Opened once the object is linked into its namespace.
+    sidecar: Option<File>,
+    block_size: u64,
+    size: u64,
+    table: Vec<u32>,
+}
+
+impl Checksums {
+    fn blocks(&self, size: u64) -> usize {
+        size.div_ceil(self.block_size) as usize
+    }
+
+    /// Checks `data`, the contents of the blocks starting at `first`, and returns the
+    /// range of blocks that do not match.
+    fn verify(&self, data: &[u8], first: u64) -> Option<(u64, u64)> {
+        let mut bad: Option<(u64, u64)> = None;
+        for (i, block) in data.chunks(self.block_size as usize).enumerate() {
+            let n = first + i as u64;
+            if self.table.get(n as usize) != Some(&crc32c::crc32c(block)) {
+                bad = Some((bad.map_or(n, |(start, _)| start), n));
+            }
+        }
+        bad
+    }
+
+    /// Opens the sidecar once the object has its final name and writes out all checksums.
+    fn attach(&mut self) -> Result<()> {
+        if self.sidecar.is_some() {
+            return Ok(());
+        }
+        let path = match object_path(self.file.as_raw_fd())? {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let sidecar = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(checksum_path(&path))?;
+
+        let mut header = [0u8; HEADER_LEN as usize];
+        header[0..4].copy_from_slice(MAGIC);
+        header[4..8].copy_from_slice(&VERSION.to_le_bytes());
+        header[8..12].copy_from_slice(&(self.block_size as u32).to_le_bytes());
+        sidecar.write_all_at(&header, 0)?;
+        self.path = path;
+        self.sidecar = Some(sidecar);
+        self.store(0, self.table.len())
+    }
+
+    /// Writes the checksums of blocks `first..last` to the sidecar.
+    fn store(&self, first: usize, last: usize) -> Result<()> {
+        let sidecar = match &self.sidecar {
+            Some(sidecar) => sidecar,
+            None => return Ok(()),
+        };
+
+        let entries: Vec<u8> = self.table[first..last]
+            .iter()
+            .flat_map(|sum| sum.to_le_bytes())
+            .collect();
+        sidecar.write_all_at(&entries, HEADER_LEN + first as u64 * ENTRY_LEN)?;
+        sidecar.set_len(HEADER_LEN + self.table.len() as u64 * ENTRY_LEN)?;
+        Ok(())
+    }
+}
+
+impl<T: BackendObject> ChecksummedObject<T> {
+    fn wrap(file: File, config: &Config, open: fn(File, &Config) -> Result<T>) -> Result<Self> {
+        if !config.get_bool("checksum")? {
+            return Ok(ChecksummedObject {
+                inner: open(file, config)?,
+                sums: None,
+            });
+        }
+
+        let metadata = file.metadata()?;
+        let object = file.try_clone()?;
+        let inner = open(file, config)?;
+        let sums = CHECKSUMS.get_or_init(&metadata, || Self::checksums(object, &inner, config))?;
+
+        Ok(ChecksummedObject {
+            inner,
+            sums: Some(sums),
+        })
+    }
+
+    /// Loads the checksums of an object from its sidecar, or computes them if it has none.
+    fn checksums(file: File, inner: &T, config: &Config) -> Result<Checksums> {
+        let path = fs::read_link(format!("/proc/self/fd/{}", file.as_raw_fd()))?;
+        let mut sums = Checksums {
+            file,
+            path: path.clone(),
+            sidecar: None,
+            block_size: block_size(config).map_err(|e| e.set_action(Action::Open))?,
+            size: inner.status()?.1,
+            table: Vec::new(),
+        };
+
+        match Self::load(&checksum_path(&path), &mut sums) {
+            Ok(true) => {
+                sums.sidecar = Some(
+                    OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .open(checksum_path(&path))?,
+                );
+            }
+            Ok(false) => {
+                debug!("checksumming {path:?}");
+                sums.table = Self::compute(inner, &sums)?;
+            }
+            Err(e)
+                if matches!(e.kind(), ErrorKind::CorruptChecksums { .. })
+                    && config.get_bool("checksum_rebuild")? =>
+            {
+                warn!("{e}, checksumming {path:?} again");
+                sums.block_size = block_size(config)?;
+                sums.table = Self::compute(inner, &sums)?;
+            }
+            Err(e) => return Err(e),
+        }
+        sums.attach()?;
+        Ok(sums)
+    }
+
+    /// Reads the checksums from `sidecar`. Returns `false` if there are none.
+    fn load(sidecar: &Path, sums: &mut Checksums) -> Result<bool> {
+        let content = match fs::read(sidecar) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(BackendError::map(&e, Action::Open)),
+        };
+
+        if content.len() < HEADER_LEN as usize
+            || &content[0..4] != MAGIC
+            || content[4..8] != VERSION.to_le_bytes()
+            || content[8..12] == [0; 4]
+        {
+            return Err(BackendError::corrupt_checksums(
+                sidecar,
+                "not a checksum file",
+                Action::Open,
+            ));
+        }
+        sums.block_size = u32::from_le_bytes(content[8..12].try_into().unwrap()) as u64;
+        sums.table = content[HEADER_LEN as usize..]
+            .chunks_exact(ENTRY_LEN as usize)
+            .map(|entry| u32::from_le_bytes(entry.try_into().unwrap()))
+            .collect();
+
+        if sums.table.len() != sums.blocks(sums.size) {
+            return Err(BackendError::corrupt_checksums(
+                sidecar,
+                &format!("{} checksums for {} b", sums.table.len(), sums.size),
+                Action::Open,
+            ));
+        }
+        Ok(true)
+    }
+
+    /// Checksums all data of `inner`.
+    fn compute(inner: &T, sums: &Checksums) -> Result<Vec<u32>> {
+        let mut table = Vec::with_capacity(sums.blocks(sums.size));
+        let mut block = vec![0u8; sums.block_size as usize];
+        let mut position = 0;
+
+        while position < sums.size {
+            let len = sums.block_size.min(sums.size - position);
+            let n_read = inner.read(&mut block[..len as usize], position, len)?;
+            table.push(crc32c::crc32c(&block[..n_read as usize]));
+            position += len;
+        }
+        Ok(table)
+    }
+}
+
+impl<T: BackendObject> BackendObject for ChecksummedObject<T> {
+    fn init(config: &Config) -> Result<()> {
+        block_size(config)?;
+        T::init(config)
+    }
+
+    fn new(file: File, config: &Config) -> Result<Self> {
+        Self::wrap(file, config, T::new)
+    }
+
+    fn create(file: File, config: &Config) -> Result<Self> {
+        Self::wrap(file, config, T::create)
+    }
+
+    fn read(&self, buffer: &mut [u8], offset: u64, length: u64) -> Result<u64> {
+        let sums = match &self.sums {
+            Some(sums) => lock(sums)?,
+            None => return self.inner.read(buffer, offset, length),
+        };
+
+        let end = sums.size.min(offset + buffer.len() as u64);
+        if offset >= end {
+            return Ok(0);
+        }
+
+        // only whole blocks can be verified
+        let first = offset / sums.block_size;
+        let start = first * sums.block_size;
+        let block_end = end.next_multiple_of(sums.block_size).min(sums.size);
+        let mut data = vec![0u8; (block_end - start) as usize];
+        let n_read = self.inner.read(&mut data, start, block_end - start)?;
+        data.truncate(n_read as usize);
+
+        if let Some((bad_first, bad_last)) = sums.verify(&data, first) {
+            return Err(BackendError::checksum_mismatch(
+                &sums.path,
+                bad_first..=bad_last,
+                Action::Read,
+            ));
+        }
+
+        let n = (end - offset) as usize;
+        let at = (offset - start) as usize;
+        buffer[..n].copy_from_slice(&data[at..at + n]);
+        Ok(n as u64)
+    }
+
+    fn write(&mut self, buffer: &[u8], offset: u64, length: u64) -> Result<u64> {
+        let (inner, mut sums) = match &self.sums {
+            Some(sums) => (&mut self.inner, lock(sums)?),
+            None => return self.inner.write(buffer, offset, length),
+        };
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        let end = offset + buffer.len() as u64;
+        let old_size = sums.size;
+        let new_size = old_size.max(end);
+        let first = offset.min(old_size) / sums.block_size;
+        let last = (end - 1) / sums.block_size;
+
+        // partially overwritten blocks are verified before their checksum is replaced
+        let mut new_sums = Vec::with_capacity((last - first + 1) as usize);
+        for n in first..=last {
+            let start = n * sums.block_size;
+            let block_end = (start + sums.block_size).min(new_size);
+
+            if offset <= start && block_end <= end {
+                let at = (start - offset) as usize;
+                new_sums.push(crc32c::crc32c(
+                    &buffer[at..at + (block_end - start) as usize],
+                ));
+                continue;
+            }
+
+            let mut block = Vec::new();
+            if start < old_size {
+                let len = sums.block_size.min(old_size - start);
+                block.resize(len as usize, 0);
+                let n_read = inner.read(&mut block, start, len)?;
+                block.truncate(n_read as usize);
+                if sums.verify(&block, n).is_some() {
+                    return Err(BackendError::checksum_mismatch(
+                        &sums.path,
+                        n..=n,
+                        Action::Write,
+                    ));
+                }
+            }
+            block.resize((block_end - start) as usize, 0);
+
+            let from = offset.max(start);
+            let to = end.min(block_end);
+            if from < to {
+                block[(from - start) as usize..(to - start) as usize]
+                    .copy_from_slice(&buffer[(from - offset) as usize..(to - offset) as usize]);
+            }
+            new_sums.push(crc32c::crc32c(&block));
+        }
+
+        let n_written = inner.write(buffer, offset, length)?;
+
+        sums.size = new_size;
+        let blocks = sums.blocks(new_size);
+        sums.table.resize(blocks, 0);
+        sums.table[first as usize..=last as usize].copy_from_slice(&new_sums);
+        sums.attach()?;
+        sums.store(first as usize, last as usize + 1)
+            .map_err(|e| e.set_action(Action::Write))?;
+
+        Ok(n_written)
+    }
+
+    fn read_vectored(&self, buffers: &mut [IoSliceMut], offset: u64) -> Result<u64> {
+        match self.sums {
+            Some(_) => read_each(self, buffers, offset),
+            None => self.inner.read_vectored(buffers, offset),
+        }
+    }
+
+    fn write_vectored(&mut self, buffers: &[IoSlice], offset: u64) -> Result<u64> {
+        match self.sums {
+            Some(_) => write_each(self, buffers, offset),
+            None => self.inner.write_vectored(buffers, offset),
+        }
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.inner.sync()?;
+        if let Some(sums) = &self.sums {
+            if let Some(sidecar) = &lock(sums)?.sidecar {
+                sidecar
+                    .sync_data()
+                    .map_err(|e| BackendError::map(&e, Action::Sync))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn status(&self) -> Result<(i64, u64)> {
+        self.inner.status()
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.inner.close()
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, IoSlice, IoSliceMut},
+    sync::{Arc, Mutex},
+};
+
+use log::trace;
+
+use crate::common::{
+    config::Config,
+    error::{Action, BackendError, Result},
+    io_handler::BackendObject,
+    shared::{lock, SharedStates},
+};
+
+static BUFFERS: SharedStates<Buffer> = SharedStates::new();
+
+/// Gathers small writes in memory and hands them to the wrapped object as few large writes.
+///
+/// Enabled with the backend option `coalesce=<size>`: writes smaller than `<size>` are
+/// merged with adjacent or overlapping buffered writes, and everything is flushed once
+/// `<size>` bytes are buffered, on sync, on close, and before a read that could observe
+/// buffered data. All handles of an object share its buffered writes. Without the
+/// option, all operations are passed through unchanged.
+pub struct CoalescingObject<T: BackendObject> {
+    inner: Mutex<T>,
+    buffer: Option<Arc<Mutex<Buffer>>>,
+}
+
+/// Buffered writes of an object.
+struct Buffer {
+    threshold: u64,
+    /// Buffered, non-overlapping and non-adjacent extents by their start offset.
+    extents: BTreeMap<u64, Vec<u8>>,
+    buffered: u64,
+}
+
+impl Buffer {
+    fn insert(&mut self, buffer: &[u8], offset: u64) {
+        // a zero-length write changes nothing and must not leave an empty extent behind
+        if buffer.is_empty() {
+            return;
+        }
+        let end = offset + buffer.len() as u64;
+
+        // all extents that touch [offset, end] are merged into a single one
+        let touching: Vec<u64> = self
+            .extents
+            .range(..=end)
+            .rev()
+            .take_while(|(start, data)| *start + data.len() as u64 >= offset)
+            .map(|(start, _)| *start)
+            .collect();
+
+        let start = touching.last().copied().unwrap_or(offset).min(offset);
+        let mut merged_end = end;
+        let mut old = Vec::with_capacity(touching.len());
+        for key in touching {
+            let data = self.extents.remove(&key).unwrap();
+            self.buffered -= data.len() as u64;
+            merged_end = merged_end.max(key + data.len() as u64);
+            old.push((key, data));
+        }
+
+        let mut merged = vec![0u8; (merged_end - start) as usize];
+        for (key, data) in old {
+            let at = (key - start) as usize;
+            merged[at..at + data.len()].copy_from_slice(&data);
+        }
+        let at = (offset - start) as usize;
+        merged[at..at + buffer.len()].copy_from_slice(buffer);
+
+        self.buffered += merged.len() as u64;
+        self.extents.insert(start, merged);
+    }
+
+    /// Writes back all extents that end after `offset` through `inner`. An extent stays
+    /// buffered until all of it is written.
+    fn flush_from<T: BackendObject>(&mut self, inner: &mut T, offset: u64) -> Result<()> {
+        let first = match self.extents.range(..offset).next_back() {
+            Some((start, data)) if start + data.len() as u64 > offset => *start,
+            _ => offset,
+        };
+
+        let keys: Vec<u64> = self.extents.range(first..).map(|(k, _)| *k).collect();
+        for key in keys {
+            let data = &self.extents[&key];
+            trace!("flushing {} b at {key}", data.len());
+            let mut written = 0;
+            while written < data.len() {
+                let rest = &data[written..];
+                match inner.write(rest, key + written as u64, rest.len() as u64)? {
+                    0 => {
+                        return Err(BackendError::map(
+                            &io::Error::from(io::ErrorKind::WriteZero),
+                            Action::Write,
+                        ))
+                    }
+                    n => written += n as usize,
+                }
+            }
+            let data = self.extents.remove(&key).unwrap();
+            self.buffered -= data.len() as u64;
+        }
+        Ok(())
+    }
+
+    fn end(&self) -> Option<u64> {
+        self.extents
+            .iter()
+            .next_back()
+            .map(|(start, data)| start + data.len() as u64)
+    }
+}
+
+impl<T: BackendObject> CoalescingObject<T> {
+    fn wrap(file: File, config: &Config, open: fn(File, &Config) -> Result<T>) -> Result<Self> {
+        let threshold = match config.get_size("coalesce")? {
+            Some(threshold) => threshold,
+            None => {
+                return Ok(CoalescingObject {
+                    inner: Mutex::new(open(file, config)?),
+                    buffer: None,
+                })
+            }
+        };
+
+        let metadata = file.metadata()?;
+        let inner = open(file, config)?;
+        let buffer = BUFFERS.get_or_init(&metadata, || {
+            Ok(Buffer {
+                threshold,
+                extents: BTreeMap::new(),
+                buffered: 0,
+            })
+        })?;
+
+        Ok(CoalescingObject {
+            inner: Mutex::new(inner),
+            buffer: Some(buffer),
+        })
+    }
+
+    fn inner(&mut self) -> Result<&mut T> {
+        self.inner
+            .get_mut()
+            .map_err(|e| BackendError::map(&e, Action::Internal))
+    }
+
+    /// Writes back the buffered extents that end after `offset`.
+    fn flush_from(&self, offset: u64) -> Result<()> {
+        if let Some(buffer) = &self.buffer {
+            lock(buffer)?.flush_from(&mut *lock(&self.inner)?, offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: BackendObject> BackendObject for CoalescingObject<T> {
+    fn init(config: &Config) -> Result<()> {
+        T::init(config)
+    }
+
+    fn new(file: File, config: &Config) -> Result<Self> {
+        Self::wrap(file, config, T::new)
+    }
+
+    fn create(file: File, config: &Config) -> Result<Self> {
+        Self::wrap(file, config, T::create)
+    }
+
+    fn read(&self, buffer: &mut [u8], offset: u64, length: u64) -> Result<u64> {
+        self.flush_from(offset)?;
+        lock(&self.inner)?.read(buffer, offset, length)
+    }
+
+    fn write(&mut self, buffer: &[u8], offset: u64, length: u64) -> Result<u64> {
+        let shared = match &self.buffer {
+            Some(shared) => shared.clone(),
+            None => return self.inner()?.write(buffer, offset, length),
+        };
+        let mut state = lock(&shared)?;
+        let inner = self.inner()?;
+
+        if buffer.len() as u64 >= state.threshold {
+            // older buffered data in this range must not be flushed over the new data later
+            state.flush_from(inner, offset)?;
+            return inner.write(buffer, offset, length);
+        }
+
+        state.insert(buffer, offset);
+        if state.buffered >= state.threshold {
+            trace!("{} b buffered, flushing", state.buffered);
+            state.flush_from(inner, 0)?;
+        }
+
+        Ok(buffer.len() as u64)
+    }
+
+    fn read_vectored(&self, buffers: &mut [IoSliceMut], offset: u64) -> Result<u64> {
+        self.flush_from(offset)?;
+        lock(&self.inner)?.read_vectored(buffers, offset)
+    }
+
+    fn write_vectored(&mut self, buffers: &[IoSlice], offset: u64) -> Result<u64> {
+        let shared = match &self.buffer {
+            Some(shared) => shared.clone(),
+            None => return self.inner()?.write_vectored(buffers, offset),
+        };
+        let mut state = lock(&shared)?;
+        let inner = self.inner()?;
+        let length: u64 = buffers.iter().map(|b| b.len() as u64).sum();
+
+        if length >= state.threshold {
+            state.flush_from(inner, offset)?;
+            return inner.write_vectored(buffers, offset);
+        }
+
+        let mut at = offset;
+        for buffer in buffers {
+            state.insert(buffer, at);
+            at += buffer.len() as u64;
+        }
+        if state.buffered >= state.threshold {
+            trace!("{} b buffered, flushing", state.buffered);
+            state.flush_from(inner, 0)?;
+        }
+
+        Ok(length)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.flush_from(0)?;
+        self.inner()?.sync()
+    }
+
+    fn status(&self) -> Result<(i64, u64)> {
+        let end = match &self.buffer {
+            Some(buffer) => lock(buffer)?.end(),
+            None => None,
+        };
+        let (time, size) = lock(&self.inner)?.status()?;
+        Ok((time, end.map_or(size, |end| end.max(size))))
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.flush_from(0)?;
+        self.inner()?.close()
+    }
+}
@@ -0,0 +1,107 @@
+use std::path::PathBuf;
+
+use rustc_hash::FxHashMap;
+
+use crate::common::error::{Action, BackendError, Result};
+
+const OPTION_SEPARATOR: char = '?';
+const PAIR_SEPARATOR: char = '&';
+const ROOT_SEPARATOR: char = ':';
+
+/// Backend options passed along with the namespace path given to `backend_init`.
+///
+/// JULEA only hands a single string to a backend, so options are appended to it
+/// in query form: `/path/to/namespace?populate&readonly=false`.
+/// A key without a value is treated as `true`.
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    options: FxHashMap<String, String>,
+}
+
+impl Config {
+    /// Splits the init path into the namespace path and its options.
+    pub fn from_init_path(path: &str) -> Result<(String, Config)> {
+        let (path, query) = match path.split_once(OPTION_SEPARATOR) {
+            Some((path, query)) => (path, query),
+            None => return Ok((String::from(path), Config::default())),
+        };
+
+        let mut config = Config::default();
+        for pair in query.split(PAIR_SEPARATOR).filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, "true"));
+            if key.is_empty() {
+                return Err(BackendError::new(
+                    &format!("Malformed backend option '{pair}'"),
+                    Action::Init,
+                ));
+            }
+            config.set(key, value);
+        }
+
+        Ok((String::from(path), config))
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.options.insert(String::from(key), String::from(value));
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.options.get(key).map(|v| v.as_str())
+    }
+
+    pub fn get_bool(&self, key: &str) -> Result<bool> {
+        self.get_bool_or(key, false)
+    }
+
+    pub fn get_bool_or(&self, key: &str, default: bool) -> Result<bool> {
+        match self.get(key) {
+            None => Ok(default),
+            Some("true" | "1" | "yes" | "on") => Ok(true),
+            Some("false" | "0" | "no" | "off") => Ok(false),
+            Some(v) => Err(invalid_option(key, v)),
+        }
+    }
+
+    /// Reads a byte size, accepting the binary suffixes K, M, G and T.
+    pub fn get_size(&self, key: &str) -> Result<Option<u64>> {
+        let value = match self.get(key) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        let (digits, shift) = match value.chars().last() {
+            Some('K' | 'k') => (&value[..value.len() - 1], 10),
+            Some('M' | 'm') => (&value[..value.len() - 1], 20),
+            Some('G' | 'g') => (&value[..value.len() - 1], 30),
+            Some('T' | 't') => (&value[..value.len() - 1], 40),
+            _ => (value, 0),
+        };
+
+        digits
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(1 << shift))
+            .map(Some)
+            .ok_or(invalid_option(key, value))
+    }
+}
+
+/// Splits the path of a backend that spans several root directories, given like a
+/// `PATH` variable: `/mnt/a:/mnt/b`.
+pub fn split_roots(path: &str) -> Result<Vec<PathBuf>> {
+    let roots: Vec<PathBuf> = path.split(ROOT_SEPARATOR).map(PathBuf::from).collect();
+    if roots.iter().any(|root| root.as_os_str().is_empty()) {
+        return Err(BackendError::new(
+            &format!("Empty root directory in '{path}'"),
+            Action::Init,
+        ));
+    }
+    Ok(roots)
+}
+
+fn invalid_option(key: &str, value: &str) -> BackendError {
+    BackendError::new(
+        &format!("Invalid value '{value}' for backend option '{key}'"),
+        Action::Init,
+    )
+}
@@ -0,0 +1,90 @@
+use std::{
+    fs::File,
+    io::{self, ErrorKind},
+    os::{fd::AsRawFd, unix::fs::FileExt},
+    ptr,
+};
+
+use log::debug;
+
+use crate::common::error::Result;
+
+/// Size of the chunks used when the data has to be copied through user space.
+const COPY_CHUNK_SIZE: usize = 1 << 20;
+
+/// Copies the content of `src` into the empty file `dst` without routing the data
+/// through JULEA. Tries, in order, a reflink (`FICLONE`), `copy_file_range` and a
+/// chunked read/write copy. Returns the number of bytes copied.
+pub fn copy_file(src: &File, dst: &File) -> Result<u64> {
+    let len = src.metadata()?.len();
+
+    if unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) } == 0 {
+        debug!("cloned {len} b via reflink");
+        return Ok(len);
+    }
+    debug!("reflink failed: {}", io::Error::last_os_error());
+
+    let mut copied = copy_range(src, dst, len)?;
+    if copied < len {
+        copied += copy_chunked(src, dst, copied, len)?;
+    }
+
+    Ok(copied)
+}
+
+/// Copies in kernel space with `copy_file_range` for as long as the file systems allow it.
+fn copy_range(src: &File, dst: &File, len: u64) -> io::Result<u64> {
+    let mut copied = 0;
+    while copied < len {
+        let n = unsafe {
+            libc::copy_file_range(
+                src.as_raw_fd(),
+                ptr::null_mut(),
+                dst.as_raw_fd(),
+                ptr::null_mut(),
+                (len - copied) as usize,
+                0,
+            )
+        };
+
+        match n {
+            0 => break,
+            n if n > 0 => copied += n as u64,
+            _ => {
+                let e = io::Error::last_os_error();
+                match e.raw_os_error() {
+                    Some(libc::EINTR) => continue,
+                    Some(libc::EXDEV | libc::ENOSYS | libc::EOPNOTSUPP | libc::EINVAL) => {
+                        debug!("copy_file_range failed after {copied} b: {e}");
+                        break;
+                    }
+                    _ => return Err(e),
+                }
+            }
+        }
+    }
+
+    if copied > 0 {
+        debug!("copied {copied} b via copy_file_range");
+    }
+    Ok(copied)
+}
+
+fn copy_chunked(src: &File, dst: &File, from: u64, len: u64) -> io::Result<u64> {
+    let mut buffer = vec![0u8; COPY_CHUNK_SIZE];
+    let mut offset = from;
+
+    while offset < len {
+        let n = match src.read_at(&mut buffer, offset) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        dst.write_all_at(&buffer[..n], offset)?;
+        offset += n as u64;
+    }
+
+    debug!("copied {} b in chunks", offset - from);
+    Ok(offset - from)
+}
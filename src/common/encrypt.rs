@@ -0,0 +1,410 @@
+use std::{
+    fs::{self, File},
+    io::{IoSlice, IoSliceMut},
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use hkdf::Hkdf;
+use log::debug;
+use rustc_hash::FxHashMap;
+use sha2::Sha256;
+
+use crate::common::{
+    config::Config,
+    error::{Action, BackendError, Result},
+    io_handler::{read_each, write_each, BackendObject},
+    shared::{lock, SharedStates},
+};
+
+const MAGIC: &[u8; 4] = b"JENC";
+const VERSION: u32 = 2;
+
+/// magic, version, block size, reserved, salt, then the nonce and the sealed plaintext
+/// size, which authenticates the fixed part
+const HEADER_LEN: u64 = 96;
+const FIXED_LEN: usize = 48;
+const SALT_LEN: usize = 32;
+const NONCE_LEN: u64 = 24;
+const TAG_LEN: u64 = 16;
+const KEY_LEN: usize = 32;
+
+const DEFAULT_BLOCK_SIZE: u64 = 4096;
+const KEY_INFO: &[u8] = b"io-backends object key";
+
+/// Returns the block size set by `encrypt_block`. The header records it in 32 bits.
+fn block_size(config: &Config) -> Result<u64> {
+    let block_size = config
+        .get_size("encrypt_block")?
+        .unwrap_or(DEFAULT_BLOCK_SIZE);
+    if block_size == 0 || block_size > u32::MAX as u64 {
+        return Err(BackendError::new(
+            &format!("Invalid block size {block_size} for backend option 'encrypt_block'"),
+            Action::Init,
+        ));
+    }
+    Ok(block_size)
+}
+
+static CRYPTS: SharedStates<Crypt> = SharedStates::new();
+
+/// Master keys by the path they were loaded from.
+static MASTER_KEYS: OnceLock<Mutex<FxHashMap<String, [u8; KEY_LEN]>>> = OnceLock::new();
+
+/// Returns the master key stored in `path`, as 32 raw bytes or 64 hex digits.
+/// Keys are read once and kept for the lifetime of the process.
+fn master_key(path: &str) -> Result<[u8; KEY_LEN]> {
+    let mut keys = MASTER_KEYS
+        .get_or_init(|| Mutex::new(FxHashMap::default()))
+        .lock()
+        .map_err(|e| BackendError::map(&e, Action::Internal))?;
+    if let Some(key) = keys.get(path) {
+        return Ok(*key);
+    }
+
+    debug!("Loading master key from {path}");
+    let content = fs::read(path).map_err(|e| BackendError::map(&e, Action::Init))?;
+    let key = match content.len() {
+        KEY_LEN => content.try_into().unwrap(),
+        _ => parse_hex(content.trim_ascii()).ok_or(BackendError::new(
+            "Master key must be 32 bytes or 64 hex digits",
+            Action::Init,
+        ))?,
+    };
+
+    keys.insert(String::from(path), key);
+    Ok(key)
+}
+
+fn parse_hex(text: &[u8]) -> Option<[u8; KEY_LEN]> {
+    if text.len() != 2 * KEY_LEN {
+        return None;
+    }
+    let mut key = [0u8; KEY_LEN];
+    for (byte, pair) in key.iter_mut().zip(text.chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(key)
+}
+
+fn random<const N: usize>() -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| BackendError::new(&e.to_string(), Action::Internal))?;
+    Ok(bytes)
+}
+
+/// Encrypts object data at rest.
+///
+/// Enabled with the backend option `encrypt_key=<path>`, which names a file holding
+/// the master key. Every object gets a random salt in its header, from which its key
+/// is derived. The data is split into blocks that are sealed with XChaCha20-Poly1305
+/// under a fresh nonce on every write and stored in fixed-size slots, so any block can
+/// be read and rewritten on its own. The block number is authenticated as well, so
+/// blocks can neither be altered nor moved. The plaintext size is sealed in the header,
+/// so truncated objects are detected too. A write behind the end leaves the blocks it
+/// skips as holes, whose slots stay zero and read as zeros without being authenticated.
+/// Without the option, all operations are passed through unchanged.
+pub struct EncryptedObject<T: BackendObject> {
+    inner: T,
+    crypt: Option<Arc<Mutex<Crypt>>>,
+}
+
+/// Key and size of an object, shared by all its handles.
+struct Crypt {
+    cipher: XChaCha20Poly1305,
+    block_size: u64,
+    /// The fixed part of the header, authenticated along with the size.
+    fixed: [u8; FIXED_LEN],
+    /// Plaintext size.
+    size: u64,
+}
+
+impl Crypt {
+    fn slot_size(&self) -> u64 {
+        NONCE_LEN + self.block_size + TAG_LEN
+    }
+
+    fn slot_offset(&self, block: u64) -> u64 {
+        HEADER_LEN + block * self.slot_size()
+    }
+
+    /// Seals `size` as the plaintext size of the object.
+    fn store_size<T: BackendObject>(&mut self, inner: &mut T, size: u64) -> Result<()> {
+        let nonce: [u8; NONCE_LEN as usize] = random()?;
+        let sealed = self
+            .cipher
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &size.to_le_bytes(),
+                    aad: &self.fixed,
+                },
+            )
+            .map_err(|e| BackendError::new(&e.to_string(), Action::Write))?;
+
+        let mut tail = Vec::with_capacity(HEADER_LEN as usize - FIXED_LEN);
+        tail.extend_from_slice(&nonce);
+        tail.extend_from_slice(&sealed);
+        inner.write(&tail, FIXED_LEN as u64, tail.len() as u64)?;
+        self.size = size;
+        Ok(())
+    }
+
+    /// Reads the header of a new or encrypted object and derives its key.
+    fn load<T: BackendObject>(
+        inner: &mut T,
+        master: &[u8; KEY_LEN],
+        config: &Config,
+    ) -> Result<Self> {
+        let mut header = [0u8; HEADER_LEN as usize];
+        let n_read = inner.read(&mut header, 0, HEADER_LEN)?;
+
+        let is_new = n_read == 0;
+        if is_new {
+            let block_size = block_size(config).map_err(|e| e.set_action(Action::Open))?;
+            header[0..4].copy_from_slice(MAGIC);
+            header[4..8].copy_from_slice(&VERSION.to_le_bytes());
+            header[8..12].copy_from_slice(&(block_size as u32).to_le_bytes());
+            header[16..FIXED_LEN].copy_from_slice(&random::<SALT_LEN>()?);
+            inner.write(&header[..FIXED_LEN], 0, FIXED_LEN as u64)?;
+        } else if n_read != HEADER_LEN
+            || &header[0..4] != MAGIC
+            || header[4..8] != VERSION.to_le_bytes()
+            || header[8..12] == [0; 4]
+        {
+            return Err(BackendError::new("Object is not encrypted", Action::Open));
+        }
+
+        let mut key = [0u8; KEY_LEN];
+        Hkdf::<Sha256>::new(Some(&header[16..FIXED_LEN]), master)
+            .expand(KEY_INFO, &mut key)
+            .map_err(|e| BackendError::new(&e.to_string(), Action::Internal))?;
+
+        let mut crypt = Crypt {
+            cipher: XChaCha20Poly1305::new(&key.into()),
+            block_size: u32::from_le_bytes(header[8..12].try_into().unwrap()) as u64,
+            fixed: header[..FIXED_LEN].try_into().unwrap(),
+            size: 0,
+        };
+        if is_new {
+            crypt.store_size(inner, 0)?;
+            return Ok(crypt);
+        }
+
+        let (nonce, sealed) = header[FIXED_LEN..].split_at(NONCE_LEN as usize);
+        let size = crypt
+            .cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: sealed,
+                    aad: &crypt.fixed,
+                },
+            )
+            .map_err(|_| {
+                BackendError::new(
+                    "Header failed authentication, the object was modified",
+                    Action::Open,
+                )
+            })?;
+        crypt.size = u64::from_le_bytes(size.try_into().unwrap());
+        Ok(crypt)
+    }
+}
+
+impl<T: BackendObject> EncryptedObject<T> {
+    fn wrap(file: File, config: &Config, open: fn(File, &Config) -> Result<T>) -> Result<Self> {
+        let path = match config.get("encrypt_key") {
+            Some(path) => path,
+            None => {
+                return Ok(EncryptedObject {
+                    inner: open(file, config)?,
+                    crypt: None,
+                })
+            }
+        };
+        let master = master_key(path)?;
+
+        let metadata = file.metadata()?;
+        let mut inner = open(file, config)?;
+        let crypt = CRYPTS.get_or_init(&metadata, || Crypt::load(&mut inner, &master, config))?;
+
+        Ok(EncryptedObject {
+            inner,
+            crypt: Some(crypt),
+        })
+    }
+
+    /// Reads and authenticates block `n` of an object with plaintext size `size`.
+    fn read_block(inner: &T, crypt: &Crypt, n: u64, size: u64) -> Result<Vec<u8>> {
+        let len = (size - n * crypt.block_size).min(crypt.block_size);
+        let slot_len = NONCE_LEN + len + TAG_LEN;
+        let mut slot = vec![0u8; slot_len as usize];
+
+        if inner.read(&mut slot, crypt.slot_offset(n), slot_len)? != slot_len {
+            return Err(BackendError::new(
+                &format!("Block {n} is truncated"),
+                Action::Read,
+            ));
+        }
+        // a hole, sealed blocks have a random nonce and a tag
+        if slot.iter().all(|b| *b == 0) {
+            return Ok(vec![0u8; len as usize]);
+        }
+
+        let (nonce, ciphertext) = slot.split_at(NONCE_LEN as usize);
+        crypt
+            .cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: &n.to_le_bytes(),
+                },
+            )
+            .map_err(|_| {
+                BackendError::new(
+                    &format!("Block {n} failed authentication, the data was modified"),
+                    Action::Read,
+                )
+            })
+    }
+}
+
+impl<T: BackendObject> BackendObject for EncryptedObject<T> {
+    fn init(config: &Config) -> Result<()> {
+        block_size(config)?;
+        if let Some(path) = config.get("encrypt_key") {
+            master_key(path)?;
+        }
+        T::init(config)
+    }
+
+    fn new(file: File, config: &Config) -> Result<Self> {
+        Self::wrap(file, config, T::new)
+    }
+
+    fn create(file: File, config: &Config) -> Result<Self> {
+        Self::wrap(file, config, T::create)
+    }
+
+    fn read(&self, buffer: &mut [u8], offset: u64, length: u64) -> Result<u64> {
+        let crypt = match &self.crypt {
+            Some(crypt) => lock(crypt)?,
+            None => return self.inner.read(buffer, offset, length),
+        };
+
+        let end = crypt.size.min(offset + buffer.len() as u64);
+        let mut position = offset;
+        while position < end {
+            let n = position / crypt.block_size;
+            let at = position % crypt.block_size;
+            let len = (crypt.block_size - at).min(end - position);
+
+            let block = Self::read_block(&self.inner, &crypt, n, crypt.size)?;
+            let done = (position - offset) as usize;
+            buffer[done..done + len as usize]
+                .copy_from_slice(&block[at as usize..(at + len) as usize]);
+            position += len;
+        }
+
+        Ok(end.saturating_sub(offset))
+    }
+
+    fn write(&mut self, buffer: &[u8], offset: u64, length: u64) -> Result<u64> {
+        let mut crypt = match &self.crypt {
+            Some(crypt) => lock(crypt)?,
+            None => return self.inner.write(buffer, offset, length),
+        };
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+
+        let end = offset + buffer.len() as u64;
+        let old_size = crypt.size;
+        let new_size = old_size.max(end);
+        let first = offset / crypt.block_size;
+        let last = (end - 1) / crypt.block_size;
+
+        // a write behind the end seals the old last block again at its new length, the
+        // blocks between it and the write are left as holes
+        let old_last = old_size / crypt.block_size;
+        let grown = (old_size % crypt.block_size != 0 && old_last < first).then_some(old_last);
+
+        for n in grown.into_iter().chain(first..=last) {
+            let start = n * crypt.block_size;
+            let block_end = (start + crypt.block_size).min(new_size);
+
+            let mut block = match start < old_size {
+                true => Self::read_block(&self.inner, &crypt, n, old_size)?,
+                false => Vec::new(),
+            };
+            block.resize((block_end - start) as usize, 0);
+
+            let from = offset.max(start);
+            let to = end.min(block_end);
+            if from < to {
+                block[(from - start) as usize..(to - start) as usize]
+                    .copy_from_slice(&buffer[(from - offset) as usize..(to - offset) as usize]);
+            }
+
+            let nonce: [u8; NONCE_LEN as usize] = random()?;
+            let ciphertext = crypt
+                .cipher
+                .encrypt(
+                    XNonce::from_slice(&nonce),
+                    Payload {
+                        msg: &block,
+                        aad: &n.to_le_bytes(),
+                    },
+                )
+                .map_err(|e| BackendError::new(&e.to_string(), Action::Write))?;
+
+            let mut slot = Vec::with_capacity(nonce.len() + ciphertext.len());
+            slot.extend_from_slice(&nonce);
+            slot.extend_from_slice(&ciphertext);
+            self.inner
+                .write(&slot, crypt.slot_offset(n), slot.len() as u64)?;
+        }
+
+        // the blocks are written first, so a crash never exposes blocks that are missing
+        if new_size != old_size {
+            crypt.store_size(&mut self.inner, new_size)?;
+        }
+        Ok(buffer.len() as u64)
+    }
+
+    fn read_vectored(&self, buffers: &mut [IoSliceMut], offset: u64) -> Result<u64> {
+        match self.crypt {
+            Some(_) => read_each(self, buffers, offset),
+            None => self.inner.read_vectored(buffers, offset),
+        }
+    }
+
+    fn write_vectored(&mut self, buffers: &[IoSlice], offset: u64) -> Result<u64> {
+        match self.crypt {
+            Some(_) => write_each(self, buffers, offset),
+            None => self.inner.write_vectored(buffers, offset),
+        }
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.inner.sync()
+    }
+
+    fn status(&self) -> Result<(i64, u64)> {
+        let (time, size) = self.inner.status()?;
+        match &self.crypt {
+            Some(crypt) => Ok((time, lock(crypt)?.size)),
+            None => Ok((time, size)),
+        }
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.inner.close()
+    }
+}
@@ -1,6 +1,12 @@
 #![allow(dead_code)]
 
-use std::{error::Error, ffi::NulError, fmt::Display};
+use std::{
+    error::Error,
+    ffi::NulError,
+    fmt::Display,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
 
 pub type Result<T> = std::result::Result<T, BackendError>;
 
@@ -8,6 +14,23 @@ pub type Result<T> = std::result::Result<T, BackendError>;
 pub struct BackendError {
     msg: String,
     action: Action,
+    kind: ErrorKind,
+}
+
+/// What went wrong, for errors that callers may want to handle specifically.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    Other,
+    /// Stored data no longer matches the checksums recorded when it was written.
+    ChecksumMismatch {
+        path: PathBuf,
+        blocks: RangeInclusive<u64>,
+    },
+    /// The sidecar holding the checksums of an object is unreadable or does not match
+    /// the object.
+    CorruptChecksums {
+        path: PathBuf,
+    },
 }
 
 impl Display for BackendError {
@@ -38,6 +61,7 @@ impl BackendError {
         BackendError {
             msg: String::from(msg),
             action,
+            kind: ErrorKind::Other,
         }
     }
 
@@ -45,6 +69,7 @@ impl BackendError {
         BackendError {
             msg: e.to_string(),
             action,
+            kind: ErrorKind::Other,
         }
     }
 
@@ -52,9 +77,39 @@ impl BackendError {
         BackendError {
             msg: String::from(msg),
             action: Action::Internal,
+            kind: ErrorKind::Other,
+        }
+    }
+
+    pub fn checksum_mismatch(path: &Path, blocks: RangeInclusive<u64>, action: Action) -> Self {
+        BackendError {
+            msg: format!(
+                "Checksum mismatch in {path:?}, blocks {} to {}",
+                blocks.start(),
+                blocks.end()
+            ),
+            action,
+            kind: ErrorKind::ChecksumMismatch {
+                path: path.to_path_buf(),
+                blocks,
+            },
         }
     }
 
+    pub fn corrupt_checksums(path: &Path, msg: &str, action: Action) -> Self {
+        BackendError {
+            msg: format!("Corrupt checksums in {path:?}: {msg}"),
+            action,
+            kind: ErrorKind::CorruptChecksums {
+                path: path.to_path_buf(),
+            },
+        }
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
     pub fn set_action(mut self, action: Action) -> Self {
         self.action = action;
         self
@@ -68,6 +123,7 @@ pub enum Action {
     Fini,
     Create,
     Delete,
+    Copy,
     Open,
     Close,
     Status,
@@ -1,28 +1,92 @@
 use std::{
-    fs::File,
+    fs::{File, OpenOptions},
+    io::{self, IoSlice, IoSliceMut},
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::fs::OpenOptionsExt,
+    },
     sync::{Arc, RwLock},
 };
 
-use log::{error, info};
+use log::{debug, error, info};
 use rustc_hash::FxHashMap;
 
 use crate::common::error::Result;
 
 use super::{
     error::{Action, BackendError},
-    prelude::ObjectHandle,
+    prelude::{Config, ObjectHandle, PendingLinks},
 };
 
 pub trait BackendObject: Sized {
-    fn new(file: File) -> Result<Self>;
+    /// Called once by `backend_init`, before any object of the namespace is opened.
+    fn init(_config: &Config) -> Result<()> {
+        Ok(())
+    }
+
+    fn new(file: File, config: &Config) -> Result<Self>;
+
+    /// Called instead of `new` for objects that were just created.
+    fn create(file: File, config: &Config) -> Result<Self> {
+        Self::new(file, config)
+    }
 
     fn read(&self, buffer: &mut [u8], offset: u64, length: u64) -> Result<u64>;
 
     fn write(&mut self, buffer: &[u8], offset: u64, length: u64) -> Result<u64>;
 
+    /// Reads consecutive bytes starting at `offset` into several buffers.
+    /// Backends that support scatter reads should override this to issue a single request.
+    fn read_vectored(&self, buffers: &mut [IoSliceMut], offset: u64) -> Result<u64> {
+        read_each(self, buffers, offset)
+    }
+
+    /// Writes several buffers to consecutive bytes starting at `offset`.
+    /// Backends that support gather writes should override this to issue a single request.
+    fn write_vectored(&mut self, buffers: &[IoSlice], offset: u64) -> Result<u64> {
+        write_each(self, buffers, offset)
+    }
+
     fn sync(&mut self) -> Result<()>;
 
     fn status(&self) -> Result<(i64, u64)>;
+
+    /// Called when JULEA closes the object, right before it is dropped.
+    fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads into several buffers with one `read` per buffer, until one comes up short.
+/// For layers that have to see every request, whatever the inner object supports.
+pub fn read_each<T: BackendObject>(
+    object: &T,
+    buffers: &mut [IoSliceMut],
+    offset: u64,
+) -> Result<u64> {
+    let mut n_read = 0;
+    for buffer in buffers.iter_mut() {
+        let length = buffer.len() as u64;
+        let n = object.read(buffer, offset + n_read, length)?;
+        n_read += n;
+        if n < length {
+            break;
+        }
+    }
+    Ok(n_read)
+}
+
+/// Writes several buffers with one `write` per buffer.
+pub fn write_each<T: BackendObject>(
+    object: &mut T,
+    buffers: &[IoSlice],
+    offset: u64,
+) -> Result<u64> {
+    let mut n_written = 0;
+    for buffer in buffers.iter() {
+        n_written += object.write(buffer, offset + n_written, buffer.len() as u64)?;
+    }
+    Ok(n_written)
 }
 
 pub struct ObjectStore<T: BackendObject> {
@@ -113,16 +177,61 @@ impl<T: BackendObject> ObjectStore<T> {
     }
 }
 
+/// Whether the descriptor `fd` was opened for writing.
+pub fn is_writable(fd: RawFd) -> Result<bool> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(flags & libc::O_ACCMODE != libc::O_RDONLY)
+}
+
+/// Reopens the file behind the read-only descriptor `fd` for writing, in place of the
+/// old descriptor, so that everything that knows the object by `fd` keeps working.
+/// Status flags like `O_DIRECT` are carried over.
+pub fn make_writable(fd: RawFd) -> Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 {
+        return Err(BackendError::map(
+            &io::Error::last_os_error(),
+            Action::Write,
+        ));
+    }
+    if flags & libc::O_ACCMODE != libc::O_RDONLY {
+        return Ok(());
+    }
+
+    debug!("reopening read-only file for writing");
+    let writable = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(flags & !libc::O_ACCMODE)
+        .open(format!("/proc/self/fd/{fd}"))
+        .map_err(|e| BackendError::map(&e, Action::Write))?;
+
+    if unsafe { libc::dup3(writable.as_raw_fd(), fd, libc::O_CLOEXEC) } < 0 {
+        return Err(BackendError::map(
+            &io::Error::last_os_error(),
+            Action::Write,
+        ));
+    }
+    Ok(())
+}
+
 pub struct Backend<T: BackendObject> {
     pub object_store: ObjectStore<T>,
     pub namespace: String,
+    pub config: Config,
+    pub pending: PendingLinks,
 }
 
 impl<T: BackendObject> Backend<T> {
-    pub fn new(path: String) -> Self {
+    pub fn new(path: String, config: Config) -> Self {
         Backend {
             object_store: ObjectStore::new(),
             namespace: path,
+            config,
+            pending: PendingLinks::new(),
         }
     }
 
@@ -144,8 +253,18 @@ impl<T: BackendObject> Backend<T> {
         offset: u64,
         length: u64,
     ) -> Result<u64> {
-        self.object_store
-            .write(backend_object.raw_fd, buffer, offset, length)
+        if !self.config.get_bool("readonly")? {
+            return self
+                .object_store
+                .write(backend_object.raw_fd, buffer, offset, length);
+        }
+
+        // the object is upgraded under the store lock, so its file cannot be closed meanwhile
+        let fd = backend_object.raw_fd;
+        self.object_store.execute(fd, &mut |object: &mut T| {
+            make_writable(fd)?;
+            object.write(buffer, offset, length)
+        })
     }
 
     pub fn status(&self, backend_object: &ObjectHandle) -> Result<(i64, u64)> {
@@ -0,0 +1,352 @@
+use std::{
+    cell::Cell,
+    cmp::min,
+    ffi::c_void,
+    fs::File,
+    io, mem,
+    ops::Deref,
+    os::{
+        fd::AsRawFd,
+        unix::fs::{FileExt, MetadataExt},
+    },
+    ptr,
+    sync::{
+        atomic::{compiler_fence, AtomicBool, Ordering},
+        OnceLock,
+    },
+};
+
+use log::{debug, trace, warn};
+use memmap2::{Mmap, MmapMut, MmapOptions, RemapOptions};
+
+use crate::common::{
+    config::Config,
+    error::{Action, BackendError, Result},
+    io_handler::{is_writable, make_writable, BackendObject},
+};
+
+const DEFAULT_MAP_SIZE: u64 = u64::pow(2, 20);
+
+/// Objects whose file is opened read-only are mapped read-only and only
+/// remapped writable once they are written to.
+enum Mapping {
+    ReadOnly(Mmap),
+    Writable(MmapMut),
+}
+
+impl Deref for Mapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Mapping::ReadOnly(mmap) => mmap,
+            Mapping::Writable(mmap) => mmap,
+        }
+    }
+}
+
+/// An object accessed through a memory map of its file, shared by the mmap and shm
+/// backends.
+pub struct MmapObject {
+    file: File,
+    mmap: Mapping,
+    size: u64,
+    populate: bool,
+    /// Set once an access faulted because the file was truncated behind the object's
+    /// back. Parts of the mapping no longer show the file then, so all accesses use
+    /// `pread`/`pwrite` until the next write maps it again.
+    faulted: AtomicBool,
+}
+
+impl MmapObject {
+    fn map_read_only(file: &File, size: u64, populate: bool) -> Result<Mapping> {
+        let mut options = MmapOptions::new();
+        if populate {
+            options.populate();
+        }
+
+        unsafe { options.len(size as usize).map(file) }
+            .map(Mapping::ReadOnly)
+            .map_err(|e| BackendError::map(&e, Action::Init))
+    }
+
+    fn map_writable(file: &File, size: u64, populate: bool) -> Result<Mapping> {
+        let mut options = MmapOptions::new();
+        if populate {
+            options.populate();
+        }
+
+        let mmap_size = u64::max(DEFAULT_MAP_SIZE, size);
+        unsafe { options.offset(0).len(mmap_size as usize).map_mut(file) }
+            .map(Mapping::Writable)
+            .map_err(|e| BackendError::map(&e, Action::Init))
+    }
+
+    /// Replaces a read-only mapping with a writable one. If the file itself was
+    /// opened read-only, it is reopened for writing in place of the old
+    /// descriptor so that the fd the object store knows it by stays valid.
+    fn make_writable(&mut self) -> Result<()> {
+        if let Mapping::Writable(_) = self.mmap {
+            return Ok(());
+        }
+
+        make_writable(self.file.as_raw_fd())?;
+        debug!("upgrading read-only memory map to writable");
+        self.mmap = Self::map_writable(&self.file, self.size, self.populate)
+            .map_err(|e| e.set_action(Action::Write))?;
+        Ok(())
+    }
+
+    /// Maps the file again after a fault, at its current length.
+    fn remap(&mut self) -> Result<()> {
+        self.size = self.file.metadata()?.len();
+        debug!("mapping truncated file again at {} b", self.size);
+        self.mmap = Self::map_writable(&self.file, self.size, self.populate)
+            .map_err(|e| e.set_action(Action::Write))?;
+        self.faulted.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn fault(&self) {
+        warn!("file was truncated externally, falling back to pread/pwrite");
+        self.faulted.store(true, Ordering::Relaxed);
+    }
+
+    fn enlarge(&mut self) {
+        debug!(
+            "resizing memory map {} b => {} b",
+            self.mmap.len(),
+            self.size * 2
+        );
+        if let Mapping::Writable(mmap) = &mut self.mmap {
+            unsafe {
+                let _ = mmap.remap((self.size * 2) as usize, RemapOptions::new().may_move(true));
+            }
+        }
+    }
+}
+
+impl BackendObject for MmapObject {
+    fn new(file: File, config: &Config) -> Result<Self> {
+        let file_size = file.metadata()?.len();
+        let populate = config.get_bool("populate")?;
+
+        let mmap = if is_writable(file.as_raw_fd())? {
+            Self::map_writable(&file, file_size, populate)?
+        } else {
+            debug!("mapping read-only file of {file_size} b");
+            Self::map_read_only(&file, file_size, populate)?
+        };
+
+        Ok(MmapObject {
+            file,
+            mmap,
+            size: file_size,
+            populate,
+            faulted: AtomicBool::new(false),
+        })
+    }
+
+    fn read(&self, buffer: &mut [u8], offset: u64, length: u64) -> Result<u64> {
+        if offset >= self.size {
+            return Ok(0);
+        }
+
+        let length = min(
+            buffer.len() as u64,
+            min(self.size, offset + length) - offset,
+        );
+        let buffer = &mut buffer[..length as usize];
+        let end = offset + length;
+        if !self.faulted.load(Ordering::Relaxed) && end <= self.mmap.len() as u64 {
+            let mapped = &self.mmap[offset as usize..end as usize];
+            if guarded(mapped.as_ptr(), mapped.len(), || {
+                buffer.copy_from_slice(mapped)
+            }) {
+                return Ok(buffer.len() as u64);
+            }
+            self.fault();
+        }
+
+        read_at(&self.file, buffer, offset)
+    }
+
+    fn write(&mut self, buffer: &[u8], offset: u64, length: u64) -> Result<u64> {
+        self.make_writable()?;
+        if self.faulted.load(Ordering::Relaxed) {
+            self.remap()?;
+        }
+
+        let buffer = &buffer[..min(buffer.len(), length as usize)];
+        let end = offset + buffer.len() as u64;
+        if self.size < end {
+            self.size = end;
+            self.file.set_len(self.size)?;
+        }
+
+        if self.mmap.len() < end as usize {
+            trace!(
+                "Calculated size exceeds memory map: {} b < {end} b",
+                self.mmap.len()
+            );
+            self.enlarge();
+        }
+
+        if end <= self.mmap.len() as u64 {
+            let mmap = match &mut self.mmap {
+                Mapping::Writable(mmap) => mmap,
+                Mapping::ReadOnly(_) => {
+                    return Err(BackendError::new(
+                        "Memory map is not writable.",
+                        Action::Write,
+                    ))
+                }
+            };
+            let mapped = &mut mmap[offset as usize..end as usize];
+            let (start, len) = (mapped.as_ptr(), mapped.len());
+            if guarded(start, len, || mapped.copy_from_slice(buffer)) {
+                return Ok(buffer.len() as u64);
+            }
+            self.fault();
+        }
+
+        self.file
+            .write_all_at(buffer, offset)
+            .map_err(|e| BackendError::map(&e, Action::Write))?;
+        Ok(buffer.len() as u64)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        match &self.mmap {
+            Mapping::Writable(mmap) => mmap
+                .flush()
+                .map_err(|e| BackendError::map(&e, Action::Sync)),
+            Mapping::ReadOnly(_) => Ok(()),
+        }
+    }
+
+    fn status(&self) -> Result<(i64, u64)> {
+        let metadata = self.file.metadata()?;
+        Ok((metadata.atime(), min(self.size, metadata.len())))
+    }
+}
+
+fn read_at(file: &File, buffer: &mut [u8], offset: u64) -> Result<u64> {
+    let mut n_read = 0;
+    while n_read < buffer.len() {
+        match file.read_at(&mut buffer[n_read..], offset + n_read as u64) {
+            Ok(0) => break,
+            Ok(n) => n_read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(BackendError::map(&e, Action::Read)),
+        }
+    }
+    Ok(n_read as u64)
+}
+
+thread_local! {
+    /// Start and length of the mapped range the thread is accessing, and whether
+    /// touching it faulted.
+    static GUARD: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+    static FAULTED: Cell<bool> = const { Cell::new(false) };
+}
+
+type SigAction = extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut c_void);
+
+/// The SIGBUS handler, installed on the first mapped access.
+struct Handler {
+    previous: libc::sigaction,
+    page_size: usize,
+}
+
+static HANDLER: OnceLock<Handler> = OnceLock::new();
+
+impl Handler {
+    fn install() -> Handler {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = on_sigbus as SigAction as usize;
+            action.sa_flags = libc::SA_SIGINFO;
+            libc::sigemptyset(&mut action.sa_mask);
+
+            let mut previous: libc::sigaction = mem::zeroed();
+            if libc::sigaction(libc::SIGBUS, &action, &mut previous) != 0 {
+                warn!(
+                    "unable to install SIGBUS handler: {}",
+                    io::Error::last_os_error()
+                );
+            }
+            Handler {
+                previous,
+                page_size,
+            }
+        }
+    }
+}
+
+/// Touching a page of a mapping that lies past the end of its file raises SIGBUS. If
+/// the thread was inside [`guarded`] and the page belongs to the guarded range, the
+/// page is replaced by a zero page so that the access completes, and the fault is
+/// recorded. Any other SIGBUS goes to the handler that was installed before.
+extern "C" fn on_sigbus(signal: libc::c_int, info: *mut libc::siginfo_t, context: *mut c_void) {
+    let handler = match HANDLER.get() {
+        Some(handler) => handler,
+        None => return,
+    };
+    let addr = unsafe { (*info).si_addr() } as usize;
+    let guarded = GUARD
+        .try_with(Cell::get)
+        .ok()
+        .flatten()
+        .is_some_and(|(start, len)| addr >= start && addr < start + len);
+
+    if guarded {
+        let page = addr & !(handler.page_size - 1);
+        let zeros = unsafe {
+            libc::mmap(
+                page as *mut c_void,
+                handler.page_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED,
+                -1,
+                0,
+            )
+        };
+        if zeros != libc::MAP_FAILED {
+            let _ = FAULTED.try_with(|faulted| faulted.set(true));
+            return;
+        }
+    }
+
+    let previous = &handler.previous;
+    match previous.sa_sigaction {
+        // the faulting access is repeated and raises the signal again
+        libc::SIG_DFL | libc::SIG_IGN => unsafe {
+            libc::sigaction(libc::SIGBUS, previous, ptr::null_mut());
+        },
+        action if previous.sa_flags & libc::SA_SIGINFO != 0 => unsafe {
+            let action: SigAction = mem::transmute(action);
+            action(signal, info, context);
+        },
+        action => unsafe {
+            let action: extern "C" fn(libc::c_int) = mem::transmute(action);
+            action(signal);
+        },
+    }
+}
+
+/// Runs `access`, which touches the `len` mapped bytes at `start`. Returns `false` if
+/// part of them lies past the end of the file, in which case the mapping no longer
+/// shows the file and whatever `access` copied is garbage.
+fn guarded(start: *const u8, len: usize, access: impl FnOnce()) -> bool {
+    HANDLER.get_or_init(Handler::install);
+
+    GUARD.set(Some((start as usize, len)));
+    FAULTED.set(false);
+    compiler_fence(Ordering::SeqCst);
+    access();
+    compiler_fence(Ordering::SeqCst);
+    GUARD.set(None);
+    !FAULTED.replace(false)
+}
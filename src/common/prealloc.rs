@@ -0,0 +1,110 @@
+use std::{fs::File, io, os::fd::AsRawFd};
+
+use log::{debug, warn};
+
+use crate::common::{
+    config::Config,
+    error::{Action, BackendError, Result},
+};
+
+/// Smallest step by which the allocation grows ahead of the writes.
+const MIN_GROWTH: u64 = u64::pow(2, 20);
+
+/// Reserves disk space for an object ahead of its writes, so that objects written
+/// in many pieces are not extended (and fragmented) one write at a time.
+///
+/// Configured through the backend options `prealloc=<size>`, which reserves a fixed
+/// size when an object is created, and `prealloc_growth`, which doubles the
+/// reservation whenever a write passes it. Space is reserved with
+/// `FALLOC_FL_KEEP_SIZE`, so the object size is unaffected; whatever is left unused
+/// is released again by [`Preallocator::trim`].
+pub struct Preallocator {
+    initial: Option<u64>,
+    growth: bool,
+    allocated: u64,
+    supported: bool,
+}
+
+impl Preallocator {
+    pub fn new(file: &File, config: &Config) -> Result<Self> {
+        Ok(Preallocator {
+            initial: config.get_size("prealloc")?,
+            growth: config.get_bool("prealloc_growth")?,
+            allocated: file.metadata()?.len(),
+            supported: true,
+        })
+    }
+
+    pub fn on_create(&mut self, file: &File) -> Result<()> {
+        match self.initial {
+            Some(size) => self.reserve(file, size),
+            None => Ok(()),
+        }
+    }
+
+    pub fn before_write(&mut self, file: &File, offset: u64, length: u64) -> Result<()> {
+        let end = offset + length;
+        if !self.growth || end <= self.allocated {
+            return Ok(());
+        }
+
+        let step = self.initial.unwrap_or(MIN_GROWTH);
+        self.reserve(file, end.max(self.allocated * 2).max(step))
+    }
+
+    /// Releases the reserved space past the end of the object.
+    pub fn trim(&mut self, file: &File) -> Result<()> {
+        let size = file.metadata()?.len();
+        if !self.supported || self.allocated <= size {
+            return Ok(());
+        }
+
+        // truncating to the current size frees the blocks past EOF, punching a hole
+        // does not on file systems like ext4 which clamp the range to the file size
+        debug!("trimming unused preallocation {size}..{} b", self.allocated);
+        file.set_len(size)?;
+        self.allocated = size;
+        Ok(())
+    }
+
+    fn reserve(&mut self, file: &File, end: u64) -> Result<()> {
+        if !self.supported || end <= self.allocated {
+            return Ok(());
+        }
+
+        debug!("preallocating {} b => {end} b", self.allocated);
+        match fallocate(
+            file,
+            libc::FALLOC_FL_KEEP_SIZE,
+            self.allocated,
+            end - self.allocated,
+        ) {
+            Ok(_) => {
+                self.allocated = end;
+                Ok(())
+            }
+            Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => {
+                warn!("file system does not support preallocation, disabling it");
+                self.supported = false;
+                Ok(())
+            }
+            Err(e) => Err(BackendError::map(&e, Action::Write)),
+        }
+    }
+}
+
+fn fallocate(file: &File, mode: libc::c_int, offset: u64, length: u64) -> io::Result<()> {
+    let ret = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            mode,
+            offset as libc::off_t,
+            length as libc::off_t,
+        )
+    };
+
+    match ret {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
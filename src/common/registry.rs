@@ -0,0 +1,110 @@
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+use rustc_hash::FxHashMap;
+
+use crate::common::{
+    config::Config,
+    error::{Action, BackendError, Result},
+};
+
+/// State that the objects of a backend share, like a log or a chunk store, for all
+/// initialized backends.
+///
+/// Objects are set up from their file and the config alone, so `backend_init` records
+/// the key of its state in the backend option `option`, and objects find the state
+/// through the config they are opened with. Backends initialized with the same key
+/// share one state, which stays registered until the last of them is released.
+pub struct Registry<S> {
+    option: &'static str,
+    entries: OnceLock<Mutex<FxHashMap<String, Entry<S>>>>,
+}
+
+struct Entry<S> {
+    state: Arc<S>,
+    /// Number of backends that registered the state.
+    backends: usize,
+}
+
+impl<S> Registry<S> {
+    pub const fn new(option: &'static str) -> Self {
+        Registry {
+            option,
+            entries: OnceLock::new(),
+        }
+    }
+
+    fn entries(&self) -> Result<MutexGuard<'_, FxHashMap<String, Entry<S>>>> {
+        self.entries
+            .get_or_init(|| Mutex::new(FxHashMap::default()))
+            .lock()
+            .map_err(|e| BackendError::map(&e, Action::Internal))
+    }
+
+    /// Returns the state registered under `key`, or registers the one `open` returns,
+    /// and records the key in `config`. Every registration is undone by [`unregister`].
+    ///
+    /// [`unregister`]: Registry::unregister
+    pub fn register(
+        &self,
+        config: &mut Config,
+        key: &str,
+        open: impl FnOnce() -> Result<Arc<S>>,
+    ) -> Result<Arc<S>> {
+        let mut entries = self.entries()?;
+        let state = match entries.get_mut(key) {
+            Some(entry) => {
+                entry.backends += 1;
+                entry.state.clone()
+            }
+            None => {
+                let state = open()?;
+                entries.insert(
+                    String::from(key),
+                    Entry {
+                        state: state.clone(),
+                        backends: 1,
+                    },
+                );
+                state
+            }
+        };
+        config.set(self.option, key);
+        Ok(state)
+    }
+
+    /// Drops the registration recorded in `config`. Returns the state once the last
+    /// backend that registered it is gone, `None` while others still use it.
+    pub fn unregister(&self, config: &Config) -> Result<Option<Arc<S>>> {
+        let key = match config.get(self.option) {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        let mut entries = self.entries()?;
+        let entry = match entries.get_mut(key) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        entry.backends -= 1;
+        match entry.backends {
+            0 => Ok(entries.remove(key).map(|entry| entry.state)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the state recorded in `config` by `backend_init`.
+    pub fn lookup(&self, config: &Config) -> Result<Arc<S>> {
+        let key = config
+            .get(self.option)
+            .ok_or(BackendError::new_internal(&format!(
+                "Backend option '{}' was not set up for this backend",
+                self.option
+            )))?;
+        self.entries()?
+            .get(key)
+            .map(|entry| entry.state.clone())
+            .ok_or(BackendError::new_internal(&format!(
+                "Nothing registered for {key}"
+            )))
+    }
+}
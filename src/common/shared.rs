@@ -0,0 +1,59 @@
+use std::{
+    fs::Metadata,
+    os::unix::fs::MetadataExt,
+    sync::{Arc, Mutex, MutexGuard, OnceLock, Weak},
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::common::error::{Action, BackendError, Result};
+
+/// Open objects by device and inode of their file.
+type States<S> = FxHashMap<(u64, u64), Weak<Mutex<S>>>;
+
+/// State that all handles of an object share, for layers that keep metadata of an
+/// object in memory. Every handle of an object gets the same state, so a write through
+/// one handle is seen by all others. The state is dropped with the last handle.
+pub struct SharedStates<S> {
+    states: OnceLock<Mutex<States<S>>>,
+}
+
+impl<S> SharedStates<S> {
+    pub const fn new() -> Self {
+        SharedStates {
+            states: OnceLock::new(),
+        }
+    }
+
+    /// Returns the state of the object whose file has `metadata`, created by `init`
+    /// unless another handle has the object open already.
+    pub fn get_or_init(
+        &self,
+        metadata: &Metadata,
+        init: impl FnOnce() -> Result<S>,
+    ) -> Result<Arc<Mutex<S>>> {
+        let key = (metadata.dev(), metadata.ino());
+
+        let mut states = lock(self.states.get_or_init(|| Mutex::new(FxHashMap::default())))?;
+        if let Some(state) = states.get(&key).and_then(Weak::upgrade) {
+            return Ok(state);
+        }
+
+        let state = Arc::new(Mutex::new(init()?));
+        states.retain(|_, state| state.strong_count() > 0);
+        states.insert(key, Arc::downgrade(&state));
+        Ok(state)
+    }
+}
+
+impl<S> Default for SharedStates<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn lock<T>(mutex: &Mutex<T>) -> Result<MutexGuard<'_, T>> {
+    mutex
+        .lock()
+        .map_err(|e| BackendError::map(&e, Action::Internal))
+}
@@ -18,6 +18,12 @@ pub mod testing {
 use bindings::*;
 pub type ObjectBackend = JBackend__bindgen_ty_1__bindgen_ty_1;
 
+/// Defines the `BACKEND` of the object backend whose adapter lives in the module `$name`.
+///
+/// JULEA's `JBackend` only has slots for its own operations, so anything beyond them is
+/// exported as a separate `extern "C"` symbol next to `backend_info`, which callers look
+/// up in the loaded library. This macro exports `backend_copy` for every backend,
+/// backends with operations of their own, like `backend_resync`, export them alike.
 #[macro_export]
 macro_rules! generate_backend {
     ($name: ident) => {
@@ -44,5 +50,23 @@ macro_rules! generate_backend {
                 },
             },
         };
+
+        /// Server-side object copy.
+        #[no_mangle]
+        pub unsafe extern "C" fn backend_copy(
+            backend_data: gpointer,
+            src_namespace: *const gchar,
+            src_path: *const gchar,
+            dst_namespace: *const gchar,
+            dst_path: *const gchar,
+        ) -> gboolean {
+            $name::Adapter::j_copy(
+                backend_data,
+                src_namespace,
+                src_path,
+                dst_namespace,
+                dst_path,
+            )
+        }
     };
 }
@@ -1,8 +1,10 @@
 pub mod filesystem;
+pub mod iterate;
 pub mod workflow_test;
 pub mod writes;
 
 pub mod prelude {
     pub use crate::test_facility::filesystem::*;
+    pub use crate::test_facility::iterate::*;
     pub use crate::test_facility::workflow_test::*;
 }
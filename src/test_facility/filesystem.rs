@@ -3,6 +3,7 @@ use assert_fs::{
     TempDir,
 };
 use log::{debug, error, info};
+use std::path::Path;
 pub const WRITE_FILE: &'static str = "write.txt";
 pub const READ_FILE: &'static str = "read.txt";
 pub const DELETE_FILE: &'static str = "delete.txt";
@@ -10,9 +11,14 @@ pub const DELETE_FILE: &'static str = "delete.txt";
 pub const CREATE_FILE: &'static str = "create.txt";
 
 pub fn setup() -> TempDir {
+    setup_in(&std::env::temp_dir())
+}
+
+/// Like [`setup`], but creates the test directory below `dir`.
+pub fn setup_in(dir: &Path) -> TempDir {
     info!("Preparing test environment...");
 
-    let temp = match prepare_test_directory() {
+    let temp = match prepare_test_directory(dir) {
         Ok(temp) => {
             debug!("Test directory prepared.");
             temp
@@ -35,8 +41,8 @@ pub fn shutdown(temp: TempDir) {
     temp.close().unwrap();
 }
 
-fn prepare_test_directory() -> std::result::Result<TempDir, String> {
-    let temp = TempDir::new().map_err(|e| e.to_string())?;
+fn prepare_test_directory(dir: &Path) -> std::result::Result<TempDir, String> {
+    let temp = TempDir::new_in(dir).map_err(|e| e.to_string())?;
     debug!(
         "Test directory: \"{:?}\"",
         temp.to_str().unwrap_or("[cannot display]")
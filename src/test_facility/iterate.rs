@@ -0,0 +1,38 @@
+use std::ffi::CString;
+use std::ptr;
+
+use crate::bindings::{gpointer, JBackend__bindgen_ty_1__bindgen_ty_1 as ObjectBackend};
+use crate::common::prelude::{read_str, TRUE};
+
+/// Lists the objects of `namespace` through the iterator entry points of `backend`,
+/// with `get_by_prefix` if a prefix is given and `get_all` otherwise. `backend_data`
+/// has to be what `backend_init` of `backend` returned.
+pub unsafe fn names(
+    backend: &ObjectBackend,
+    backend_data: gpointer,
+    namespace: &str,
+    prefix: Option<&str>,
+) -> Vec<String> {
+    let namespace = CString::new(namespace).unwrap();
+    let prefix = prefix.map(|p| CString::new(p).unwrap());
+
+    let mut iter: gpointer = ptr::null_mut();
+    let ret = match &prefix {
+        Some(prefix) => backend.backend_get_by_prefix.unwrap()(
+            backend_data,
+            namespace.as_ptr(),
+            prefix.as_ptr(),
+            &mut iter,
+        ),
+        None => backend.backend_get_all.unwrap()(backend_data, namespace.as_ptr(), &mut iter),
+    };
+    assert_eq!(ret, TRUE, "Unable to list {namespace:?}");
+
+    // the iterator releases itself once it reached its end
+    let mut names = Vec::new();
+    let mut name = ptr::null();
+    while backend.backend_iterate.unwrap()(backend_data, iter, &mut name) == TRUE {
+        names.push(read_str(name).unwrap());
+    }
+    names
+}
@@ -2,10 +2,10 @@
 use std::ffi::{CStr, CString};
 use std::fs::{self};
 use std::os::raw::c_void;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::test_facility::filesystem::{
-    setup, shutdown, CREATE_FILE, DELETE_FILE, READ_FILE, WRITE_FILE,
+    setup_in, shutdown, CREATE_FILE, DELETE_FILE, READ_FILE, WRITE_FILE,
 };
 
 use assert_fs::fixture::PathChild;
@@ -16,12 +16,21 @@ use crate::common::prelude::{BackendIterator, ObjectHandle, FALSE, TRUE};
 use log::{error, info, warn};
 
 pub fn test_workflow(backend: &ObjectBackend, data_factory: impl Fn(String) -> *mut gpointer) {
+    test_workflow_in(&std::env::temp_dir(), backend, data_factory);
+}
+
+/// Like [`test_workflow`], but the test namespace is created below `dir`.
+pub fn test_workflow_in(
+    dir: &Path,
+    backend: &ObjectBackend,
+    data_factory: impl Fn(String) -> *mut gpointer,
+) {
     let _ = env_logger::builder()
         .filter_level(log::LevelFilter::Debug)
         //        .filter_module("io_backends", log::LevelFilter::Warn)
         .try_init();
 
-    let temp = setup();
+    let temp = setup_in(dir);
 
     let backend_data = data_factory(String::from(temp.to_str().unwrap()));
 
@@ -7,6 +7,15 @@ use crate::{
 };
 
 pub fn test_writes(backend: &ObjectBackend, data_factory: impl Fn(String) -> *mut gpointer) {
+    test_writes_with_options(backend, data_factory, "")
+}
+
+/// Runs the write benchmark with `options` appended to the namespace passed to `backend_init`.
+pub fn test_writes_with_options(
+    backend: &ObjectBackend,
+    data_factory: impl Fn(String) -> *mut gpointer,
+    options: &str,
+) {
     let _ = env_logger::builder()
         .filter_level(log::LevelFilter::Debug)
         //        .filter_module("io_backends", log::LevelFilter::Warn)
@@ -25,6 +34,7 @@ pub fn test_writes(backend: &ObjectBackend, data_factory: impl Fn(String) -> *mu
         Some(path) => String::from(path),
         None => panic!("Non-UTF8 characters in path to namespace"),
     };
+    namespace.push_str(options);
     namespace.push('\0');
 
     unsafe {